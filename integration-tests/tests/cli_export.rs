@@ -0,0 +1,74 @@
+#![allow(deprecated)]
+
+use assert_cmd::Command;
+use microfactory::{
+    adapters::outbound::persistence::{
+        SessionEnvelope, SessionMetadata, SessionStatus, SessionStore,
+    },
+    core::domain::Context,
+};
+use std::path::Path;
+
+#[test]
+fn export_ndjson_emits_one_line_per_session() {
+    let temp = tempfile::TempDir::new().unwrap();
+    seed_sessions(temp.path(), 3);
+
+    let output = Command::new(assert_cmd::cargo::cargo_bin("microfactory"))
+        .env("MICROFACTORY_HOME", temp.path())
+        .arg("export")
+        .arg("--ndjson")
+        .output()
+        .expect("run export");
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3);
+    for line in lines {
+        let value: serde_json::Value = serde_json::from_str(line).expect("valid JSON line");
+        assert!(value["session_id"].as_str().unwrap().starts_with("export-"));
+    }
+}
+
+#[test]
+fn export_without_ndjson_emits_json_array() {
+    let temp = tempfile::TempDir::new().unwrap();
+    seed_sessions(temp.path(), 2);
+
+    let output = Command::new(assert_cmd::cargo::cargo_bin("microfactory"))
+        .env("MICROFACTORY_HOME", temp.path())
+        .arg("export")
+        .arg("--limit")
+        .arg("1")
+        .output()
+        .expect("run export");
+    assert!(output.status.success());
+
+    let value: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(value.as_array().unwrap().len(), 1);
+}
+
+fn seed_sessions(home: &Path, count: usize) {
+    let store = SessionStore::open(Some(home.join(".microfactory"))).expect("open session store");
+    for idx in 0..count {
+        let mut ctx = Context::new("Export smoke test", "code");
+        ctx.session_id = format!("export-{idx}");
+        let envelope = SessionEnvelope {
+            context: ctx,
+            metadata: SessionMetadata {
+                config_path: "config.yaml".into(),
+                llm_provider: "openai".into(),
+                llm_model: "gpt-4o".into(),
+                max_concurrent_llm: 1,
+                samples: 1,
+                k: 1,
+                human_low_margin_threshold: 1,
+                ..Default::default()
+            },
+        };
+        store
+            .save(&envelope, SessionStatus::Completed)
+            .expect("seed export session");
+    }
+}
@@ -80,6 +80,45 @@ fn test_verbose_logging_adds_timestamps() {
         .stdout(predicate::str::contains(" INFO "));
 }
 
+#[test]
+fn test_run_json_keeps_logs_off_stdout() {
+    let temp = tempfile::TempDir::new().unwrap();
+    let config_path = temp.path().join("config.yaml");
+    std::fs::write(&config_path, "domains:\n  code:\n    verifier: 'echo ok'\n    applier: 'overwrite_file'\n    agents:\n      decomposition:\n        model: gpt\n        prompt_template: t\n        samples: 1\n      decomposition_discriminator:\n        model: gpt\n        prompt_template: t\n        k: 1\n      solver:\n        model: gpt\n        prompt_template: t\n        samples: 1\n      solution_discriminator:\n        model: gpt\n        prompt_template: t\n        k: 1\n").unwrap();
+    let plan_path = temp.path().join("plan.txt");
+    std::fs::write(&plan_path, "write the parser\n").unwrap();
+    // Every call renders the literal template `t`, so one repeated record answers them all
+    let trace_path = temp.path().join("trace.jsonl");
+    let record = "{\"model\":\"gpt\",\"prompt\":\"t\",\"response\":\"1\"}\n";
+    std::fs::write(&trace_path, record.repeat(30)).unwrap();
+
+    let mut cmd = Command::new(assert_cmd::cargo::cargo_bin("microfactory"));
+    let output = cmd
+        .env("MICROFACTORY_HOME", temp.path())
+        .env("NO_COLOR", "1")
+        .arg("--replay-trace")
+        .arg(&trace_path)
+        .arg("run")
+        .arg("--prompt")
+        .arg("test json output")
+        .arg("--domain")
+        .arg("code")
+        .arg("--config")
+        .arg(&config_path)
+        .arg("--plan-file")
+        .arg(&plan_path)
+        .arg("--json")
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let outcome: serde_json::Value = serde_json::from_str(&stdout)
+        .unwrap_or_else(|err| panic!("stdout is not one JSON document ({err}):\n{stdout}"));
+    assert!(outcome["session_id"].is_string());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Starting session"), "{stderr}");
+}
+
 #[test]
 fn test_pretty_logging_is_formatted() {
     let mut cmd = Command::new(assert_cmd::cargo::cargo_bin("microfactory"));
@@ -179,8 +218,8 @@ fn seed_logging_session(home: &Path) {
             max_concurrent_llm: 1,
             samples: 1,
             k: 1,
-            adaptive_k: false,
             human_low_margin_threshold: 1,
+            ..Default::default()
         },
     };
     store
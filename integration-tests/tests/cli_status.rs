@@ -21,8 +21,8 @@ fn seed_session(store: &SessionStore, session_id: &str, prompt: &str, domain: &s
             max_concurrent_llm: 2,
             samples: 2,
             k: 2,
-            adaptive_k: false,
             human_low_margin_threshold: 1,
+            ..Default::default()
         },
     };
 
@@ -71,3 +71,35 @@ fn status_json_lists_seeded_sessions() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn status_compact_json_emits_single_line() -> Result<()> {
+    let temp = tempdir()?;
+    let home = temp.path();
+    let store = SessionStore::open(Some(home.join(".microfactory")))?;
+    seed_session(&store, "session-compact", "Trim output", "code");
+
+    let mut cmd = Command::new("cargo");
+    cmd.arg("run")
+        .arg("--quiet")
+        .arg("-p")
+        .arg("microfactory")
+        .arg("--bin")
+        .arg("microfactory")
+        .arg("--");
+    let assert = cmd
+        .env("MICROFACTORY_HOME", home)
+        .arg("status")
+        .arg("--json")
+        .arg("--compact-json")
+        .assert()
+        .success();
+
+    let stdout = String::from_utf8(assert.get_output().stdout.clone())?;
+    let line = stdout.trim_end_matches('\n');
+    assert!(!line.contains('\n'), "compact JSON must fit on one line");
+    let export: SessionListExport = serde_json::from_str(line)?;
+    assert_eq!(export.sessions.len(), 1);
+
+    Ok(())
+}
@@ -100,12 +100,30 @@ domains:
         default_samples: 2,
         default_k: 2,
         adaptive_k: false,
+        adaptive_k_limits: Default::default(),
         max_decomposition_depth: 1,
         min_words_for_decomposition: usize::MAX,
         human_red_flag_threshold: usize::MAX,
         human_resample_threshold: usize::MAX,
         human_low_margin_threshold: 1,
         step_by_step: false,
+        min_samples_accepted: None,
+        dump_prompts: false,
+        record_prompts: false,
+        red_flag_concurrency: 4,
+        parallel_red_flaggers: false,
+        max_response_bytes: None,
+        flag_truncated: false,
+        retry_on_empty: 0,
+        prompt_vars: Default::default(),
+        line_endings: None,
+        bom: false,
+        dedupe_candidates: false,
+        explain: false,
+        collapse_trivial_decomposition: false,
+        flat: false,
+        adaptive_samples: false,
+        shuffle_seed: None,
     };
 
     let renderer = Arc::new(HandlebarsRenderer::new());
@@ -9,9 +9,10 @@ use microfactory::{
     core::{
         domain::Context,
         ports::{
-            DryRunResult, PauseInfo, ResumeSessionRequest, RunSessionRequest, SessionDetail,
-            SessionMetadataInfo, SessionOutcome, SessionSummary, SubprocessOutcome,
-            SubprocessRequest, WorkflowService,
+            ConfigCheck, DryRunResult, ListModelsRequest, PauseInfo, ResumeSessionRequest,
+            RunSessionRequest, SessionDetail, SessionExportSink, SessionMetadataInfo, SessionNote,
+            SessionOutcome, SessionSummary, StepDetail, SubprocessOutcome, SubprocessRequest,
+            VerifyOutcome, VerifyRequest, WorkflowService,
         },
     },
     status_export::SessionListExport,
@@ -36,8 +37,8 @@ fn seed_session(store: &SessionStore, session_id: &str, prompt: &str, domain: &s
             max_concurrent_llm: 2,
             samples: 2,
             k: 2,
-            adaptive_k: false,
             human_low_margin_threshold: 1,
+            ..Default::default()
         },
     };
     store
@@ -79,6 +80,13 @@ impl WorkflowService for MockWorkflowService {
         unimplemented!("not needed for serve tests")
     }
 
+    async fn run_subprocess_batch(
+        &self,
+        _requests: Vec<SubprocessRequest>,
+    ) -> microfactory::core::Result<Vec<SubprocessOutcome>> {
+        unimplemented!("not needed for serve tests")
+    }
+
     async fn get_session(
         &self,
         session_id: &str,
@@ -99,6 +107,10 @@ impl WorkflowService for MockWorkflowService {
                     status: record.status.as_str().to_string(),
                     updated_at: record.updated_at.to_string(),
                     steps_completed: 0,
+                    partial: false,
+                    steps_running: 0,
+                    top_flaggers: Vec::new(),
+                    stage_timings: Vec::new(),
                     wait_state,
                     metadata: SessionMetadataInfo {
                         config_path: record.envelope.metadata.config_path.clone(),
@@ -107,6 +119,7 @@ impl WorkflowService for MockWorkflowService {
                         samples: record.envelope.metadata.samples,
                         k: record.envelope.metadata.k,
                     },
+                    notes: Vec::new(),
                 }))
             }
             Err(e) if e.to_string().contains("not found") => Ok(None),
@@ -114,6 +127,22 @@ impl WorkflowService for MockWorkflowService {
         }
     }
 
+    async fn add_session_note(
+        &self,
+        _session_id: &str,
+        _note: &str,
+    ) -> microfactory::core::Result<Option<SessionNote>> {
+        unimplemented!("not needed for serve tests")
+    }
+
+    async fn get_step(
+        &self,
+        _session_id: &str,
+        _step_id: usize,
+    ) -> microfactory::core::Result<Option<StepDetail>> {
+        Ok(None)
+    }
+
     async fn list_sessions(&self, limit: usize) -> microfactory::core::Result<Vec<SessionSummary>> {
         let summaries = self
             .store
@@ -127,17 +156,52 @@ impl WorkflowService for MockWorkflowService {
                 domain: s.domain,
                 prompt: s.prompt,
                 status: s.status.as_str().to_string(),
+                pause_trigger: s.pause_trigger,
                 updated_at: s.updated_at.to_string(),
             })
             .collect())
     }
 
+    async fn search_sessions(
+        &self,
+        _query: &str,
+        _limit: usize,
+    ) -> microfactory::core::Result<Vec<SessionSummary>> {
+        Ok(Vec::new())
+    }
+
+    async fn export_sessions(
+        &self,
+        _limit: Option<usize>,
+        _sink: &mut SessionExportSink<'_>,
+    ) -> microfactory::core::Result<usize> {
+        unimplemented!("not needed for serve tests")
+    }
+
     async fn dry_run_probe(
         &self,
         _request: &RunSessionRequest,
     ) -> microfactory::core::Result<DryRunResult> {
         unimplemented!("not needed for serve tests")
     }
+
+    async fn check_run_config(
+        &self,
+        _request: &RunSessionRequest,
+    ) -> microfactory::core::Result<ConfigCheck> {
+        unimplemented!("not needed for serve tests")
+    }
+
+    async fn verify(&self, _request: VerifyRequest) -> microfactory::core::Result<VerifyOutcome> {
+        unimplemented!("not needed for serve tests")
+    }
+
+    async fn list_models(
+        &self,
+        _request: ListModelsRequest,
+    ) -> microfactory::core::Result<Vec<String>> {
+        unimplemented!("not needed for serve tests")
+    }
 }
 
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
@@ -159,6 +223,7 @@ async fn serve_routes_return_session_json() -> Result<()> {
     let options = ServeOptions {
         default_limit: 5,
         poll_interval: Duration::from_millis(200),
+        ..ServeOptions::default()
     };
 
     let service: Arc<dyn WorkflowService> = Arc::new(MockWorkflowService::new(store));
@@ -219,6 +284,7 @@ async fn serve_sse_stream_emits_snapshots() -> Result<()> {
     let options = ServeOptions {
         default_limit: 5,
         poll_interval: Duration::from_millis(100),
+        ..ServeOptions::default()
     };
 
     let service: Arc<dyn WorkflowService> = Arc::new(MockWorkflowService::new(store));
@@ -246,6 +312,14 @@ async fn serve_sse_stream_emits_snapshots() -> Result<()> {
         .expect("sse chunk")?;
     let payload = String::from_utf8(first_chunk.to_vec())?;
     assert!(payload.contains("data:"), "chunk contains SSE data field");
+    assert!(
+        payload.contains("event: sessions"),
+        "SSE chunk carries the event name"
+    );
+    assert!(
+        payload.contains("id: 0"),
+        "first SSE event id starts at zero"
+    );
     assert!(
         payload.contains("sessions"),
         "export JSON present in SSE chunk"
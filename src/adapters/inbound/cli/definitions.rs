@@ -4,6 +4,11 @@ use std::path::PathBuf;
 
 use clap::{Args, Parser, Subcommand, ValueEnum};
 
+use crate::{
+    adapters::inbound::server::JsonCase,
+    core::{domain::LineEnding, ports::SummaryFormat},
+};
+
 /// Microfactory CLI definition following the architecture spec.
 #[derive(Debug, Parser)]
 #[command(name = "microfactory")]
@@ -47,6 +52,39 @@ pub struct Cli {
     )]
     pub inspect: Option<InspectMode>,
 
+    #[arg(
+        long,
+        global = true,
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Rotate the session log file once it reaches this many MiB (keeps 3 older files)"
+    )]
+    pub log_max_mb: Option<u64>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATH",
+        help = "Append every LLM call (model, prompt, options, response, timing) to this JSONL file"
+    )]
+    pub trace_llm_to: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATH",
+        conflicts_with = "trace_llm_to",
+        help = "Answer LLM calls from a --trace-llm-to file instead of contacting the provider"
+    )]
+    pub replay_trace: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATH",
+        help = "Append telemetry events (step, vote, pause, outcome) to this JSONL file instead of the log"
+    )]
+    pub telemetry_file: Option<PathBuf>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -62,23 +100,46 @@ pub enum InspectMode {
 #[derive(Debug, Subcommand)]
 pub enum Commands {
     /// Run a microfactory workflow for a given prompt.
-    Run(RunArgs),
+    Run(Box<RunArgs>),
     /// Inspect the progress of a workflow session.
     Status(StatusArgs),
     /// Resume a paused or failed workflow session.
     Resume(ResumeArgs),
+    /// Attach a timestamped note to a stored session.
+    Annotate(AnnotateArgs),
     /// Execute a single-step subprocess workflow and emit JSON.
     Subprocess(SubprocessArgs),
     /// Serve session data over HTTP (REST + SSE).
     Serve(ServeArgs),
+    /// Stream every stored session to stdout for backup.
+    Export(ExportArgs),
+    /// Run a domain's verifier against the working tree without any LLM calls.
+    Verify(VerifyArgs),
+    /// List supported LLM providers and the API key env var each one reads.
+    Providers(ProvidersArgs),
+    /// List the model ids a provider offers to your API key.
+    Models(ModelsArgs),
+    /// Run the same prompt once per model and compare the outcomes side by side.
+    Compare(CompareArgs),
     /// Provide structured help so operators or agents can self-orient.
     Help(HelpArgs),
 }
 
 #[derive(Debug, Args, Clone)]
 pub struct RunArgs {
-    #[arg(long, help = "High-level task description")]
-    pub prompt: String,
+    #[arg(
+        long,
+        required_unless_present = "prompt_from_stdin",
+        help = "High-level task description"
+    )]
+    pub prompt: Option<String>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["prompt", "interactive"],
+        help = "Read the task description from stdin instead of --prompt"
+    )]
+    pub prompt_from_stdin: bool,
 
     #[arg(
         long,
@@ -87,12 +148,27 @@ pub struct RunArgs {
     )]
     pub config: PathBuf,
 
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Resolve relative prompt template paths against this directory instead of the config's"
+    )]
+    pub template_dir: Option<PathBuf>,
+
     #[arg(long, help = "Domain identifier (e.g., code)")]
     pub domain: String,
 
     #[arg(long, help = "LLM provider API key (can also come from env vars)")]
     pub api_key: Option<String>,
 
+    #[arg(
+        long = "llm-header",
+        value_name = "NAME=VALUE",
+        value_parser = parse_llm_header,
+        help = "Extra HTTP header sent with every LLM request (repeatable)"
+    )]
+    pub llm_headers: Vec<(String, String)>,
+
     #[arg(
         long,
         default_value = "gpt-5.1-codex-mini",
@@ -117,6 +193,38 @@ pub struct RunArgs {
     #[arg(long, help = "Enable adaptive k adjustment")]
     pub adaptive_k: bool,
 
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        requires = "adaptive_k",
+        help = "Lowest k an adaptive adjustment may choose"
+    )]
+    pub adaptive_k_min: usize,
+
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "adaptive_k",
+        help = "Highest k an adaptive adjustment may choose (default: unbounded)"
+    )]
+    pub adaptive_k_max: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        requires = "adaptive_k",
+        help = "How far one adaptive adjustment moves k from the agent's base"
+    )]
+    pub adaptive_k_step: usize,
+
+    #[arg(
+        long,
+        help = "Shrink the solver's samples after decisive votes and grow them after close ones"
+    )]
+    pub adaptive_samples: bool,
+
     #[arg(long, default_value_t = 4, help = "Maximum concurrent LLM calls")]
     pub max_concurrent_llm: usize,
 
@@ -126,6 +234,20 @@ pub struct RunArgs {
     #[arg(long, help = "Skips persistence and runs a single probe for testing.")]
     pub dry_run: bool,
 
+    #[arg(
+        long,
+        conflicts_with = "dry_run",
+        help = "Run the full flow but print the diffs apply would make instead of writing files"
+    )]
+    pub dry_run_apply: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["dry_run", "dry_run_apply", "interactive"],
+        help = "Validate config, domain, API key, and provider/model setup, print \"ready\", and exit without running"
+    )]
+    pub config_check_only: bool,
+
     #[arg(
         long,
         help = "Pauses execution after decomposition and after each step completion."
@@ -139,12 +261,200 @@ pub struct RunArgs {
     )]
     pub human_low_margin_threshold: usize,
 
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Maximum decomposition depth before subtasks are solved directly (default 2, minimum 1)"
+    )]
+    pub max_depth: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Stop decomposing once N child steps have been spawned in total; remaining steps are solved directly"
+    )]
+    pub max_children_total: Option<usize>,
+
     #[arg(
         short = 'o',
         long,
-        help = "Directory for output files (default: current working directory)"
+        help = "Directory for output files (default: current working directory); supports {session_id}, {date}, and {timestamp}"
     )]
     pub output_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Skip LLM decomposition and use the subtasks listed in this file (one per line or a JSON array)"
+    )]
+    pub plan_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Write every rendered prompt to <output-dir>/prompts/ for auditing"
+    )]
+    pub dump_prompts: bool,
+
+    #[arg(
+        long,
+        help = "Save each step's rendered prompts with the session for reproducibility audits"
+    )]
+    pub record_prompts: bool,
+
+    #[arg(
+        long,
+        default_value_t = 4,
+        help = "Maximum red-flag evaluations (e.g. llm_critique calls) running at once per sampling batch"
+    )]
+    pub red_flag_concurrency: usize,
+
+    #[arg(
+        long,
+        help = "Run each sample's red flaggers concurrently and reject on the first match"
+    )]
+    pub parallel_red_flaggers: bool,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Reject samples larger than this many bytes before red-flag evaluation (default: unlimited)"
+    )]
+    pub max_response_bytes: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Red-flag and resample responses the provider cut off at its output token limit"
+    )]
+    pub flag_truncated: bool,
+
+    #[arg(
+        long,
+        default_value_t = 0,
+        value_name = "N",
+        help = "Drop blank solver responses and resample an all-blank batch up to N times before failing (0 keeps them)"
+    )]
+    pub retry_on_empty: usize,
+
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Vote once N samples per stage survive red-flagging instead of waiting for all of them"
+    )]
+    pub min_samples_accepted: Option<u64>,
+
+    #[arg(
+        long = "prompt-var",
+        value_name = "KEY=VALUE",
+        value_parser = parse_prompt_var,
+        help = "Template variable available to every prompt as {{KEY}} (repeatable)"
+    )]
+    pub prompt_vars: Vec<(String, String)>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Line endings for files written by overwrite_file (default: the domain's output_encoding, else lf)"
+    )]
+    pub line_endings: Option<LineEndingArg>,
+
+    #[arg(long, help = "Prefix files written by overwrite_file with a UTF-8 BOM")]
+    pub bom: bool,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Write the full final workflow context (steps, metrics, work queue, domain data) as JSON"
+    )]
+    pub dump_context: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Write a run summary in this format (json, yaml, markdown) to the output directory"
+    )]
+    pub summary_format: Option<SummaryFormatArg>,
+
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        help = "Summary file name; {session_id}, {date}, and {timestamp} expand (default summary.<ext>)"
+    )]
+    pub summary_name: Option<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = ResumePolicy::Manual,
+        help = "What to do when the run pauses: manual (wait for `resume`), auto (resume in the background), or fail (exit non-zero)"
+    )]
+    pub resume_policy: ResumePolicy,
+
+    #[arg(
+        long,
+        help = "Merge identical (whitespace-normalized) solver candidates before voting; votes count once per duplicate"
+    )]
+    pub dedupe_candidates: bool,
+
+    #[arg(
+        long,
+        help = "Do not save the session to the store (ephemeral run; it cannot be resumed)"
+    )]
+    pub no_persist: bool,
+
+    #[arg(
+        long,
+        help = "Record why each step was or was not decomposed further and print it in the run summary"
+    )]
+    pub explain: bool,
+
+    #[arg(
+        long,
+        help = "Solve a step directly when its plan is a single subtask that restates it"
+    )]
+    pub collapse_trivial_decomposition: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "max_depth",
+        help = "Decompose only the root task and solve every subtask directly"
+    )]
+    pub flat: bool,
+
+    #[arg(
+        long,
+        help = "Show vote options in a seeded random order to reduce positional bias"
+    )]
+    pub shuffle_candidates: bool,
+
+    #[arg(
+        long,
+        requires = "shuffle_candidates",
+        value_name = "SEED",
+        help = "Seed for --shuffle-candidates (defaults to the run's start time)"
+    )]
+    pub shuffle_seed: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "TOKENS",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Pause the session once LLM calls have used more than this many tokens in total"
+    )]
+    pub max_total_tokens: Option<u64>,
+
+    #[arg(
+        long,
+        conflicts_with = "dry_run",
+        help = "Print the final outcome and run metrics as JSON instead of log lines"
+    )]
+    pub json: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "dry_run",
+        help = "Review step-by-step checkpoints in the terminal (approve/reject/edit) without pausing"
+    )]
+    pub interactive: bool,
 }
 
 #[derive(Args, Debug)]
@@ -159,8 +469,31 @@ pub struct StatusArgs {
     )]
     pub limit: usize,
 
+    #[arg(
+        long,
+        value_name = "TEXT",
+        conflicts_with = "session_id",
+        help = "Only list sessions whose prompt contains TEXT (case-insensitive)"
+    )]
+    pub search: Option<String>,
+
     #[arg(long, help = "Emit JSON instead of human-readable output")]
     pub json: bool,
+
+    #[arg(
+        long,
+        requires = "json",
+        help = "Emit single-line JSON instead of pretty-printed output"
+    )]
+    pub compact_json: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = StatusFormat::Human,
+        help = "Layout of the session listing: human or table (aligned columns, truncated prompts)"
+    )]
+    pub format: StatusFormat,
 }
 
 impl Default for StatusArgs {
@@ -168,11 +501,33 @@ impl Default for StatusArgs {
         Self {
             session_id: None,
             limit: 10,
+            search: None,
             json: false,
+            compact_json: false,
+            format: StatusFormat::Human,
         }
     }
 }
 
+/// What `run` does when the session pauses for human input.
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq, Default)]
+pub enum ResumePolicy {
+    /// Stop and leave the session for an explicit `resume`.
+    #[default]
+    Manual,
+    /// Spawn a detached `resume` process right away.
+    Auto,
+    /// Exit with an error so scripts notice the pause.
+    Fail,
+}
+
+/// Layouts accepted by `status --format` for the session listing.
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq)]
+pub enum StatusFormat {
+    Human,
+    Table,
+}
+
 #[derive(Debug, Args, Clone)]
 pub struct ResumeArgs {
     #[arg(long, help = "Session identifier to resume")]
@@ -181,9 +536,24 @@ pub struct ResumeArgs {
     #[arg(long, help = "Override config file path (defaults to stored path)")]
     pub config: Option<PathBuf>,
 
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Override the prompt template directory (defaults to the one stored with the session)"
+    )]
+    pub template_dir: Option<PathBuf>,
+
     #[arg(long, help = "Override LLM provider API key")]
     pub api_key: Option<String>,
 
+    #[arg(
+        long = "llm-header",
+        value_name = "NAME=VALUE",
+        value_parser = parse_llm_header,
+        help = "Extra HTTP header sent with every LLM request (repeatable; run headers are not saved)"
+    )]
+    pub llm_headers: Vec<(String, String)>,
+
     #[arg(
         long,
         value_enum,
@@ -208,12 +578,49 @@ pub struct ResumeArgs {
         help = "Override low-margin pause threshold (0 disables); defaults to stored value"
     )]
     pub human_low_margin_threshold: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Reset this step and all its descendants, then re-run them (earlier results are kept)"
+    )]
+    pub from_step: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Refuse to resume when the config or its templates changed since the session was saved"
+    )]
+    pub strict: bool,
+
+    #[arg(
+        long,
+        value_name = "TOKENS",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Replace the session's token limit (defaults to the limit saved by run)"
+    )]
+    pub max_total_tokens: Option<u64>,
+}
+
+#[derive(Debug, Args, Clone)]
+pub struct AnnotateArgs {
+    #[arg(long, help = "Session identifier to annotate")]
+    pub session_id: String,
+
+    #[arg(
+        long,
+        value_parser = clap::builder::NonEmptyStringValueParser::new(),
+        help = "Note text (e.g. \"known flaky\" or \"approved by ops\")"
+    )]
+    pub note: String,
 }
 
 #[derive(Debug, Args, Clone)]
 pub struct SubprocessArgs {
-    #[arg(long, help = "Domain identifier (e.g., code)")]
-    pub domain: String,
+    #[arg(
+        long,
+        required_unless_present = "batch",
+        help = "Domain identifier (e.g., code)"
+    )]
+    pub domain: Option<String>,
 
     #[arg(
         long,
@@ -222,8 +629,12 @@ pub struct SubprocessArgs {
     )]
     pub config: PathBuf,
 
-    #[arg(long, help = "Step description to execute in isolation")]
-    pub step: String,
+    #[arg(
+        long,
+        required_unless_present = "batch",
+        help = "Step description to execute in isolation"
+    )]
+    pub step: Option<String>,
 
     #[arg(
         long,
@@ -231,9 +642,24 @@ pub struct SubprocessArgs {
     )]
     pub context_json: Option<String>,
 
+    #[arg(
+        long,
+        conflicts_with_all = ["domain", "step", "context_json"],
+        help = "Read a JSON array of {domain, step, context_json, samples, k} objects from stdin and print a JSON array of outcomes"
+    )]
+    pub batch: bool,
+
     #[arg(long, help = "LLM provider API key (can also come from env vars)")]
     pub api_key: Option<String>,
 
+    #[arg(
+        long = "llm-header",
+        value_name = "NAME=VALUE",
+        value_parser = parse_llm_header,
+        help = "Extra HTTP header sent with every LLM request (repeatable)"
+    )]
+    pub llm_headers: Vec<(String, String)>,
+
     #[arg(
         long,
         default_value = "gpt-5.1-codex-mini",
@@ -261,6 +687,9 @@ pub struct SubprocessArgs {
 
     #[arg(long, default_value_t = 2, help = "Max concurrent LLM calls")]
     pub max_concurrent_llm: usize,
+
+    #[arg(long, help = "Emit single-line JSON instead of pretty-printed output")]
+    pub compact_json: bool,
 }
 
 #[derive(Debug, Args, Clone)]
@@ -268,7 +697,7 @@ pub struct ServeArgs {
     #[arg(
         long,
         default_value = "127.0.0.1",
-        help = "Bind interface for the HTTP server"
+        help = "Bind address for the HTTP server (IPv4 or IPv6, e.g. 0.0.0.0 or ::)"
     )]
     pub bind: String,
 
@@ -288,6 +717,197 @@ pub struct ServeArgs {
         help = "Polling interval for SSE stream in milliseconds"
     )]
     pub poll_interval_ms: u64,
+
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value_t = 250,
+        help = "Lowest accepted --poll-interval-ms; lower it for fast local polling (never below 10)"
+    )]
+    pub min_poll_ms: u64,
+
+    #[arg(
+        long,
+        default_value = "sessions",
+        value_parser = parse_sse_event_name,
+        help = "SSE event name attached to session snapshots (single line, non-empty)"
+    )]
+    pub sse_event_name: String,
+
+    #[arg(
+        long,
+        default_value_t = 50,
+        help = "Number of session status transitions kept for GET /recent"
+    )]
+    pub recent_capacity: usize,
+
+    #[arg(
+        long,
+        value_name = "PREFIX",
+        help = "Mount every route under this path prefix (e.g. /microfactory behind a reverse proxy)"
+    )]
+    pub base_path: Option<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = JsonCaseArg::Snake,
+        help = "Field naming of JSON responses: snake (session_id) or camel (sessionId)"
+    )]
+    pub json_case: JsonCaseArg,
+
+    #[arg(
+        long,
+        value_name = "SECS",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Close each SSE connection after this many seconds; clients reconnect with Last-Event-ID"
+    )]
+    pub sse_idle_timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Args, Clone)]
+pub struct ExportArgs {
+    #[arg(long, help = "Emit one JSON session per line instead of a JSON array")]
+    pub ndjson: bool,
+
+    #[arg(long, help = "Maximum number of sessions to export (newest first)")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Args, Clone)]
+pub struct VerifyArgs {
+    #[arg(long, help = "Domain whose verifier should run (e.g., code)")]
+    pub domain: String,
+
+    #[arg(
+        long,
+        default_value = "config.yaml",
+        help = "Path to the domain configuration file"
+    )]
+    pub config: PathBuf,
+
+    #[arg(long, help = "Print the verifier outcome as JSON")]
+    pub json: bool,
+}
+
+#[derive(Debug, Args, Clone)]
+pub struct CompareArgs {
+    #[arg(long, help = "High-level task description")]
+    pub prompt: String,
+
+    #[arg(long, help = "Domain identifier (e.g., code)")]
+    pub domain: String,
+
+    #[arg(
+        long,
+        default_value = "config.yaml",
+        help = "Path to the domain configuration file"
+    )]
+    pub config: PathBuf,
+
+    #[arg(
+        long,
+        required = true,
+        value_delimiter = ',',
+        value_name = "PROVIDER:MODEL",
+        value_parser = parse_compare_target,
+        help = "Comma-separated models to compare, e.g. openai:gpt-4.1,anthropic:claude-sonnet-4-5"
+    )]
+    pub models: Vec<CompareTarget>,
+
+    #[arg(long, default_value_t = 10, help = "Samples per microagent step")]
+    pub samples: usize,
+
+    #[arg(long, default_value_t = 3, help = "First-to-ahead-by-k voting margin")]
+    pub k: usize,
+
+    #[arg(long, default_value_t = 4, help = "Maximum concurrent LLM calls")]
+    pub max_concurrent_llm: usize,
+
+    #[arg(
+        long,
+        default_value_t = 4,
+        help = "Maximum red-flag evaluations running at once per sampling batch"
+    )]
+    pub red_flag_concurrency: usize,
+
+    #[arg(
+        long,
+        default_value_t = 1,
+        help = "Pause a run when vote margins are <= this value (set 0 to disable the guard)"
+    )]
+    pub human_low_margin_threshold: usize,
+
+    #[arg(
+        short = 'o',
+        long,
+        default_value = "compare/{session_id}",
+        help = "Output directory per run; keep {session_id} so runs do not overwrite each other"
+    )]
+    pub output_dir: PathBuf,
+
+    #[arg(long, help = "Print the comparison as JSON")]
+    pub json: bool,
+}
+
+/// One `provider:model` entry of `compare --models`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompareTarget {
+    pub provider: LlmProvider,
+    pub model: String,
+}
+
+impl std::fmt::Display for CompareTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.provider.as_str(), self.model)
+    }
+}
+
+fn parse_compare_target(raw: &str) -> Result<CompareTarget, String> {
+    let (provider, model) = raw
+        .split_once(':')
+        .ok_or_else(|| format!("expected PROVIDER:MODEL, got '{raw}'"))?;
+    let provider = LlmProvider::from_name(provider.trim())
+        .ok_or_else(|| format!("unknown provider '{}'", provider.trim()))?;
+    let model = model.trim();
+    if model.is_empty() {
+        return Err(format!("model is empty in '{raw}'"));
+    }
+    Ok(CompareTarget {
+        provider,
+        model: model.to_string(),
+    })
+}
+
+#[derive(Debug, Args, Clone)]
+pub struct ProvidersArgs {
+    #[arg(long, help = "Print the provider list as JSON")]
+    pub json: bool,
+}
+
+#[derive(Debug, Args, Clone)]
+pub struct ModelsArgs {
+    #[arg(
+        long,
+        default_value_t = LlmProvider::Openai,
+        value_enum,
+        help = "LLM provider whose models to list"
+    )]
+    pub llm_provider: LlmProvider,
+
+    #[arg(long, help = "LLM provider API key (can also come from env vars)")]
+    pub api_key: Option<String>,
+
+    #[arg(
+        long = "llm-header",
+        value_name = "NAME=VALUE",
+        value_parser = parse_llm_header,
+        help = "Extra HTTP header sent with the listing request (repeatable)"
+    )]
+    pub llm_headers: Vec<(String, String)>,
+
+    #[arg(long, help = "Print the model ids as a JSON array")]
+    pub json: bool,
 }
 
 #[derive(Debug, Args, Clone)]
@@ -315,8 +935,14 @@ pub enum HelpTopic {
     Run,
     Status,
     Resume,
+    Annotate,
     Subprocess,
     Serve,
+    Export,
+    Verify,
+    Providers,
+    Models,
+    Compare,
 }
 
 #[derive(Debug, Copy, Clone, ValueEnum)]
@@ -325,6 +951,111 @@ pub enum HelpFormat {
     Json,
 }
 
+/// Line endings accepted by `run --line-endings`.
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq)]
+#[clap(rename_all = "lower")]
+pub enum LineEndingArg {
+    Lf,
+    Crlf,
+}
+
+impl From<LineEndingArg> for LineEnding {
+    fn from(value: LineEndingArg) -> Self {
+        match value {
+            LineEndingArg::Lf => LineEnding::Lf,
+            LineEndingArg::Crlf => LineEnding::Crlf,
+        }
+    }
+}
+
+/// Formats accepted by `run --summary-format`.
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq)]
+#[clap(rename_all = "lower")]
+pub enum SummaryFormatArg {
+    Json,
+    Yaml,
+    Markdown,
+}
+
+impl From<SummaryFormatArg> for SummaryFormat {
+    fn from(value: SummaryFormatArg) -> Self {
+        match value {
+            SummaryFormatArg::Json => SummaryFormat::Json,
+            SummaryFormatArg::Yaml => SummaryFormat::Yaml,
+            SummaryFormatArg::Markdown => SummaryFormat::Markdown,
+        }
+    }
+}
+
+/// Field naming accepted by `serve --json-case`.
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq)]
+#[clap(rename_all = "lower")]
+pub enum JsonCaseArg {
+    Snake,
+    Camel,
+}
+
+impl From<JsonCaseArg> for JsonCase {
+    fn from(value: JsonCaseArg) -> Self {
+        match value {
+            JsonCaseArg::Snake => JsonCase::Snake,
+            JsonCaseArg::Camel => JsonCase::Camel,
+        }
+    }
+}
+
+/// Parse a `--llm-header NAME=VALUE` argument; the adapter validates the header itself.
+fn parse_llm_header(raw: &str) -> Result<(String, String), String> {
+    let (name, value) = raw
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=VALUE, got '{raw}'"))?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(format!("header name is empty in '{raw}'"));
+    }
+    Ok((name.to_string(), value.trim().to_string()))
+}
+
+/// Parse `--sse-event-name`; SSE frames are line-delimited, so the name must fit on one line.
+fn parse_sse_event_name(raw: &str) -> Result<String, String> {
+    if raw.is_empty() {
+        return Err("event name is empty".to_string());
+    }
+    if raw.contains(['\r', '\n']) {
+        return Err(format!("event name {raw:?} must not contain line breaks"));
+    }
+    Ok(raw.to_string())
+}
+
+/// Variables every prompt template already receives; `--prompt-var` may not shadow them.
+const RESERVED_PROMPT_VARS: &[&str] = &[
+    "prompt",
+    "task",
+    "role",
+    "context",
+    "solution",
+    "verifier_feedback",
+];
+
+/// Parse a `--prompt-var KEY=VALUE` argument. Keys must be plain template identifiers.
+fn parse_prompt_var(raw: &str) -> Result<(String, String), String> {
+    let (key, value) = raw
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got '{raw}'"))?;
+    let key = key.trim();
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!(
+            "variable name '{key}' must be non-empty and use only letters, digits, and '_'"
+        ));
+    }
+    if RESERVED_PROMPT_VARS.contains(&key) {
+        return Err(format!(
+            "'{key}' is a built-in template variable and cannot be overridden"
+        ));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
 /// Supported LLM providers surfaced via the CLI.
 #[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq)]
 #[clap(rename_all = "lower")]
@@ -397,7 +1128,7 @@ mod tests {
 
         match cli.command {
             Commands::Run(run) => {
-                assert_eq!(run.prompt, "fix tests");
+                assert_eq!(run.prompt.as_deref(), Some("fix tests"));
                 assert_eq!(run.domain, "code");
                 assert_eq!(run.repo_path.unwrap(), PathBuf::from("./repo"));
                 assert!(run.dry_run);
@@ -406,6 +1137,87 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parses_repeated_llm_headers() {
+        let cli = Cli::parse_from([
+            "microfactory",
+            "run",
+            "--prompt",
+            "p",
+            "--domain",
+            "code",
+            "--llm-header",
+            "OpenAI-Organization=org-1",
+            "--llm-header",
+            "X-Route = a=b",
+        ]);
+        let Commands::Run(run) = cli.command else {
+            panic!("expected run command");
+        };
+        assert_eq!(
+            run.llm_headers,
+            vec![
+                ("OpenAI-Organization".to_string(), "org-1".to_string()),
+                ("X-Route".to_string(), "a=b".to_string()),
+            ]
+        );
+        assert!(
+            Cli::try_parse_from([
+                "microfactory",
+                "run",
+                "--prompt",
+                "p",
+                "--domain",
+                "code",
+                "--llm-header",
+                "missing-separator",
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn parses_prompt_vars_and_rejects_built_in_names() {
+        let cli = Cli::parse_from([
+            "microfactory",
+            "run",
+            "--prompt",
+            "p",
+            "--domain",
+            "code",
+            "--prompt-var",
+            "language=Rust",
+            "--prompt-var",
+            "style=a = b",
+        ]);
+        let Commands::Run(run) = cli.command else {
+            panic!("expected run command");
+        };
+        assert_eq!(
+            run.prompt_vars,
+            vec![
+                ("language".to_string(), "Rust".to_string()),
+                ("style".to_string(), "a = b".to_string()),
+            ]
+        );
+        for bad in ["task=x", "my-var=x", "=x", "novalue"] {
+            assert!(
+                Cli::try_parse_from([
+                    "microfactory",
+                    "run",
+                    "--prompt",
+                    "p",
+                    "--domain",
+                    "code",
+                    "--prompt-var",
+                    bad,
+                ])
+                .is_err(),
+                "{bad}"
+            );
+        }
+    }
+
     #[test]
     fn parses_status_with_json_limit() {
         let cli = Cli::parse_from(["microfactory", "status", "--json", "--limit", "5"]);
@@ -419,6 +1231,16 @@ mod tests {
         }
     }
 
+    #[test]
+    fn compact_json_requires_json_for_status() {
+        assert!(Cli::try_parse_from(["microfactory", "status", "--compact-json"]).is_err());
+        let cli = Cli::parse_from(["microfactory", "status", "--json", "--compact-json"]);
+        match cli.command {
+            Commands::Status(status) => assert!(status.compact_json),
+            _ => panic!("Expected status command"),
+        }
+    }
+
     #[test]
     fn parses_serve_command() {
         let cli = Cli::parse_from([
@@ -445,6 +1267,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn serve_rejects_multiline_sse_event_names() {
+        for name in ["", "a\nb", "a\rb", "a\r\nb"] {
+            assert!(
+                Cli::try_parse_from(["microfactory", "serve", "--sse-event-name", name]).is_err(),
+                "{name:?} should be rejected"
+            );
+        }
+        let cli = Cli::parse_from(["microfactory", "serve", "--sse-event-name", "updates"]);
+        match cli.command {
+            Commands::Serve(args) => assert_eq!(args.sse_event_name, "updates"),
+            _ => panic!("expected serve command"),
+        }
+    }
+
+    #[test]
+    fn parses_export_command() {
+        let cli = Cli::parse_from(["microfactory", "export", "--ndjson", "--limit", "3"]);
+
+        match cli.command {
+            Commands::Export(args) => {
+                assert!(args.ndjson);
+                assert_eq!(args.limit, Some(3));
+            }
+            _ => panic!("expected export command"),
+        }
+    }
+
     #[test]
     fn parses_help_with_topic_and_format() {
         let cli = Cli::parse_from(["microfactory", "help", "--topic", "run", "--format", "json"]);
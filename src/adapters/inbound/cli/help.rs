@@ -70,6 +70,10 @@ pub fn build_help_section(topic: HelpTopic) -> HelpSection {
                     flag: "resume",
                     description: "Continue a paused session after addressing the wait reason.",
                 },
+                FlagHelp {
+                    flag: "annotate",
+                    description: "Attach a timestamped note to a stored session.",
+                },
                 FlagHelp {
                     flag: "subprocess",
                     description: "Execute a single MAKER step in isolation and emit JSON.",
@@ -78,10 +82,42 @@ pub fn build_help_section(topic: HelpTopic) -> HelpSection {
                     flag: "serve",
                     description: "Expose sessions over HTTP (REST + SSE) for higher-level tooling.",
                 },
+                FlagHelp {
+                    flag: "export",
+                    description: "Stream every stored session to stdout (JSON array or NDJSON) for backup.",
+                },
+                FlagHelp {
+                    flag: "verify",
+                    description: "Run a domain's verifier on the working tree without any LLM calls.",
+                },
+                FlagHelp {
+                    flag: "compare",
+                    description: "Run one prompt against several provider:model pairs and compare the outcomes.",
+                },
+                FlagHelp {
+                    flag: "providers",
+                    description: "List supported LLM providers, their API key env vars, and which are set.",
+                },
+                FlagHelp {
+                    flag: "models",
+                    description: "List the model ids a provider offers to your API key.",
+                },
                 FlagHelp {
                     flag: "--inspect <mode>",
                     description: "Stream detailed LLM ops/messages (ops, payloads, messages, files) to stdout.",
                 },
+                FlagHelp {
+                    flag: "--log-max-mb <n>",
+                    description: "Rotate the per-session log file at n MiB, keeping 3 older files.",
+                },
+                FlagHelp {
+                    flag: "--trace-llm-to <path>",
+                    description: "Append one JSON line per LLM call (model, prompt, options, response, timing) to path.",
+                },
+                FlagHelp {
+                    flag: "--replay-trace <path>",
+                    description: "Serve recorded responses from a --trace-llm-to file instead of calling the provider, for deterministic offline re-runs.",
+                },
             ],
             notes: vec![
                 "Use `microfactory help --topic <command>` for focused instructions or `--format json` for machine parsing.",
@@ -96,12 +132,17 @@ pub fn build_help_section(topic: HelpTopic) -> HelpSection {
                 r#"microfactory run --prompt "stabilize auth" --domain code --config config.yaml"#,
                 r#"microfactory run --prompt "audit notebooks" --domain analysis --dry-run --samples 6 --k 4"#,
                 r#"microfactory run --prompt "fix bug" --inspect messages"#,
+                r#"cat task.md | microfactory run --prompt-from-stdin --domain code"#,
             ],
             key_flags: vec![
                 FlagHelp {
                     flag: "--prompt <text>",
                     description: "Required task description fed into the decomposition agent.",
                 },
+                FlagHelp {
+                    flag: "--prompt-from-stdin",
+                    description: "Read the task description from stdin instead of --prompt; empty input is an error. Cannot be combined with --interactive.",
+                },
                 FlagHelp {
                     flag: "--domain <name>",
                     description: "Selects which domain section of the YAML config to load (e.g. code).",
@@ -110,10 +151,18 @@ pub fn build_help_section(topic: HelpTopic) -> HelpSection {
                     flag: "--config <path>",
                     description: "Defaults to ./config.yaml; point to custom configs per domain.",
                 },
+                FlagHelp {
+                    flag: "--template-dir <path>",
+                    description: "Resolve relative prompt template paths against this directory instead of the config's.",
+                },
                 FlagHelp {
                     flag: "--api-key <key>",
                     description: "Override provider API key; otherwise resolves from env/~/\\.env.",
                 },
+                FlagHelp {
+                    flag: "--llm-header <name=value>",
+                    description: "Extra HTTP header on every LLM request (repeatable); overrides the domain's llm_headers.",
+                },
                 FlagHelp {
                     flag: "--llm-provider <id>",
                     description: "openai | anthropic | gemini | grok; determines API key lookup.",
@@ -134,6 +183,18 @@ pub fn build_help_section(topic: HelpTopic) -> HelpSection {
                     flag: "--adaptive-k",
                     description: "Enable adaptive voting margins driven by live metrics.",
                 },
+                FlagHelp {
+                    flag: "--adaptive-k-min <n> / --adaptive-k-max <n>",
+                    description: "Bound the k chosen by --adaptive-k (defaults: 1 and unbounded).",
+                },
+                FlagHelp {
+                    flag: "--adaptive-k-step <n>",
+                    description: "How far one adaptive adjustment moves k from the agent's base (default 1).",
+                },
+                FlagHelp {
+                    flag: "--adaptive-samples",
+                    description: "Use a quarter fewer solver samples after decisive votes, a quarter more after close ones.",
+                },
                 FlagHelp {
                     flag: "--max-concurrent-llm <n>",
                     description: "Cap simultaneous LLM calls (default 4) for rate limits.",
@@ -146,17 +207,137 @@ pub fn build_help_section(topic: HelpTopic) -> HelpSection {
                     flag: "--dry-run",
                     description: "Skips persistence and issues a single LLM probe for validation.",
                 },
+                FlagHelp {
+                    flag: "--dry-run-apply",
+                    description: "Run the whole workflow, but at apply print each step's target files and diff instead of writing or verifying.",
+                },
+                FlagHelp {
+                    flag: "--config-check-only",
+                    description: "Load and validate the config, domain, output dir, plan file, and API key, build the LLM client, print \"ready\", and exit without calling the provider.",
+                },
                 FlagHelp {
                     flag: "--step-by-step",
-                    description: "Pause after decomposition and step completion for manual review.",
+                    description: "Pause after decomposition, before file writes (with a diff preview), and after each step.",
                 },
                 FlagHelp {
                     flag: "--human-low-margin-threshold <n>",
                     description: "Human pause trigger for thin vote margins (set 0 to keep running despite ties).",
                 },
+                FlagHelp {
+                    flag: "--max-depth <n>",
+                    description: "Maximum decomposition depth before subtasks are solved directly (default 2, minimum 1; reused on resume).",
+                },
+                FlagHelp {
+                    flag: "--flat",
+                    description: "Decompose only the root task and solve every subtask directly (reused on resume).",
+                },
+                FlagHelp {
+                    flag: "--max-children-total <n>",
+                    description: "Stop decomposing once n child steps exist in total; later subtasks are solved directly.",
+                },
                 FlagHelp {
                     flag: "-o, --output-dir <path>",
-                    description: "Directory for output files (default: current working directory).",
+                    description: "Directory for output files (default: current working directory); {session_id}, {date}, and {timestamp} placeholders expand per run.",
+                },
+                FlagHelp {
+                    flag: "--dump-prompts",
+                    description: "Write every rendered prompt to <output-dir>/prompts/ (one file per distinct prompt, named by step, role, and attempt).",
+                },
+                FlagHelp {
+                    flag: "--record-prompts",
+                    description: "Store the last rendered prompt per step and role in the session (redacted by the domain's prompt_redactions, capped at 32 KiB each).",
+                },
+                FlagHelp {
+                    flag: "--red-flag-concurrency <n>",
+                    description: "Maximum red-flag evaluations (e.g. llm_critique calls) running at once per sampling batch (default: 4).",
+                },
+                FlagHelp {
+                    flag: "--parallel-red-flaggers",
+                    description: "Run a sample's red flaggers concurrently and reject it on the first match.",
+                },
+                FlagHelp {
+                    flag: "--max-response-bytes <n>",
+                    description: "Reject samples larger than n bytes as a built-in red flag before any configured flagger runs (default: unlimited).",
+                },
+                FlagHelp {
+                    flag: "--prompt-var <key=value>",
+                    description: "Expose a custom template variable to every role's prompt as {{key}} (repeatable; saved for resume).",
+                },
+                FlagHelp {
+                    flag: "--flag-truncated",
+                    description: "Reject samples whose finish reason says they hit the output token limit (length, max_tokens) and resample them.",
+                },
+                FlagHelp {
+                    flag: "--retry-on-empty <n>",
+                    description: "Drop blank solver responses and resample a batch with none left up to n times before failing the step (default 0: keep blank responses as candidates).",
+                },
+                FlagHelp {
+                    flag: "--min-samples-accepted <n>",
+                    description: "Clean samples the decomposition and solver stages must accept before voting; overrides the domain's min_samples_accepted (default: every requested sample).",
+                },
+                FlagHelp {
+                    flag: "--dedupe-candidates",
+                    description: "Collapse solver candidates that match after whitespace normalization into one option; votes for it count once per duplicate.",
+                },
+                FlagHelp {
+                    flag: "--shuffle-candidates",
+                    description: "Show vote options in a seeded random order to reduce positional bias; votes map back to the original candidates.",
+                },
+                FlagHelp {
+                    flag: "--shuffle-seed <n>",
+                    description: "Seed for --shuffle-candidates so the order is reproducible (default: the run's start time).",
+                },
+                FlagHelp {
+                    flag: "--max-total-tokens <n>",
+                    description: "Pause the session with `token_budget_exhausted` once LLM calls have used more than n input plus output tokens.",
+                },
+                FlagHelp {
+                    flag: "--no-persist",
+                    description: "Run in memory without saving the session to the store; the run cannot be resumed.",
+                },
+                FlagHelp {
+                    flag: "--collapse-trivial-decomposition",
+                    description: "Solve a step directly when its winning plan is one subtask that restates it, instead of spawning a redundant child.",
+                },
+                FlagHelp {
+                    flag: "--resume-policy <manual|auto|fail>",
+                    description: "On pause: wait for `resume` (manual, default), resume in a background process (auto), or exit non-zero (fail).",
+                },
+                FlagHelp {
+                    flag: "--explain",
+                    description: "Record why each subtask was or was not decomposed further (depth, word count, thresholds) and print it in the run summary.",
+                },
+                FlagHelp {
+                    flag: "--dump-context <path>",
+                    description: "Write the full final context (steps, metrics, work queue, domain data) as JSON for debugging.",
+                },
+                FlagHelp {
+                    flag: "--summary-format <json|yaml|markdown>",
+                    description: "Write a run summary into the output directory in this format (default: json).",
+                },
+                FlagHelp {
+                    flag: "--summary-name <template>",
+                    description: "File name for the run summary; supports {session_id}, {date} and {timestamp} (default: summary.<ext>).",
+                },
+                FlagHelp {
+                    flag: "--line-endings <lf|crlf>",
+                    description: "Line endings for files written by overwrite_file (default: the domain's output_encoding, else lf).",
+                },
+                FlagHelp {
+                    flag: "--bom",
+                    description: "Prefix files written by overwrite_file with a UTF-8 byte order mark.",
+                },
+                FlagHelp {
+                    flag: "--plan-file <path>",
+                    description: "Skip LLM decomposition and solve the listed subtasks (one per line or JSON array).",
+                },
+                FlagHelp {
+                    flag: "--interactive",
+                    description: "Answer step-by-step checkpoints in the terminal (approve/reject/edit/pause) and keep running.",
+                },
+                FlagHelp {
+                    flag: "--json",
+                    description: "Print the final outcome (completed/paused, failed steps, run metrics) as one JSON object.",
                 },
                 FlagHelp {
                     flag: "-v, --verbose",
@@ -192,10 +373,22 @@ pub fn build_help_section(topic: HelpTopic) -> HelpSection {
                     flag: "--limit <n>",
                     description: "Restrict the number of listed sessions (default 10).",
                 },
+                FlagHelp {
+                    flag: "--search <text>",
+                    description: "Only list sessions whose prompt contains the text (case-insensitive).",
+                },
                 FlagHelp {
                     flag: "--json",
                     description: "Emit structured summaries matching the HTTP API schema.",
                 },
+                FlagHelp {
+                    flag: "--compact-json",
+                    description: "With --json, print single-line JSON for line-oriented tools like jq.",
+                },
+                FlagHelp {
+                    flag: "--format <human|table>",
+                    description: "Listing layout: one line per session (default) or an aligned table with truncated prompts.",
+                },
                 FlagHelp {
                     flag: "-v, --verbose",
                     description: "Include timestamps/debug output in the human-readable listing.",
@@ -216,6 +409,7 @@ pub fn build_help_section(topic: HelpTopic) -> HelpSection {
             usage_examples: vec![
                 "microfactory resume --session-id a1b2",
                 "microfactory resume --session-id a1b2 --llm-provider anthropic --llm-model claude-3.5",
+                "microfactory resume --session-id a1b2 --from-step 3",
             ],
             key_flags: vec![
                 FlagHelp {
@@ -226,6 +420,10 @@ pub fn build_help_section(topic: HelpTopic) -> HelpSection {
                     flag: "--config <path>",
                     description: "Override the saved config path if files moved.",
                 },
+                FlagHelp {
+                    flag: "--template-dir <path>",
+                    description: "Override the template directory saved with the session.",
+                },
                 FlagHelp {
                     flag: "--api-key <key>",
                     description: "Swap credentials when resuming (falls back to stored/env otherwise).",
@@ -242,6 +440,14 @@ pub fn build_help_section(topic: HelpTopic) -> HelpSection {
                     flag: "--human-low-margin-threshold <n>",
                     description: "Override the low-margin pause guard (0 disables).",
                 },
+                FlagHelp {
+                    flag: "--from-step <id>",
+                    description: "Reset the step and its descendants to pending and re-solve them; other steps keep their results.",
+                },
+                FlagHelp {
+                    flag: "--strict",
+                    description: "Fail instead of warning when the config or its templates changed since the session was saved.",
+                },
                 FlagHelp {
                     flag: "-v, --verbose / --log-json",
                     description: "Global logging controls apply just like on `run`.",
@@ -256,16 +462,39 @@ pub fn build_help_section(topic: HelpTopic) -> HelpSection {
                 "Failures update their status immediately; inspect via `status --session-id <id>`.",
             ],
         },
+        HelpTopic::Annotate => HelpSection {
+            topic: "annotate",
+            summary: "Attach a timestamped note to a stored session for later review.",
+            usage_examples: vec![
+                r#"microfactory annotate --session-id a1b2 --note "known flaky""#,
+                r#"microfactory annotate --session-id a1b2 --note "approved by ops""#,
+            ],
+            key_flags: vec![
+                FlagHelp {
+                    flag: "--session-id <id>",
+                    description: "Session to annotate (required).",
+                },
+                FlagHelp {
+                    flag: "--note <text>",
+                    description: "Note text; must not be blank.",
+                },
+            ],
+            notes: vec![
+                "Notes are appended, never replaced, and listed oldest first by `status --session-id <id>`.",
+                "`serve` exposes the same operation as `POST /sessions/{id}/notes`.",
+            ],
+        },
         HelpTopic::Subprocess => HelpSection {
             topic: "subprocess",
             summary: "Run a single microtask (e.g., solver) with JSON I/O for tooling hooks.",
             usage_examples: vec![
                 r#"microfactory subprocess --domain code --step solver --context-json '{"files":["lib.rs"]}' --samples 4"#,
+                r#"echo '[{"domain":"code","step":"Fix the parser"}]' | microfactory subprocess --batch"#,
             ],
             key_flags: vec![
                 FlagHelp {
                     flag: "--domain <name>",
-                    description: "Required domain key matching your config file.",
+                    description: "Domain key matching your config file (required unless --batch).",
                 },
                 FlagHelp {
                     flag: "--config <path>",
@@ -279,6 +508,10 @@ pub fn build_help_section(topic: HelpTopic) -> HelpSection {
                     flag: "--context-json <blob>",
                     description: "Inline JSON merged into the domain-specific context.",
                 },
+                FlagHelp {
+                    flag: "--batch",
+                    description: "Read a JSON array of {domain, step, context_json, samples, k} from stdin; print an array of outcomes.",
+                },
                 FlagHelp {
                     flag: "--samples / --k",
                     description: "Sampling + vote settings for this isolated run.",
@@ -291,10 +524,18 @@ pub fn build_help_section(topic: HelpTopic) -> HelpSection {
                     flag: "--api-key <key>",
                     description: "Provide credentials explicitly if env resolution is insufficient.",
                 },
+                FlagHelp {
+                    flag: "--llm-header <name=value>",
+                    description: "Extra HTTP header on every LLM request (repeatable).",
+                },
                 FlagHelp {
                     flag: "--max-concurrent-llm <n>",
                     description: "Limit simultaneous LLM calls (default 2).",
                 },
+                FlagHelp {
+                    flag: "--compact-json",
+                    description: "Print the result as single-line JSON instead of pretty output.",
+                },
                 FlagHelp {
                     flag: "-v, --verbose",
                     description: "Show human-friendly logs during the subprocess run.",
@@ -320,7 +561,7 @@ pub fn build_help_section(topic: HelpTopic) -> HelpSection {
             key_flags: vec![
                 FlagHelp {
                     flag: "--bind <ip>",
-                    description: "Interface for the Axum HTTP server (default 127.0.0.1).",
+                    description: "IPv4 or IPv6 address for the Axum HTTP server, e.g. 0.0.0.0, ::1, or [::] (default 127.0.0.1).",
                 },
                 FlagHelp {
                     flag: "--port <n>",
@@ -332,7 +573,31 @@ pub fn build_help_section(topic: HelpTopic) -> HelpSection {
                 },
                 FlagHelp {
                     flag: "--poll-interval-ms <n>",
-                    description: "SSE polling cadence for /sessions/stream (min 250ms).",
+                    description: "SSE polling cadence for /sessions/stream (raised to --min-poll-ms).",
+                },
+                FlagHelp {
+                    flag: "--min-poll-ms <n>",
+                    description: "Floor for --poll-interval-ms (default 250, never below 10); lower it to poll faster on localhost.",
+                },
+                FlagHelp {
+                    flag: "--sse-event-name <name>",
+                    description: "SSE `event:` name for session snapshots (default sessions).",
+                },
+                FlagHelp {
+                    flag: "--sse-idle-timeout-secs <n>",
+                    description: "Close each SSE connection after n seconds so abandoned tabs do not pile up (default: never).",
+                },
+                FlagHelp {
+                    flag: "--recent-capacity <n>",
+                    description: "Status transitions retained for GET /recent (default 50).",
+                },
+                FlagHelp {
+                    flag: "--base-path <prefix>",
+                    description: "Mount all REST and SSE routes under a prefix, e.g. /microfactory/sessions.",
+                },
+                FlagHelp {
+                    flag: "--json-case <snake|camel>",
+                    description: "Field naming of JSON responses and SSE payloads (default snake).",
                 },
                 FlagHelp {
                     flag: "-v, --verbose",
@@ -348,10 +613,132 @@ pub fn build_help_section(topic: HelpTopic) -> HelpSection {
                 },
             ],
             notes: vec![
-                "Endpoints: GET /sessions, GET /sessions/{id}, GET /sessions/stream (SSE).",
+                "Endpoints: GET /sessions, GET /sessions/search?q=<text>, GET /sessions/{id}, GET /sessions/stream (SSE), GET /recent.",
                 "Combine with `curl` or dashboards to watch sessions without invoking the CLI.",
                 "Serve shares the same serialization structs as status --json for parity.",
             ],
         },
+        HelpTopic::Export => HelpSection {
+            topic: "export",
+            summary: "Stream every stored session to stdout for backup or offline analysis.",
+            usage_examples: vec![
+                "microfactory export --ndjson > sessions.ndjson",
+                "microfactory export --limit 100 > recent.json",
+            ],
+            key_flags: vec![
+                FlagHelp {
+                    flag: "--ndjson",
+                    description: "Emit one session per line instead of a single JSON array.",
+                },
+                FlagHelp {
+                    flag: "--limit <n>",
+                    description: "Export only the newest n sessions (default: all).",
+                },
+            ],
+            notes: vec![
+                "Sessions are read from the store one row at a time, so memory stays flat for large histories.",
+                "Each entry carries the session status, steps, metrics, and stored run metadata.",
+            ],
+        },
+        HelpTopic::Verify => HelpSection {
+            topic: "verify",
+            summary: "Run a domain's configured verifier once (no LLM calls) and exit non-zero if it fails.",
+            usage_examples: vec![
+                "microfactory verify --domain code",
+                "microfactory verify --domain code --config config.yaml --json",
+            ],
+            key_flags: vec![
+                FlagHelp {
+                    flag: "--domain <name>",
+                    description: "Domain whose `verifier` command should run (required).",
+                },
+                FlagHelp {
+                    flag: "--config <path>",
+                    description: "Config file that defines the domain (default: config.yaml).",
+                },
+                FlagHelp {
+                    flag: "--json",
+                    description: "Print command, pass/fail, exit status, stdout, and stderr as JSON.",
+                },
+            ],
+            notes: vec![
+                "Useful after editing files by hand: the same verifier the runner uses after applying a solution.",
+                "Errors if the domain has no verifier configured.",
+            ],
+        },
+        HelpTopic::Compare => HelpSection {
+            topic: "compare",
+            summary: "Run the full workflow once per model and print metrics side by side, followed by each model's winning solutions.",
+            usage_examples: vec![
+                r#"microfactory compare --prompt "fix the parser" --domain code --models openai:gpt-4.1,anthropic:claude-sonnet-4-5"#,
+                r#"microfactory compare --prompt "summarize" --domain analysis --models openai:gpt-4.1-mini,gemini:gemini-2.5-flash --json"#,
+            ],
+            key_flags: vec![
+                FlagHelp {
+                    flag: "--models <list>",
+                    description: "Comma-separated provider:model pairs (at least two).",
+                },
+                FlagHelp {
+                    flag: "--output-dir <path>",
+                    description: "Per-run output directory (default compare/{session_id}); keep {session_id} so runs do not overwrite each other.",
+                },
+                FlagHelp {
+                    flag: "--samples / --k",
+                    description: "Sampling and voting settings shared by every run.",
+                },
+                FlagHelp {
+                    flag: "--json",
+                    description: "Print the comparison (per-model status, metrics, solutions, errors) as JSON.",
+                },
+            ],
+            notes: vec![
+                "Runs execute one after another; each is stored as a normal session and can be resumed by id.",
+                "API keys come from each provider's env var (see `microfactory providers`).",
+                "A run that errors is reported in the comparison instead of stopping the others.",
+            ],
+        },
+        HelpTopic::Providers => HelpSection {
+            topic: "providers",
+            summary: "List every supported LLM provider with the env var its API key is read from.",
+            usage_examples: vec!["microfactory providers", "microfactory providers --json"],
+            key_flags: vec![FlagHelp {
+                flag: "--json",
+                description: "Print name, env_var, and set for each provider as a JSON array.",
+            }],
+            notes: vec![
+                "`set` reflects the environment after ~/.env has been loaded; key values are never printed.",
+                "Use the provider name with `run --llm-provider <name>`.",
+            ],
+        },
+        HelpTopic::Models => HelpSection {
+            topic: "models",
+            summary: "Ask a provider which models your API key can use before picking --llm-model.",
+            usage_examples: vec![
+                "microfactory models --llm-provider openai",
+                "microfactory models --llm-provider anthropic --json",
+            ],
+            key_flags: vec![
+                FlagHelp {
+                    flag: "--llm-provider <name>",
+                    description: "Provider whose models to list (default openai).",
+                },
+                FlagHelp {
+                    flag: "--api-key <key>",
+                    description: "Provide credentials explicitly instead of the provider's env var.",
+                },
+                FlagHelp {
+                    flag: "--llm-header <name=value>",
+                    description: "Extra HTTP header on the listing request (repeatable).",
+                },
+                FlagHelp {
+                    flag: "--json",
+                    description: "Print the model ids as a JSON array.",
+                },
+            ],
+            notes: vec![
+                "Ids are sorted and printed one per line; Gemini names lose their `models/` prefix.",
+                "A provider that has no model list endpoint reports an error instead of an empty list.",
+            ],
+        },
     }
 }
@@ -0,0 +1,167 @@
+//! Terminal prompts for `run --interactive`, answering step-by-step checkpoints in-process.
+
+use std::{
+    io::{self, BufRead, Write},
+    sync::Mutex,
+};
+
+use crate::core::{
+    domain::{Context, WaitState},
+    ports::{CheckpointDecision, CheckpointReviewer},
+};
+
+/// Line that ends multi-line input when editing a plan or solution.
+const EDIT_TERMINATOR: &str = ".";
+
+/// [`CheckpointReviewer`] that shows each checkpoint and reads approve / reject / edit answers.
+/// Prompts go to `output` (stderr by default) so stdout stays free for command output.
+pub struct TerminalReviewer {
+    io: Mutex<ReviewerIo>,
+}
+
+struct ReviewerIo {
+    input: Box<dyn BufRead + Send>,
+    output: Box<dyn Write + Send>,
+}
+
+impl TerminalReviewer {
+    pub fn new(input: impl BufRead + Send + 'static, output: impl Write + Send + 'static) -> Self {
+        Self {
+            io: Mutex::new(ReviewerIo {
+                input: Box::new(input),
+                output: Box::new(output),
+            }),
+        }
+    }
+
+    /// Review on the process terminal: answers from stdin, prompts on stderr.
+    pub fn stdio() -> Self {
+        Self::new(io::BufReader::new(io::stdin()), io::stderr())
+    }
+}
+
+impl CheckpointReviewer for TerminalReviewer {
+    fn review(&self, context: &Context, wait: &WaitState) -> CheckpointDecision {
+        let Ok(mut io) = self.io.lock() else {
+            return CheckpointDecision::Pause;
+        };
+        // A closed or broken terminal falls back to a regular pause rather than guessing.
+        io.review(context, wait)
+            .unwrap_or(CheckpointDecision::Pause)
+    }
+}
+
+impl ReviewerIo {
+    fn review(&mut self, context: &Context, wait: &WaitState) -> io::Result<CheckpointDecision> {
+        self.describe(context, wait)?;
+        loop {
+            write!(
+                self.output,
+                "[a]pprove / [r]eject / [e]dit / [p]ause and persist > "
+            )?;
+            self.output.flush()?;
+            let Some(answer) = self.read_line()? else {
+                return Ok(CheckpointDecision::Pause);
+            };
+            match answer.trim().to_ascii_lowercase().as_str() {
+                "a" | "approve" => return Ok(CheckpointDecision::Approve),
+                "r" | "reject" => return Ok(CheckpointDecision::Reject),
+                "p" | "pause" => return Ok(CheckpointDecision::Pause),
+                "e" | "edit" => {
+                    writeln!(
+                        self.output,
+                        "Enter the replacement (one subtask per line for plans); finish with a line containing only '{EDIT_TERMINATOR}':"
+                    )?;
+                    return Ok(match self.read_edit()? {
+                        Some(text) => CheckpointDecision::Edit(text),
+                        None => CheckpointDecision::Pause,
+                    });
+                }
+                other => writeln!(self.output, "Unrecognized answer '{other}'.")?,
+            }
+        }
+    }
+
+    fn describe(&mut self, context: &Context, wait: &WaitState) -> io::Result<()> {
+        writeln!(
+            self.output,
+            "\n=== Checkpoint: step {} ({}) ===",
+            wait.step_id, wait.trigger
+        )?;
+        writeln!(self.output, "{}", wait.details)?;
+        let Some(step) = context.step(wait.step_id) else {
+            return Ok(());
+        };
+        writeln!(self.output, "Step: {}", step.description)?;
+        if let Some(solution) = &step.winning_solution {
+            writeln!(
+                self.output,
+                "--- Solution ---\n{solution}\n----------------"
+            )?;
+        } else if !step.children.is_empty() {
+            writeln!(self.output, "Planned subtasks:")?;
+            for (idx, child) in step.children.iter().enumerate() {
+                if let Some(child) = context.step(*child) {
+                    writeln!(self.output, "  {}. {}", idx + 1, child.description)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line))
+    }
+
+    /// Read lines up to the terminator; `None` if input ends first.
+    fn read_edit(&mut self) -> io::Result<Option<String>> {
+        let mut lines = Vec::new();
+        while let Some(line) = self.read_line()? {
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line == EDIT_TERMINATOR {
+                return Ok(Some(lines.join("\n")));
+            }
+            lines.push(line.to_string());
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn review(script: &str) -> CheckpointDecision {
+        let mut context = Context::new("Write notes", "code");
+        let root = context.ensure_root();
+        context.add_child_step(root, "Draft outline");
+        let wait = WaitState {
+            step_id: root,
+            trigger: "step_by_step_checkpoint".into(),
+            details: "Decomposition plan ready for review".into(),
+        };
+        TerminalReviewer::new(io::Cursor::new(script.to_string()), io::sink())
+            .review(&context, &wait)
+    }
+
+    #[test]
+    fn parses_scripted_answers() {
+        assert_eq!(review("a\n"), CheckpointDecision::Approve);
+        assert_eq!(review("huh\nreject\n"), CheckpointDecision::Reject);
+        assert_eq!(
+            review("e\nfirst task\nsecond task\n.\n"),
+            CheckpointDecision::Edit("first task\nsecond task".into())
+        );
+        assert_eq!(review("p\n"), CheckpointDecision::Pause);
+    }
+
+    #[test]
+    fn closed_input_pauses() {
+        assert_eq!(review(""), CheckpointDecision::Pause);
+        assert_eq!(review("e\nunfinished\n"), CheckpointDecision::Pause);
+    }
+}
@@ -2,34 +2,66 @@
 
 mod definitions;
 mod help;
+mod interactive;
 
 pub use definitions::*;
+pub use interactive::TerminalReviewer;
 
-use std::sync::Arc;
+use std::{
+    io::{BufWriter, Read, Write},
+    sync::Arc,
+};
 
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    core::ports::{ResumeSessionRequest, RunSessionRequest, SubprocessRequest, WorkflowService},
+    adapters::outbound::process::spawn_background_resume,
+    core::{
+        domain::AdaptiveKLimits,
+        error::Error as CoreError,
+        ports::{
+            FlaggerRejections, ListModelsRequest, ResumeSessionRequest, RunSessionRequest,
+            SessionOutcome, SessionRunMetrics, SessionSummary, StageTiming, StepSolution,
+            SubprocessRequest, VerifyOutcome, VerifyRequest, WorkflowService,
+        },
+    },
     status_export::{SessionListExport, SessionSummaryExport},
 };
 
+/// Starts a background `resume` for a session id; used by `run --resume-policy auto`.
+pub type ResumeSpawner = Arc<dyn Fn(&str) -> Result<()> + Send + Sync>;
+
 /// CLI adapter that consumes the `WorkflowService` to execute commands.
 pub struct CliAdapter {
     service: Arc<dyn WorkflowService>,
+    resume_spawner: ResumeSpawner,
 }
 
 impl CliAdapter {
     pub fn new(service: Arc<dyn WorkflowService>) -> Self {
-        Self { service }
+        Self {
+            service,
+            resume_spawner: Arc::new(spawn_background_resume),
+        }
+    }
+
+    /// Replace how `--resume-policy auto` starts the background resume (tests record calls).
+    pub fn with_resume_spawner(mut self, spawner: ResumeSpawner) -> Self {
+        self.resume_spawner = spawner;
+        self
     }
 
     /// Execute a CLI command by dispatching to the appropriate service method.
     pub async fn execute(&self, command: Commands) -> Result<()> {
         match command {
-            Commands::Run(args) => self.run_command(args).await,
+            Commands::Run(args) => self.run_command(*args).await,
             Commands::Status(args) => self.status_command(args).await,
             Commands::Resume(args) => self.resume_command(args).await,
+            Commands::Annotate(args) => {
+                self.annotate_with_output(&args, &mut std::io::stdout().lock())
+                    .await
+            }
             Commands::Subprocess(args) => self.subprocess_command(args).await,
             Commands::Serve(_) => {
                 // Serve is handled separately in main.rs since it needs special setup
@@ -37,11 +69,29 @@ impl CliAdapter {
                     "Serve command should be handled by the composition root"
                 ))
             }
+            Commands::Export(args) => self.export_command(args).await,
+            Commands::Verify(args) => self.verify_command(args).await,
+            Commands::Providers(args) => {
+                providers_with_output(&args, &mut std::io::stdout().lock(), &|var| {
+                    std::env::var_os(var).is_some_and(|value| !value.is_empty())
+                })
+            }
+            Commands::Models(args) => {
+                self.models_with_output(&args, &mut std::io::stdout().lock())
+                    .await
+            }
+            Commands::Compare(args) => {
+                self.compare_with_output(&args, &mut std::io::stdout().lock())
+                    .await
+            }
             Commands::Help(args) => self.help_command(args).await,
         }
     }
 
-    async fn run_command(&self, args: RunArgs) -> Result<()> {
+    async fn run_command(&self, mut args: RunArgs) -> Result<()> {
+        if args.prompt_from_stdin {
+            args.prompt = Some(read_prompt(&mut std::io::stdin().lock())?);
+        }
         if args.dry_run {
             let request = self.run_args_to_request(&args);
             let result = self.service.dry_run_probe(&request).await?;
@@ -52,23 +102,127 @@ impl CliAdapter {
             );
             return Ok(());
         }
+        if args.config_check_only {
+            return self
+                .config_check_with_output(&args, &mut std::io::stdout())
+                .await;
+        }
+
+        self.run_session_with_output(&args, &mut std::io::stdout())
+            .await
+    }
 
-        let request = self.run_args_to_request(&args);
+    /// Validate everything `run` would set up and print `ready` (as JSON with `--json`).
+    async fn config_check_with_output(&self, args: &RunArgs, out: &mut dyn Write) -> Result<()> {
+        let request = self.run_args_to_request(args);
+        let check = self.service.check_run_config(&request).await?;
+        if args.json {
+            writeln!(out, "{}", render_json(&check, false)?)?;
+        } else {
+            writeln!(
+                out,
+                "ready (domain: {}, provider: {}, model: {})",
+                check.domain, check.provider, check.model
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Run a session and report its outcome to `out`: a single JSON object with `--json`,
+    /// otherwise the usual log lines.
+    async fn run_session_with_output(&self, args: &RunArgs, out: &mut dyn Write) -> Result<()> {
+        if args.no_persist && args.resume_policy == ResumePolicy::Auto {
+            return Err(anyhow::anyhow!(
+                "--resume-policy auto cannot be combined with --no-persist: an unsaved session cannot be resumed"
+            ));
+        }
+        let request = self.run_args_to_request(args);
         let outcome = self.service.run_session(request).await?;
 
-        if outcome.paused
-            && let Some(reason) = &outcome.pause_reason
-        {
-            tracing::info!(
-                "Session {} paused at step {} ({}) - {}",
-                outcome.session_id,
-                reason.step_id,
-                reason.trigger,
-                reason.details
-            );
+        if args.json {
+            writeln!(out, "{}", render_json(&outcome, false)?)?;
+        } else {
+            if outcome.paused
+                && let Some(reason) = &outcome.pause_reason
+            {
+                tracing::info!(
+                    "Session {} paused at step {} ({}) - {}",
+                    outcome.session_id,
+                    reason.step_id,
+                    reason.trigger,
+                    reason.details
+                );
+            }
+            if !outcome.metrics.top_flaggers.is_empty() {
+                writeln!(
+                    out,
+                    "Top red flaggers: {}",
+                    format_top_flaggers(&outcome.metrics.top_flaggers)
+                )?;
+            }
+            if !outcome.metrics.stage_timings.is_empty() {
+                writeln!(
+                    out,
+                    "Stage timing: {}",
+                    StageTiming::summarize(&outcome.metrics.stage_timings)
+                )?;
+            }
+            if !outcome.recursion.is_empty() {
+                writeln!(out, "Recursion decisions:")?;
+                for entry in &outcome.recursion {
+                    writeln!(
+                        out,
+                        "  step {} (depth {}, {} words): {} - {}",
+                        entry.step_id,
+                        entry.decision.depth,
+                        entry.decision.word_count,
+                        entry.decision.reason,
+                        entry.description
+                    )?;
+                }
+            }
+            if !outcome.apply_previews.is_empty() {
+                writeln!(out, "Dry-run apply (nothing was written):")?;
+                for preview in &outcome.apply_previews {
+                    writeln!(
+                        out,
+                        "  step {}: {}",
+                        preview.step_id,
+                        preview.files.join(", ")
+                    )?;
+                    write!(out, "{}", preview.diff)?;
+                }
+            }
         }
 
-        Ok(())
+        if outcome.paused {
+            self.apply_resume_policy(args.resume_policy, &outcome)?;
+        }
+        ensure_no_failed_steps(&outcome)
+    }
+
+    /// Act on a paused run according to `--resume-policy`.
+    fn apply_resume_policy(&self, policy: ResumePolicy, outcome: &SessionOutcome) -> Result<()> {
+        match policy {
+            ResumePolicy::Manual => Ok(()),
+            ResumePolicy::Auto => {
+                (self.resume_spawner)(&outcome.session_id)?;
+                tracing::info!("Session {} resuming in the background", outcome.session_id);
+                Ok(())
+            }
+            ResumePolicy::Fail => {
+                let reason = outcome
+                    .pause_reason
+                    .as_ref()
+                    .map_or_else(String::new, |pause| {
+                        format!(" at step {} ({})", pause.step_id, pause.trigger)
+                    });
+                Err(anyhow::anyhow!(
+                    "Session {} paused{reason} and --resume-policy is fail",
+                    outcome.session_id
+                ))
+            }
+        }
     }
 
     async fn status_command(&self, args: StatusArgs) -> Result<()> {
@@ -76,7 +230,7 @@ impl CliAdapter {
             let detail = self.service.get_session(&id).await?;
             if let Some(session) = detail {
                 if args.json {
-                    println!("{}", serde_json::to_string_pretty(&session)?);
+                    println!("{}", render_json(&session, args.compact_json)?);
                 } else {
                     println!("Session: {}", session.session_id);
                     println!("Status: {}", session.status);
@@ -90,13 +244,37 @@ impl CliAdapter {
                         );
                     }
                     println!("Steps completed: {}", session.steps_completed);
+                    if session.partial {
+                        println!(
+                            "Partial results: run in progress ({} step(s) running)",
+                            session.steps_running
+                        );
+                    }
+                    if !session.top_flaggers.is_empty() {
+                        println!(
+                            "Top red flaggers: {}",
+                            format_top_flaggers(&session.top_flaggers)
+                        );
+                    }
+                    if !session.stage_timings.is_empty() {
+                        println!(
+                            "Stage timing: {}",
+                            StageTiming::summarize(&session.stage_timings)
+                        );
+                    }
+                    for note in &session.notes {
+                        println!("Note ({}): {}", note.created_at, note.note);
+                    }
                 }
             } else {
                 return Err(anyhow::anyhow!("Session {id} not found"));
             }
         } else {
             let limit = args.limit.max(1);
-            let summaries = self.service.list_sessions(limit).await?;
+            let summaries = match &args.search {
+                Some(query) => self.service.search_sessions(query, limit).await?,
+                None => self.service.list_sessions(limit).await?,
+            };
             if args.json {
                 // Convert to export format for backward compatibility
                 let export_summaries: Vec<SessionSummaryExport> = summaries
@@ -104,6 +282,7 @@ impl CliAdapter {
                     .map(|s| SessionSummaryExport {
                         session_id: s.session_id,
                         status: s.status,
+                        pause_trigger: s.pause_trigger,
                         prompt: s.prompt,
                         domain: s.domain,
                         updated_at: s.updated_at.parse().unwrap_or(0),
@@ -112,19 +291,28 @@ impl CliAdapter {
                 let payload = SessionListExport {
                     sessions: export_summaries,
                 };
-                println!("{}", serde_json::to_string_pretty(&payload)?);
+                println!("{}", render_json(&payload, args.compact_json)?);
             } else if summaries.is_empty() {
-                println!("No sessions recorded yet.");
+                match &args.search {
+                    Some(query) => println!("No sessions match '{query}'."),
+                    None => println!("No sessions recorded yet."),
+                }
+            } else if args.format == StatusFormat::Table {
+                let mut out = std::io::stdout().lock();
+                write_session_table(&summaries, &mut out)?;
             } else {
-                println!("Recent sessions:");
+                match &args.search {
+                    Some(query) => println!("Sessions matching '{query}':"),
+                    None => println!("Recent sessions:"),
+                }
                 for summary in summaries {
+                    let status = match &summary.pause_trigger {
+                        Some(trigger) => format!("{}: {trigger}", summary.status),
+                        None => summary.status.clone(),
+                    };
                     println!(
-                        "- {} [{}] domain={} updated={} prompt={}",
-                        summary.session_id,
-                        summary.status,
-                        summary.domain,
-                        summary.updated_at,
-                        summary.prompt
+                        "- {} [{status}] domain={} updated={} prompt={}",
+                        summary.session_id, summary.domain, summary.updated_at, summary.prompt
                     );
                 }
             }
@@ -136,13 +324,18 @@ impl CliAdapter {
         let request = ResumeSessionRequest {
             session_id: args.session_id.clone(),
             config_path: args.config.clone(),
+            template_dir: args.template_dir.clone(),
             llm_provider: args.llm_provider.map(|p| p.as_str().to_string()),
             llm_model: args.llm_model.clone(),
             api_key: args.api_key.clone(),
+            llm_headers: args.llm_headers.clone(),
             samples: args.samples,
             k: args.k,
             max_concurrent_llm: args.max_concurrent_llm,
             human_low_margin_threshold: args.human_low_margin_threshold,
+            from_step: args.from_step,
+            strict: args.strict,
+            max_total_tokens: args.max_total_tokens,
         };
 
         let outcome = self.service.resume_session(request).await?;
@@ -159,25 +352,191 @@ impl CliAdapter {
             );
         }
 
-        Ok(())
+        ensure_no_failed_steps(&outcome)
     }
 
     async fn subprocess_command(&self, args: SubprocessArgs) -> Result<()> {
-        let request = SubprocessRequest {
+        if args.batch {
+            return self
+                .subprocess_batch_with_output(
+                    &args,
+                    &mut std::io::stdin().lock(),
+                    &mut std::io::stdout(),
+                )
+                .await;
+        }
+        let item = SubprocessBatchItem {
+            domain: args.domain.clone().unwrap_or_default(),
+            step: args.step.clone().unwrap_or_default(),
+            context_json: args.context_json.clone().map(serde_json::Value::String),
+            samples: None,
+            k: None,
+        };
+
+        let outcome = self
+            .service
+            .run_subprocess(item.into_request(&args))
+            .await?;
+        println!("{}", render_json(&outcome, args.compact_json)?);
+        Ok(())
+    }
+
+    /// `subprocess --batch`: run every item of the JSON array in `input` and write the outcomes
+    /// as one JSON array.
+    async fn subprocess_batch_with_output(
+        &self,
+        args: &SubprocessArgs,
+        input: &mut dyn Read,
+        out: &mut dyn Write,
+    ) -> Result<()> {
+        let mut raw = String::new();
+        input
+            .read_to_string(&mut raw)
+            .map_err(|e| anyhow::anyhow!("Failed to read the batch from stdin: {e}"))?;
+        let items: Vec<SubprocessBatchItem> = serde_json::from_str(&raw).map_err(|e| {
+            anyhow::anyhow!("--batch expects a JSON array of {{domain, step}} objects: {e}")
+        })?;
+        let requests = items
+            .into_iter()
+            .map(|item| item.into_request(args))
+            .collect();
+
+        let outcomes = self.service.run_subprocess_batch(requests).await?;
+        writeln!(out, "{}", render_json(&outcomes, args.compact_json)?)?;
+        Ok(())
+    }
+
+    async fn export_command(&self, args: ExportArgs) -> Result<()> {
+        let mut out = BufWriter::new(std::io::stdout());
+        let ndjson = args.ndjson;
+        let mut first = true;
+
+        if !ndjson {
+            out.write_all(b"[")?;
+        }
+        let mut sink = |line: &str| -> crate::core::Result<()> {
+            let written = if ndjson {
+                writeln!(out, "{line}")
+            } else {
+                let separator = if first { "\n" } else { ",\n" };
+                first = false;
+                write!(out, "{separator}{line}")
+            };
+            written.map_err(|e| CoreError::FileSystem(e.to_string()))
+        };
+        let exported = self.service.export_sessions(args.limit, &mut sink).await?;
+        if !ndjson {
+            let closing = if exported == 0 { "]\n" } else { "\n]\n" };
+            out.write_all(closing.as_bytes())?;
+        }
+        out.flush()?;
+
+        tracing::debug!("Exported {exported} session(s)");
+        Ok(())
+    }
+
+    /// Attach the note and confirm it on `out`.
+    async fn annotate_with_output(&self, args: &AnnotateArgs, out: &mut dyn Write) -> Result<()> {
+        let note = self
+            .service
+            .add_session_note(&args.session_id, &args.note)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Session {} not found", args.session_id))?;
+        writeln!(
+            out,
+            "Added note to session {} at {}",
+            args.session_id, note.created_at
+        )?;
+        Ok(())
+    }
+
+    async fn verify_command(&self, args: VerifyArgs) -> Result<()> {
+        self.verify_with_output(&args, &mut std::io::stdout().lock())
+            .await
+    }
+
+    /// Run the verifier and report to `out`; failures become a non-zero exit.
+    async fn verify_with_output(&self, args: &VerifyArgs, out: &mut dyn Write) -> Result<()> {
+        let outcome = self
+            .service
+            .verify(VerifyRequest {
+                domain: args.domain.clone(),
+                config_path: args.config.clone(),
+            })
+            .await?;
+
+        if args.json {
+            writeln!(out, "{}", render_json(&outcome, false)?)?;
+        } else {
+            let verdict = if outcome.passed { "passed" } else { "FAILED" };
+            writeln!(out, "Verifier {verdict}: {}", outcome.command)?;
+            if !outcome.stdout.is_empty() {
+                writeln!(out, "--- stdout ---\n{}", outcome.stdout.trim_end())?;
+            }
+            if !outcome.stderr.is_empty() {
+                writeln!(out, "--- stderr ---\n{}", outcome.stderr.trim_end())?;
+            }
+        }
+        out.flush()?;
+        ensure_verified(&outcome)
+    }
+
+    /// Print the model ids the provider offers, one per line or as a JSON array.
+    async fn models_with_output(&self, args: &ModelsArgs, out: &mut dyn Write) -> Result<()> {
+        let provider = args.llm_provider.as_str();
+        let models = self
+            .service
+            .list_models(ListModelsRequest {
+                llm_provider: provider.to_string(),
+                api_key: args.api_key.clone(),
+                llm_headers: args.llm_headers.clone(),
+            })
+            .await
+            .map_err(|e| anyhow::anyhow!("Could not list models for '{provider}': {e}"))?;
+
+        if args.json {
+            writeln!(out, "{}", render_json(&models, false)?)?;
+        } else {
+            for model in &models {
+                writeln!(out, "{model}")?;
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Run the prompt once per model and report the runs side by side. A run that errors is
+    /// recorded in the comparison instead of aborting the remaining models.
+    async fn compare_with_output(&self, args: &CompareArgs, out: &mut dyn Write) -> Result<()> {
+        if args.models.len() < 2 {
+            return Err(anyhow::anyhow!(
+                "compare needs at least two models, got {}",
+                args.models.len()
+            ));
+        }
+
+        let mut runs = Vec::with_capacity(args.models.len());
+        for target in &args.models {
+            tracing::info!("Comparing: running {target}");
+            let request = compare_request(args, target);
+            let run = match self.service.run_session(request).await {
+                Ok(outcome) => ModelRun::from_outcome(target, outcome),
+                Err(err) => ModelRun::from_error(target, err.to_string()),
+            };
+            runs.push(run);
+        }
+        let report = CompareReport {
+            prompt: args.prompt.clone(),
             domain: args.domain.clone(),
-            config_path: args.config.clone(),
-            step: args.step.clone(),
-            context_json: args.context_json.clone(),
-            llm_provider: args.llm_provider.as_str().to_string(),
-            llm_model: args.llm_model.clone(),
-            api_key: args.api_key.clone(),
-            samples: args.samples,
-            k: args.k,
-            max_concurrent_llm: args.max_concurrent_llm,
+            runs,
         };
 
-        let outcome = self.service.run_subprocess(request).await?;
-        println!("{}", serde_json::to_string_pretty(&outcome)?);
+        if args.json {
+            writeln!(out, "{}", render_json(&report, false)?)?;
+        } else {
+            write_compare_table(&report, out)?;
+        }
+        out.flush()?;
         Ok(())
     }
 
@@ -193,20 +552,1118 @@ impl CliAdapter {
 
     fn run_args_to_request(&self, args: &RunArgs) -> RunSessionRequest {
         RunSessionRequest {
-            prompt: args.prompt.clone(),
+            prompt: args.prompt.clone().unwrap_or_default(),
             domain: args.domain.clone(),
             config_path: args.config.clone(),
+            template_dir: args.template_dir.clone(),
             llm_provider: args.llm_provider.as_str().to_string(),
             llm_model: args.llm_model.clone(),
             api_key: args.api_key.clone(),
+            llm_headers: args.llm_headers.clone(),
             samples: args.samples,
             k: args.k,
             adaptive_k: args.adaptive_k,
+            adaptive_k_limits: AdaptiveKLimits {
+                min: args.adaptive_k_min,
+                max: args.adaptive_k_max,
+                step: args.adaptive_k_step,
+            },
+            adaptive_samples: args.adaptive_samples,
             max_concurrent_llm: args.max_concurrent_llm,
             dry_run: args.dry_run,
-            step_by_step: args.step_by_step,
+            dry_run_apply: args.dry_run_apply,
+            step_by_step: args.step_by_step || args.interactive,
             human_low_margin_threshold: args.human_low_margin_threshold,
+            max_depth: args.max_depth,
+            max_children_total: args.max_children_total,
             output_dir: args.output_dir.clone(),
+            plan_file: args.plan_file.clone(),
+            dump_prompts: args.dump_prompts,
+            record_prompts: args.record_prompts,
+            red_flag_concurrency: args.red_flag_concurrency,
+            parallel_red_flaggers: args.parallel_red_flaggers,
+            max_response_bytes: args.max_response_bytes,
+            flag_truncated: args.flag_truncated,
+            retry_on_empty: args.retry_on_empty,
+            min_samples_accepted: args.min_samples_accepted.map(|n| n as usize),
+            prompt_vars: args.prompt_vars.clone(),
+            line_endings: args.line_endings.map(Into::into),
+            bom: args.bom,
+            dump_context: args.dump_context.clone(),
+            summary_format: args.summary_format.map(Into::into),
+            summary_name: args.summary_name.clone(),
+            dedupe_candidates: args.dedupe_candidates,
+            no_persist: args.no_persist,
+            explain: args.explain,
+            collapse_trivial_decomposition: args.collapse_trivial_decomposition,
+            flat: args.flat,
+            shuffle_candidates: args.shuffle_candidates,
+            shuffle_seed: args.shuffle_seed,
+            max_total_tokens: args.max_total_tokens,
+        }
+    }
+}
+
+/// Serialize command output as pretty JSON (default) or a single line for line-oriented tools.
+fn render_json<T: Serialize>(value: &T, compact: bool) -> serde_json::Result<String> {
+    if compact {
+        serde_json::to_string(value)
+    } else {
+        serde_json::to_string_pretty(value)
+    }
+}
+
+/// Outcome of one model's run within `microfactory compare`.
+#[derive(Debug, Serialize)]
+struct ModelRun {
+    model: String,
+    session_id: Option<String>,
+    completed: bool,
+    paused: bool,
+    failed_steps: Vec<usize>,
+    metrics: SessionRunMetrics,
+    solutions: Vec<StepSolution>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl ModelRun {
+    fn from_outcome(target: &CompareTarget, outcome: SessionOutcome) -> Self {
+        Self {
+            model: target.to_string(),
+            session_id: Some(outcome.session_id),
+            completed: outcome.completed,
+            paused: outcome.paused,
+            failed_steps: outcome.failed_steps,
+            metrics: outcome.metrics,
+            solutions: outcome.solutions,
+            error: None,
+        }
+    }
+
+    fn from_error(target: &CompareTarget, error: String) -> Self {
+        Self {
+            model: target.to_string(),
+            session_id: None,
+            completed: false,
+            paused: false,
+            failed_steps: Vec::new(),
+            metrics: SessionRunMetrics::default(),
+            solutions: Vec::new(),
+            error: Some(error),
+        }
+    }
+
+    fn status(&self) -> &'static str {
+        if self.error.is_some() {
+            "error"
+        } else if self.paused {
+            "paused"
+        } else if !self.failed_steps.is_empty() {
+            "failed steps"
+        } else if self.completed {
+            "completed"
+        } else {
+            "incomplete"
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CompareReport {
+    prompt: String,
+    domain: String,
+    runs: Vec<ModelRun>,
+}
+
+/// The `run` request issued for one model of a comparison.
+fn compare_request(args: &CompareArgs, target: &CompareTarget) -> RunSessionRequest {
+    RunSessionRequest {
+        prompt: args.prompt.clone(),
+        domain: args.domain.clone(),
+        config_path: args.config.clone(),
+        template_dir: None,
+        llm_provider: target.provider.as_str().to_string(),
+        llm_model: target.model.clone(),
+        api_key: None,
+        llm_headers: Vec::new(),
+        samples: args.samples,
+        k: args.k,
+        adaptive_k: false,
+        adaptive_k_limits: AdaptiveKLimits::default(),
+        adaptive_samples: false,
+        max_concurrent_llm: args.max_concurrent_llm,
+        dry_run: false,
+        dry_run_apply: false,
+        step_by_step: false,
+        human_low_margin_threshold: args.human_low_margin_threshold,
+        max_depth: None,
+        max_children_total: None,
+        output_dir: Some(args.output_dir.clone()),
+        plan_file: None,
+        dump_prompts: false,
+        record_prompts: false,
+        red_flag_concurrency: args.red_flag_concurrency,
+        parallel_red_flaggers: false,
+        max_response_bytes: None,
+        flag_truncated: false,
+        retry_on_empty: 0,
+        min_samples_accepted: None,
+        prompt_vars: Vec::new(),
+        line_endings: None,
+        bom: false,
+        dump_context: None,
+        summary_format: None,
+        summary_name: None,
+        dedupe_candidates: false,
+        no_persist: false,
+        explain: false,
+        collapse_trivial_decomposition: false,
+        flat: false,
+        shuffle_candidates: false,
+        shuffle_seed: None,
+        max_total_tokens: None,
+    }
+}
+
+/// One entry of the `subprocess --batch` array; omitted `samples` and `k` fall back to the
+/// command-line values.
+#[derive(Debug, Deserialize)]
+struct SubprocessBatchItem {
+    domain: String,
+    step: String,
+    /// Either a JSON string holding the blob or the blob itself.
+    #[serde(default)]
+    context_json: Option<serde_json::Value>,
+    #[serde(default)]
+    samples: Option<usize>,
+    #[serde(default)]
+    k: Option<usize>,
+}
+
+impl SubprocessBatchItem {
+    fn into_request(self, args: &SubprocessArgs) -> SubprocessRequest {
+        SubprocessRequest {
+            domain: self.domain,
+            config_path: args.config.clone(),
+            step: self.step,
+            context_json: self.context_json.map(|value| match value {
+                serde_json::Value::String(text) => text,
+                other => other.to_string(),
+            }),
+            llm_provider: args.llm_provider.as_str().to_string(),
+            llm_model: args.llm_model.clone(),
+            api_key: args.api_key.clone(),
+            llm_headers: args.llm_headers.clone(),
+            samples: self.samples.unwrap_or(args.samples),
+            k: self.k.unwrap_or(args.k),
+            max_concurrent_llm: args.max_concurrent_llm,
+        }
+    }
+}
+
+/// Read the whole of `input` as the task description for `run --prompt-from-stdin`.
+fn read_prompt(input: &mut dyn Read) -> Result<String> {
+    let mut prompt = String::new();
+    input
+        .read_to_string(&mut prompt)
+        .map_err(|e| anyhow::anyhow!("Failed to read the prompt from stdin: {e}"))?;
+    let prompt = prompt.trim();
+    if prompt.is_empty() {
+        return Err(anyhow::anyhow!(
+            "--prompt-from-stdin received no input; pipe the task description on stdin"
+        ));
+    }
+    Ok(prompt.to_string())
+}
+
+/// Fixed column widths of `status --format table`; the prompt column is truncated to fit.
+const SESSION_TABLE_COLUMNS: [(&str, usize); 5] = [
+    ("SESSION", 36),
+    ("STATUS", 9),
+    ("DOMAIN", 12),
+    ("UPDATED", 10),
+    ("PROMPT", 48),
+];
+
+/// Render the session listing as an aligned table with a header row.
+fn write_session_table(summaries: &[SessionSummary], out: &mut dyn Write) -> std::io::Result<()> {
+    let header: Vec<String> = SESSION_TABLE_COLUMNS
+        .iter()
+        .map(|(label, _)| label.to_string())
+        .collect();
+    write_table_row(out, &header)?;
+    for summary in summaries {
+        let prompt = summary
+            .prompt
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        write_table_row(
+            out,
+            &[
+                summary.session_id.clone(),
+                summary.status.clone(),
+                summary.domain.clone(),
+                summary.updated_at.clone(),
+                prompt,
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// Write one row of `SESSION_TABLE_COLUMNS`, padding each cell and truncating it with an
+/// ellipsis when it is wider than its column.
+fn write_table_row(out: &mut dyn Write, cells: &[String]) -> std::io::Result<()> {
+    let row: Vec<String> = SESSION_TABLE_COLUMNS
+        .iter()
+        .zip(cells)
+        .map(|((_, width), cell)| format!("{:<width$}", truncate_cell(cell, *width)))
+        .collect();
+    writeln!(out, "{}", row.join("  ").trim_end())
+}
+
+fn truncate_cell(cell: &str, width: usize) -> String {
+    if cell.chars().count() <= width {
+        return cell.to_string();
+    }
+    let kept: String = cell.chars().take(width.saturating_sub(1)).collect();
+    format!("{kept}…")
+}
+
+/// Metrics as one row per measure with a column per model, then each model's solutions.
+fn write_compare_table(report: &CompareReport, out: &mut dyn Write) -> std::io::Result<()> {
+    let width = report
+        .runs
+        .iter()
+        .map(|run| run.model.len())
+        .max()
+        .unwrap_or(0)
+        .max(12);
+    type Cell = fn(&ModelRun) -> String;
+    let rows: [(&str, Cell); 8] = [
+        ("model", |run| run.model.clone()),
+        ("status", |run| run.status().to_string()),
+        ("steps", |run| {
+            format!(
+                "{}/{}",
+                run.metrics.completed_steps, run.metrics.total_steps
+            )
+        }),
+        ("samples", |run| run.metrics.samples.to_string()),
+        ("resamples", |run| run.metrics.resamples.to_string()),
+        ("vote attempts", |run| run.metrics.vote_attempts.to_string()),
+        ("red-flag hits", |run| run.metrics.red_flag_hits.to_string()),
+        ("session", |run| {
+            run.session_id.clone().unwrap_or_else(|| "-".into())
+        }),
+    ];
+    for (label, cell) in rows {
+        write!(out, "{label:<14}")?;
+        for run in &report.runs {
+            write!(out, "  {:<width$}", cell(run))?;
+        }
+        writeln!(out)?;
+    }
+
+    for run in &report.runs {
+        writeln!(out, "\n=== {} ===", run.model)?;
+        if let Some(error) = &run.error {
+            writeln!(out, "error: {error}")?;
+        }
+        for solution in &run.solutions {
+            writeln!(
+                out,
+                "--- step {}: {} ---\n{}",
+                solution.step_id,
+                solution.description,
+                solution.solution.trim_end()
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// One row of `microfactory providers`.
+#[derive(Debug, Serialize)]
+struct ProviderInfo {
+    name: &'static str,
+    env_var: &'static str,
+    set: bool,
+}
+
+/// List every provider with its API key env var; `is_set` reports whether that var is present.
+fn providers_with_output(
+    args: &ProvidersArgs,
+    out: &mut dyn Write,
+    is_set: &dyn Fn(&str) -> bool,
+) -> Result<()> {
+    use clap::ValueEnum;
+
+    let providers = LlmProvider::value_variants()
+        .iter()
+        .map(|provider| ProviderInfo {
+            name: provider.as_str(),
+            env_var: provider.env_var(),
+            set: is_set(provider.env_var()),
+        })
+        .collect::<Vec<_>>();
+
+    if args.json {
+        writeln!(out, "{}", render_json(&providers, false)?)?;
+    } else {
+        for provider in &providers {
+            let state = if provider.set { "set" } else { "not set" };
+            writeln!(
+                out,
+                "{:<10} {:<18} {state}",
+                provider.name, provider.env_var
+            )?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// `syntax 3, length 1` for the status and run summaries.
+fn format_top_flaggers(flaggers: &[FlaggerRejections]) -> String {
+    flaggers
+        .iter()
+        .map(|entry| format!("{} {}", entry.flagger, entry.rejections))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Turn a run that drained its queue with failed steps into a non-zero exit.
+fn ensure_no_failed_steps(outcome: &SessionOutcome) -> Result<()> {
+    if outcome.failed_steps.is_empty() {
+        return Ok(());
+    }
+    let ids = outcome
+        .failed_steps
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    Err(anyhow::anyhow!(
+        "Session {} completed with {} failed step(s): {ids}",
+        outcome.session_id,
+        outcome.failed_steps.len()
+    ))
+}
+
+/// Turn a failing verifier into a non-zero exit.
+fn ensure_verified(outcome: &VerifyOutcome) -> Result<()> {
+    if outcome.passed {
+        return Ok(());
+    }
+    let status = outcome.status.map_or_else(
+        || "terminated by signal".to_string(),
+        |code| format!("exit status {code}"),
+    );
+    Err(anyhow::anyhow!(
+        "Verifier for domain '{}' failed ({status})",
+        outcome.domain
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ports::{
+        ConfigCheck, DryRunResult, PauseInfo, SessionDetail, SessionExportSink, SessionNote,
+        SessionRunMetrics, StepDetail, SubprocessOutcome,
+    };
+    use async_trait::async_trait;
+    use clap::Parser;
+
+    struct CompletedRunService;
+
+    #[async_trait]
+    impl WorkflowService for CompletedRunService {
+        /// Domain `pausing` pauses at step 2; every other domain completes.
+        async fn run_session(
+            &self,
+            request: RunSessionRequest,
+        ) -> crate::core::Result<SessionOutcome> {
+            let paused = request.domain == "pausing";
+            Ok(SessionOutcome {
+                session_id: "session-json".into(),
+                completed: !paused,
+                paused,
+                pause_reason: paused.then(|| PauseInfo {
+                    step_id: 2,
+                    trigger: "low_margin".into(),
+                    details: "margin 0".into(),
+                }),
+                failed_steps: Vec::new(),
+                metrics: SessionRunMetrics {
+                    total_steps: 3,
+                    completed_steps: 3,
+                    samples: 12,
+                    ..SessionRunMetrics::default()
+                },
+                recursion: Vec::new(),
+                solutions: Vec::new(),
+                apply_previews: Vec::new(),
+            })
+        }
+
+        async fn resume_session(
+            &self,
+            _request: ResumeSessionRequest,
+        ) -> crate::core::Result<SessionOutcome> {
+            unimplemented!()
+        }
+
+        async fn run_subprocess(
+            &self,
+            _request: SubprocessRequest,
+        ) -> crate::core::Result<SubprocessOutcome> {
+            unimplemented!()
+        }
+
+        /// One outcome per item, echoing its domain, step, and samples.
+        async fn run_subprocess_batch(
+            &self,
+            requests: Vec<SubprocessRequest>,
+        ) -> crate::core::Result<Vec<SubprocessOutcome>> {
+            Ok(requests
+                .into_iter()
+                .enumerate()
+                .map(|(idx, request)| SubprocessOutcome {
+                    session_id: format!("subprocess-{}", request.domain),
+                    step_id: idx,
+                    candidate_solutions: vec![request.step; request.samples],
+                    winning_solution: request.context_json,
+                    metrics: None,
+                })
+                .collect())
+        }
+
+        async fn get_session(
+            &self,
+            _session_id: &str,
+        ) -> crate::core::Result<Option<SessionDetail>> {
+            unimplemented!()
+        }
+
+        async fn add_session_note(
+            &self,
+            _session_id: &str,
+            _note: &str,
+        ) -> crate::core::Result<Option<SessionNote>> {
+            unimplemented!()
+        }
+
+        async fn get_step(
+            &self,
+            _session_id: &str,
+            _step_id: usize,
+        ) -> crate::core::Result<Option<StepDetail>> {
+            unimplemented!()
+        }
+
+        async fn list_sessions(&self, _limit: usize) -> crate::core::Result<Vec<SessionSummary>> {
+            unimplemented!()
+        }
+
+        async fn search_sessions(
+            &self,
+            _query: &str,
+            _limit: usize,
+        ) -> crate::core::Result<Vec<SessionSummary>> {
+            unimplemented!()
+        }
+
+        async fn export_sessions(
+            &self,
+            _limit: Option<usize>,
+            _sink: &mut SessionExportSink<'_>,
+        ) -> crate::core::Result<usize> {
+            unimplemented!()
+        }
+
+        async fn dry_run_probe(
+            &self,
+            _request: &RunSessionRequest,
+        ) -> crate::core::Result<DryRunResult> {
+            unimplemented!()
+        }
+
+        async fn check_run_config(
+            &self,
+            _request: &RunSessionRequest,
+        ) -> crate::core::Result<ConfigCheck> {
+            unimplemented!()
+        }
+
+        /// Domain `broken` fails verification; every other domain passes.
+        async fn verify(&self, request: VerifyRequest) -> crate::core::Result<VerifyOutcome> {
+            let passed = request.domain != "broken";
+            Ok(VerifyOutcome {
+                domain: request.domain,
+                command: "./verify.sh".into(),
+                passed,
+                status: Some(if passed { 0 } else { 1 }),
+                stdout: "checked 3 files".into(),
+                stderr: String::new(),
+            })
+        }
+
+        /// `openai` lists two models; every other provider has no listing.
+        async fn list_models(
+            &self,
+            request: ListModelsRequest,
+        ) -> crate::core::Result<Vec<String>> {
+            match request.llm_provider.as_str() {
+                "openai" => Ok(vec!["gpt-4o-mini".into(), "gpt-5.1".into()]),
+                other => Err(CoreError::LlmProvider {
+                    provider: other.into(),
+                    details: "no model list endpoint".into(),
+                    retryable: false,
+                }),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn run_json_prints_parseable_outcome() {
+        let cli = Cli::try_parse_from([
+            "microfactory",
+            "run",
+            "--prompt",
+            "Fix it",
+            "--domain",
+            "code",
+            "--json",
+        ])
+        .unwrap();
+        let Commands::Run(args) = cli.command else {
+            panic!("expected run command");
+        };
+        let adapter = CliAdapter::new(Arc::new(CompletedRunService));
+
+        let mut out = Vec::new();
+        adapter
+            .run_session_with_output(&args, &mut out)
+            .await
+            .unwrap();
+
+        let value: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(value["session_id"], "session-json");
+        assert_eq!(value["completed"], true);
+        assert_eq!(value["paused"], false);
+        assert_eq!(value["metrics"]["completed_steps"], 3);
+        assert_eq!(value["metrics"]["samples"], 12);
+    }
+
+    #[tokio::test]
+    async fn resume_policy_decides_what_a_paused_run_does() {
+        let spawned = Arc::new(std::sync::Mutex::new(Vec::<String>::new()));
+        let recorder = spawned.clone();
+        let adapter = CliAdapter::new(Arc::new(CompletedRunService)).with_resume_spawner(Arc::new(
+            move |session_id: &str| {
+                recorder.lock().unwrap().push(session_id.to_string());
+                Ok(())
+            },
+        ));
+        let run = |domain: &str, policy: &str| {
+            let cli = Cli::try_parse_from([
+                "microfactory",
+                "run",
+                "--prompt",
+                "Fix it",
+                "--domain",
+                domain,
+                "--resume-policy",
+                policy,
+            ])
+            .unwrap();
+            let Commands::Run(args) = cli.command else {
+                panic!("expected run command");
+            };
+            args
+        };
+
+        let mut out = Vec::new();
+        adapter
+            .run_session_with_output(&run("pausing", "manual"), &mut out)
+            .await
+            .expect("manual leaves the pause for an explicit resume");
+        assert!(spawned.lock().unwrap().is_empty());
+
+        adapter
+            .run_session_with_output(&run("pausing", "auto"), &mut out)
+            .await
+            .expect("auto resumes in the background");
+        assert_eq!(spawned.lock().unwrap().as_slice(), ["session-json"]);
+
+        let err = adapter
+            .run_session_with_output(&run("pausing", "fail"), &mut out)
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("paused at step 2 (low_margin) and --resume-policy is fail"),
+            "{err}"
+        );
+
+        // Runs that never pause ignore the policy.
+        adapter
+            .run_session_with_output(&run("code", "fail"), &mut out)
+            .await
+            .unwrap();
+        adapter
+            .run_session_with_output(&run("code", "auto"), &mut out)
+            .await
+            .unwrap();
+        assert_eq!(spawned.lock().unwrap().len(), 1);
+
+        // An unsaved session has nothing to resume, so auto is refused before the run starts.
+        let mut ephemeral = run("pausing", "auto");
+        ephemeral.no_persist = true;
+        let err = adapter
+            .run_session_with_output(&ephemeral, &mut out)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("--no-persist"), "{err}");
+        assert_eq!(spawned.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn subprocess_batch_returns_one_outcome_per_item() {
+        let cli = Cli::try_parse_from(["microfactory", "subprocess", "--batch", "--samples", "3"])
+            .unwrap();
+        let Commands::Subprocess(args) = cli.command else {
+            panic!("expected subprocess command");
+        };
+        let adapter = CliAdapter::new(Arc::new(CompletedRunService));
+        let mut stdin = std::io::Cursor::new(
+            r#"[
+                {"domain": "code", "step": "Fix the parser", "context_json": {"repo": "mf"}},
+                {"domain": "docs", "step": "Add a test", "context_json": "{}", "samples": 1}
+            ]"#,
+        );
+        let mut out = Vec::new();
+        adapter
+            .subprocess_batch_with_output(&args, &mut stdin, &mut out)
+            .await
+            .unwrap();
+
+        let outcomes: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        let outcomes = outcomes.as_array().unwrap();
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!(outcomes[0]["session_id"], "subprocess-code");
+        assert_eq!(
+            outcomes[0]["candidate_solutions"].as_array().unwrap().len(),
+            3
+        );
+        assert_eq!(outcomes[0]["winning_solution"], r#"{"repo":"mf"}"#);
+        assert_eq!(outcomes[1]["session_id"], "subprocess-docs");
+        assert_eq!(
+            outcomes[1]["candidate_solutions"].as_array().unwrap().len(),
+            1
+        );
+
+        let mut bad = std::io::Cursor::new(r#"{"domain": "code"}"#);
+        let err = adapter
+            .subprocess_batch_with_output(&args, &mut bad, &mut Vec::new())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("JSON array"), "{err}");
+        assert!(
+            Cli::try_parse_from(["microfactory", "subprocess", "--batch", "--step", "x"]).is_err()
+        );
+    }
+
+    #[test]
+    fn prompt_from_stdin_reads_the_whole_input() {
+        let cli = Cli::try_parse_from([
+            "microfactory",
+            "run",
+            "--prompt-from-stdin",
+            "--domain",
+            "code",
+        ])
+        .unwrap();
+        let Commands::Run(args) = cli.command else {
+            panic!("expected run command");
+        };
+        assert!(args.prompt_from_stdin);
+        assert!(args.prompt.is_none());
+
+        let mut stdin = std::io::Cursor::new("Fix the parser\nand add a test\n");
+        assert_eq!(
+            read_prompt(&mut stdin).unwrap(),
+            "Fix the parser\nand add a test"
+        );
+        let err = read_prompt(&mut std::io::Cursor::new(" \n")).unwrap_err();
+        assert!(err.to_string().contains("received no input"), "{err}");
+
+        for conflicting in [&["--prompt", "Fix it"][..], &["--interactive"][..]] {
+            let mut argv = vec![
+                "microfactory",
+                "run",
+                "--prompt-from-stdin",
+                "--domain",
+                "code",
+            ];
+            argv.extend_from_slice(conflicting);
+            assert!(Cli::try_parse_from(argv).is_err());
+        }
+        assert!(Cli::try_parse_from(["microfactory", "run", "--domain", "code"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn verify_exit_follows_verifier_result() {
+        let adapter = CliAdapter::new(Arc::new(CompletedRunService));
+        let args = |domain: &str| {
+            let cli = Cli::try_parse_from(["microfactory", "verify", "--domain", domain]).unwrap();
+            let Commands::Verify(args) = cli.command else {
+                panic!("expected verify command");
+            };
+            args
+        };
+
+        let mut out = Vec::new();
+        adapter
+            .verify_with_output(&args("code"), &mut out)
+            .await
+            .expect("passing verifier exits zero");
+        let printed = String::from_utf8(out).unwrap();
+        assert!(printed.starts_with("Verifier passed: ./verify.sh"));
+        assert!(printed.contains("checked 3 files"));
+
+        let mut out = Vec::new();
+        let err = adapter
+            .verify_with_output(&args("broken"), &mut out)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("exit status 1"), "{err}");
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .starts_with("Verifier FAILED")
+        );
+    }
+
+    #[test]
+    fn session_table_aligns_columns_and_truncates_prompts() {
+        let cli = Cli::try_parse_from(["microfactory", "status", "--format", "table"]).unwrap();
+        let Commands::Status(args) = cli.command else {
+            panic!("expected status command");
+        };
+        assert_eq!(args.format, StatusFormat::Table);
+
+        let summaries = vec![
+            SessionSummary {
+                session_id: "a1b2".into(),
+                domain: "code".into(),
+                prompt: "Fix the bug".into(),
+                status: "completed".into(),
+                pause_trigger: None,
+                updated_at: "1700000000".into(),
+            },
+            SessionSummary {
+                session_id: "c3d4".into(),
+                domain: "code".into(),
+                prompt: "Refactor the parser\nso that it ".repeat(5),
+                status: "paused".into(),
+                pause_trigger: Some("low_margin".into()),
+                updated_at: "1700000001".into(),
+            },
+        ];
+        let mut out = Vec::new();
+        write_session_table(&summaries, &mut out).unwrap();
+        let printed = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = printed.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("SESSION "), "{}", lines[0]);
+        let prompt_col = lines[0].find("PROMPT").unwrap();
+        assert_eq!(lines[1].find("Fix the bug"), Some(prompt_col));
+        let prompt = &lines[2][prompt_col..];
+        assert_eq!(prompt.chars().count(), 48);
+        assert!(prompt.ends_with('…'), "{prompt}");
+        assert!(!prompt.contains('\n'));
+    }
+
+    #[tokio::test]
+    async fn models_prints_the_ids_the_provider_lists() {
+        let adapter = CliAdapter::new(Arc::new(CompletedRunService));
+        let args = |argv: &[&str]| {
+            let cli =
+                Cli::try_parse_from([&["microfactory", "models"][..], argv].concat()).unwrap();
+            let Commands::Models(args) = cli.command else {
+                panic!("expected models command");
+            };
+            args
+        };
+
+        let mut out = Vec::new();
+        adapter
+            .models_with_output(&args(&["--llm-provider", "openai"]), &mut out)
+            .await
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "gpt-4o-mini\ngpt-5.1\n");
+
+        let mut out = Vec::new();
+        adapter
+            .models_with_output(&args(&["--json"]), &mut out)
+            .await
+            .unwrap();
+        let ids: Vec<String> = serde_json::from_slice(&out).unwrap();
+        assert_eq!(ids, vec!["gpt-4o-mini", "gpt-5.1"]);
+
+        let err = adapter
+            .models_with_output(&args(&["--llm-provider", "gemini"]), &mut Vec::new())
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Could not list models for 'gemini'"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn providers_lists_every_provider_with_its_env_var() {
+        let cli = Cli::try_parse_from(["microfactory", "providers", "--json"]).unwrap();
+        let Commands::Providers(args) = cli.command else {
+            panic!("expected providers command");
+        };
+
+        let mut out = Vec::new();
+        providers_with_output(&args, &mut out, &|var| var == "ANTHROPIC_API_KEY").unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        let rows = value.as_array().expect("provider array");
+        let expected = [
+            ("openai", "OPENAI_API_KEY", false),
+            ("anthropic", "ANTHROPIC_API_KEY", true),
+            ("gemini", "GEMINI_API_KEY", false),
+            ("grok", "XAI_API_KEY", false),
+        ];
+        assert_eq!(rows.len(), expected.len());
+        for (row, (name, env_var, set)) in rows.iter().zip(expected) {
+            assert_eq!(row["name"], name);
+            assert_eq!(row["env_var"], env_var);
+            assert_eq!(row["set"], set);
         }
+
+        let mut out = Vec::new();
+        let text_args = ProvidersArgs { json: false };
+        providers_with_output(&text_args, &mut out, &|_| false).unwrap();
+        let printed = String::from_utf8(out).unwrap();
+        for (name, env_var, _) in expected {
+            let line = printed
+                .lines()
+                .find(|line| line.starts_with(name))
+                .unwrap_or_else(|| panic!("missing {name}"));
+            assert!(
+                line.contains(env_var) && line.ends_with("not set"),
+                "{line}"
+            );
+        }
+    }
+
+    /// Answers every run with a solution named after the requested model.
+    struct PerModelService {
+        requests: std::sync::Mutex<Vec<RunSessionRequest>>,
+    }
+
+    #[async_trait]
+    impl WorkflowService for PerModelService {
+        async fn run_session(
+            &self,
+            request: RunSessionRequest,
+        ) -> crate::core::Result<SessionOutcome> {
+            let model = request.llm_model.clone();
+            self.requests.lock().unwrap().push(request);
+            if model == "broken" {
+                return Err(CoreError::System("model unavailable".into()));
+            }
+            Ok(SessionOutcome {
+                session_id: format!("session-{model}"),
+                completed: true,
+                paused: false,
+                pause_reason: None,
+                failed_steps: Vec::new(),
+                metrics: SessionRunMetrics {
+                    total_steps: 2,
+                    completed_steps: 2,
+                    samples: model.len(),
+                    ..SessionRunMetrics::default()
+                },
+                recursion: Vec::new(),
+                solutions: vec![StepSolution {
+                    step_id: 1,
+                    description: "fix it".into(),
+                    solution: format!("patch from {model}"),
+                }],
+                apply_previews: Vec::new(),
+            })
+        }
+
+        async fn resume_session(
+            &self,
+            _request: ResumeSessionRequest,
+        ) -> crate::core::Result<SessionOutcome> {
+            unimplemented!()
+        }
+
+        async fn run_subprocess(
+            &self,
+            _request: SubprocessRequest,
+        ) -> crate::core::Result<SubprocessOutcome> {
+            unimplemented!()
+        }
+
+        async fn run_subprocess_batch(
+            &self,
+            _requests: Vec<SubprocessRequest>,
+        ) -> crate::core::Result<Vec<SubprocessOutcome>> {
+            unimplemented!()
+        }
+
+        async fn get_session(
+            &self,
+            _session_id: &str,
+        ) -> crate::core::Result<Option<SessionDetail>> {
+            unimplemented!()
+        }
+
+        async fn add_session_note(
+            &self,
+            _session_id: &str,
+            _note: &str,
+        ) -> crate::core::Result<Option<SessionNote>> {
+            unimplemented!()
+        }
+
+        async fn get_step(
+            &self,
+            _session_id: &str,
+            _step_id: usize,
+        ) -> crate::core::Result<Option<StepDetail>> {
+            unimplemented!()
+        }
+
+        async fn list_sessions(&self, _limit: usize) -> crate::core::Result<Vec<SessionSummary>> {
+            unimplemented!()
+        }
+
+        async fn search_sessions(
+            &self,
+            _query: &str,
+            _limit: usize,
+        ) -> crate::core::Result<Vec<SessionSummary>> {
+            unimplemented!()
+        }
+
+        async fn export_sessions(
+            &self,
+            _limit: Option<usize>,
+            _sink: &mut SessionExportSink<'_>,
+        ) -> crate::core::Result<usize> {
+            unimplemented!()
+        }
+
+        async fn dry_run_probe(
+            &self,
+            _request: &RunSessionRequest,
+        ) -> crate::core::Result<DryRunResult> {
+            unimplemented!()
+        }
+
+        async fn check_run_config(
+            &self,
+            _request: &RunSessionRequest,
+        ) -> crate::core::Result<ConfigCheck> {
+            unimplemented!()
+        }
+
+        async fn verify(&self, _request: VerifyRequest) -> crate::core::Result<VerifyOutcome> {
+            unimplemented!()
+        }
+
+        async fn list_models(
+            &self,
+            _request: ListModelsRequest,
+        ) -> crate::core::Result<Vec<String>> {
+            unimplemented!()
+        }
+    }
+
+    #[tokio::test]
+    async fn compare_runs_every_model_and_reports_side_by_side() {
+        let service = Arc::new(PerModelService {
+            requests: std::sync::Mutex::new(Vec::new()),
+        });
+        let adapter = CliAdapter::new(service.clone());
+        let args = |extra: &[&str]| {
+            let mut argv = vec![
+                "microfactory",
+                "compare",
+                "--prompt",
+                "fix it",
+                "--domain",
+                "code",
+                "--models",
+                "openai:gpt-a,anthropic:claude-b",
+            ];
+            argv.extend(extra);
+            let cli = Cli::try_parse_from(argv).unwrap();
+            let Commands::Compare(args) = cli.command else {
+                panic!("expected compare command");
+            };
+            args
+        };
+
+        let mut out = Vec::new();
+        adapter
+            .compare_with_output(&args(&["--json"]), &mut out)
+            .await
+            .unwrap();
+        let report: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        let runs = report["runs"].as_array().expect("runs array");
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0]["model"], "openai:gpt-a");
+        assert_eq!(runs[0]["session_id"], "session-gpt-a");
+        assert_eq!(runs[0]["solutions"][0]["solution"], "patch from gpt-a");
+        assert_eq!(runs[1]["model"], "anthropic:claude-b");
+        assert_eq!(runs[1]["metrics"]["samples"], 8);
+        {
+            let requests = service.requests.lock().unwrap();
+            let targets: Vec<_> = requests
+                .iter()
+                .map(|r| (r.llm_provider.as_str(), r.llm_model.as_str()))
+                .collect();
+            assert_eq!(targets, [("openai", "gpt-a"), ("anthropic", "claude-b")]);
+            assert_eq!(
+                requests[0].output_dir.as_deref(),
+                Some(std::path::Path::new("compare/{session_id}"))
+            );
+        }
+
+        let mut cli_args = args(&[]);
+        cli_args.models[1].model = "broken".into();
+        let mut out = Vec::new();
+        adapter
+            .compare_with_output(&cli_args, &mut out)
+            .await
+            .expect("a failing model is reported, not fatal");
+        let printed = String::from_utf8(out).unwrap();
+        let status = printed
+            .lines()
+            .find(|line| line.starts_with("status"))
+            .unwrap();
+        assert!(
+            status.contains("completed") && status.contains("error"),
+            "{status}"
+        );
+        assert!(printed.contains("=== openai:gpt-a ===\n--- step 1: fix it ---\npatch from gpt-a"));
+        assert!(printed.contains("error: "), "{printed}");
     }
 }
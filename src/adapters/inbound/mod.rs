@@ -5,4 +5,4 @@ pub mod cli;
 pub mod server;
 
 pub use cli::{Cli, CliAdapter, Commands, InspectMode, LlmProvider, RunArgs, ServeArgs};
-pub use server::{ServeOptions, ServerAdapter};
+pub use server::{JsonCase, ServeOptions, ServerAdapter, normalize_base_path};
@@ -1,37 +1,64 @@
 //! HTTP server inbound adapter that exposes session data via REST and SSE.
 
 use std::{
+    collections::{HashMap, VecDeque},
     convert::Infallible,
     net::SocketAddr,
-    sync::Arc,
-    time::{Duration, Instant},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use anyhow::{Context as AnyhowContext, Result};
+use anyhow::{Context as AnyhowContext, Result, bail};
 use axum::response::sse::{Event, KeepAlive};
 use axum::{
     Json, Router,
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::{IntoResponse, Sse},
     routing::{get, post},
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json;
 use tokio::net::TcpListener;
 use tokio_stream::{StreamExt, wrappers::IntervalStream};
+use tower_http::compression::CompressionLayer;
 use tracing::info;
 
 use crate::{
-    core::ports::{SessionDetail, WorkflowService},
+    adapters::outbound::process::spawn_background_resume,
+    core::{
+        error::Error as CoreError,
+        ports::{
+            SessionDetail, SessionNote, SessionSummary, SessionTiming, StepDetail, WorkflowService,
+        },
+    },
     status_export::{SessionListExport, SessionSummaryExport},
 };
 
+/// Default floor for the SSE and transition polling interval.
+pub const DEFAULT_MIN_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Lowest floor `min_poll_interval` may be set to, so polling never spins.
+const HARD_MIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
 /// Configuration options for the server adapter.
 #[derive(Debug, Clone)]
 pub struct ServeOptions {
     pub default_limit: usize,
     pub poll_interval: Duration,
+    /// Floor applied to `poll_interval`; itself never below 10ms.
+    pub min_poll_interval: Duration,
+    /// SSE `event:` name attached to every session snapshot.
+    pub sse_event_name: String,
+    /// How many status transitions `GET /recent` keeps before dropping the oldest.
+    pub recent_capacity: usize,
+    /// Prefix every route is mounted under (e.g. `/microfactory` behind a reverse proxy).
+    pub base_path: Option<String>,
+    /// Field naming of every JSON response body and SSE payload.
+    pub json_case: JsonCase,
+    /// Maximum lifetime of one SSE connection; the stream ends at the first poll tick after it.
+    /// `None` keeps streams open until the client disconnects.
+    pub sse_idle_timeout: Option<Duration>,
 }
 
 impl Default for ServeOptions {
@@ -39,10 +66,103 @@ impl Default for ServeOptions {
         Self {
             default_limit: 25,
             poll_interval: Duration::from_secs(1),
+            min_poll_interval: DEFAULT_MIN_POLL_INTERVAL,
+            sse_event_name: "sessions".into(),
+            recent_capacity: 50,
+            base_path: None,
+            json_case: JsonCase::default(),
+            sse_idle_timeout: None,
+        }
+    }
+}
+
+/// Field naming used for JSON responses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsonCase {
+    /// Rust field names as-is (`session_id`).
+    #[default]
+    Snake,
+    /// camelCase keys (`sessionId`) for clients that expect JavaScript-style names.
+    Camel,
+}
+
+impl JsonCase {
+    /// Serialize `value`, renaming its field names when camelCase is selected.
+    fn to_value<T: Serialize>(self, value: &T) -> serde_json::Result<serde_json::Value> {
+        let value = serde_json::to_value(value)?;
+        Ok(match self {
+            JsonCase::Snake => value,
+            JsonCase::Camel => camelize_keys(value),
+        })
+    }
+}
+
+/// Fields whose contents are data rather than schema (template variables, flagger params,
+/// tallies keyed by role or flagger name); the field is renamed but its contents are not.
+const VERBATIM_FIELDS: &[&str] = &[
+    "prompt_vars",
+    "domain_data",
+    "params",
+    "rendered_prompts",
+    "red_flags_by_flagger",
+    "stage_durations_ms",
+    "vote_orders",
+];
+
+/// Maps keyed by data (step ids, agent kinds) whose values are schema objects.
+const DATA_KEYED_FIELDS: &[&str] = &["per_step", "vote_history"];
+
+fn camelize_keys(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => serde_json::Value::Object(
+            map.into_iter()
+                .map(|(key, value)| {
+                    let value = if VERBATIM_FIELDS.contains(&key.as_str()) {
+                        value
+                    } else if DATA_KEYED_FIELDS.contains(&key.as_str()) {
+                        camelize_values(value)
+                    } else {
+                        camelize_keys(value)
+                    };
+                    (snake_to_camel(&key), value)
+                })
+                .collect(),
+        ),
+        serde_json::Value::Array(items) => {
+            serde_json::Value::Array(items.into_iter().map(camelize_keys).collect())
         }
+        other => other,
     }
 }
 
+/// Keep the keys of a data-keyed map and camelize the objects it holds.
+fn camelize_values(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => serde_json::Value::Object(
+            map.into_iter()
+                .map(|(key, value)| (key, camelize_keys(value)))
+                .collect(),
+        ),
+        other => camelize_keys(other),
+    }
+}
+
+fn snake_to_camel(key: &str) -> String {
+    let mut out = String::with_capacity(key.len());
+    let mut upper_next = false;
+    for ch in key.chars() {
+        if ch == '_' && !out.is_empty() {
+            upper_next = true;
+        } else if upper_next {
+            out.push(ch.to_ascii_uppercase());
+            upper_next = false;
+        } else {
+            out.push(ch);
+        }
+    }
+    out
+}
+
 /// Server adapter that exposes the `WorkflowService` via HTTP.
 pub struct ServerAdapter {
     service: Arc<dyn WorkflowService>,
@@ -64,10 +184,12 @@ impl ServerAdapter {
 
     /// Run the HTTP server with an existing listener (useful for tests).
     pub async fn run_with_listener(self, listener: TcpListener) -> Result<()> {
-        let state = Arc::new(ServeState::new(self.service, self.options));
+        let state = Arc::new(ServeState::new(self.service, self.options)?);
+        tokio::spawn(watch_transitions(state.clone()));
+        let base_path = state.base_path.clone().unwrap_or_default();
         let router = build_router(state);
         if let Ok(addr) = listener.local_addr() {
-            info!(%addr, "microfactory serve listening");
+            info!(%addr, base_path, "microfactory serve listening");
         } else {
             info!("microfactory serve listening");
         }
@@ -82,15 +204,60 @@ struct ServeState {
     service: Arc<dyn WorkflowService>,
     default_limit: usize,
     poll_interval: Duration,
+    sse_event_name: String,
+    base_path: Option<String>,
+    json_case: JsonCase,
+    sse_idle_timeout: Option<Duration>,
+    history: Arc<Mutex<TransitionHistory>>,
 }
 
 impl ServeState {
-    fn new(service: Arc<dyn WorkflowService>, options: ServeOptions) -> Self {
-        Self {
+    fn new(service: Arc<dyn WorkflowService>, options: ServeOptions) -> Result<Self> {
+        let base_path = match options.base_path.as_deref() {
+            Some(raw) => normalize_base_path(raw)?,
+            None => None,
+        };
+        Ok(Self {
             service,
             default_limit: options.default_limit.max(1),
-            poll_interval: options.poll_interval.max(Duration::from_millis(200)),
-        }
+            poll_interval: options
+                .poll_interval
+                .max(options.min_poll_interval.max(HARD_MIN_POLL_INTERVAL)),
+            sse_event_name: options.sse_event_name,
+            base_path,
+            json_case: options.json_case,
+            sse_idle_timeout: options.sse_idle_timeout,
+            history: Arc::new(Mutex::new(TransitionHistory::new(options.recent_capacity))),
+        })
+    }
+
+    /// Take one snapshot of recent sessions and record any status changes since the last one.
+    async fn poll_transitions(&self) -> Result<()> {
+        let export = self.list_sessions(self.default_limit).await?;
+        let observed_at_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        self.history
+            .lock()
+            .map_err(|_| anyhow::anyhow!("transition history lock poisoned"))?
+            .observe(&export.sessions, observed_at_ms);
+        Ok(())
+    }
+
+    fn recent_transitions(&self) -> Vec<StatusTransition> {
+        self.history
+            .lock()
+            .map(|history| history.transitions.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Response body for `payload` in the configured field naming.
+    fn json<T: Serialize>(&self, payload: &T) -> Result<Json<serde_json::Value>, StatusCode> {
+        self.json_case.to_value(payload).map(Json).map_err(|err| {
+            tracing::error!(error = %err, "failed to serialize response");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
     }
 
     fn limit_or_default(&self, value: Option<usize>) -> usize {
@@ -103,22 +270,16 @@ impl ServeState {
             .list_sessions(limit)
             .await
             .map_err(|e| anyhow::anyhow!("{e}"))?;
+        Ok(summaries_export(summaries))
+    }
 
-        // Convert to export format for backward compatibility
-        let export_summaries: Vec<SessionSummaryExport> = summaries
-            .into_iter()
-            .map(|s| SessionSummaryExport {
-                session_id: s.session_id,
-                status: s.status,
-                prompt: s.prompt,
-                domain: s.domain,
-                updated_at: s.updated_at.parse().unwrap_or(0),
-            })
-            .collect();
-
-        Ok(SessionListExport {
-            sessions: export_summaries,
-        })
+    async fn search_sessions(&self, query: &str, limit: usize) -> Result<SessionListExport> {
+        let summaries = self
+            .service
+            .search_sessions(query, limit)
+            .await
+            .map_err(|e| anyhow::anyhow!("{e}"))?;
+        Ok(summaries_export(summaries))
     }
 
     async fn load_session(&self, session_id: &str) -> Result<Option<SessionDetail>> {
@@ -128,30 +289,157 @@ impl ServeState {
             .map_err(|e| anyhow::anyhow!("{e}"))
     }
 
+    async fn load_step(&self, session_id: &str, step_id: usize) -> Result<Option<StepDetail>> {
+        self.service
+            .get_step(session_id, step_id)
+            .await
+            .map_err(|e| anyhow::anyhow!("{e}"))
+    }
+
+    async fn add_note(
+        &self,
+        session_id: &str,
+        note: &str,
+    ) -> crate::core::Result<Option<SessionNote>> {
+        self.service.add_session_note(session_id, note).await
+    }
+
     fn resume_session(&self, session_id: &str) -> Result<bool> {
         // Note: Resume spawns a background process, so we use the CLI approach
         // This is a special case where we spawn a new process rather than using the service directly
-        let exe = std::env::current_exe().context("Failed to determine current executable path")?;
+        spawn_background_resume(session_id)?;
+        Ok(true)
+    }
+}
 
-        info!(session_id, "Spawning background resume process");
-        std::process::Command::new(exe)
-            .arg("resume")
-            .arg("--session-id")
-            .arg(session_id)
-            .spawn()
-            .context("Failed to spawn resume process")?;
+/// Convert service summaries to the export format for backward compatibility.
+fn summaries_export(summaries: Vec<SessionSummary>) -> SessionListExport {
+    SessionListExport {
+        sessions: summaries
+            .into_iter()
+            .map(|s| SessionSummaryExport {
+                session_id: s.session_id,
+                status: s.status,
+                pause_trigger: s.pause_trigger,
+                prompt: s.prompt,
+                domain: s.domain,
+                updated_at: s.updated_at.parse().unwrap_or(0),
+            })
+            .collect(),
+    }
+}
 
-        Ok(true)
+/// A session status change seen between two background polls.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusTransition {
+    pub session_id: String,
+    /// Previous status; `None` when the session first appeared after the server started.
+    pub from: Option<String>,
+    pub to: String,
+    pub observed_at_ms: u64,
+}
+
+/// Payload returned by `GET /recent`, oldest transition first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentTransitions {
+    pub transitions: Vec<StatusTransition>,
+}
+
+/// Ring buffer of status transitions plus the last status seen per session.
+struct TransitionHistory {
+    capacity: usize,
+    primed: bool,
+    last_seen: HashMap<String, String>,
+    transitions: VecDeque<StatusTransition>,
+}
+
+impl TransitionHistory {
+    fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            capacity,
+            primed: false,
+            last_seen: HashMap::new(),
+            transitions: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Diff `sessions` against the previous snapshot. The first snapshot only sets the baseline.
+    fn observe(&mut self, sessions: &[SessionSummaryExport], observed_at_ms: u64) {
+        for session in sessions {
+            let previous = self
+                .last_seen
+                .insert(session.session_id.clone(), session.status.clone());
+            if !self.primed || previous.as_deref() == Some(session.status.as_str()) {
+                continue;
+            }
+            if self.transitions.len() == self.capacity {
+                self.transitions.pop_front();
+            }
+            self.transitions.push_back(StatusTransition {
+                session_id: session.session_id.clone(),
+                from: previous,
+                to: session.status.clone(),
+                observed_at_ms,
+            });
+        }
+        self.primed = true;
+    }
+}
+
+/// Background task feeding `GET /recent`; runs for the lifetime of the server.
+async fn watch_transitions(state: Arc<ServeState>) {
+    let mut interval = tokio::time::interval(state.poll_interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        if let Err(err) = state.poll_transitions().await {
+            tracing::error!(error = %err, "serve failed to poll session transitions");
+        }
     }
 }
 
+/// Turn `api/`, `/api`, or `/api/` into `/api`; a blank or bare `/` prefix means no nesting.
+/// Segments must be literal: empty segments and the router's capture syntax (`:`, `*`, `{`,
+/// `}`) are rejected, since nesting under them would panic when the router is built.
+pub fn normalize_base_path(raw: &str) -> Result<Option<String>> {
+    let trimmed = raw.trim().trim_matches('/');
+    if trimmed.is_empty() {
+        return Ok(None);
+    }
+    for segment in trimmed.split('/') {
+        if segment.is_empty() {
+            bail!("invalid --base-path '{raw}': empty path segment");
+        }
+        if segment.contains([':', '*', '{', '}']) {
+            bail!(
+                "invalid --base-path '{raw}': segment '{segment}' may not contain ':', '*', '{{' or '}}'"
+            );
+        }
+    }
+    Ok(Some(format!("/{trimmed}")))
+}
+
 fn build_router(state: Arc<ServeState>) -> Router {
-    Router::new()
+    let routes = Router::new()
         .route("/sessions", get(list_sessions_handler))
         .route("/sessions/{id}", get(session_detail_handler))
         .route("/sessions/{id}/resume", post(resume_session_handler))
+        .route("/sessions/{id}/notes", post(add_note_handler))
+        .route("/sessions/{id}/timing", get(session_timing_handler))
+        .route("/sessions/{id}/steps/{step_id}", get(step_detail_handler))
         .route("/sessions/stream", get(stream_sessions_handler))
+        .route("/sessions/search", get(search_sessions_handler))
+        .route("/recent", get(recent_transitions_handler));
+    let routes = match &state.base_path {
+        Some(prefix) => Router::new().nest(prefix, routes),
+        None => routes,
+    };
+    // Gzip responses for clients that accept it. The default predicate skips
+    // `text/event-stream`, so SSE frames still reach the client as soon as they are sent.
+    routes
         .with_state(state)
+        .layer(CompressionLayer::new().gzip(true))
 }
 
 #[derive(Deserialize)]
@@ -162,25 +450,71 @@ struct ListQuery {
 async fn list_sessions_handler(
     State(state): State<Arc<ServeState>>,
     Query(query): Query<ListQuery>,
-) -> Result<Json<SessionListExport>, StatusCode> {
+) -> Result<Json<serde_json::Value>, StatusCode> {
     let limit = state.limit_or_default(query.limit);
-    state
+    let export = state
         .list_sessions(limit)
         .await
-        .map(Json)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.json(&export)
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    q: String,
+    limit: Option<usize>,
+}
+
+async fn search_sessions_handler(
+    State(state): State<Arc<ServeState>>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let limit = state.limit_or_default(query.limit);
+    let export = state
+        .search_sessions(&query.q, limit)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.json(&export)
 }
 
 async fn session_detail_handler(
     Path(session_id): Path<String>,
     State(state): State<Arc<ServeState>>,
-) -> Result<Json<SessionDetail>, StatusCode> {
+) -> Result<Json<serde_json::Value>, StatusCode> {
     match state
         .load_session(&session_id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
     {
-        Some(detail) => Ok(Json(detail)),
+        Some(detail) => state.json(&detail),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+async fn session_timing_handler(
+    Path(session_id): Path<String>,
+    State(state): State<Arc<ServeState>>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    match state
+        .load_session(&session_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        Some(detail) => state.json(&SessionTiming::new(detail.session_id, detail.stage_timings)),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+async fn step_detail_handler(
+    Path((session_id, step_id)): Path<(String, usize)>,
+    State(state): State<Arc<ServeState>>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    match state
+        .load_step(&session_id, step_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        Some(detail) => state.json(&detail),
         None => Err(StatusCode::NOT_FOUND),
     }
 }
@@ -213,13 +547,61 @@ async fn resume_session_handler(
     }
 }
 
-async fn stream_sessions_handler(State(state): State<Arc<ServeState>>) -> impl IntoResponse {
+#[derive(Deserialize)]
+struct NoteBody {
+    note: String,
+}
+
+async fn add_note_handler(
+    Path(session_id): Path<String>,
+    State(state): State<Arc<ServeState>>,
+    Json(body): Json<NoteBody>,
+) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, String)> {
+    match state.add_note(&session_id, &body.note).await {
+        Ok(Some(note)) => state
+            .json(&note)
+            .map(|json| (StatusCode::CREATED, json))
+            .map_err(|status| (status, "Failed to serialize note".into())),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Session not found".into())),
+        Err(CoreError::InvalidState(msg)) => Err((StatusCode::BAD_REQUEST, msg)),
+        Err(err) => Err((StatusCode::INTERNAL_SERVER_ERROR, err.to_string())),
+    }
+}
+
+async fn recent_transitions_handler(
+    State(state): State<Arc<ServeState>>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    state.json(&RecentTransitions {
+        transitions: state.recent_transitions(),
+    })
+}
+
+async fn stream_sessions_handler(
+    State(state): State<Arc<ServeState>>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
     let poll = state.poll_interval;
     let mut interval = tokio::time::interval(poll);
     interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
     let stream_state = state.clone();
-    let stream = IntervalStream::new(interval).then(move |_| {
+    // Browsers reconnect with `Last-Event-ID`; continue numbering after it so ids stay monotonic.
+    let mut next_id = last_event_id(&headers).map_or(0, |id| id.saturating_add(1));
+    // Dead browser tabs never disconnect on their own; bound each connection's lifetime so they
+    // cannot pile up. Live clients simply reconnect with `Last-Event-ID`.
+    let deadline = state
+        .sse_idle_timeout
+        .map(|timeout| Instant::now() + timeout);
+    let ticks = IntervalStream::new(interval).take_while(move |_| {
+        let open = deadline.is_none_or(|deadline| Instant::now() < deadline);
+        if !open {
+            tracing::debug!("closing SSE stream after its idle timeout");
+        }
+        open
+    });
+    let stream = ticks.then(move |_| {
         let state = stream_state.clone();
+        let event_id = next_id;
+        next_id = next_id.saturating_add(1);
         async move {
             let start = Instant::now();
             let payload = state
@@ -230,8 +612,15 @@ async fn stream_sessions_handler(State(state): State<Arc<ServeState>>) -> impl I
                 })
                 .ok();
             let event = if let Some(export) = payload {
-                match serde_json::to_string(&export) {
-                    Ok(json) => Event::default().data(json),
+                match state
+                    .json_case
+                    .to_value(&export)
+                    .and_then(|value| serde_json::to_string(&value))
+                {
+                    Ok(json) => Event::default()
+                        .event(state.sse_event_name.as_str())
+                        .id(event_id.to_string())
+                        .data(json),
                     Err(err) => {
                         tracing::error!(error = %err, "failed to serialize session export");
                         Event::default().comment("serialization_error")
@@ -251,26 +640,34 @@ async fn stream_sessions_handler(State(state): State<Arc<ServeState>>) -> impl I
     Sse::new(stream).keep_alive(KeepAlive::new().interval(poll).text("keep-alive"))
 }
 
+fn last_event_id(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::core::ports::{
-        DryRunResult, ResumeSessionRequest, RunSessionRequest, SessionMetadataInfo, SessionOutcome,
-        SessionSummary, SubprocessOutcome, SubprocessRequest,
+        ConfigCheck, DryRunResult, ListModelsRequest, ResumeSessionRequest, RunSessionRequest,
+        SessionExportSink, SessionMetadataInfo, SessionOutcome, SessionSummary, StageTiming,
+        SubprocessOutcome, SubprocessRequest, VerifyOutcome, VerifyRequest,
     };
     use async_trait::async_trait;
     use axum::body::Body;
     use tower::ServiceExt;
 
     struct MockWorkflowService {
-        sessions: Vec<SessionSummary>,
+        sessions: Mutex<Vec<SessionSummary>>,
         details: std::collections::HashMap<String, SessionDetail>,
     }
 
     impl MockWorkflowService {
         fn new() -> Self {
             Self {
-                sessions: vec![],
+                sessions: Mutex::new(vec![]),
                 details: std::collections::HashMap::new(),
             }
         }
@@ -281,9 +678,10 @@ mod tests {
                 domain: "code".to_string(),
                 prompt: "test prompt".to_string(),
                 status: status.to_string(),
+                pause_trigger: None,
                 updated_at: "12345".to_string(),
             };
-            self.sessions.push(summary);
+            self.sessions.get_mut().unwrap().push(summary);
 
             let detail = SessionDetail {
                 session_id: id.to_string(),
@@ -292,6 +690,10 @@ mod tests {
                 status: status.to_string(),
                 updated_at: "12345".to_string(),
                 steps_completed: 0,
+                partial: status == "running",
+                steps_running: 0,
+                top_flaggers: Vec::new(),
+                stage_timings: Vec::new(),
                 wait_state: None,
                 metadata: SessionMetadataInfo {
                     config_path: "config.yaml".to_string(),
@@ -300,10 +702,18 @@ mod tests {
                     samples: 2,
                     k: 2,
                 },
+                notes: Vec::new(),
             };
             self.details.insert(id.to_string(), detail);
             self
         }
+
+        fn set_status(&self, id: &str, status: &str) {
+            let mut sessions = self.sessions.lock().unwrap();
+            if let Some(summary) = sessions.iter_mut().find(|s| s.session_id == id) {
+                summary.status = status.to_string();
+            }
+        }
     }
 
     #[async_trait]
@@ -329,6 +739,13 @@ mod tests {
             unimplemented!()
         }
 
+        async fn run_subprocess_batch(
+            &self,
+            _requests: Vec<SubprocessRequest>,
+        ) -> crate::core::Result<Vec<SubprocessOutcome>> {
+            unimplemented!()
+        }
+
         async fn get_session(
             &self,
             session_id: &str,
@@ -336,8 +753,63 @@ mod tests {
             Ok(self.details.get(session_id).cloned())
         }
 
+        async fn add_session_note(
+            &self,
+            session_id: &str,
+            note: &str,
+        ) -> crate::core::Result<Option<SessionNote>> {
+            if note.trim().is_empty() {
+                return Err(CoreError::InvalidState(
+                    "Session notes cannot be empty".into(),
+                ));
+            }
+            Ok(self.details.contains_key(session_id).then(|| SessionNote {
+                note: note.to_string(),
+                created_at: "12345".to_string(),
+            }))
+        }
+
+        async fn get_step(
+            &self,
+            _session_id: &str,
+            _step_id: usize,
+        ) -> crate::core::Result<Option<StepDetail>> {
+            Ok(None)
+        }
+
         async fn list_sessions(&self, limit: usize) -> crate::core::Result<Vec<SessionSummary>> {
-            Ok(self.sessions.iter().take(limit).cloned().collect())
+            Ok(self
+                .sessions
+                .lock()
+                .unwrap()
+                .iter()
+                .take(limit)
+                .cloned()
+                .collect())
+        }
+
+        async fn search_sessions(
+            &self,
+            query: &str,
+            limit: usize,
+        ) -> crate::core::Result<Vec<SessionSummary>> {
+            Ok(self
+                .sessions
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|s| s.prompt.contains(query))
+                .take(limit)
+                .cloned()
+                .collect())
+        }
+
+        async fn export_sessions(
+            &self,
+            _limit: Option<usize>,
+            _sink: &mut SessionExportSink<'_>,
+        ) -> crate::core::Result<usize> {
+            unimplemented!()
         }
 
         async fn dry_run_probe(
@@ -346,12 +818,30 @@ mod tests {
         ) -> crate::core::Result<DryRunResult> {
             unimplemented!()
         }
+
+        async fn check_run_config(
+            &self,
+            _request: &RunSessionRequest,
+        ) -> crate::core::Result<ConfigCheck> {
+            unimplemented!()
+        }
+
+        async fn verify(&self, _request: VerifyRequest) -> crate::core::Result<VerifyOutcome> {
+            unimplemented!()
+        }
+
+        async fn list_models(
+            &self,
+            _request: ListModelsRequest,
+        ) -> crate::core::Result<Vec<String>> {
+            unimplemented!()
+        }
     }
 
     #[tokio::test]
     async fn list_endpoint_returns_sessions() {
         let service = Arc::new(MockWorkflowService::new().with_session("session-a", "running"));
-        let state = Arc::new(ServeState::new(service, ServeOptions::default()));
+        let state = Arc::new(ServeState::new(service, ServeOptions::default()).unwrap());
         let app = build_router(state);
         let response = app
             .oneshot(
@@ -365,10 +855,162 @@ mod tests {
         assert_eq!(response.status(), StatusCode::OK);
     }
 
+    #[tokio::test]
+    async fn search_endpoint_filters_by_prompt() {
+        let service = Arc::new(MockWorkflowService::new().with_session("session-a", "running"));
+        let state = Arc::new(ServeState::new(service, ServeOptions::default()).unwrap());
+        let app = build_router(state);
+        let search = |query: &str| {
+            let app = app.clone();
+            let uri = format!("/sessions/search?q={query}");
+            async move {
+                let response = app
+                    .oneshot(
+                        axum::http::Request::builder()
+                            .uri(uri)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                serde_json::from_slice::<SessionListExport>(&bytes).unwrap()
+            }
+        };
+
+        assert_eq!(search("prompt").await.sessions.len(), 1);
+        assert!(search("missing").await.sessions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn json_case_controls_response_field_names() {
+        let body = |json_case: JsonCase, uri: &'static str| async move {
+            let service = Arc::new(MockWorkflowService::new().with_session("session-a", "paused"));
+            let options = ServeOptions {
+                json_case,
+                ..ServeOptions::default()
+            };
+            let response = build_router(Arc::new(ServeState::new(service, options).unwrap()))
+                .oneshot(
+                    axum::http::Request::builder()
+                        .uri(uri)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            String::from_utf8(bytes.to_vec()).unwrap()
+        };
+
+        let snake = body(JsonCase::Snake, "/sessions").await;
+        assert!(snake.contains("\"session_id\""), "{snake}");
+        assert!(!snake.contains("sessionId"), "{snake}");
+
+        let camel = body(JsonCase::Camel, "/sessions").await;
+        assert!(camel.contains("\"sessionId\""), "{camel}");
+        assert!(camel.contains("\"updatedAt\""), "{camel}");
+        assert!(!camel.contains("session_id"), "{camel}");
+
+        // Nested objects are renamed too.
+        let detail = body(JsonCase::Camel, "/sessions/session-a").await;
+        assert!(detail.contains("\"stepsCompleted\""), "{detail}");
+        assert!(detail.contains("\"llmProvider\""), "{detail}");
+    }
+
+    #[test]
+    fn camel_case_leaves_user_data_keys_alone() {
+        let value = serde_json::json!({
+            "session_id": "s",
+            "prompt_vars": { "target_lang": "rust" },
+            "params": { "max_tokens": 3, "nested_opts": { "keep_me": true } },
+            "per_step": { "0": { "samples_requested": 2, "vote_orders": { "solution_vote": [] } } },
+        });
+
+        assert_eq!(
+            camelize_keys(value),
+            serde_json::json!({
+                "sessionId": "s",
+                "promptVars": { "target_lang": "rust" },
+                "params": { "max_tokens": 3, "nested_opts": { "keep_me": true } },
+                "perStep": { "0": { "samplesRequested": 2, "voteOrders": { "solution_vote": [] } } },
+            })
+        );
+    }
+
+    #[test]
+    fn poll_interval_respects_a_lowered_floor() {
+        let service = Arc::new(MockWorkflowService::new());
+        let effective = |poll_ms: u64, min_poll: Option<Duration>| {
+            let options = ServeOptions {
+                poll_interval: Duration::from_millis(poll_ms),
+                min_poll_interval: min_poll.unwrap_or(DEFAULT_MIN_POLL_INTERVAL),
+                ..ServeOptions::default()
+            };
+            ServeState::new(service.clone(), options)
+                .unwrap()
+                .poll_interval
+        };
+
+        assert_eq!(effective(50, None), DEFAULT_MIN_POLL_INTERVAL);
+        assert_eq!(
+            effective(50, Some(Duration::from_millis(20))),
+            Duration::from_millis(50)
+        );
+        assert_eq!(effective(1, Some(Duration::ZERO)), HARD_MIN_POLL_INTERVAL);
+    }
+
+    #[tokio::test]
+    async fn base_path_nests_every_route() {
+        let service = Arc::new(MockWorkflowService::new().with_session("session-a", "running"));
+        let options = ServeOptions {
+            base_path: Some("/api/".into()),
+            ..ServeOptions::default()
+        };
+        let app = build_router(Arc::new(ServeState::new(service, options).unwrap()));
+
+        let status_of = |uri: &'static str| {
+            let app = app.clone();
+            async move {
+                app.oneshot(
+                    axum::http::Request::builder()
+                        .uri(uri)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap()
+                .status()
+            }
+        };
+        assert_eq!(status_of("/api/sessions").await, StatusCode::OK);
+        assert_eq!(status_of("/api/sessions/session-a").await, StatusCode::OK);
+        assert_eq!(status_of("/api/sessions/stream").await, StatusCode::OK);
+        assert_eq!(status_of("/sessions").await, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn normalizes_base_paths() {
+        assert_eq!(normalize_base_path("api/").unwrap(), Some("/api".into()));
+        assert_eq!(normalize_base_path("/a/b").unwrap(), Some("/a/b".into()));
+        assert_eq!(normalize_base_path("/").unwrap(), None);
+        assert_eq!(normalize_base_path("").unwrap(), None);
+        for raw in ["/a//b", "/:id", "/api/*rest", "/{*rest}", "/v{n}"] {
+            let err = normalize_base_path(raw).unwrap_err().to_string();
+            assert!(err.contains("--base-path"), "{raw}: {err}");
+        }
+    }
+
     #[tokio::test]
     async fn detail_endpoint_returns_not_found_for_unknown() {
         let service = Arc::new(MockWorkflowService::new());
-        let state = Arc::new(ServeState::new(service, ServeOptions::default()));
+        let state = Arc::new(ServeState::new(service, ServeOptions::default()).unwrap());
         let app = build_router(state);
         let response = app
             .oneshot(
@@ -382,11 +1024,112 @@ mod tests {
         assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 
+    #[tokio::test]
+    async fn notes_endpoint_creates_notes_for_known_sessions() {
+        let service = Arc::new(MockWorkflowService::new().with_session("session-a", "completed"));
+        let state = Arc::new(ServeState::new(service, ServeOptions::default()).unwrap());
+        let app = build_router(state);
+        let post = |uri: &str, body: &str| {
+            axum::http::Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(post(
+                "/sessions/session-a/notes",
+                r#"{"note":"known flaky"}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let note: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(note["note"], "known flaky");
+
+        let status_of = |uri: &'static str, body: &'static str| {
+            let app = app.clone();
+            async move { app.oneshot(post(uri, body)).await.unwrap().status() }
+        };
+        assert_eq!(
+            status_of("/sessions/missing/notes", r#"{"note":"lost"}"#).await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status_of("/sessions/session-a/notes", r#"{"note":"  "}"#).await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn timing_endpoint_breaks_the_session_down_by_stage() {
+        let mut service = MockWorkflowService::new().with_session("session-a", "completed");
+        service.details.get_mut("session-a").unwrap().stage_timings = vec![
+            StageTiming {
+                stage: "solve".into(),
+                duration_ms: 1200,
+            },
+            StageTiming {
+                stage: "decomposition".into(),
+                duration_ms: 800,
+            },
+        ];
+        let state = Arc::new(ServeState::new(Arc::new(service), ServeOptions::default()).unwrap());
+        let app = build_router(state);
+        let get = |uri: &str| {
+            axum::http::Request::builder()
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(get("/sessions/session-a/timing"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let timing: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(timing["session_id"], "session-a");
+        assert_eq!(timing["total_duration_ms"], 2000);
+        assert_eq!(timing["stages"][0]["stage"], "solve");
+        assert_eq!(timing["stages"][1]["duration_ms"], 800);
+
+        let response = app.oneshot(get("/sessions/missing/timing")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn step_endpoint_returns_not_found_for_unknown() {
+        let service = Arc::new(MockWorkflowService::new().with_session("session-a", "completed"));
+        let state = Arc::new(ServeState::new(service, ServeOptions::default()).unwrap());
+        let app = build_router(state);
+        let response = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri("/sessions/session-a/steps/7")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
     #[tokio::test]
     async fn resume_endpoint_rejects_running_session() {
         let service =
             Arc::new(MockWorkflowService::new().with_session("running-session", "running"));
-        let state = Arc::new(ServeState::new(service, ServeOptions::default()));
+        let state = Arc::new(ServeState::new(service, ServeOptions::default()).unwrap());
         let app = build_router(state);
         let response = app
             .oneshot(
@@ -400,4 +1143,154 @@ mod tests {
             .unwrap();
         assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     }
+
+    #[tokio::test]
+    async fn stream_endpoint_names_events_and_resumes_ids() {
+        let service = Arc::new(MockWorkflowService::new().with_session("session-a", "running"));
+        let state = Arc::new(ServeState::new(service, ServeOptions::default()).unwrap());
+        let app = build_router(state);
+        let response = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri("/sessions/stream")
+                    .header("Last-Event-ID", "41")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut body = response.into_body().into_data_stream();
+        let chunk = body.next().await.unwrap().unwrap();
+        let frame = String::from_utf8(chunk.to_vec()).unwrap();
+        assert!(frame.contains("event: sessions\n"), "frame: {frame}");
+        assert!(frame.contains("id: 42\n"), "frame: {frame}");
+        assert!(frame.contains("data: "), "frame: {frame}");
+    }
+
+    #[tokio::test]
+    async fn stream_closes_after_the_idle_timeout() {
+        let service = Arc::new(MockWorkflowService::new().with_session("session-a", "running"));
+        let options = ServeOptions {
+            poll_interval: Duration::from_millis(20),
+            min_poll_interval: Duration::from_millis(20),
+            sse_idle_timeout: Some(Duration::from_millis(100)),
+            ..ServeOptions::default()
+        };
+        let app = build_router(Arc::new(ServeState::new(service, options).unwrap()));
+        let response = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri("/sessions/stream")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let started = Instant::now();
+        let mut body = response.into_body().into_data_stream();
+        let mut events = 0;
+        let drained = tokio::time::timeout(Duration::from_secs(5), async {
+            while let Some(chunk) = body.next().await {
+                if String::from_utf8(chunk.unwrap().to_vec())
+                    .unwrap()
+                    .contains("event: sessions")
+                {
+                    events += 1;
+                }
+            }
+        })
+        .await;
+        assert!(drained.is_ok(), "stream stayed open past the idle timeout");
+        assert!(started.elapsed() >= Duration::from_millis(90));
+        assert!(events >= 1, "snapshots are sent until the timeout");
+    }
+
+    #[tokio::test]
+    async fn gzip_clients_get_compressed_lists_but_plain_sse() {
+        let service = Arc::new(
+            MockWorkflowService::new()
+                .with_session("session-a", "running")
+                .with_session("session-b", "paused"),
+        );
+        let options = ServeOptions {
+            poll_interval: Duration::from_millis(20),
+            min_poll_interval: Duration::from_millis(20),
+            sse_idle_timeout: Some(Duration::from_millis(50)),
+            ..ServeOptions::default()
+        };
+        let app = build_router(Arc::new(ServeState::new(service, options).unwrap()));
+        let request = |uri: &str| {
+            axum::http::Request::builder()
+                .uri(uri)
+                .header("accept-encoding", "gzip")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request("/sessions")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-encoding"], "gzip");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body[..2], [0x1f, 0x8b], "body is a gzip stream");
+
+        let response = app.oneshot(request("/sessions/stream")).await.unwrap();
+        assert!(response.headers().get("content-encoding").is_none());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(String::from_utf8_lossy(&body).starts_with("event: sessions"));
+    }
+
+    #[tokio::test]
+    async fn recent_endpoint_lists_observed_transitions() {
+        let service = Arc::new(
+            MockWorkflowService::new()
+                .with_session("session-a", "running")
+                .with_session("session-b", "running"),
+        );
+        let state = Arc::new(ServeState::new(service.clone(), ServeOptions::default()).unwrap());
+
+        state.poll_transitions().await.unwrap();
+        service.set_status("session-a", "paused");
+        state.poll_transitions().await.unwrap();
+        service.set_status("session-a", "completed");
+        service.set_status("session-b", "failed");
+        state.poll_transitions().await.unwrap();
+
+        let app = build_router(state);
+        let response = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri("/recent")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let recent: RecentTransitions = serde_json::from_slice(&bytes).unwrap();
+        let summary: Vec<(&str, Option<&str>, &str)> = recent
+            .transitions
+            .iter()
+            .map(|t| (t.session_id.as_str(), t.from.as_deref(), t.to.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("session-a", Some("running"), "paused"),
+                ("session-a", Some("paused"), "completed"),
+                ("session-b", Some("running"), "failed"),
+            ]
+        );
+    }
 }
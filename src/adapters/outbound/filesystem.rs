@@ -1,4 +1,4 @@
-use std::{fs, path::Path};
+use std::{fs, io::Write, path::Path};
 
 use crate::core::ports::FileSystem;
 use crate::core::{Error, Result};
@@ -21,6 +21,15 @@ impl FileSystem for StdFileSystem {
         fs::write(path, content).map_err(|e| Error::FileSystem(e.to_string()))
     }
 
+    fn append(&self, path: &Path, content: &str) -> Result<()> {
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .map_err(|e| Error::FileSystem(e.to_string()))
+    }
+
     fn exists(&self, path: &Path) -> bool {
         path.exists()
     }
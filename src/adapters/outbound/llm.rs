@@ -1,17 +1,21 @@
-use std::{cmp::max, sync::Arc};
+use std::{cmp::max, future::Future, sync::Arc};
 
 use anyhow::{Context, Result, anyhow};
 use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use rig::{
+    agent::Agent,
     client::CompletionClient,
-    completion::Prompt,
+    completion::{AssistantContent, Completion, CompletionError, CompletionModel, Message},
     providers::{anthropic, gemini, openai, xai},
 };
 use tokio::{sync::Semaphore, task::JoinSet};
 
 use crate::cli::LlmProvider;
 use crate::core::error::Error as CoreError;
-use crate::core::ports::{LlmClient as CoreLlmClient, LlmOptions};
+use crate::core::ports::{
+    ChatMessage, ChatRole, LlmClient as CoreLlmClient, LlmOptions, LlmResponse, TokenUsage,
+};
 
 /// Abstraction over whichever LLM backend is configured.
 #[async_trait]
@@ -47,6 +51,7 @@ impl RigLlmClient {
         api_key: impl Into<String>,
         model: impl Into<String>,
         max_concurrent: usize,
+        headers: &[(String, String)],
     ) -> Result<Self> {
         let api_key = api_key.into();
         if api_key.trim().is_empty() {
@@ -58,7 +63,7 @@ impl RigLlmClient {
             return Err(anyhow!("Model identifier may not be empty"));
         }
 
-        let http_client = build_http_client()?;
+        let http_client = build_http_client(build_header_map(headers)?)?;
         let limit = max(1, max_concurrent);
         Ok(Self {
             inner: Arc::new(RigLlmClientInner {
@@ -84,22 +89,13 @@ impl std::fmt::Debug for RigLlmClient {
 #[async_trait]
 impl LlmClient for RigLlmClient {
     async fn sample(&self, prompt: &str, model_override: Option<&str>) -> Result<String> {
-        let permit = self
-            .inner
-            .semaphore
-            .clone()
-            .acquire_owned()
-            .await
-            .context("Semaphore closed while waiting for LLM slot")?;
-
         let model = model_override.unwrap_or(&self.inner.default_model);
-        let response = self
-            .prompt_once(model, prompt, None)
+        let conversation = ProviderConversation::single_prompt(prompt);
+        self.with_llm_slot(self.prompt_once(model, &conversation, None, &[]))
             .await
-            .map_err(|err| anyhow!("LLM prompt failed: {err}"));
-
-        drop(permit);
-        response
+            .context("Semaphore closed while waiting for LLM slot")?
+            .map(|response| response.content)
+            .map_err(|err| anyhow!("LLM prompt failed: {err}"))
     }
 
     async fn sample_n(&self, prompt: &str, n: usize, model: Option<&str>) -> Result<Vec<String>> {
@@ -134,31 +130,173 @@ impl CoreLlmClient for RigLlmClient {
         prompt: &str,
         options: &LlmOptions,
     ) -> crate::core::Result<String> {
-        let _permit = self
-            .inner
-            .semaphore
-            .clone()
-            .acquire_owned()
+        self.chat(model, &[ChatMessage::user(prompt)], options)
             .await
-            .map_err(|e| CoreError::System(format!("Semaphore error: {e}")))?;
+    }
+
+    async fn chat_completion_with_finish_reason(
+        &self,
+        model: &str,
+        prompt: &str,
+        options: &LlmOptions,
+    ) -> crate::core::Result<LlmResponse> {
+        self.chat_response(model, &[ChatMessage::user(prompt)], options)
+            .await
+    }
 
-        self.prompt_once(model, prompt, options.temperature.map(f64::from))
+    async fn chat(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        options: &LlmOptions,
+    ) -> crate::core::Result<String> {
+        self.chat_response(model, messages, options)
             .await
-            .map_err(|err| CoreError::LlmProvider {
-                provider: self.inner.provider.as_str().to_string(),
-                details: err.to_string(),
-                retryable: true,
+            .map(|response| response.content)
+    }
+
+    async fn chat_with_finish_reason(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        options: &LlmOptions,
+    ) -> crate::core::Result<LlmResponse> {
+        self.chat_response(model, messages, options).await
+    }
+
+    async fn list_models(&self) -> crate::core::Result<Vec<String>> {
+        self.fetch_models(api_base(self.inner.provider))
+            .await
+            .map_err(|err| {
+                let details = err.to_string();
+                CoreError::LlmProvider {
+                    provider: self.inner.provider.as_str().to_string(),
+                    retryable: is_retryable_error(&details),
+                    details,
+                }
             })
     }
 }
 
 impl RigLlmClient {
+    /// GET the provider's model listing under `base_url`, following its pages, and return the
+    /// model ids, sorted.
+    async fn fetch_models(&self, base_url: &str) -> Result<Vec<String>> {
+        let url = format!("{}/models", base_url.trim_end_matches('/'));
+        let mut ids = Vec::new();
+        let mut cursor = None;
+        loop {
+            let body = self.fetch_model_page(&url, cursor.as_ref()).await?;
+            ids.extend(parse_model_ids(&body)?);
+            match next_model_page(&body) {
+                Some(next) if cursor.as_ref() != Some(&next) => cursor = Some(next),
+                _ => break,
+            }
+        }
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    /// GET one page of the model listing at `url`, starting after `cursor` when given.
+    async fn fetch_model_page(
+        &self,
+        url: &str,
+        cursor: Option<&(&'static str, String)>,
+    ) -> Result<serde_json::Value> {
+        let provider = self.inner.provider;
+        let key = self.inner.api_key.as_str();
+        let mut request = self.inner.http_client.get(url);
+        if let Some((param, value)) = cursor {
+            request = request.query(&[(param, value)]);
+        }
+        let request = match provider {
+            LlmProvider::Openai | LlmProvider::Grok => request.bearer_auth(key),
+            LlmProvider::Anthropic => request
+                .header("x-api-key", key)
+                .header("anthropic-version", ANTHROPIC_API_VERSION),
+            LlmProvider::Gemini => request.header("x-goog-api-key", key),
+        };
+        let response = request
+            .send()
+            .await
+            .map_err(|err| anyhow!("Model list request to {url} failed: {err}"))?;
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|err| anyhow!("Failed to read the model list from {url}: {err}"))?;
+        if matches!(status.as_u16(), 404 | 405 | 501) {
+            return Err(anyhow!(
+                "Provider '{}' does not offer a model list at {url} (HTTP {status})",
+                provider.as_str()
+            ));
+        }
+        if !status.is_success() {
+            return Err(anyhow!(
+                "Model list request failed with HTTP {status}: {}",
+                body.trim()
+            ));
+        }
+        serde_json::from_str(&body)
+            .map_err(|err| anyhow!("Model list from {url} is not JSON: {err}"))
+    }
+
+    /// Send `messages` and keep the provider's finish reason next to the (stop-truncated) text.
+    async fn chat_response(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        options: &LlmOptions,
+    ) -> crate::core::Result<LlmResponse> {
+        let conversation = map_conversation(self.inner.provider, messages).map_err(|err| {
+            CoreError::LlmProvider {
+                provider: self.inner.provider.as_str().to_string(),
+                retryable: false,
+                details: err.to_string(),
+            }
+        })?;
+        self.with_llm_slot(self.prompt_once(
+            model,
+            &conversation,
+            options.temperature.map(f64::from),
+            &options.stop_sequences,
+        ))
+        .await?
+        .map(|response| LlmResponse {
+            content: truncate_at_stop(response.content, &options.stop_sequences),
+            ..response
+        })
+        .map_err(|err| {
+            let details = err.to_string();
+            CoreError::LlmProvider {
+                provider: self.inner.provider.as_str().to_string(),
+                retryable: is_retryable_error(&details),
+                details,
+            }
+        })
+    }
+
+    /// Run `call` once one of the `max_concurrent` request slots is free. Clones share the
+    /// slots, so the cap holds across every task using this client.
+    async fn with_llm_slot<T>(&self, call: impl Future<Output = T>) -> crate::core::Result<T> {
+        let _permit = self
+            .inner
+            .semaphore
+            .acquire()
+            .await
+            .map_err(|e| CoreError::System(format!("Semaphore error: {e}")))?;
+        Ok(call.await)
+    }
+
     async fn prompt_once(
         &self,
         model: &str,
-        prompt: &str,
+        conversation: &ProviderConversation,
         temperature: Option<f64>,
-    ) -> Result<String> {
+        stop_sequences: &[String],
+    ) -> Result<LlmResponse> {
+        let extra_params = additional_params(self.inner.provider, temperature, stop_sequences);
         match self.inner.provider {
             LlmProvider::Openai => {
                 let client: openai::Client<reqwest::Client> =
@@ -169,14 +307,24 @@ impl RigLlmClient {
                         .map_err(|err| anyhow!("Failed to create OpenAI client: {err}"))?;
 
                 let mut agent_builder = client.agent(model);
+                if let Some(preamble) = &conversation.preamble {
+                    agent_builder = agent_builder.preamble(preamble);
+                }
                 if let Some(temp) = temperature {
                     agent_builder = agent_builder.temperature(temp);
                 }
-                agent_builder
-                    .build()
-                    .prompt(prompt)
-                    .await
-                    .map_err(|err| anyhow!("OpenAI prompt error: {err}"))
+                if let Some(params) = extra_params {
+                    agent_builder = agent_builder.additional_params(params);
+                }
+                // The Responses API only gives a reason for incomplete responses; its
+                // `completed` status is not a finish reason, so complete answers report none
+                complete(agent_builder.build(), conversation, |raw| {
+                    raw.incomplete_details
+                        .as_ref()
+                        .map(|details| details.reason.clone())
+                })
+                .await
+                .map_err(|err| anyhow!("OpenAI prompt error: {err}"))
             }
             LlmProvider::Anthropic => {
                 let client: anthropic::Client<reqwest::Client> =
@@ -187,14 +335,20 @@ impl RigLlmClient {
                         .map_err(|err| anyhow!("Failed to create Anthropic client: {err}"))?;
 
                 let mut agent_builder = client.agent(model);
+                if let Some(preamble) = &conversation.preamble {
+                    agent_builder = agent_builder.preamble(preamble);
+                }
                 if let Some(temp) = temperature {
                     agent_builder = agent_builder.temperature(temp);
                 }
-                agent_builder
-                    .build()
-                    .prompt(prompt)
-                    .await
-                    .map_err(|err| anyhow!("Anthropic prompt error: {err}"))
+                if let Some(params) = extra_params {
+                    agent_builder = agent_builder.additional_params(params);
+                }
+                complete(agent_builder.build(), conversation, |raw| {
+                    raw.stop_reason.clone()
+                })
+                .await
+                .map_err(|err| anyhow!("Anthropic prompt error: {err}"))
             }
             LlmProvider::Gemini => {
                 let client: gemini::Client<reqwest::Client> =
@@ -205,14 +359,23 @@ impl RigLlmClient {
                         .map_err(|err| anyhow!("Failed to create Gemini client: {err}"))?;
 
                 let mut agent_builder = client.agent(model);
+                if let Some(preamble) = &conversation.preamble {
+                    agent_builder = agent_builder.preamble(preamble);
+                }
                 if let Some(temp) = temperature {
                     agent_builder = agent_builder.temperature(temp);
                 }
-                agent_builder
-                    .build()
-                    .prompt(prompt)
-                    .await
-                    .map_err(|err| anyhow!("Gemini prompt error: {err}"))
+                if let Some(params) = extra_params {
+                    agent_builder = agent_builder.additional_params(params);
+                }
+                complete(agent_builder.build(), conversation, |raw| {
+                    raw.candidates
+                        .first()
+                        .and_then(|candidate| candidate.finish_reason.as_ref())
+                        .and_then(serde_name)
+                })
+                .await
+                .map_err(|err| anyhow!("Gemini prompt error: {err}"))
             }
             LlmProvider::Grok => {
                 let client: xai::Client<reqwest::Client> =
@@ -223,20 +386,271 @@ impl RigLlmClient {
                         .map_err(|err| anyhow!("Failed to create xAI client: {err}"))?;
 
                 let mut agent_builder = client.agent(model);
+                if let Some(preamble) = &conversation.preamble {
+                    agent_builder = agent_builder.preamble(preamble);
+                }
                 if let Some(temp) = temperature {
                     agent_builder = agent_builder.temperature(temp);
                 }
-                agent_builder
-                    .build()
-                    .prompt(prompt)
-                    .await
-                    .map_err(|err| anyhow!("xAI prompt error: {err}"))
+                if let Some(params) = extra_params {
+                    agent_builder = agent_builder.additional_params(params);
+                }
+                complete(agent_builder.build(), conversation, |raw| {
+                    raw.choices
+                        .first()
+                        .map(|choice| choice.finish_reason.clone())
+                })
+                .await
+                .map_err(|err| anyhow!("xAI prompt error: {err}"))
+            }
+        }
+    }
+}
+
+/// Send `conversation` through `agent` as one completion request. Unlike `rig`'s `Chat`, this
+/// keeps the raw provider response, from which `finish_reason` picks the stop reason.
+async fn complete<M: CompletionModel>(
+    agent: Agent<M>,
+    conversation: &ProviderConversation,
+    finish_reason: impl FnOnce(&M::Response) -> Option<String>,
+) -> std::result::Result<LlmResponse, CompletionError> {
+    let response = agent
+        .completion(conversation.prompt.as_str(), conversation.rig_history())
+        .await?
+        .send()
+        .await?;
+    let content = response
+        .choice
+        .iter()
+        .filter_map(|content| match content {
+            AssistantContent::Text(text) => Some(text.text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok(LlmResponse {
+        content,
+        finish_reason: finish_reason(&response.raw_response),
+        usage: Some(TokenUsage {
+            input_tokens: response.usage.input_tokens,
+            output_tokens: response.usage.output_tokens,
+        }),
+    })
+}
+
+/// The serde name of a provider enum value, e.g. Gemini's `MAX_TOKENS`.
+fn serde_name(value: &impl serde::Serialize) -> Option<String> {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+}
+
+/// A conversation split into the pieces a `rig` agent accepts: the preamble (which `rig` sends
+/// as a system message for OpenAI/xAI, Anthropic's top-level `system` field, or Gemini's
+/// `systemInstruction`), the prior turns, and the final user prompt.
+#[derive(Debug, PartialEq)]
+struct ProviderConversation {
+    preamble: Option<String>,
+    history: Vec<ChatMessage>,
+    prompt: String,
+}
+
+impl ProviderConversation {
+    fn single_prompt(prompt: &str) -> Self {
+        Self {
+            preamble: None,
+            history: Vec::new(),
+            prompt: prompt.to_string(),
+        }
+    }
+
+    fn rig_history(&self) -> Vec<Message> {
+        self.history
+            .iter()
+            .map(|message| match message.role {
+                ChatRole::Assistant => Message::assistant(message.content.as_str()),
+                _ => Message::user(message.content.as_str()),
+            })
+            .collect()
+    }
+}
+
+/// Map port-level messages onto what `provider` accepts. System messages are folded into the
+/// preamble wherever they appear. Anthropic and Gemini reject consecutive turns from the same
+/// role, so those are merged; OpenAI and xAI keep every turn as sent. The last turn must come
+/// from the user.
+fn map_conversation(
+    provider: LlmProvider,
+    messages: &[ChatMessage],
+) -> Result<ProviderConversation> {
+    let merge_turns = matches!(provider, LlmProvider::Anthropic | LlmProvider::Gemini);
+    let mut system = Vec::new();
+    let mut turns: Vec<ChatMessage> = Vec::new();
+    for message in messages {
+        match (message.role, turns.last_mut()) {
+            (ChatRole::System, _) => system.push(message.content.as_str()),
+            (role, Some(last)) if merge_turns && last.role == role => {
+                last.content.push_str("\n\n");
+                last.content.push_str(&message.content);
             }
+            _ => turns.push(message.clone()),
+        }
+    }
+    let prompt = match turns.pop() {
+        Some(ChatMessage {
+            role: ChatRole::User,
+            content,
+        }) => content,
+        _ => return Err(anyhow!("Conversation must end with a user message")),
+    };
+    Ok(ProviderConversation {
+        preamble: (!system.is_empty()).then(|| system.join("\n\n")),
+        history: turns,
+        prompt,
+    })
+}
+
+/// Classify a provider failure as retryable based on the HTTP status `rig` reports
+/// (e.g. `Invalid status code 429 Too Many Requests with message: ...`) or, when no status is
+/// present, on well-known provider error codes. Throttling and server-side failures are
+/// retryable; request, auth, and lookup errors are not. Unknown shapes (transport failures,
+/// malformed responses) default to retryable.
+fn is_retryable_error(message: &str) -> bool {
+    if let Some(status) = extract_http_status(message) {
+        return matches!(status, 408 | 429 | 500..=599);
+    }
+
+    let lower = message.to_ascii_lowercase();
+    const PERMANENT: [&str; 7] = [
+        "failed to create",
+        "invalid_api_key",
+        "authentication_error",
+        "permission_error",
+        "not_found_error",
+        "model_not_found",
+        "invalid_request_error",
+    ];
+    if PERMANENT.iter().any(|code| lower.contains(code)) {
+        return false;
+    }
+    true
+}
+
+fn extract_http_status(message: &str) -> Option<u16> {
+    static STATUS_RE: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+    let re = STATUS_RE.get_or_init(|| {
+        regex::Regex::new(r"(?i)status code:?\s*(\d{3})\b").expect("valid status regex")
+    });
+    re.captures(message)
+        .and_then(|caps| caps.get(1))
+        .and_then(|status| status.as_str().parse().ok())
+}
+
+/// Provider-specific request parameters carrying stop sequences, plus Gemini's temperature:
+/// rig drops the agent temperature for Gemini unless a `generationConfig` is supplied, so it
+/// is set there explicitly. OpenAI agents go through the Responses API, which has no stop
+/// parameter; those rely on [`truncate_at_stop`] alone.
+fn additional_params(
+    provider: LlmProvider,
+    temperature: Option<f64>,
+    stop_sequences: &[String],
+) -> Option<serde_json::Value> {
+    if provider == LlmProvider::Gemini {
+        let mut config = serde_json::Map::new();
+        if let Some(temperature) = temperature {
+            config.insert("temperature".into(), serde_json::json!(temperature));
         }
+        if !stop_sequences.is_empty() {
+            config.insert("stopSequences".into(), serde_json::json!(stop_sequences));
+        }
+        return (!config.is_empty()).then(|| serde_json::json!({ "generationConfig": config }));
+    }
+    if stop_sequences.is_empty() {
+        return None;
+    }
+    match provider {
+        LlmProvider::Openai | LlmProvider::Gemini => None,
+        LlmProvider::Anthropic => Some(serde_json::json!({ "stop_sequences": stop_sequences })),
+        LlmProvider::Grok => Some(serde_json::json!({ "stop": stop_sequences })),
     }
 }
 
-fn build_http_client() -> Result<reqwest::Client> {
+/// Cut `response` at the earliest stop sequence so every provider honours them the same way.
+fn truncate_at_stop(mut response: String, stop_sequences: &[String]) -> String {
+    let cut = stop_sequences
+        .iter()
+        .filter(|stop| !stop.is_empty())
+        .filter_map(|stop| response.find(stop.as_str()))
+        .min();
+    if let Some(idx) = cut {
+        response.truncate(idx);
+    }
+    response
+}
+
+/// `anthropic-version` header required by Anthropic's REST API.
+const ANTHROPIC_API_VERSION: &str = "2023-06-01";
+
+/// REST base each provider's model listing lives under.
+fn api_base(provider: LlmProvider) -> &'static str {
+    match provider {
+        LlmProvider::Openai => "https://api.openai.com/v1",
+        LlmProvider::Anthropic => "https://api.anthropic.com/v1",
+        LlmProvider::Gemini => "https://generativelanguage.googleapis.com/v1beta",
+        LlmProvider::Grok => "https://api.x.ai/v1",
+    }
+}
+
+/// Model ids from a list-models response: `data[].id` (OpenAI, Anthropic, xAI) or
+/// `models[].name` without its `models/` prefix (Gemini).
+fn parse_model_ids(body: &serde_json::Value) -> Result<Vec<String>> {
+    let ids: Option<Vec<String>> = if let Some(data) = body["data"].as_array() {
+        data.iter()
+            .map(|model| model["id"].as_str().map(str::to_string))
+            .collect()
+    } else if let Some(models) = body["models"].as_array() {
+        models
+            .iter()
+            .map(|model| {
+                model["name"]
+                    .as_str()
+                    .map(|name| name.strip_prefix("models/").unwrap_or(name).to_string())
+            })
+            .collect()
+    } else {
+        None
+    };
+    ids.ok_or_else(|| anyhow!("Unrecognized model list response"))
+}
+
+/// Query parameter and value requesting the page after `body`: Anthropic's `has_more` and
+/// `last_id` or Gemini's `nextPageToken`. `None` on the last page.
+fn next_model_page(body: &serde_json::Value) -> Option<(&'static str, String)> {
+    if body["has_more"].as_bool() == Some(true) {
+        return body["last_id"]
+            .as_str()
+            .map(|last_id| ("after_id", last_id.to_string()));
+    }
+    body["nextPageToken"]
+        .as_str()
+        .filter(|token| !token.is_empty())
+        .map(|token| ("pageToken", token.to_string()))
+}
+
+/// Validate extra `name: value` headers sent with every provider request.
+fn build_header_map(headers: &[(String, String)]) -> Result<HeaderMap> {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+        let header_name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|_| anyhow!("Invalid LLM header name '{name}'"))?;
+        let header_value = HeaderValue::from_str(value.trim())
+            .map_err(|_| anyhow!("Invalid value for LLM header '{name}'"))?;
+        map.insert(header_name, header_value);
+    }
+    Ok(map)
+}
+
+fn build_http_client(headers: HeaderMap) -> Result<reqwest::Client> {
     // `reqwest::Client::default()` can consult OS-level proxy settings.
     // On macOS this can involve `system-configuration`, which has been observed to panic in
     // sandboxed/restricted environments. We avoid that path by default.
@@ -248,6 +662,7 @@ fn build_http_client() -> Result<reqwest::Client> {
         builder = builder.no_proxy();
     }
     builder
+        .default_headers(headers)
         .build()
         .map_err(|err| anyhow!("Failed to build HTTP client: {err}"))
 }
@@ -258,13 +673,354 @@ mod tests {
 
     #[test]
     fn rejects_empty_api_key() {
-        let err = RigLlmClient::new(LlmProvider::Openai, "   ", "model", 1).unwrap_err();
+        let err = RigLlmClient::new(LlmProvider::Openai, "   ", "model", 1, &[]).unwrap_err();
         assert!(err.to_string().contains("API key"));
     }
 
     #[test]
     fn rejects_empty_model() {
-        let err = RigLlmClient::new(LlmProvider::Openai, "key", "   ", 1).unwrap_err();
+        let err = RigLlmClient::new(LlmProvider::Openai, "key", "   ", 1, &[]).unwrap_err();
         assert!(err.to_string().contains("Model"));
     }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        let header = |name: &str, value: &str| vec![(name.to_string(), value.to_string())];
+        let err = RigLlmClient::new(LlmProvider::Openai, "key", "m", 1, &header("bad name", "x"))
+            .unwrap_err();
+        assert!(err.to_string().contains("Invalid LLM header name"), "{err}");
+        let err = RigLlmClient::new(LlmProvider::Openai, "key", "m", 1, &header("X-Ok", "a\nb"))
+            .unwrap_err();
+        assert!(err.to_string().contains("Invalid value"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn attaches_extra_headers_to_every_request() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 4096];
+            let read = stream.read(&mut buf).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\ncontent-length: 0\r\n\r\n")
+                .unwrap();
+            String::from_utf8_lossy(&buf[..read]).to_lowercase()
+        });
+
+        let headers = vec![
+            ("OpenAI-Organization".to_string(), "org-123".to_string()),
+            ("X-Route".to_string(), " eu ".to_string()),
+        ];
+        let client = RigLlmClient::new(LlmProvider::Openai, "key", "model", 1, &headers).unwrap();
+        client
+            .inner
+            .http_client
+            .get(format!("http://{addr}/"))
+            .send()
+            .await
+            .unwrap();
+
+        let request = server.join().unwrap();
+        assert!(
+            request.contains("openai-organization: org-123"),
+            "{request}"
+        );
+        assert!(request.contains("x-route: eu"), "{request}");
+    }
+
+    /// Serve one HTTP response on a local port and return the address plus the raw request.
+    fn serve_once(response: String) -> (String, std::thread::JoinHandle<String>) {
+        let (base, server) = serve_each(vec![response]);
+        (
+            base,
+            std::thread::spawn(move || server.join().unwrap().remove(0)),
+        )
+    }
+
+    /// Serve `responses` to successive connections and return the address plus the raw requests.
+    fn serve_each(responses: Vec<String>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            responses
+                .into_iter()
+                .map(|response| {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let mut buf = [0u8; 4096];
+                    let read = stream.read(&mut buf).unwrap();
+                    stream.write_all(response.as_bytes()).unwrap();
+                    String::from_utf8_lossy(&buf[..read]).to_lowercase()
+                })
+                .collect()
+        });
+        (format!("http://{addr}/v1"), server)
+    }
+
+    fn http_response(status: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    #[tokio::test]
+    async fn lists_model_ids_from_the_provider() {
+        let body = r#"{"object":"list","data":[{"id":"gpt-5.1"},{"id":"gpt-4o-mini"}]}"#;
+        let (base, server) = serve_once(http_response("200 OK", body));
+        let client = RigLlmClient::new(LlmProvider::Openai, "sk-test", "m", 1, &[]).unwrap();
+        let ids = client.fetch_models(&base).await.unwrap();
+        assert_eq!(ids, vec!["gpt-4o-mini", "gpt-5.1"]);
+        let request = server.join().unwrap();
+        assert!(request.starts_with("get /v1/models "), "{request}");
+        assert!(
+            request.contains("authorization: bearer sk-test"),
+            "{request}"
+        );
+
+        let body = r#"{"models":[{"name":"models/gemini-2.5-pro"}]}"#;
+        let (base, server) = serve_once(http_response("200 OK", body));
+        let client = RigLlmClient::new(LlmProvider::Gemini, "g-key", "m", 1, &[]).unwrap();
+        assert_eq!(
+            client.fetch_models(&base).await.unwrap(),
+            vec!["gemini-2.5-pro"]
+        );
+        assert!(server.join().unwrap().contains("x-goog-api-key: g-key"));
+
+        let (base, server) = serve_once(http_response("404 Not Found", "{}"));
+        let client = RigLlmClient::new(LlmProvider::Grok, "key", "m", 1, &[]).unwrap();
+        let err = client.fetch_models(&base).await.unwrap_err();
+        assert!(
+            err.to_string().contains("does not offer a model list"),
+            "{err}"
+        );
+        server.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn model_listing_follows_every_page() {
+        let (base, server) = serve_each(vec![
+            http_response(
+                "200 OK",
+                r#"{"data":[{"id":"claude-b"}],"has_more":true,"last_id":"claude-b"}"#,
+            ),
+            http_response(
+                "200 OK",
+                r#"{"data":[{"id":"claude-a"}],"has_more":false,"last_id":"claude-a"}"#,
+            ),
+        ]);
+        let client = RigLlmClient::new(LlmProvider::Anthropic, "a-key", "m", 1, &[]).unwrap();
+        assert_eq!(
+            client.fetch_models(&base).await.unwrap(),
+            vec!["claude-a", "claude-b"]
+        );
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("get /v1/models "), "{requests:?}");
+        assert!(
+            requests[1].starts_with("get /v1/models?after_id=claude-b "),
+            "{requests:?}"
+        );
+
+        let (base, server) = serve_each(vec![
+            http_response(
+                "200 OK",
+                r#"{"models":[{"name":"models/gemini-a"}],"nextPageToken":"p2"}"#,
+            ),
+            http_response("200 OK", r#"{"models":[{"name":"models/gemini-b"}]}"#),
+        ]);
+        let client = RigLlmClient::new(LlmProvider::Gemini, "g-key", "m", 1, &[]).unwrap();
+        assert_eq!(
+            client.fetch_models(&base).await.unwrap(),
+            vec!["gemini-a", "gemini-b"]
+        );
+        let requests = server.join().unwrap();
+        assert!(
+            requests[1].starts_with("get /v1/models?pagetoken=p2 "),
+            "{requests:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_never_exceed_max_concurrent() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let client = RigLlmClient::new(LlmProvider::Openai, "key", "model", 2, &[]).unwrap();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let mut calls = JoinSet::new();
+        for _ in 0..8 {
+            let client = client.clone();
+            let (in_flight, peak) = (in_flight.clone(), peak.clone());
+            calls.spawn(async move {
+                client
+                    .with_llm_slot(async {
+                        let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now, Ordering::SeqCst);
+                        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+                    })
+                    .await
+                    .unwrap();
+            });
+        }
+        while let Some(call) = calls.join_next().await {
+            call.unwrap();
+        }
+
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn classifies_http_statuses() {
+        let cases = [
+            (
+                "OpenAI prompt error: CompletionError: HttpError: Invalid status code 429 Too Many Requests with message: slow down",
+                true,
+            ),
+            (
+                "Anthropic prompt error: CompletionError: HttpError: Invalid status code: 503 Service Unavailable",
+                true,
+            ),
+            (
+                "Gemini prompt error: CompletionError: HttpError: Invalid status code 500 Internal Server Error with message: oops",
+                true,
+            ),
+            (
+                "OpenAI prompt error: CompletionError: HttpError: Invalid status code 400 Bad Request with message: bad",
+                false,
+            ),
+            (
+                "xAI prompt error: CompletionError: HttpError: Invalid status code 401 Unauthorized with message: no key",
+                false,
+            ),
+            (
+                "OpenAI prompt error: CompletionError: HttpError: Invalid status code 404 Not Found with message: no model",
+                false,
+            ),
+        ];
+        for (message, expected) in cases {
+            assert_eq!(is_retryable_error(message), expected, "{message}");
+        }
+    }
+
+    #[test]
+    fn classifies_provider_error_bodies_without_status() {
+        assert!(!is_retryable_error(
+            r#"Anthropic prompt error: CompletionError: ProviderError: {"type":"authentication_error","message":"invalid x-api-key"}"#
+        ));
+        assert!(is_retryable_error(
+            r#"Anthropic prompt error: CompletionError: ProviderError: {"type":"overloaded_error","message":"Overloaded"}"#
+        ));
+        assert!(is_retryable_error(
+            "OpenAI prompt error: CompletionError: HttpError: Http error: connection reset"
+        ));
+    }
+
+    #[test]
+    fn provider_enum_finish_reasons_use_their_wire_names() {
+        use rig::providers::gemini::completion::gemini_api_types::FinishReason;
+
+        let gemini = serde_name(&FinishReason::MaxTokens).unwrap();
+        assert_eq!(gemini, "MAX_TOKENS");
+        assert!(LlmResponse::new("").with_finish_reason(gemini).truncated());
+    }
+
+    #[test]
+    fn truncates_at_earliest_stop_sequence() {
+        let stops = vec!["</file>".to_string(), "\n\n".to_string()];
+        assert_eq!(
+            truncate_at_stop("<file>a</file>\n\nmore".into(), &stops),
+            "<file>a"
+        );
+        assert_eq!(truncate_at_stop("plain".into(), &stops), "plain");
+        assert_eq!(truncate_at_stop("plain".into(), &[]), "plain");
+    }
+
+    #[test]
+    fn two_message_conversation_maps_system_to_preamble() {
+        let messages = [
+            ChatMessage::system("You are terse."),
+            ChatMessage::user("Fix the bug."),
+        ];
+        for provider in [
+            LlmProvider::Openai,
+            LlmProvider::Anthropic,
+            LlmProvider::Gemini,
+            LlmProvider::Grok,
+        ] {
+            let conversation = map_conversation(provider, &messages).unwrap();
+            assert_eq!(
+                conversation,
+                ProviderConversation {
+                    preamble: Some("You are terse.".into()),
+                    history: Vec::new(),
+                    prompt: "Fix the bug.".into(),
+                },
+                "{provider:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn consecutive_turns_merge_only_for_strict_providers() {
+        let messages = [
+            ChatMessage::user("first"),
+            ChatMessage::user("second"),
+            ChatMessage::assistant("reply"),
+            ChatMessage::user("last"),
+        ];
+        let anthropic = map_conversation(LlmProvider::Anthropic, &messages).unwrap();
+        assert_eq!(
+            anthropic.history,
+            vec![
+                ChatMessage::user("first\n\nsecond"),
+                ChatMessage::assistant("reply")
+            ]
+        );
+        let openai = map_conversation(LlmProvider::Openai, &messages).unwrap();
+        assert_eq!(openai.history.len(), 3);
+        assert_eq!(openai.prompt, "last");
+        assert!(matches!(openai.rig_history()[2], Message::Assistant { .. }));
+
+        let err = map_conversation(LlmProvider::Gemini, &[ChatMessage::assistant("hi")]);
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn additional_params_match_provider_fields() {
+        let stops = vec!["END".to_string()];
+        assert!(additional_params(LlmProvider::Anthropic, Some(0.5), &[]).is_none());
+        assert_eq!(
+            additional_params(LlmProvider::Anthropic, None, &stops).unwrap(),
+            serde_json::json!({ "stop_sequences": ["END"] })
+        );
+        assert_eq!(
+            additional_params(LlmProvider::Gemini, None, &stops).unwrap(),
+            serde_json::json!({ "generationConfig": { "stopSequences": ["END"] } })
+        );
+        assert_eq!(
+            additional_params(LlmProvider::Grok, None, &stops).unwrap(),
+            serde_json::json!({ "stop": ["END"] })
+        );
+        assert!(additional_params(LlmProvider::Openai, Some(0.5), &stops).is_none());
+    }
+
+    #[test]
+    fn gemini_temperature_goes_in_generation_config() {
+        assert!(additional_params(LlmProvider::Gemini, None, &[]).is_none());
+        assert_eq!(
+            additional_params(LlmProvider::Gemini, Some(0.5), &[]).unwrap(),
+            serde_json::json!({ "generationConfig": { "temperature": 0.5 } })
+        );
+        assert_eq!(
+            additional_params(LlmProvider::Gemini, Some(0.5), &["END".to_string()]).unwrap(),
+            serde_json::json!({
+                "generationConfig": { "temperature": 0.5, "stopSequences": ["END"] }
+            })
+        );
+    }
 }
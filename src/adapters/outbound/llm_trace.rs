@@ -0,0 +1,432 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use serde_json::{Value, json};
+use tracing::warn;
+
+use crate::core::{
+    Result,
+    error::Error as CoreError,
+    ports::{ChatMessage, Clock, FileSystem, LlmClient, LlmOptions, LlmResponse},
+};
+use crate::tracing_inspect::redact_secrets;
+
+/// [`LlmClient`] decorator that appends one JSON object per call (model, prompt or messages,
+/// options, response or error, timing) to a JSONL audit file. It wraps any other client, so it
+/// composes with rate limiting or caching layers. Failing to write the trace never fails the
+/// call itself.
+pub struct RecordingLlmClient {
+    inner: Arc<dyn LlmClient>,
+    file_system: Arc<dyn FileSystem>,
+    clock: Arc<dyn Clock>,
+    path: PathBuf,
+    /// Serializes appends so concurrent calls never interleave partial lines.
+    write_lock: Mutex<()>,
+}
+
+impl RecordingLlmClient {
+    pub fn new(
+        inner: Arc<dyn LlmClient>,
+        file_system: Arc<dyn FileSystem>,
+        clock: Arc<dyn Clock>,
+        path: impl Into<PathBuf>,
+    ) -> Self {
+        Self {
+            inner,
+            file_system,
+            clock,
+            path: path.into(),
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    fn record(
+        &self,
+        model: &str,
+        input: (&str, Value),
+        options: &LlmOptions,
+        started_ms: u128,
+        result: std::result::Result<&str, &crate::core::Error>,
+        finish_reason: Option<&str>,
+    ) {
+        let finished_ms = self.clock.now_ms();
+        let (input_field, mut input_value) = input;
+        redact_secrets(&mut input_value);
+        let mut record = json!({
+            "timestamp_ms": started_ms,
+            "duration_ms": finished_ms.saturating_sub(started_ms),
+            "model": model,
+            "options": {
+                "temperature": options.temperature,
+                "max_tokens": options.max_tokens,
+                "reasoning_effort": options.reasoning_effort,
+                "stop_sequences": options.stop_sequences,
+            },
+        });
+        record[input_field] = input_value;
+        match result {
+            Ok(response) => {
+                let mut response = Value::String(response.to_string());
+                redact_secrets(&mut response);
+                record["response"] = response;
+                if let Some(reason) = finish_reason {
+                    record["finish_reason"] = Value::String(reason.to_string());
+                }
+            }
+            Err(err) => record["error"] = Value::String(err.to_string()),
+        }
+
+        let line = format!("{record}\n");
+        let _guard = self
+            .write_lock
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        if let Err(err) = self.file_system.append(&self.path, &line) {
+            warn!(path = %self.path.display(), error = %err, "Failed to append LLM trace record");
+        }
+    }
+}
+
+#[async_trait]
+impl LlmClient for RecordingLlmClient {
+    async fn chat_completion(
+        &self,
+        model: &str,
+        prompt: &str,
+        options: &LlmOptions,
+    ) -> Result<String> {
+        let started_ms = self.clock.now_ms();
+        let result = self.inner.chat_completion(model, prompt, options).await;
+        self.record(
+            model,
+            ("prompt", Value::String(prompt.to_string())),
+            options,
+            started_ms,
+            result.as_deref(),
+            None,
+        );
+        result
+    }
+
+    async fn chat_completion_with_finish_reason(
+        &self,
+        model: &str,
+        prompt: &str,
+        options: &LlmOptions,
+    ) -> Result<LlmResponse> {
+        let started_ms = self.clock.now_ms();
+        let result = self
+            .inner
+            .chat_completion_with_finish_reason(model, prompt, options)
+            .await;
+        self.record(
+            model,
+            ("prompt", Value::String(prompt.to_string())),
+            options,
+            started_ms,
+            result.as_ref().map(|response| response.content.as_str()),
+            result
+                .as_ref()
+                .ok()
+                .and_then(|response| response.finish_reason.as_deref()),
+        );
+        result
+    }
+
+    async fn chat(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        options: &LlmOptions,
+    ) -> Result<String> {
+        let started_ms = self.clock.now_ms();
+        let result = self.inner.chat(model, messages, options).await;
+        self.record(
+            model,
+            ("messages", json!(messages)),
+            options,
+            started_ms,
+            result.as_deref(),
+            None,
+        );
+        result
+    }
+
+    async fn chat_with_finish_reason(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        options: &LlmOptions,
+    ) -> Result<LlmResponse> {
+        let started_ms = self.clock.now_ms();
+        let result = self
+            .inner
+            .chat_with_finish_reason(model, messages, options)
+            .await;
+        self.record(
+            model,
+            ("messages", json!(messages)),
+            options,
+            started_ms,
+            result.as_ref().map(|response| response.content.as_str()),
+            result
+                .as_ref()
+                .ok()
+                .and_then(|response| response.finish_reason.as_deref()),
+        );
+        result
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        self.inner.list_models().await
+    }
+}
+
+/// One recorded call outcome: the response, or the error message the provider returned.
+type RecordedCall = std::result::Result<LlmResponse, String>;
+
+/// [`LlmClient`] that answers from a trace written by [`RecordingLlmClient`] instead of
+/// calling a provider, for deterministic offline re-runs. Each call is matched to the recorded
+/// calls with the same model and prompt (or messages); repeated identical calls are served in
+/// recorded order, so concurrent samples of one prompt still get their own responses.
+pub struct ReplayLlmClient {
+    path: PathBuf,
+    calls: Mutex<HashMap<String, VecDeque<RecordedCall>>>,
+}
+
+impl ReplayLlmClient {
+    /// Read every record of the JSONL trace at `path`.
+    pub fn open(file_system: &dyn FileSystem, path: &Path) -> Result<Self> {
+        let contents = file_system.read_to_string(path)?;
+        let mut calls: HashMap<String, VecDeque<RecordedCall>> = HashMap::new();
+        for (index, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: Value = serde_json::from_str(line).map_err(|err| {
+                CoreError::Config(format!(
+                    "Invalid LLM trace record on line {} of {}: {err}",
+                    index + 1,
+                    path.display()
+                ))
+            })?;
+            let model = record["model"].as_str().unwrap_or_default();
+            let key = match (record.get("prompt"), record.get("messages")) {
+                (Some(prompt), _) => call_key(model, "prompt", prompt),
+                (None, Some(messages)) => call_key(model, "messages", messages),
+                (None, None) => {
+                    return Err(CoreError::Config(format!(
+                        "LLM trace record on line {} of {} has neither prompt nor messages",
+                        index + 1,
+                        path.display()
+                    )));
+                }
+            };
+            let call = match (record["response"].as_str(), record["error"].as_str()) {
+                (Some(content), _) => {
+                    let response = LlmResponse::new(content);
+                    Ok(match record["finish_reason"].as_str() {
+                        Some(reason) => response.with_finish_reason(reason),
+                        None => response,
+                    })
+                }
+                (None, error) => Err(error.unwrap_or("recorded call failed").to_string()),
+            };
+            calls.entry(key).or_default().push_back(call);
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            calls: Mutex::new(calls),
+        })
+    }
+
+    /// Take the next recorded outcome for this call. Inputs are redacted the way the recorder
+    /// redacted them, so prompts that carried key-like tokens still match.
+    fn replay(&self, model: &str, input: (&str, Value)) -> Result<LlmResponse> {
+        let (input_field, mut input_value) = input;
+        redact_secrets(&mut input_value);
+        let key = call_key(model, input_field, &input_value);
+        let call = self
+            .calls
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .get_mut(&key)
+            .and_then(VecDeque::pop_front);
+        match call {
+            Some(Ok(response)) => Ok(response),
+            Some(Err(details)) => Err(CoreError::LlmProvider {
+                provider: "replay".into(),
+                retryable: false,
+                details,
+            }),
+            None => Err(CoreError::InvalidState(format!(
+                "{} has no recorded response left for this {input_field} to {model}",
+                self.path.display()
+            ))),
+        }
+    }
+}
+
+fn call_key(model: &str, input_field: &str, input: &Value) -> String {
+    format!("{model}\n{input_field}\n{input}")
+}
+
+#[async_trait]
+impl LlmClient for ReplayLlmClient {
+    async fn chat_completion(
+        &self,
+        model: &str,
+        prompt: &str,
+        _options: &LlmOptions,
+    ) -> Result<String> {
+        self.replay(model, ("prompt", Value::String(prompt.to_string())))
+            .map(|response| response.content)
+    }
+
+    async fn chat_completion_with_finish_reason(
+        &self,
+        model: &str,
+        prompt: &str,
+        _options: &LlmOptions,
+    ) -> Result<LlmResponse> {
+        self.replay(model, ("prompt", Value::String(prompt.to_string())))
+    }
+
+    async fn chat(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        _options: &LlmOptions,
+    ) -> Result<String> {
+        self.replay(model, ("messages", json!(messages)))
+            .map(|response| response.content)
+    }
+
+    async fn chat_with_finish_reason(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        _options: &LlmOptions,
+    ) -> Result<LlmResponse> {
+        self.replay(model, ("messages", json!(messages)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::outbound::{clock::SystemClock, filesystem::StdFileSystem};
+
+    struct UpperLlm;
+
+    #[async_trait]
+    impl LlmClient for UpperLlm {
+        async fn chat_completion(
+            &self,
+            _model: &str,
+            prompt: &str,
+            _options: &LlmOptions,
+        ) -> Result<String> {
+            Ok(prompt.to_uppercase())
+        }
+    }
+
+    #[tokio::test]
+    async fn two_calls_append_two_jsonl_records() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("llm.jsonl");
+        let client = RecordingLlmClient::new(
+            Arc::new(UpperLlm),
+            Arc::new(StdFileSystem::new()),
+            Arc::new(SystemClock::new()),
+            &path,
+        );
+        let options = LlmOptions {
+            temperature: Some(0.3),
+            stop_sequences: vec!["END".into()],
+            ..LlmOptions::default()
+        };
+
+        client
+            .chat_completion("model-a", "first", &options)
+            .await
+            .unwrap();
+        client
+            .chat(
+                "model-b",
+                &[
+                    ChatMessage::system("key sk-abcdefghijklmnopqrstuv"),
+                    ChatMessage::user("second"),
+                ],
+                &LlmOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let records: Vec<Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+
+        assert_eq!(records[0]["model"], "model-a");
+        assert_eq!(records[0]["prompt"], "first");
+        assert_eq!(records[0]["response"], "FIRST");
+        assert_eq!(records[0]["options"]["stop_sequences"], json!(["END"]));
+        assert!(records[0]["options"]["temperature"].is_number());
+        assert!(records[0]["duration_ms"].is_u64());
+        assert!(records[0]["timestamp_ms"].is_u64());
+
+        assert_eq!(records[1]["model"], "model-b");
+        assert_eq!(records[1]["messages"][0]["role"], "system");
+        assert_eq!(records[1]["messages"][0]["content"], "key [redacted_key]");
+        assert_eq!(records[1]["messages"][1]["role"], "user");
+        assert!(records[1]["response"].as_str().unwrap().ends_with("SECOND"));
+    }
+
+    #[tokio::test]
+    async fn replay_serves_identical_calls_in_recorded_order() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("llm.jsonl");
+        std::fs::write(
+            &path,
+            concat!(
+                r#"{"model":"m","prompt":"vote","response":"1"}"#,
+                "\n",
+                r#"{"model":"m","prompt":"solve","response":"done","finish_reason":"stop"}"#,
+                "\n",
+                r#"{"model":"m","prompt":"vote","error":"rate limited"}"#,
+                "\n",
+            ),
+        )
+        .unwrap();
+        let client = ReplayLlmClient::open(&StdFileSystem::new(), &path).unwrap();
+        let options = LlmOptions::default();
+
+        let solved = client
+            .chat_completion_with_finish_reason("m", "solve", &options)
+            .await
+            .unwrap();
+        assert_eq!(solved.content, "done");
+        assert_eq!(solved.finish_reason.as_deref(), Some("stop"));
+        assert_eq!(
+            client.chat_completion("m", "vote", &options).await.unwrap(),
+            "1"
+        );
+        let err = client
+            .chat_completion("m", "vote", &options)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("rate limited"), "{err}");
+        let err = client
+            .chat_completion("m", "solve", &options)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("no recorded response"), "{err}");
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+
+use crate::core::{
+    Result,
+    error::Error,
+    ports::{
+        SessionFilter, SessionLoadResponse, SessionNoteRecord, SessionRepository,
+        SessionSaveRequest, SessionVisitor,
+    },
+};
+
+/// [`SessionRepository`] kept entirely in process memory. Useful for tests and ephemeral
+/// runs; nothing survives the process.
+#[derive(Debug, Default)]
+pub struct InMemorySessionRepository {
+    /// Sessions ordered oldest-write first; saving moves a session to the end.
+    sessions: Mutex<Vec<SessionLoadResponse>>,
+    /// Notes in the order they were added, keyed by session id.
+    notes: Mutex<Vec<(String, SessionNoteRecord)>>,
+}
+
+impl InMemorySessionRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Matching sessions, newest write first.
+    fn snapshot(&self, filter: &SessionFilter) -> Vec<SessionLoadResponse> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions
+            .iter()
+            .rev()
+            .filter(|session| filter.matches(session))
+            .take(filter.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Replace any stored copy of the session with `request`, as its newest write.
+fn store(sessions: &mut Vec<SessionLoadResponse>, request: &SessionSaveRequest) {
+    sessions.retain(|session| session.session_id != request.session_id);
+    sessions.push(SessionLoadResponse {
+        session_id: request.session_id.clone(),
+        domain: request.domain.clone(),
+        prompt: request.prompt.clone(),
+        status: request.status.clone(),
+        pause_trigger: request.pause_trigger.clone(),
+        context_json: request.context_json.clone(),
+        metadata_json: request.metadata_json.clone(),
+        updated_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|dur| dur.as_secs() as i64)
+            .unwrap_or_default(),
+    });
+}
+
+#[async_trait]
+impl SessionRepository for InMemorySessionRepository {
+    async fn save_session(&self, request: &SessionSaveRequest) -> Result<()> {
+        store(&mut self.sessions.lock().unwrap(), request);
+        Ok(())
+    }
+
+    async fn insert_session(&self, request: &SessionSaveRequest) -> Result<()> {
+        // Check and store under one lock so concurrent inserts of an id cannot both succeed
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions
+            .iter()
+            .any(|session| session.session_id == request.session_id)
+        {
+            return Err(Error::InvalidState(format!(
+                "session id {} already in use",
+                request.session_id
+            )));
+        }
+        store(&mut sessions, request);
+        Ok(())
+    }
+
+    async fn load_session(&self, session_id: &str) -> Result<Option<SessionLoadResponse>> {
+        let sessions = self.sessions.lock().unwrap();
+        Ok(sessions
+            .iter()
+            .find(|session| session.session_id == session_id)
+            .cloned())
+    }
+
+    async fn list_sessions(&self, limit: usize) -> Result<Vec<SessionLoadResponse>> {
+        Ok(self.snapshot(&SessionFilter {
+            limit: Some(limit),
+            ..SessionFilter::default()
+        }))
+    }
+
+    async fn find_sessions(&self, filter: &SessionFilter) -> Result<Vec<SessionLoadResponse>> {
+        Ok(self.snapshot(filter))
+    }
+
+    async fn delete_session(&self, session_id: &str) -> Result<bool> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let before = sessions.len();
+        sessions.retain(|session| session.session_id != session_id);
+        self.notes
+            .lock()
+            .unwrap()
+            .retain(|(owner, _)| owner != session_id);
+        Ok(sessions.len() != before)
+    }
+
+    async fn add_note(&self, session_id: &str, note: &str) -> Result<Option<SessionNoteRecord>> {
+        let sessions = self.sessions.lock().unwrap();
+        if !sessions
+            .iter()
+            .any(|session| session.session_id == session_id)
+        {
+            return Ok(None);
+        }
+        let record = SessionNoteRecord {
+            note: note.to_string(),
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|dur| dur.as_secs() as i64)
+                .unwrap_or_default(),
+        };
+        self.notes
+            .lock()
+            .unwrap()
+            .push((session_id.to_string(), record.clone()));
+        Ok(Some(record))
+    }
+
+    async fn list_notes(&self, session_id: &str) -> Result<Vec<SessionNoteRecord>> {
+        Ok(self
+            .notes
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(owner, _)| owner == session_id)
+            .map(|(_, record)| record.clone())
+            .collect())
+    }
+
+    async fn for_each_session(
+        &self,
+        limit: Option<usize>,
+        visit: &mut SessionVisitor<'_>,
+    ) -> Result<usize> {
+        let sessions = self.snapshot(&SessionFilter {
+            limit,
+            ..SessionFilter::default()
+        });
+        let visited = sessions.len();
+        for session in sessions {
+            visit(session)?;
+        }
+        Ok(visited)
+    }
+}
@@ -1,6 +1,10 @@
 pub mod clock;
 pub mod filesystem;
 pub mod llm;
+pub mod llm_trace;
+pub mod memory;
 pub mod persistence;
+pub mod process;
 pub mod telemetry;
 pub mod templating;
+pub mod token_budget;
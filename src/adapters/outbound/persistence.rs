@@ -1,25 +1,31 @@
 use std::{
+    collections::BTreeMap,
     fs,
     path::PathBuf,
-    time::{SystemTime, UNIX_EPOCH},
+    sync::Arc,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::{Context as AnyhowContext, Result, anyhow};
 use async_trait::async_trait;
-use rusqlite::{Connection, params};
+use rusqlite::{Connection, ErrorCode, Transaction, TransactionBehavior, params};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     core::{
-        domain::Context,
+        domain::{AdaptiveKLimits, Context, LineEnding},
         error::Error as CoreError,
-        ports::{SessionLoadResponse, SessionRepository, SessionSaveRequest},
+        ports::{
+            SessionFilter, SessionLoadResponse, SessionNoteRecord, SessionRepository,
+            SessionSaveRequest, SessionVisitor, SummaryFormat,
+        },
     },
     paths::data_dir,
 };
 
 /// Metadata captured alongside a persisted workflow context.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SessionMetadata {
     pub config_path: String,
     pub llm_provider: String,
@@ -28,8 +34,84 @@ pub struct SessionMetadata {
     pub samples: usize,
     pub k: usize,
     pub adaptive_k: bool,
+    /// `run --adaptive-k-min/--adaptive-k-max/--adaptive-k-step`; resume keeps the same bounds.
+    #[serde(default)]
+    pub adaptive_k_limits: AdaptiveKLimits,
     #[serde(default = "default_low_margin_threshold")]
     pub human_low_margin_threshold: usize,
+    /// Decomposition depth cap chosen at `run` time; `None` keeps the runner default.
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    /// Whether `run --adaptive-samples` was set; resume keeps adapting.
+    #[serde(default)]
+    pub adaptive_samples: bool,
+    /// `run --prompt-var` template variables; resume renders prompts with the same values.
+    #[serde(default)]
+    pub prompt_vars: BTreeMap<String, String>,
+    /// [`MicrofactoryConfig::content_hash`] of the config the session last ran with; resume
+    /// compares it to detect drift. `None` for sessions saved before it was recorded.
+    ///
+    /// [`MicrofactoryConfig::content_hash`]: crate::config::MicrofactoryConfig::content_hash
+    #[serde(default)]
+    pub config_hash: Option<String>,
+    /// `run --template-dir`; resume resolves templates from the same directory.
+    #[serde(default)]
+    pub template_dir: Option<String>,
+    /// Whether `run --flat` was set; resume keeps solving subtasks without decomposing them.
+    #[serde(default)]
+    pub flat: bool,
+    /// `run --min-samples-accepted`; resume keeps the same sampling floor.
+    #[serde(default)]
+    pub min_samples_accepted: Option<usize>,
+    /// `run --line-endings`; resume writes files with the same terminator.
+    #[serde(default)]
+    pub line_endings: Option<LineEnding>,
+    /// Whether `run --bom` was set; resume keeps prefixing written files with a BOM.
+    #[serde(default)]
+    pub bom: bool,
+    /// Whether `run --record-prompts` was set; resume keeps recording rendered prompts.
+    #[serde(default)]
+    pub record_prompts: bool,
+    /// `run --max-response-bytes`; resume keeps rejecting oversized samples.
+    #[serde(default)]
+    pub max_response_bytes: Option<usize>,
+    /// Whether `run --dedupe-candidates` was set; resume keeps merging duplicate candidates.
+    #[serde(default)]
+    pub dedupe_candidates: bool,
+    /// Whether `run --collapse-trivial-decomposition` was set; resume keeps collapsing.
+    #[serde(default)]
+    pub collapse_trivial_decomposition: bool,
+    /// Whether `run --explain` was set; resume keeps recording recursion decisions.
+    #[serde(default)]
+    pub explain: bool,
+    /// Whether `run --parallel-red-flaggers` was set; resume keeps racing flaggers.
+    #[serde(default)]
+    pub parallel_red_flaggers: bool,
+    /// Seed `run --shuffle-candidates` resolved to; resume shuffles votes the same way.
+    /// `None` when candidates were not shuffled.
+    #[serde(default)]
+    pub shuffle_seed: Option<u64>,
+    /// `run --retry-on-empty`; resume keeps resampling all-blank solver batches.
+    #[serde(default)]
+    pub retry_on_empty: usize,
+    /// Whether `run --flag-truncated` was set; resume keeps rejecting truncated samples.
+    #[serde(default)]
+    pub flag_truncated: bool,
+    /// `run --red-flag-concurrency`; resume keeps the same cap. `None` for sessions saved
+    /// before it was recorded, which resume with the default.
+    #[serde(default)]
+    pub red_flag_concurrency: Option<usize>,
+    /// Format of the run summary; `None` when `run` wrote none.
+    #[serde(default)]
+    pub summary_format: Option<SummaryFormat>,
+    /// Summary file name under the output directory, placeholders already expanded, so
+    /// resume rewrites the file the paused run wrote.
+    #[serde(default)]
+    pub summary_name: Option<String>,
+    /// `run --max-total-tokens`, or the latest `resume` override; resume rebuilds the budget
+    /// from it and the tokens already recorded in the metrics.
+    #[serde(default)]
+    pub max_total_tokens: Option<u64>,
 }
 
 impl SessionMetadata {
@@ -49,11 +131,27 @@ pub struct SessionEnvelope {
     pub metadata: SessionMetadata,
 }
 
+impl SessionEnvelope {
+    /// Serialize into the backend-neutral DTO accepted by any [`SessionRepository`].
+    pub fn to_save_request(&self, status: SessionStatus) -> Result<SessionSaveRequest> {
+        Ok(SessionSaveRequest {
+            session_id: self.context.session_id.clone(),
+            domain: self.context.domain.clone(),
+            prompt: self.context.prompt.clone(),
+            status: status.as_str().to_string(),
+            pause_trigger: pause_trigger(&self.context, status),
+            context_json: serde_json::to_string(&self.context)?,
+            metadata_json: serde_json::to_string(&self.metadata)?,
+        })
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SessionStatus {
     Running,
     Paused,
     Completed,
+    CompletedWithFailures,
     Failed,
 }
 
@@ -63,6 +161,7 @@ impl SessionStatus {
             SessionStatus::Running => "running",
             SessionStatus::Paused => "paused",
             SessionStatus::Completed => "completed",
+            SessionStatus::CompletedWithFailures => "completed_with_failures",
             SessionStatus::Failed => "failed",
         }
     }
@@ -72,6 +171,7 @@ impl SessionStatus {
             "running" => Some(SessionStatus::Running),
             "paused" => Some(SessionStatus::Paused),
             "completed" => Some(SessionStatus::Completed),
+            "completed_with_failures" => Some(SessionStatus::CompletedWithFailures),
             "failed" => Some(SessionStatus::Failed),
             _ => None,
         }
@@ -82,6 +182,9 @@ impl SessionStatus {
 pub struct SessionSummary {
     pub session_id: String,
     pub status: SessionStatus,
+    /// Why a paused session is waiting (its `WaitState::trigger`), read without decoding the
+    /// stored context.
+    pub pause_trigger: Option<String>,
     pub prompt: String,
     pub domain: String,
     pub updated_at: i64,
@@ -94,6 +197,18 @@ pub struct SessionRecord {
     pub updated_at: i64,
 }
 
+impl SessionRecord {
+    /// Decode a row returned by any [`SessionRepository`].
+    pub fn from_response(response: &SessionLoadResponse) -> Result<Self> {
+        decode_record(
+            &response.status,
+            &response.context_json,
+            &response.metadata_json,
+            response.updated_at,
+        )
+    }
+}
+
 /// Simple SQLite-backed store for session data.
 #[derive(Clone)]
 pub struct SessionStore {
@@ -115,34 +230,51 @@ impl SessionStore {
     }
 
     pub fn save(&self, envelope: &SessionEnvelope, status: SessionStatus) -> Result<()> {
-        let conn = self.connect()?;
-        let context_json = serde_json::to_string(&envelope.context)?;
-        let metadata_json = serde_json::to_string(&envelope.metadata)?;
-        let now = timestamp();
-        conn.execute(
-            r#"
-            INSERT INTO sessions (session_id, domain, prompt, status, context_json, metadata_json, updated_at)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
-            ON CONFLICT(session_id)
-            DO UPDATE SET
-                domain=excluded.domain,
-                prompt=excluded.prompt,
-                status=excluded.status,
-                context_json=excluded.context_json,
-                metadata_json=excluded.metadata_json,
-                updated_at=excluded.updated_at
-            "#,
-            params![
-                envelope.context.session_id,
-                envelope.context.domain,
-                envelope.context.prompt,
-                status.as_str(),
-                context_json,
-                metadata_json,
-                now
-            ],
-        )?;
-        Ok(())
+        retry_on_lock(|| {
+            let conn = self.connect()?;
+            upsert_session(&conn, envelope, status)
+        })
+    }
+
+    /// Runs `f` inside a single SQLite transaction. Every write made through the
+    /// [`SessionTransaction`] is committed together when `f` returns `Ok`; an error (or a
+    /// crash before commit) rolls all of them back.
+    pub fn with_transaction<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&SessionTransaction<'_>) -> Result<T>,
+    {
+        let mut conn = self.connect()?;
+        // Take the write lock up front so a concurrent writer makes us wait (bounded by the
+        // busy timeout) instead of failing when a read lock would later need upgrading.
+        let tx = SessionTransaction {
+            tx: conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .context("Failed to begin session transaction")?,
+        };
+        let value = f(&tx)?;
+        tx.tx
+            .commit()
+            .context("Failed to commit session transaction")?;
+        Ok(value)
+    }
+
+    /// Saves a batch of sessions atomically: either every envelope is stored or, if any item
+    /// fails (to decode or to write), none are. Returns the number of sessions imported.
+    pub fn import<I>(&self, sessions: I) -> Result<usize>
+    where
+        I: IntoIterator<Item = Result<(SessionEnvelope, SessionStatus)>>,
+    {
+        self.with_transaction(|tx| {
+            let mut imported = 0usize;
+            for item in sessions {
+                let (envelope, status) = item?;
+                tx.save(&envelope, status).with_context(|| {
+                    format!("Failed to import session {}", envelope.context.session_id)
+                })?;
+                imported += 1;
+            }
+            Ok(imported)
+        })
     }
 
     pub fn load(&self, session_id: &str) -> Result<SessionRecord> {
@@ -165,23 +297,36 @@ impl SessionStore {
             )
             .with_context(|| format!("Session {session_id} not found"))?;
 
-        let status = SessionStatus::from_str(&row.0)
-            .ok_or_else(|| anyhow!("Invalid status '{}' in store", row.0))?;
-        let context: Context = serde_json::from_str(&row.1)?;
-        let metadata: SessionMetadata = serde_json::from_str(&row.2)?;
+        decode_record(&row.0, &row.1, &row.2, row.3)
+    }
 
-        Ok(SessionRecord {
-            envelope: SessionEnvelope { context, metadata },
-            status,
-            updated_at: row.3,
-        })
+    /// Notes attached to `session_id`, oldest first.
+    pub fn notes(&self, session_id: &str) -> Result<Vec<SessionNoteRecord>> {
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT note, created_at
+            FROM session_notes
+            WHERE session_id = ?1
+            ORDER BY id
+            "#,
+        )?;
+        let notes = stmt
+            .query_map(params![session_id], |row| {
+                Ok(SessionNoteRecord {
+                    note: row.get(0)?,
+                    created_at: row.get(1)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(notes)
     }
 
     pub fn list(&self, limit: usize) -> Result<Vec<SessionSummary>> {
         let conn = self.connect()?;
         let mut stmt = conn.prepare(
             r#"
-            SELECT session_id, prompt, domain, status, updated_at
+            SELECT session_id, prompt, domain, status, updated_at, pause_trigger
             FROM sessions
             ORDER BY updated_at DESC
             LIMIT ?1
@@ -194,12 +339,13 @@ impl SessionStore {
                 row.get::<_, String>(2)?,
                 row.get::<_, String>(3)?,
                 row.get::<_, i64>(4)?,
+                row.get::<_, Option<String>>(5)?,
             ))
         })?;
 
         let mut summaries = Vec::new();
         for row in rows {
-            let (session_id, prompt, domain, status_str, updated_at) = row?;
+            let (session_id, prompt, domain, status_str, updated_at, pause_trigger) = row?;
             let status = SessionStatus::from_str(&status_str)
                 .ok_or_else(|| anyhow!("Invalid status '{status_str}' in store"))?;
             summaries.push(SessionSummary {
@@ -207,19 +353,84 @@ impl SessionStore {
                 prompt,
                 domain,
                 status,
+                pause_trigger,
                 updated_at,
             });
         }
         Ok(summaries)
     }
 
+    /// Streams stored sessions (newest first) into `callback` one row at a time.
+    ///
+    /// Rows are decoded lazily from the SQLite cursor so memory stays bounded by a single
+    /// session regardless of store size. Returns the number of sessions visited.
+    pub fn for_each_session<F>(&self, limit: Option<usize>, mut callback: F) -> Result<usize>
+    where
+        F: FnMut(SessionRecord) -> Result<()>,
+    {
+        self.for_each_row(
+            &SessionFilter {
+                limit,
+                ..SessionFilter::default()
+            },
+            |row| callback(SessionRecord::from_response(&row)?),
+        )
+    }
+
+    /// Streams raw rows matching `filter` (newest first) from the SQLite cursor.
+    fn for_each_row<F>(&self, filter: &SessionFilter, mut callback: F) -> Result<usize>
+    where
+        F: FnMut(SessionLoadResponse) -> Result<()>,
+    {
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT session_id, domain, prompt, status, context_json, metadata_json, updated_at,
+                   pause_trigger
+            FROM sessions
+            WHERE (?1 IS NULL OR status = ?1) AND (?2 IS NULL OR domain = ?2)
+              AND (?4 IS NULL OR prompt LIKE '%' || ?4 || '%' ESCAPE '\')
+            ORDER BY updated_at DESC
+            LIMIT ?3
+            "#,
+        )?;
+        // SQLite treats a negative LIMIT as "no limit".
+        let limit = filter.limit.map(|value| value as i64).unwrap_or(-1);
+        let pattern = filter.prompt_contains.as_deref().map(escape_like);
+        let mut rows = stmt.query(params![filter.status, filter.domain, limit, pattern])?;
+
+        let mut visited = 0usize;
+        while let Some(row) = rows.next()? {
+            callback(SessionLoadResponse {
+                session_id: row.get(0)?,
+                domain: row.get(1)?,
+                prompt: row.get(2)?,
+                status: row.get(3)?,
+                context_json: row.get(4)?,
+                metadata_json: row.get(5)?,
+                updated_at: row.get(6)?,
+                pause_trigger: row.get(7)?,
+            })?;
+            visited += 1;
+        }
+        Ok(visited)
+    }
+
     fn connect(&self) -> Result<Connection> {
-        Connection::open(&self.db_path)
-            .with_context(|| format!("Failed to open session database {}", self.db_path.display()))
+        let conn = Connection::open(&self.db_path).with_context(|| {
+            format!("Failed to open session database {}", self.db_path.display())
+        })?;
+        conn.busy_timeout(BUSY_TIMEOUT)
+            .context("Failed to set session database busy timeout")?;
+        Ok(conn)
     }
 
     fn init_schema(&self) -> Result<()> {
-        let conn = self.connect()?;
+        let mut conn = self.connect()?;
+        // WAL lets `serve` and its `resume` subprocesses read while another process writes;
+        // the mode is persistent, so setting it once per open is enough.
+        conn.query_row("PRAGMA journal_mode=WAL", [], |row| row.get::<_, String>(0))
+            .context("Failed to enable WAL journal mode")?;
         conn.execute_batch(
             r#"
             CREATE TABLE IF NOT EXISTS sessions (
@@ -229,14 +440,234 @@ impl SessionStore {
                 status TEXT NOT NULL,
                 context_json TEXT NOT NULL,
                 metadata_json TEXT NOT NULL,
-                updated_at INTEGER NOT NULL
+                updated_at INTEGER NOT NULL,
+                pause_trigger TEXT
             );
+            CREATE TABLE IF NOT EXISTS session_notes (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                session_id TEXT NOT NULL,
+                note TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS session_notes_by_session
+                ON session_notes (session_id);
             "#,
         )?;
-        Ok(())
+        migrate_pause_trigger(&mut conn)
+    }
+}
+
+/// Write handle passed to [`SessionStore::with_transaction`] callbacks.
+pub struct SessionTransaction<'conn> {
+    tx: Transaction<'conn>,
+}
+
+impl SessionTransaction<'_> {
+    pub fn save(&self, envelope: &SessionEnvelope, status: SessionStatus) -> Result<()> {
+        upsert_session(&self.tx, envelope, status)
+    }
+
+    pub fn delete(&self, session_id: &str) -> Result<bool> {
+        let removed = self.tx.execute(
+            "DELETE FROM sessions WHERE session_id = ?1",
+            params![session_id],
+        )?;
+        self.tx.execute(
+            "DELETE FROM session_notes WHERE session_id = ?1",
+            params![session_id],
+        )?;
+        Ok(removed > 0)
+    }
+
+    /// Append a note to an existing session; `None` when no session has `session_id`.
+    pub fn add_note(&self, session_id: &str, note: &str) -> Result<Option<SessionNoteRecord>> {
+        let exists = self
+            .tx
+            .prepare("SELECT 1 FROM sessions WHERE session_id = ?1")?
+            .exists(params![session_id])?;
+        if !exists {
+            return Ok(None);
+        }
+        let created_at = timestamp();
+        self.tx.execute(
+            "INSERT INTO session_notes (session_id, note, created_at) VALUES (?1, ?2, ?3)",
+            params![session_id, note, created_at],
+        )?;
+        Ok(Some(SessionNoteRecord {
+            note: note.to_string(),
+            created_at,
+        }))
+    }
+}
+
+/// How long a connection waits on another process's lock before SQLite reports it busy.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Attempts made by [`retry_on_lock`] before a lock error is surfaced.
+const LOCK_ATTEMPTS: u32 = 3;
+
+/// Runs `op`, retrying with a short backoff while it fails on a transient lock
+/// (`SQLITE_BUSY`/`SQLITE_LOCKED`) that outlasted the busy timeout.
+fn retry_on_lock<T>(mut op: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut attempt = 1;
+    loop {
+        match op() {
+            Err(err) if attempt < LOCK_ATTEMPTS && is_lock_error(&err) => {
+                tracing::debug!(attempt, error = %err, "Session database locked; retrying");
+                thread::sleep(Duration::from_millis(50 * u64::from(attempt)));
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Attempts made by [`run_blocking`] when the blocking task is cancelled before it runs.
+const BLOCKING_ATTEMPTS: u32 = 3;
+
+/// Runs `op` on the blocking pool, retrying a bounded number of times if the task is cancelled.
+/// A panic inside `op` is reported with its message rather than as a bare join error.
+async fn run_blocking<T: Send + 'static>(
+    op: impl Fn() -> crate::core::Result<T> + Send + Sync + 'static,
+) -> crate::core::Result<T> {
+    let op = Arc::new(op);
+    let mut attempt = 1;
+    loop {
+        let task = op.clone();
+        match tokio::task::spawn_blocking(move || (*task)()).await {
+            Ok(result) => return result,
+            Err(err) if err.is_cancelled() && attempt < BLOCKING_ATTEMPTS => {
+                tracing::debug!(attempt, "Session store task cancelled; retrying");
+                attempt += 1;
+            }
+            Err(err) => return Err(join_error(err)),
+        }
     }
 }
 
+fn join_error(err: tokio::task::JoinError) -> CoreError {
+    if !err.is_panic() {
+        return CoreError::System(format!("Session store task failed: {err}"));
+    }
+    let payload = err.into_panic();
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "non-string panic payload".to_string());
+    CoreError::System(format!("Session store task panicked: {message}"))
+}
+
+fn is_lock_error(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        matches!(
+            cause
+                .downcast_ref::<rusqlite::Error>()
+                .and_then(rusqlite::Error::sqlite_error_code),
+            Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked)
+        )
+    })
+}
+
+/// Adds the `pause_trigger` column to stores created before it existed, backfilling it from
+/// the stored wait state of paused sessions. The check and the `ALTER TABLE` share one write
+/// transaction so concurrent opens cannot both try to add the column.
+fn migrate_pause_trigger(conn: &mut Connection) -> Result<()> {
+    let tx = conn
+        .transaction_with_behavior(TransactionBehavior::Immediate)
+        .context("Failed to begin schema migration")?;
+    let has_column = tx
+        .prepare("SELECT 1 FROM pragma_table_info('sessions') WHERE name = 'pause_trigger'")?
+        .exists([])?;
+    if !has_column {
+        tx.execute_batch(
+            r#"
+            ALTER TABLE sessions ADD COLUMN pause_trigger TEXT;
+            UPDATE sessions
+            SET pause_trigger = json_extract(context_json, '$.wait_state.trigger')
+            WHERE status = 'paused';
+            "#,
+        )
+        .context("Failed to add pause_trigger column")?;
+    }
+    tx.commit().context("Failed to commit schema migration")?;
+    Ok(())
+}
+
+/// The wait-state trigger to store for a session saved with `status`; only paused sessions
+/// carry one.
+fn pause_trigger(context: &Context, status: SessionStatus) -> Option<String> {
+    if status != SessionStatus::Paused {
+        return None;
+    }
+    context.wait_state.as_ref().map(|wait| wait.trigger.clone())
+}
+
+/// Escape `LIKE` wildcards so the search text matches literally (paired with `ESCAPE '\'`).
+fn escape_like(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if matches!(ch, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+fn upsert_session(
+    conn: &Connection,
+    envelope: &SessionEnvelope,
+    status: SessionStatus,
+) -> Result<()> {
+    let context_json = serde_json::to_string(&envelope.context)?;
+    let metadata_json = serde_json::to_string(&envelope.metadata)?;
+    let now = timestamp();
+    conn.execute(
+        r#"
+        INSERT INTO sessions (session_id, domain, prompt, status, context_json, metadata_json, updated_at, pause_trigger)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+        ON CONFLICT(session_id)
+        DO UPDATE SET
+            domain=excluded.domain,
+            prompt=excluded.prompt,
+            status=excluded.status,
+            pause_trigger=excluded.pause_trigger,
+            context_json=excluded.context_json,
+            metadata_json=excluded.metadata_json,
+            updated_at=excluded.updated_at
+        "#,
+        params![
+            envelope.context.session_id,
+            envelope.context.domain,
+            envelope.context.prompt,
+            status.as_str(),
+            context_json,
+            metadata_json,
+            now,
+            pause_trigger(&envelope.context, status)
+        ],
+    )?;
+    Ok(())
+}
+
+fn decode_record(
+    status: &str,
+    context_json: &str,
+    metadata_json: &str,
+    updated_at: i64,
+) -> Result<SessionRecord> {
+    let status = SessionStatus::from_str(status)
+        .ok_or_else(|| anyhow!("Invalid status '{status}' in store"))?;
+    let context: Context = serde_json::from_str(context_json)?;
+    let metadata: SessionMetadata = serde_json::from_str(metadata_json)?;
+
+    Ok(SessionRecord {
+        envelope: SessionEnvelope { context, metadata },
+        status,
+        updated_at,
+    })
+}
+
 fn timestamp() -> i64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -249,20 +680,20 @@ impl SessionRepository for SessionStore {
     async fn save_session(&self, request: &SessionSaveRequest) -> crate::core::Result<()> {
         let store = self.clone();
         let request = request.clone();
-        tokio::task::spawn_blocking(move || {
-            let conn = store
-                .connect()
-                .map_err(|e| CoreError::Persistence(e.to_string()))?;
-            let now = timestamp();
-            conn.execute(
+        run_blocking(move || {
+            retry_on_lock(|| {
+                let conn = store.connect()?;
+                let now = timestamp();
+                conn.execute(
                 r#"
-            INSERT INTO sessions (session_id, domain, prompt, status, context_json, metadata_json, updated_at)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            INSERT INTO sessions (session_id, domain, prompt, status, context_json, metadata_json, updated_at, pause_trigger)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
             ON CONFLICT(session_id)
             DO UPDATE SET
                 domain=excluded.domain,
                 prompt=excluded.prompt,
                 status=excluded.status,
+                pause_trigger=excluded.pause_trigger,
                 context_json=excluded.context_json,
                 metadata_json=excluded.metadata_json,
                 updated_at=excluded.updated_at
@@ -274,14 +705,53 @@ impl SessionRepository for SessionStore {
                     request.status,
                     request.context_json,
                     request.metadata_json,
-                    now
+                    now,
+                    request.pause_trigger
                 ],
-            )
+            )?;
+                Ok(())
+            })
+            .map_err(|e| CoreError::Persistence(e.to_string()))
+        })
+        .await
+    }
+
+    async fn insert_session(&self, request: &SessionSaveRequest) -> crate::core::Result<()> {
+        let store = self.clone();
+        let request = request.clone();
+        run_blocking(move || {
+            let inserted = retry_on_lock(|| {
+                let conn = store.connect()?;
+                let rows = conn.execute(
+                    r#"
+            INSERT INTO sessions (session_id, domain, prompt, status, context_json, metadata_json, updated_at, pause_trigger)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            ON CONFLICT(session_id) DO NOTHING
+            "#,
+                    params![
+                        request.session_id,
+                        request.domain,
+                        request.prompt,
+                        request.status,
+                        request.context_json,
+                        request.metadata_json,
+                        timestamp(),
+                        request.pause_trigger
+                    ],
+                )?;
+                Ok(rows > 0)
+            })
             .map_err(|e| CoreError::Persistence(e.to_string()))?;
-            Ok(())
+            if inserted {
+                Ok(())
+            } else {
+                Err(CoreError::InvalidState(format!(
+                    "session id {} already in use",
+                    request.session_id
+                )))
+            }
         })
         .await
-        .map_err(|e| CoreError::System(format!("Join error: {e}")))?
     }
 
     async fn load_session(
@@ -290,14 +760,15 @@ impl SessionRepository for SessionStore {
     ) -> crate::core::Result<Option<SessionLoadResponse>> {
         let store = self.clone();
         let session_id = session_id.to_string();
-        tokio::task::spawn_blocking(move || {
+        run_blocking(move || {
             let conn = store
                 .connect()
                 .map_err(|e| CoreError::Persistence(e.to_string()))?;
             let mut stmt = conn
                 .prepare(
                     r#"
-                SELECT domain, prompt, status, context_json, metadata_json, updated_at
+                SELECT domain, prompt, status, context_json, metadata_json, updated_at,
+                       pause_trigger
                 FROM sessions
                 WHERE session_id = ?1
                 "#,
@@ -313,7 +784,7 @@ impl SessionRepository for SessionStore {
                 .map_err(|e| CoreError::Persistence(e.to_string()))?
             {
                 Ok(Some(SessionLoadResponse {
-                    session_id,
+                    session_id: session_id.clone(),
                     domain: row
                         .get(0)
                         .map_err(|e| CoreError::Persistence(e.to_string()))?,
@@ -332,25 +803,28 @@ impl SessionRepository for SessionStore {
                     updated_at: row
                         .get(5)
                         .map_err(|e| CoreError::Persistence(e.to_string()))?,
+                    pause_trigger: row
+                        .get(6)
+                        .map_err(|e| CoreError::Persistence(e.to_string()))?,
                 }))
             } else {
                 Ok(None)
             }
         })
         .await
-        .map_err(|e| CoreError::System(format!("Join error: {e}")))?
     }
 
     async fn list_sessions(&self, limit: usize) -> crate::core::Result<Vec<SessionLoadResponse>> {
         let store = self.clone();
-        tokio::task::spawn_blocking(move || {
+        run_blocking(move || {
             let conn = store
                 .connect()
                 .map_err(|e| CoreError::Persistence(e.to_string()))?;
             let mut stmt = conn
                 .prepare(
                     r#"
-                SELECT session_id, domain, prompt, status, context_json, metadata_json, updated_at
+                SELECT session_id, domain, prompt, status, context_json, metadata_json, updated_at,
+                       pause_trigger
                 FROM sessions
                 ORDER BY updated_at DESC
                 LIMIT ?1
@@ -368,6 +842,7 @@ impl SessionRepository for SessionStore {
                         context_json: row.get(4)?,
                         metadata_json: row.get(5)?,
                         updated_at: row.get(6)?,
+                        pause_trigger: row.get(7)?,
                     })
                 })
                 .map_err(|e| CoreError::Persistence(e.to_string()))?;
@@ -379,7 +854,81 @@ impl SessionRepository for SessionStore {
             Ok(result)
         })
         .await
-        .map_err(|e| CoreError::System(format!("Join error: {e}")))?
+    }
+
+    async fn find_sessions(
+        &self,
+        filter: &SessionFilter,
+    ) -> crate::core::Result<Vec<SessionLoadResponse>> {
+        let store = self.clone();
+        let filter = filter.clone();
+        run_blocking(move || {
+            let mut result = Vec::new();
+            store
+                .for_each_row(&filter, |row| {
+                    result.push(row);
+                    Ok(())
+                })
+                .map_err(|e| CoreError::Persistence(e.to_string()))?;
+            Ok(result)
+        })
+        .await
+    }
+
+    async fn delete_session(&self, session_id: &str) -> crate::core::Result<bool> {
+        let store = self.clone();
+        let session_id = session_id.to_string();
+        run_blocking(move || {
+            retry_on_lock(|| store.with_transaction(|tx| tx.delete(&session_id)))
+                .map_err(|e| CoreError::Persistence(e.to_string()))
+        })
+        .await
+    }
+
+    async fn add_note(
+        &self,
+        session_id: &str,
+        note: &str,
+    ) -> crate::core::Result<Option<SessionNoteRecord>> {
+        let store = self.clone();
+        let session_id = session_id.to_string();
+        let note = note.to_string();
+        run_blocking(move || {
+            retry_on_lock(|| store.with_transaction(|tx| tx.add_note(&session_id, &note)))
+                .map_err(|e| CoreError::Persistence(e.to_string()))
+        })
+        .await
+    }
+
+    async fn list_notes(&self, session_id: &str) -> crate::core::Result<Vec<SessionNoteRecord>> {
+        let store = self.clone();
+        let session_id = session_id.to_string();
+        run_blocking(move || {
+            store
+                .notes(&session_id)
+                .map_err(|e| CoreError::Persistence(e.to_string()))
+        })
+        .await
+    }
+
+    async fn for_each_session(
+        &self,
+        limit: Option<usize>,
+        visit: &mut SessionVisitor<'_>,
+    ) -> crate::core::Result<usize> {
+        // The visitor borrows from the caller, so rows are read on this thread rather than
+        // moved to the blocking pool.
+        self.for_each_row(
+            &SessionFilter {
+                limit,
+                ..SessionFilter::default()
+            },
+            |row| visit(row).map_err(anyhow::Error::from),
+        )
+        .map_err(|e| match e.downcast::<CoreError>() {
+            Ok(core) => core,
+            Err(other) => CoreError::Persistence(other.to_string()),
+        })
     }
 }
 
@@ -403,8 +952,8 @@ mod tests {
                 max_concurrent_llm: 2,
                 samples: 2,
                 k: 2,
-                adaptive_k: false,
                 human_low_margin_threshold: 1,
+                ..Default::default()
             },
         };
 
@@ -421,4 +970,395 @@ mod tests {
         assert_eq!(list.len(), 1);
         assert_eq!(list[0].session_id, "test-session");
     }
+
+    #[tokio::test]
+    async fn notes_round_trip_and_are_deleted_with_their_session() {
+        let temp = tempdir().unwrap();
+        let store = SessionStore::open(Some(temp.path().to_path_buf())).unwrap();
+        let mut ctx = Context::new("demo task", "code");
+        ctx.session_id = "noted".into();
+        let envelope = SessionEnvelope {
+            context: ctx,
+            metadata: SessionMetadata {
+                config_path: "config.yaml".into(),
+                llm_provider: "openai".into(),
+                llm_model: "gpt".into(),
+                max_concurrent_llm: 1,
+                samples: 1,
+                k: 1,
+                human_low_margin_threshold: 1,
+                ..Default::default()
+            },
+        };
+        store.save(&envelope, SessionStatus::Completed).unwrap();
+
+        let first = store
+            .add_note("noted", "known flaky")
+            .await
+            .unwrap()
+            .unwrap();
+        store.add_note("noted", "approved by ops").await.unwrap();
+        assert_eq!(first.note, "known flaky");
+        assert!(store.add_note("missing", "lost").await.unwrap().is_none());
+
+        let notes = store.list_notes("noted").await.unwrap();
+        let texts: Vec<&str> = notes.iter().map(|n| n.note.as_str()).collect();
+        assert_eq!(texts, ["known flaky", "approved by ops"]);
+        assert_eq!(notes[0], first);
+
+        // Reopening reads the same notes back from disk.
+        let reopened = SessionStore::open(Some(temp.path().to_path_buf())).unwrap();
+        assert_eq!(reopened.notes("noted").unwrap(), notes);
+
+        assert!(store.delete_session("noted").await.unwrap());
+        assert!(store.list_notes("noted").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn insert_session_refuses_an_existing_id() {
+        let temp = tempdir().unwrap();
+        let store = SessionStore::open(Some(temp.path().to_path_buf())).unwrap();
+        let envelope = |prompt: &str| {
+            let mut ctx = Context::new(prompt, "code");
+            ctx.session_id = "fixed-id".into();
+            SessionEnvelope {
+                context: ctx,
+                metadata: SessionMetadata {
+                    config_path: "config.yaml".into(),
+                    llm_provider: "openai".into(),
+                    llm_model: "gpt".into(),
+                    max_concurrent_llm: 2,
+                    samples: 2,
+                    k: 2,
+                    human_low_margin_threshold: 1,
+                    ..Default::default()
+                },
+            }
+            .to_save_request(SessionStatus::Running)
+            .unwrap()
+        };
+
+        store.insert_session(&envelope("first run")).await.unwrap();
+        let err = store
+            .insert_session(&envelope("second run"))
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("already in use"), "{err}");
+        let record = store.load("fixed-id").unwrap();
+        assert_eq!(record.envelope.context.prompt, "first run");
+    }
+
+    #[test]
+    fn for_each_session_streams_one_record_per_callback() {
+        let temp = tempdir().unwrap();
+        let store = SessionStore::open(Some(temp.path().to_path_buf())).unwrap();
+        for idx in 0..3 {
+            let mut ctx = Context::new(format!("task {idx}"), "code");
+            ctx.session_id = format!("session-{idx}");
+            let envelope = SessionEnvelope {
+                context: ctx,
+                metadata: SessionMetadata {
+                    config_path: "config.yaml".into(),
+                    llm_provider: "openai".into(),
+                    llm_model: "gpt".into(),
+                    max_concurrent_llm: 1,
+                    samples: 1,
+                    k: 1,
+                    human_low_margin_threshold: 1,
+                    ..Default::default()
+                },
+            };
+            store.save(&envelope, SessionStatus::Completed).unwrap();
+        }
+
+        // The callback only ever sees a single owned record, so the caller decides what to
+        // retain; here we serialize each record straight into an NDJSON buffer.
+        let mut buffer = Vec::new();
+        let visited = store
+            .for_each_session(None, |record| {
+                serde_json::to_writer(&mut buffer, &record.envelope.context.session_id)?;
+                buffer.push(b'\n');
+                Ok(())
+            })
+            .expect("streamed");
+        let output = String::from_utf8(buffer).unwrap();
+        assert_eq!(visited, 3);
+        assert_eq!(output.lines().count(), visited);
+
+        let limited = store.for_each_session(Some(2), |_| Ok(())).unwrap();
+        assert_eq!(limited, 2);
+    }
+
+    #[tokio::test]
+    async fn find_sessions_matches_prompt_substrings() {
+        let temp = tempdir().unwrap();
+        let store = SessionStore::open(Some(temp.path().to_path_buf())).unwrap();
+        let prompts = [
+            ("fix-parser", "Fix the Parser crash on empty input"),
+            ("add-logging", "Add logging to the parser"),
+            ("percent", "Raise coverage to 100% on utils"),
+            ("unrelated", "Rename the config loader"),
+        ];
+        for (id, prompt) in prompts {
+            let mut ctx = Context::new(prompt, "code");
+            ctx.session_id = id.into();
+            let envelope = SessionEnvelope {
+                context: ctx,
+                metadata: SessionMetadata {
+                    config_path: "config.yaml".into(),
+                    llm_provider: "openai".into(),
+                    llm_model: "gpt".into(),
+                    max_concurrent_llm: 1,
+                    samples: 1,
+                    k: 1,
+                    human_low_margin_threshold: 1,
+                    ..Default::default()
+                },
+            };
+            store.save(&envelope, SessionStatus::Completed).unwrap();
+        }
+        let search = |text: &str| {
+            let store = store.clone();
+            let filter = SessionFilter {
+                prompt_contains: Some(text.to_string()),
+                ..SessionFilter::default()
+            };
+            async move {
+                let mut ids: Vec<String> = store
+                    .find_sessions(&filter)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|row| row.session_id)
+                    .collect();
+                ids.sort();
+                ids
+            }
+        };
+
+        assert_eq!(search("parser").await, vec!["add-logging", "fix-parser"]);
+        // Wildcards in the query are matched literally.
+        assert_eq!(search("100%").await, vec!["percent"]);
+        assert_eq!(search("%").await, vec!["percent"]);
+        assert!(search("_").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn pause_trigger_is_stored_and_listed() {
+        let temp = tempdir().unwrap();
+        let store = SessionStore::open(Some(temp.path().to_path_buf())).unwrap();
+        let envelope = |id: &str| {
+            let mut ctx = Context::new("demo task", "code");
+            ctx.session_id = id.into();
+            ctx.set_checkpoint(1, "step_by_step_checkpoint", "review step 1");
+            SessionEnvelope {
+                context: ctx,
+                metadata: SessionMetadata {
+                    config_path: "config.yaml".into(),
+                    llm_provider: "openai".into(),
+                    llm_model: "gpt".into(),
+                    max_concurrent_llm: 1,
+                    samples: 1,
+                    k: 1,
+                    human_low_margin_threshold: 1,
+                    ..Default::default()
+                },
+            }
+        };
+        store
+            .save(&envelope("paused"), SessionStatus::Paused)
+            .unwrap();
+        // A stale wait state on a session that is no longer paused is not reported.
+        store
+            .save(&envelope("failed"), SessionStatus::Failed)
+            .unwrap();
+        store
+            .save_session(
+                &envelope("via-port")
+                    .to_save_request(SessionStatus::Paused)
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let mut summaries: Vec<(String, Option<String>)> = store
+            .list(10)
+            .unwrap()
+            .into_iter()
+            .map(|summary| (summary.session_id, summary.pause_trigger))
+            .collect();
+        summaries.sort();
+        let checkpoint = Some("step_by_step_checkpoint".to_string());
+        assert_eq!(
+            summaries,
+            vec![
+                ("failed".to_string(), None),
+                ("paused".to_string(), checkpoint.clone()),
+                ("via-port".to_string(), checkpoint.clone()),
+            ]
+        );
+        let loaded = store.load_session("paused").await.unwrap().unwrap();
+        assert_eq!(loaded.pause_trigger, checkpoint);
+    }
+
+    #[test]
+    fn opening_a_legacy_store_adds_and_backfills_pause_trigger() {
+        let temp = tempdir().unwrap();
+        let mut ctx = Context::new("demo task", "code");
+        ctx.session_id = "legacy".into();
+        ctx.set_checkpoint(1, "low_margin", "margin 0 below threshold 1");
+        let conn = Connection::open(temp.path().join("sessions.sqlite3")).unwrap();
+        conn.execute_batch(
+            r#"
+            CREATE TABLE sessions (
+                session_id TEXT PRIMARY KEY,
+                domain TEXT NOT NULL,
+                prompt TEXT NOT NULL,
+                status TEXT NOT NULL,
+                context_json TEXT NOT NULL,
+                metadata_json TEXT NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+            "#,
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO sessions VALUES ('legacy', 'code', 'demo task', 'paused', ?1, '{}', 1)",
+            params![serde_json::to_string(&ctx).unwrap()],
+        )
+        .unwrap();
+        drop(conn);
+
+        let store = SessionStore::open(Some(temp.path().to_path_buf())).unwrap();
+        let summaries = store.list(10).unwrap();
+        assert_eq!(summaries[0].pause_trigger.as_deref(), Some("low_margin"));
+        // Reopening an already migrated store is a no-op.
+        SessionStore::open(Some(temp.path().to_path_buf())).unwrap();
+    }
+
+    #[test]
+    fn concurrent_writers_do_not_hit_lock_errors() {
+        let temp = tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        SessionStore::open(Some(root.clone())).unwrap();
+
+        let writers: Vec<_> = ["a", "b"]
+            .into_iter()
+            .map(|writer| {
+                let root = root.clone();
+                std::thread::spawn(move || -> Result<()> {
+                    // Each writer opens its own store, like separate processes would.
+                    let store = SessionStore::open(Some(root))?;
+                    for idx in 0..25 {
+                        let mut ctx = Context::new(format!("task {idx}"), "code");
+                        ctx.session_id = format!("{writer}-{idx}");
+                        let envelope = SessionEnvelope {
+                            context: ctx,
+                            metadata: SessionMetadata {
+                                config_path: "config.yaml".into(),
+                                llm_provider: "openai".into(),
+                                llm_model: "gpt".into(),
+                                max_concurrent_llm: 1,
+                                samples: 1,
+                                k: 1,
+                                human_low_margin_threshold: 1,
+                                ..Default::default()
+                            },
+                        };
+                        if idx % 2 == 0 {
+                            store.save(&envelope, SessionStatus::Running)?;
+                        } else {
+                            store
+                                .with_transaction(|tx| tx.save(&envelope, SessionStatus::Paused))?;
+                        }
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer
+                .join()
+                .unwrap()
+                .expect("writer finished without lock errors");
+        }
+
+        let store = SessionStore::open(Some(root)).unwrap();
+        assert_eq!(store.list(100).unwrap().len(), 50);
+    }
+
+    #[test]
+    fn import_failing_partway_leaves_store_unchanged() {
+        let temp = tempdir().unwrap();
+        let store = SessionStore::open(Some(temp.path().to_path_buf())).unwrap();
+        let envelope = |id: &str| {
+            let mut ctx = Context::new(format!("task {id}"), "code");
+            ctx.session_id = id.into();
+            SessionEnvelope {
+                context: ctx,
+                metadata: SessionMetadata {
+                    config_path: "config.yaml".into(),
+                    llm_provider: "openai".into(),
+                    llm_model: "gpt".into(),
+                    max_concurrent_llm: 1,
+                    samples: 1,
+                    k: 1,
+                    human_low_margin_threshold: 1,
+                    ..Default::default()
+                },
+            }
+        };
+        store
+            .save(&envelope("existing"), SessionStatus::Paused)
+            .unwrap();
+
+        let mut changed = envelope("existing");
+        changed.context.prompt = "overwritten".into();
+        let err = store
+            .import(vec![
+                Ok((envelope("new-1"), SessionStatus::Completed)),
+                Ok((changed, SessionStatus::Completed)),
+                Err(anyhow!("corrupt line 3")),
+                Ok((envelope("new-2"), SessionStatus::Completed)),
+            ])
+            .unwrap_err();
+        assert!(err.to_string().contains("corrupt line 3"));
+
+        let sessions = store.list(10).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].session_id, "existing");
+        assert_eq!(sessions[0].status, SessionStatus::Paused);
+        assert_eq!(sessions[0].prompt, "task existing");
+
+        let imported = store
+            .import(vec![Ok((envelope("new-1"), SessionStatus::Completed))])
+            .unwrap();
+        assert_eq!(imported, 1);
+        assert_eq!(store.list(10).unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn panicking_blocking_task_reports_the_panic_message() {
+        let err = run_blocking(|| -> crate::core::Result<()> { panic!("disk on fire") })
+            .await
+            .unwrap_err();
+
+        let CoreError::System(message) = err else {
+            panic!("expected a system error, got {err:?}");
+        };
+        assert!(message.contains("panicked"), "{message}");
+        assert!(message.contains("disk on fire"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn blocking_task_errors_pass_through_unchanged() {
+        let err = run_blocking(|| -> crate::core::Result<()> {
+            Err(CoreError::Persistence("row missing".into()))
+        })
+        .await
+        .unwrap_err();
+
+        assert!(matches!(err, CoreError::Persistence(message) if message == "row missing"));
+    }
 }
@@ -0,0 +1,102 @@
+use std::process::Command;
+#[cfg(test)]
+use std::sync::Mutex;
+
+use anyhow::Context as _;
+
+use crate::core::ports::{CommandOutput, CommandRunner};
+use crate::core::{Error, Result};
+
+/// Runs commands with `sh -c` in the current working directory.
+#[derive(Debug, Default)]
+pub struct StdCommandRunner;
+
+impl StdCommandRunner {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl CommandRunner for StdCommandRunner {
+    fn run(&self, command: &str) -> Result<CommandOutput> {
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .output()
+            .map_err(|e| Error::System(format!("Failed to spawn `{command}`: {e}")))?;
+        Ok(CommandOutput {
+            status: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+}
+
+/// Spawns a detached `microfactory resume --session-id <id>` using the current executable, so
+/// a paused session continues in the background while the caller returns.
+pub fn spawn_background_resume(session_id: &str) -> anyhow::Result<()> {
+    let exe = std::env::current_exe().context("Failed to determine current executable path")?;
+    tracing::info!(session_id, "Spawning background resume process");
+    Command::new(exe)
+        .arg("resume")
+        .arg("--session-id")
+        .arg(session_id)
+        .spawn()
+        .context("Failed to spawn resume process")?;
+    Ok(())
+}
+
+/// Scripted [`CommandRunner`] that records invocations instead of spawning processes.
+/// Returns the queued outputs in order, then a successful empty output once exhausted.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MockCommandRunner {
+    outputs: Mutex<Vec<CommandOutput>>,
+    commands: Mutex<Vec<String>>,
+}
+
+#[cfg(test)]
+impl MockCommandRunner {
+    pub fn new(outputs: Vec<CommandOutput>) -> Self {
+        Self {
+            outputs: Mutex::new(outputs),
+            commands: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Commands received so far, in call order.
+    pub fn commands(&self) -> Vec<String> {
+        self.commands.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl CommandRunner for MockCommandRunner {
+    fn run(&self, command: &str) -> Result<CommandOutput> {
+        self.commands.lock().unwrap().push(command.to_string());
+        let mut outputs = self.outputs.lock().unwrap();
+        if outputs.is_empty() {
+            return Ok(CommandOutput {
+                status: Some(0),
+                ..CommandOutput::default()
+            });
+        }
+        Ok(outputs.remove(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn std_runner_captures_status_and_output() {
+        let output = StdCommandRunner::new()
+            .run("echo out; echo err >&2; exit 3")
+            .unwrap();
+        assert_eq!(output.status, Some(3));
+        assert!(!output.success());
+        assert_eq!(output.stdout, "out\n");
+        assert_eq!(output.stderr, "err\n");
+    }
+}
@@ -1,5 +1,12 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::Mutex,
+};
 
+use serde_json::json;
 use tracing::event;
 
 use crate::core::ports::TelemetrySink;
@@ -18,3 +25,79 @@ impl TelemetrySink for TracingTelemetrySink {
         event!(target: "microfactory::telemetry", tracing::Level::INFO, %event_name, props = ?properties);
     }
 }
+
+/// Appends one JSON object per event (`event` plus sorted `properties`) to a file. Writes are
+/// buffered; [`TelemetrySink::flush`] or dropping the sink writes out whatever is pending.
+#[derive(Debug)]
+pub struct FileTelemetrySink {
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl FileTelemetrySink {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path.as_ref())?;
+        Ok(Self {
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+}
+
+impl TelemetrySink for FileTelemetrySink {
+    fn record_event(&self, event_name: &str, properties: HashMap<String, String>) {
+        let properties: BTreeMap<_, _> = properties.into_iter().collect();
+        let line = json!({ "event": event_name, "properties": properties });
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(err) = writeln!(writer, "{line}") {
+            tracing::warn!("Failed to record telemetry event {event_name}: {err}");
+        }
+    }
+
+    fn flush(&self) {
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(err) = writer.flush() {
+            tracing::warn!("Failed to flush telemetry events: {err}");
+        }
+    }
+}
+
+impl Drop for FileTelemetrySink {
+    fn drop(&mut self) {
+        TelemetrySink::flush(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffered_events_reach_the_file_when_the_sink_is_dropped() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("telemetry.jsonl");
+        let sink = FileTelemetrySink::open(&path).unwrap();
+
+        sink.record_event("session_started", HashMap::new());
+        sink.record_event(
+            "session_paused",
+            HashMap::from([("step_id".to_string(), "3".to_string())]),
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+
+        drop(sink);
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                json!({ "event": "session_started", "properties": {} }),
+                json!({ "event": "session_paused", "properties": { "step_id": "3" } }),
+            ]
+        );
+    }
+}
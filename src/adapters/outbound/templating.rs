@@ -1,4 +1,4 @@
-use handlebars::Handlebars;
+use handlebars::{Handlebars, handlebars_helper};
 use serde_json::Value;
 use std::sync::Arc;
 
@@ -16,10 +16,23 @@ impl Default for HandlebarsRenderer {
     }
 }
 
+// Formatting helpers available to every prompt template.
+handlebars_helper!(json: |value: Json| serde_json::to_string(value).unwrap_or_default());
+handlebars_helper!(upper: |value: str| value.to_uppercase());
+handlebars_helper!(lower: |value: str| value.to_lowercase());
+handlebars_helper!(truncate: |value: str, max_chars: u64| {
+    value.chars().take(max_chars as usize).collect::<String>()
+});
+
 impl HandlebarsRenderer {
+    /// Build a renderer with the `json`, `upper`, `lower`, and `truncate` helpers registered.
     pub fn new() -> Self {
         let mut handlebars = Handlebars::new();
         handlebars.set_strict_mode(false);
+        handlebars.register_helper("json", Box::new(json));
+        handlebars.register_helper("upper", Box::new(upper));
+        handlebars.register_helper("lower", Box::new(lower));
+        handlebars.register_helper("truncate", Box::new(truncate));
         Self {
             engine: Arc::new(handlebars),
         }
@@ -33,3 +46,38 @@ impl PromptRenderer for HandlebarsRenderer {
             .map_err(|e| CoreError::TemplateRendering(e.to_string()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn render(template: &str, data: Value) -> String {
+        HandlebarsRenderer::new().render(template, &data).unwrap()
+    }
+
+    #[test]
+    fn json_helper_serializes_values() {
+        let data = json!({"context": {"files": ["a.rs", "b.rs"], "strict": true}});
+        assert_eq!(
+            render("{{{json context}}}", data),
+            r#"{"files":["a.rs","b.rs"],"strict":true}"#
+        );
+    }
+
+    #[test]
+    fn case_helpers_change_case() {
+        let data = json!({"role": "Solver"});
+        assert_eq!(
+            render("{{upper role}}/{{lower role}}", data),
+            "SOLVER/solver"
+        );
+    }
+
+    #[test]
+    fn truncate_helper_limits_characters() {
+        let data = json!({"task": "héllo world"});
+        assert_eq!(render("{{truncate task 5}}", data.clone()), "héllo");
+        assert_eq!(render("{{truncate task 50}}", data), "héllo world");
+    }
+}
@@ -0,0 +1,196 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tracing::{debug, warn};
+
+use crate::core::{
+    Result,
+    error::Error as CoreError,
+    ports::{ChatMessage, LlmClient, LlmOptions, LlmResponse, TokenBudget},
+};
+
+/// [`LlmClient`] decorator that adds the usage each call reports to a [`TokenBudget`] and
+/// refuses new calls once the budget is exhausted, which stops the sampling in progress.
+/// Calls whose backend does not report usage are let through uncounted.
+pub struct TokenBudgetLlmClient {
+    inner: Arc<dyn LlmClient>,
+    budget: Arc<TokenBudget>,
+}
+
+impl TokenBudgetLlmClient {
+    pub fn new(inner: Arc<dyn LlmClient>, budget: Arc<TokenBudget>) -> Self {
+        Self { inner, budget }
+    }
+
+    fn ensure_available(&self) -> Result<()> {
+        if self.budget.exhausted() {
+            return Err(CoreError::InvalidState(format!(
+                "Token budget exhausted: {} of {} tokens used",
+                self.budget.used(),
+                self.budget.limit()
+            )));
+        }
+        Ok(())
+    }
+
+    fn record(&self, model: &str, response: &LlmResponse) {
+        let Some(usage) = response.usage else {
+            return;
+        };
+        let total = self.budget.record(usage);
+        let before = total.saturating_sub(usage.total());
+        debug!(
+            model,
+            input_tokens = usage.input_tokens,
+            output_tokens = usage.output_tokens,
+            total_tokens = total,
+            limit = self.budget.limit(),
+            "LLM token usage"
+        );
+        if before <= self.budget.limit() && total > self.budget.limit() {
+            warn!(
+                used = total,
+                limit = self.budget.limit(),
+                "Token budget exhausted; refusing further LLM calls"
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl LlmClient for TokenBudgetLlmClient {
+    async fn chat_completion(
+        &self,
+        model: &str,
+        prompt: &str,
+        options: &LlmOptions,
+    ) -> Result<String> {
+        self.chat_completion_with_finish_reason(model, prompt, options)
+            .await
+            .map(|response| response.content)
+    }
+
+    async fn chat_completion_with_finish_reason(
+        &self,
+        model: &str,
+        prompt: &str,
+        options: &LlmOptions,
+    ) -> Result<LlmResponse> {
+        self.ensure_available()?;
+        let response = self
+            .inner
+            .chat_completion_with_finish_reason(model, prompt, options)
+            .await?;
+        self.record(model, &response);
+        Ok(response)
+    }
+
+    async fn chat(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        options: &LlmOptions,
+    ) -> Result<String> {
+        self.chat_with_finish_reason(model, messages, options)
+            .await
+            .map(|response| response.content)
+    }
+
+    async fn chat_with_finish_reason(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        options: &LlmOptions,
+    ) -> Result<LlmResponse> {
+        self.ensure_available()?;
+        let response = self
+            .inner
+            .chat_with_finish_reason(model, messages, options)
+            .await?;
+        self.record(model, &response);
+        Ok(response)
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        self.inner.list_models().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ports::TokenUsage;
+
+    struct FixedUsageLlm;
+
+    #[async_trait]
+    impl LlmClient for FixedUsageLlm {
+        async fn chat_completion(
+            &self,
+            _model: &str,
+            prompt: &str,
+            _options: &LlmOptions,
+        ) -> Result<String> {
+            Ok(prompt.to_string())
+        }
+
+        async fn chat_completion_with_finish_reason(
+            &self,
+            _model: &str,
+            prompt: &str,
+            _options: &LlmOptions,
+        ) -> Result<LlmResponse> {
+            Ok(LlmResponse::new(prompt).with_usage(TokenUsage {
+                input_tokens: 30,
+                output_tokens: 20,
+            }))
+        }
+
+        async fn chat_with_finish_reason(
+            &self,
+            _model: &str,
+            _messages: &[ChatMessage],
+            _options: &LlmOptions,
+        ) -> Result<LlmResponse> {
+            Ok(LlmResponse::new("PASS").with_usage(TokenUsage {
+                input_tokens: 70,
+                output_tokens: 10,
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn calls_are_refused_once_the_budget_is_crossed() {
+        let budget = Arc::new(TokenBudget::new(120));
+        let client = TokenBudgetLlmClient::new(Arc::new(FixedUsageLlm), budget.clone());
+        let options = LlmOptions::default();
+
+        for _ in 0..3 {
+            client.chat_completion("m", "hi", &options).await.unwrap();
+        }
+        assert_eq!(budget.used(), 150);
+        assert!(budget.exhausted());
+
+        let err = client
+            .chat_completion("m", "hi", &options)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("150 of 120"), "{err}");
+        assert_eq!(budget.used(), 150);
+    }
+
+    #[tokio::test]
+    async fn chat_calls_count_against_the_budget() {
+        let budget = Arc::new(TokenBudget::new(100));
+        let client = TokenBudgetLlmClient::new(Arc::new(FixedUsageLlm), budget.clone());
+        let options = LlmOptions::default();
+        let messages = [ChatMessage::user("verify this")];
+
+        assert_eq!(client.chat("m", &messages, &options).await.unwrap(), "PASS");
+        client.chat("m", &messages, &options).await.unwrap();
+        assert_eq!(budget.used(), 160);
+
+        let err = client.chat("m", &messages, &options).await.unwrap_err();
+        assert!(err.to_string().contains("160 of 100"), "{err}");
+    }
+}
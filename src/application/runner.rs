@@ -1,24 +1,53 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
 
 use anyhow::{Context, Result, anyhow};
-use tracing::{debug, info};
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, Span, debug, info, info_span, warn};
 
 use crate::{
     application::tasks::{
-        ApplyVerifyTask, DecompositionTask, DecompositionVoteTask, MicroTask, NextAction,
-        SolutionVoteTask, SolveTask, TaskEffect,
+        ApplyVerifyTask, DEFAULT_RED_FLAG_CONCURRENCY, DecompositionTask, DecompositionVoteTask,
+        LlmVerifier, MicroTask, NextAction, PromptDump, PromptRecorder, SolutionVoteTask,
+        SolveTask, TaskEffect, adopt_sole_proposal,
     },
+    apply_ignore::{IGNORE_FILE_NAME, IgnoreRules},
     config::MicrofactoryConfig,
     core::{
         config::{AgentDefaults, AgentSettings, DomainRuntimeConfig},
         domain::{
-            AgentConfig, AgentKind, Context as WorkflowContext, StepStatus, WaitState, WorkItem,
+            AdaptiveKLimits, AgentConfig, AgentKind, Context as WorkflowContext, LineEnding,
+            OutputEncoding, RecursionDecision, StepStatus, WaitState, WorkItem,
+        },
+        ports::{
+            CheckpointDecision, CheckpointReviewer, Clock, CommandRunner, FileSystem, LlmClient,
+            NoCommandRunner, PromptRenderer, TelemetrySink, TokenBudget,
         },
-        ports::{Clock, FileSystem, LlmClient, PromptRenderer, TelemetrySink},
     },
-    red_flaggers::RedFlagPipeline,
+    red_flaggers::{RedFlagPipeline, Workspace},
 };
 
+/// Span wrapping one `MicroTask::run`, so profiles attribute time to a work item's kind and step
+/// (`micro_task{task=decomposition step_id=3}`).
+fn task_span(item: &WorkItem) -> Span {
+    info_span!("micro_task", task = item.kind(), step_id = item.step_id())
+}
+
+/// Debug events (visible with `--verbose`) for the queue changes since the last call.
+fn log_queue_transitions(context: &mut WorkflowContext) {
+    for transition in context.drain_queue_transitions() {
+        debug!(
+            op = transition.op,
+            kind = transition.kind,
+            step_id = transition.step_id,
+            queue_len = transition.queue_len,
+            "Work queue transition"
+        );
+    }
+}
+
 /// Orchestrates MAKER-style workflows across decomposition, solving, and voting tasks.
 pub struct FlowRunner {
     config: Arc<MicrofactoryConfig>,
@@ -28,6 +57,18 @@ pub struct FlowRunner {
     file_system: Arc<dyn FileSystem>,
     clock: Arc<dyn Clock>,
     telemetry: Arc<dyn TelemetrySink>,
+    command_runner: Arc<dyn CommandRunner>,
+    checkpoint_reviewer: Option<Arc<dyn CheckpointReviewer>>,
+    cancellation: Option<CancellationToken>,
+    token_budget: Option<Arc<TokenBudget>>,
+}
+
+/// Which step-by-step checkpoint is being reviewed; decides what reject/edit mean.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CheckpointStage {
+    Plan,
+    PreApply,
+    StepDone,
 }
 
 impl FlowRunner {
@@ -48,9 +89,42 @@ impl FlowRunner {
             file_system,
             clock,
             telemetry,
+            command_runner: Arc::new(NoCommandRunner),
+            checkpoint_reviewer: None,
+            cancellation: None,
+            token_budget: None,
         }
     }
 
+    /// Review step-by-step checkpoints in-process instead of pausing the session.
+    pub fn with_checkpoint_reviewer(
+        mut self,
+        reviewer: Option<Arc<dyn CheckpointReviewer>>,
+    ) -> Self {
+        self.checkpoint_reviewer = reviewer;
+        self
+    }
+
+    /// Stop before the next LLM batch once `token` is cancelled, pausing the session with a
+    /// `cancelled` wait state so the interrupted work item is retried on resume.
+    pub fn with_cancellation(mut self, token: Option<CancellationToken>) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Pause with a `token_budget_exhausted` wait state once `budget` is spent, both before the
+    /// next work item and when a task fails because the budgeted client refused a call.
+    pub fn with_token_budget(mut self, budget: Option<Arc<TokenBudget>>) -> Self {
+        self.token_budget = budget;
+        self
+    }
+
+    /// Set the process runner used for verifier commands; without one, they fail.
+    pub fn with_command_runner(mut self, command_runner: Arc<dyn CommandRunner>) -> Self {
+        self.command_runner = command_runner;
+        self
+    }
+
     /// Executes pending work items stored in the context until completion or a human-in-loop pause.
     pub async fn execute(&self, context: &mut WorkflowContext) -> Result<RunnerOutcome> {
         debug!(
@@ -73,6 +147,21 @@ impl FlowRunner {
             })?;
         let agent_configs = self.agent_configs(&domain_cfg);
         let domain_flaggers = &domain_cfg.red_flaggers;
+        let prompt_dump = self.options.dump_prompts.then(|| {
+            let root = context.output_dir.clone().unwrap_or_default();
+            Arc::new(PromptDump::new(
+                self.file_system.clone(),
+                root.join("prompts"),
+            ))
+        });
+        let prompt_recorder = if self.options.record_prompts {
+            Some(Arc::new(PromptRecorder::new(
+                &domain_cfg.prompt_redactions,
+            )?))
+        } else {
+            None
+        };
+        let ignore_rules = Arc::new(self.load_ignore_rules(context)?);
         // Red flaggers are now resolved per-agent inside the loop.
 
         if context.root_step_id().is_none() {
@@ -96,22 +185,45 @@ impl FlowRunner {
         self.emit_telemetry(context, "runner_execute_start", start_props);
 
         while let Some(item) = context.dequeue_work() {
+            log_queue_transitions(context);
             let current_item = item.clone();
+            // Tasks also check the token before each LLM batch; this catches it between items.
+            if let Some(wait) = self.cancelled_wait(&item) {
+                info!(step_id = wait.step_id, "Run cancelled; pausing session");
+                let pause = self.pause_with(context, wait, current_item);
+                return self.finish_with(context, pause);
+            }
+            if let Some(wait) = self.token_budget_wait(&item) {
+                info!(
+                    step_id = wait.step_id,
+                    "Token budget exhausted; pausing session"
+                );
+                let pause = self.pause_with(context, wait, current_item);
+                return self.finish_with(context, pause);
+            }
             match item {
                 WorkItem::Decomposition { step_id } => {
                     let step_prompt = context
                         .step(step_id)
                         .map(|s| s.description.clone())
                         .unwrap_or_else(|| context.prompt.clone());
-                    let agent = agent_configs
+                    let mut agent = agent_configs
                         .get(&AgentKind::Decomposition)
                         .expect("missing decomposition agent")
                         .clone();
+                    if domain_cfg.single_shot_decomposition {
+                        agent.samples = 1;
+                    }
 
                     let rf_configs = agent.red_flaggers.as_deref().unwrap_or(domain_flaggers);
                     let red_flag_pipeline = Arc::new(
-                        RedFlagPipeline::from_configs(rf_configs, Some(llm.clone()))
-                            .context("Failed to build decomposition red-flagger pipeline")?,
+                        RedFlagPipeline::from_configs_in(
+                            rf_configs,
+                            Some(llm.clone()),
+                            Some(self.workspace(context)),
+                        )
+                        .context("Failed to build decomposition red-flagger pipeline")?
+                        .with_parallel_flaggers(self.options.parallel_red_flaggers),
                     );
 
                     let task = DecompositionTask::new(
@@ -122,8 +234,25 @@ impl FlowRunner {
                         red_flag_pipeline.clone(),
                         self.renderer.clone(),
                         self.clock.clone(),
-                    );
-                    let result = task.run(context).await?;
+                    )
+                    .with_min_samples_accepted(
+                        self.options
+                            .min_samples_accepted
+                            .or(domain_cfg.min_samples_accepted),
+                    )
+                    .with_red_flag_concurrency(self.options.red_flag_concurrency)
+                    .with_max_response_bytes(self.options.max_response_bytes)
+                    .with_flag_truncated(self.options.flag_truncated)
+                    .with_prompt_dump(prompt_dump.clone())
+                    .with_prompt_recorder(prompt_recorder.clone())
+                    .with_prompt_vars(self.options.prompt_vars.clone())
+                    .with_empty_plan_policy(domain_cfg.on_empty_plan)
+                    .with_cancellation(self.cancellation.clone());
+                    let result = match task.run(context).instrument(task_span(&current_item)).await
+                    {
+                        Ok(result) => result,
+                        Err(err) => return self.pause_if_interrupted(context, current_item, err),
+                    };
                     if let Some(outcome) =
                         self.handle_next_action(result.action, &current_item, context)
                     {
@@ -135,7 +264,14 @@ impl FlowRunner {
                         let pause = self.pause_with(context, wait, current_item);
                         return self.finish_with(context, pause);
                     }
-                    context.enqueue_work_front(WorkItem::DecompositionVote { step_id });
+                    if domain_cfg.single_shot_decomposition {
+                        let children = adopt_sole_proposal(context, step_id)?;
+                        if let Some(pause) = self.schedule_plan(context, step_id, children).await {
+                            return self.finish_with(context, pause);
+                        }
+                    } else {
+                        context.enqueue_work_front(WorkItem::DecompositionVote { step_id });
+                    }
                 }
                 WorkItem::DecompositionVote { step_id } => {
                     let agent = agent_configs
@@ -151,8 +287,18 @@ impl FlowRunner {
                         vote_k,
                         self.renderer.clone(),
                         self.clock.clone(),
-                    );
-                    let result = task.run(context).await?;
+                    )
+                    .with_prompt_dump(prompt_dump.clone())
+                    .with_prompt_recorder(prompt_recorder.clone())
+                    .with_prompt_vars(self.options.prompt_vars.clone())
+                    .with_enumeration(domain_cfg.vote_enumeration)
+                    .with_shuffle_seed(self.options.shuffle_seed)
+                    .with_cancellation(self.cancellation.clone());
+                    let result = match task.run(context).instrument(task_span(&current_item)).await
+                    {
+                        Ok(result) => result,
+                        Err(err) => return self.pause_if_interrupted(context, current_item, err),
+                    };
                     if let Some(outcome) =
                         self.handle_next_action(result.action, &current_item, context)
                     {
@@ -166,45 +312,35 @@ impl FlowRunner {
                         return self.finish_with(context, pause);
                     }
 
-                    if let TaskEffect::SpawnedSteps(children) = result.effect {
-                        if children.is_empty() {
-                            context.enqueue_work(WorkItem::Solve { step_id });
-                        } else {
-                            for child in children {
-                                let next = if self.should_recurse(context, child) {
-                                    WorkItem::Decomposition { step_id: child }
-                                } else {
-                                    WorkItem::Solve { step_id: child }
-                                };
-                                context.enqueue_work(next);
-                            }
-                        }
-                    }
-
-                    if self.options.step_by_step {
-                        let wait = WaitState {
-                            step_id,
-                            trigger: "step_by_step_checkpoint".into(),
-                            details: "Decomposition plan ready for review".into(),
-                        };
-                        context.set_checkpoint(
-                            wait.step_id,
-                            wait.trigger.clone(),
-                            wait.details.clone(),
-                        );
-                        return self.finish_with(context, RunnerOutcome::Paused(wait));
+                    let children = match result.effect {
+                        TaskEffect::SpawnedSteps(children) => children,
+                        _ => Vec::new(),
+                    };
+                    if let Some(pause) = self.schedule_plan(context, step_id, children).await {
+                        return self.finish_with(context, pause);
                     }
                 }
                 WorkItem::Solve { step_id } => {
-                    let agent = agent_configs
+                    let mut agent = agent_configs
                         .get(&AgentKind::Solver)
                         .expect("missing solver agent")
                         .clone();
-
-                    let rf_configs = agent.red_flaggers.as_deref().unwrap_or(domain_flaggers);
+                    agent.samples = self.resolve_samples(&agent, &agent_configs, context);
+
+                    let mut rf_configs = agent
+                        .red_flaggers
+                        .as_deref()
+                        .unwrap_or(domain_flaggers)
+                        .to_vec();
+                    rf_configs.extend(domain_cfg.granularity_flagger.clone());
                     let red_flag_pipeline = Arc::new(
-                        RedFlagPipeline::from_configs(rf_configs, Some(llm.clone()))
-                            .context("Failed to build solver red-flagger pipeline")?,
+                        RedFlagPipeline::from_configs_in(
+                            &rf_configs,
+                            Some(llm.clone()),
+                            Some(self.workspace(context)),
+                        )
+                        .context("Failed to build solver red-flagger pipeline")?
+                        .with_parallel_flaggers(self.options.parallel_red_flaggers),
                     );
 
                     let task = SolveTask::new(
@@ -214,8 +350,25 @@ impl FlowRunner {
                         red_flag_pipeline.clone(),
                         self.renderer.clone(),
                         self.clock.clone(),
-                    );
-                    let result = task.run(context).await?;
+                    )
+                    .with_min_samples_accepted(
+                        self.options
+                            .min_samples_accepted
+                            .or(domain_cfg.min_samples_accepted),
+                    )
+                    .with_red_flag_concurrency(self.options.red_flag_concurrency)
+                    .with_max_response_bytes(self.options.max_response_bytes)
+                    .with_flag_truncated(self.options.flag_truncated)
+                    .with_retry_on_empty(self.options.retry_on_empty)
+                    .with_prompt_dump(prompt_dump.clone())
+                    .with_prompt_recorder(prompt_recorder.clone())
+                    .with_prompt_vars(self.options.prompt_vars.clone())
+                    .with_cancellation(self.cancellation.clone());
+                    let result = match task.run(context).instrument(task_span(&current_item)).await
+                    {
+                        Ok(result) => result,
+                        Err(err) => return self.pause_if_interrupted(context, current_item, err),
+                    };
                     if let Some(outcome) =
                         self.handle_next_action(result.action, &current_item, context)
                     {
@@ -227,7 +380,10 @@ impl FlowRunner {
                         let pause = self.pause_with(context, wait, current_item);
                         return self.finish_with(context, pause);
                     }
-                    if matches!(result.effect, TaskEffect::SolutionsReady { .. }) {
+                    // A caller that queued the vote up front (e.g. a subprocess) keeps its item
+                    if matches!(result.effect, TaskEffect::SolutionsReady { .. })
+                        && !context.has_queued_solution_vote(step_id)
+                    {
                         context.enqueue_work_front(WorkItem::SolutionVote { step_id });
                     }
                 }
@@ -244,8 +400,19 @@ impl FlowRunner {
                         vote_k,
                         self.renderer.clone(),
                         self.clock.clone(),
-                    );
-                    let result = task.run(context).await?;
+                    )
+                    .with_prompt_dump(prompt_dump.clone())
+                    .with_prompt_recorder(prompt_recorder.clone())
+                    .with_prompt_vars(self.options.prompt_vars.clone())
+                    .with_dedupe_candidates(self.options.dedupe_candidates)
+                    .with_enumeration(domain_cfg.vote_enumeration)
+                    .with_shuffle_seed(self.options.shuffle_seed)
+                    .with_cancellation(self.cancellation.clone());
+                    let result = match task.run(context).instrument(task_span(&current_item)).await
+                    {
+                        Ok(result) => result,
+                        Err(err) => return self.pause_if_interrupted(context, current_item, err),
+                    };
                     if let Some(outcome) =
                         self.handle_next_action(result.action, &current_item, context)
                     {
@@ -258,6 +425,37 @@ impl FlowRunner {
                     }
                     if let TaskEffect::WinnerSelected { step_id } = result.effect {
                         context.enqueue_work_front(WorkItem::ApplyVerify { step_id });
+                        if self.options.step_by_step && !context.dry_run && !context.dry_run_apply {
+                            let preview = ApplyVerifyTask::new(
+                                step_id,
+                                domain_cfg.applier.clone(),
+                                domain_cfg.verifier.clone(),
+                                self.file_system.clone(),
+                                self.clock.clone(),
+                                self.command_runner.clone(),
+                            )
+                            .with_output_encoding(self.output_encoding(&domain_cfg))
+                            .with_path_extraction(
+                                domain_cfg.path_extraction,
+                                domain_cfg.path_pattern.clone(),
+                            )
+                            .with_ignore_rules(ignore_rules.clone())
+                            .with_max_file_bytes(domain_cfg.max_file_bytes)
+                            .preview(context)?;
+                            if !preview.files.is_empty() {
+                                let wait = WaitState {
+                                    step_id,
+                                    trigger: "step_by_step_pre_apply".into(),
+                                    details: preview.describe(),
+                                };
+                                if let Some(pause) = self
+                                    .checkpoint(context, wait, CheckpointStage::PreApply)
+                                    .await
+                                {
+                                    return self.finish_with(context, pause);
+                                }
+                            }
+                        }
                     }
                 }
                 WorkItem::ApplyVerify { step_id } => {
@@ -267,8 +465,29 @@ impl FlowRunner {
                         domain_cfg.verifier.clone(),
                         self.file_system.clone(),
                         self.clock.clone(),
+                        self.command_runner.clone(),
+                    )
+                    .with_output_encoding(self.output_encoding(&domain_cfg))
+                    .with_path_extraction(
+                        domain_cfg.path_extraction,
+                        domain_cfg.path_pattern.clone(),
+                    )
+                    .with_pre_verifier(domain_cfg.pre_verifier.clone())
+                    .with_ignore_rules(ignore_rules.clone())
+                    .with_max_file_bytes(domain_cfg.max_file_bytes)
+                    .with_llm_verifier(
+                        agent_configs.get(&AgentKind::Verifier).map(|agent| {
+                            Arc::new(
+                                LlmVerifier::new(agent.clone(), llm.clone(), self.renderer.clone())
+                                    .with_prompt_vars(self.options.prompt_vars.clone()),
+                            )
+                        }),
                     );
-                    let result = task.run(context).await?;
+                    let result = match task.run(context).instrument(task_span(&current_item)).await
+                    {
+                        Ok(result) => result,
+                        Err(err) => return self.pause_if_interrupted(context, current_item, err),
+                    };
                     if let Some(outcome) =
                         self.handle_next_action(result.action, &current_item, context)
                     {
@@ -286,12 +505,12 @@ impl FlowRunner {
                                     "Step finished execution. Resume to process next pending work."
                                         .into(),
                             };
-                            context.set_checkpoint(
-                                wait.step_id,
-                                wait.trigger.clone(),
-                                wait.details.clone(),
-                            );
-                            return self.finish_with(context, RunnerOutcome::Paused(wait));
+                            if let Some(pause) = self
+                                .checkpoint(context, wait, CheckpointStage::StepDone)
+                                .await
+                            {
+                                return self.finish_with(context, pause);
+                            }
                         }
                     }
                 }
@@ -303,12 +522,170 @@ impl FlowRunner {
             .iter()
             .filter(|step| matches!(step.status, StepStatus::Completed))
             .count();
+        let failed_steps: Vec<usize> = context
+            .steps
+            .iter()
+            .filter(|step| matches!(step.status, StepStatus::Failed))
+            .map(|step| step.id)
+            .collect();
         info!(
             completed,
+            failed = failed_steps.len(),
             total = context.steps.len(),
             "FlowRunner execution complete"
         );
-        self.finish_with(context, RunnerOutcome::Completed)
+        if failed_steps.is_empty() {
+            self.finish_with(context, RunnerOutcome::Completed)
+        } else {
+            self.finish_with(
+                context,
+                RunnerOutcome::CompletedWithFailures { failed_steps },
+            )
+        }
+    }
+
+    /// Queue the work for a step's adopted plan (recursing or solving each child, or solving the
+    /// step itself when the plan is empty or only restates it), then offer the plan checkpoint.
+    async fn schedule_plan(
+        &self,
+        context: &mut WorkflowContext,
+        step_id: usize,
+        children: Vec<usize>,
+    ) -> Option<RunnerOutcome> {
+        if children.is_empty() {
+            context.enqueue_work(WorkItem::Solve { step_id });
+        } else if self.options.collapse_trivial_decomposition
+            && restates_parent(context, step_id, &children)
+        {
+            info!(step_id, "Plan only restates the step; solving it directly");
+            context.discard_children(step_id);
+            context.enqueue_work(WorkItem::Solve { step_id });
+        } else {
+            for child in children {
+                let next = if self.should_recurse(context, child) {
+                    WorkItem::Decomposition { step_id: child }
+                } else {
+                    WorkItem::Solve { step_id: child }
+                };
+                context.enqueue_work(next);
+            }
+        }
+
+        if self.options.step_by_step {
+            let wait = WaitState {
+                step_id,
+                trigger: "step_by_step_checkpoint".into(),
+                details: "Decomposition plan ready for review".into(),
+            };
+            return self.checkpoint(context, wait, CheckpointStage::Plan).await;
+        }
+        None
+    }
+
+    /// Stop at a step-by-step checkpoint, or let the attached reviewer decide and keep going.
+    async fn checkpoint(
+        &self,
+        context: &mut WorkflowContext,
+        wait: WaitState,
+        stage: CheckpointStage,
+    ) -> Option<RunnerOutcome> {
+        let step_id = wait.step_id;
+        let decision = match &self.checkpoint_reviewer {
+            Some(reviewer) => {
+                // Reviewers may block on terminal input, so they run on the blocking pool
+                let reviewer = reviewer.clone();
+                let (snapshot, pending) = (context.clone(), wait.clone());
+                let decision =
+                    tokio::task::spawn_blocking(move || reviewer.review(&snapshot, &pending))
+                        .await
+                        .unwrap_or_else(|err| {
+                            warn!(step_id, error = %err, "Checkpoint reviewer failed; pausing");
+                            CheckpointDecision::Pause
+                        });
+                info!(step_id, trigger = %wait.trigger, ?decision, "Checkpoint reviewed");
+                decision
+            }
+            None => CheckpointDecision::Pause,
+        };
+        match (stage, decision) {
+            (_, CheckpointDecision::Pause) => {
+                context.set_checkpoint(step_id, wait.trigger.clone(), wait.details.clone());
+                return Some(RunnerOutcome::Paused(wait));
+            }
+            (_, CheckpointDecision::Approve) => {}
+            (CheckpointStage::Plan, CheckpointDecision::Reject) => {
+                context.discard_children(step_id);
+                context.enqueue_work_front(WorkItem::Decomposition { step_id });
+            }
+            (CheckpointStage::Plan, CheckpointDecision::Edit(plan)) => {
+                context.discard_children(step_id);
+                for subtask in plan.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                    let child = context.add_child_step(step_id, subtask);
+                    context.enqueue_work(WorkItem::Solve { step_id: child });
+                }
+            }
+            (_, CheckpointDecision::Reject) => {
+                context.reset_solution(step_id);
+                context.enqueue_work_front(WorkItem::Solve { step_id });
+            }
+            (stage, CheckpointDecision::Edit(solution)) => {
+                if let Some(step) = context.step_mut(step_id) {
+                    step.winning_solution = Some(solution);
+                }
+                // Pre-apply already has ApplyVerify queued; a finished step must be re-applied.
+                if stage == CheckpointStage::StepDone {
+                    context.enqueue_work_front(WorkItem::ApplyVerify { step_id });
+                }
+            }
+        }
+        None
+    }
+
+    fn cancelled_wait(&self, item: &WorkItem) -> Option<WaitState> {
+        self.cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+            .then(|| WaitState {
+                step_id: item.step_id(),
+                trigger: "cancelled".into(),
+                details: format!("Run cancelled before {item:?}"),
+            })
+    }
+
+    fn token_budget_wait(&self, item: &WorkItem) -> Option<WaitState> {
+        let budget = self
+            .token_budget
+            .as_ref()
+            .filter(|budget| budget.exhausted())?;
+        Some(WaitState {
+            step_id: item.step_id(),
+            trigger: "token_budget_exhausted".into(),
+            details: format!(
+                "Used {} of {} budgeted tokens before {item:?}",
+                budget.used(),
+                budget.limit()
+            ),
+        })
+    }
+
+    /// A task that failed because the run was cancelled or the token budget ran out
+    /// mid-sampling pauses the session (retrying the item on resume) instead of failing it;
+    /// any other error propagates.
+    fn pause_if_interrupted(
+        &self,
+        context: &mut WorkflowContext,
+        item: WorkItem,
+        err: anyhow::Error,
+    ) -> Result<RunnerOutcome> {
+        let Some(wait) = self
+            .cancelled_wait(&item)
+            .or_else(|| self.token_budget_wait(&item))
+        else {
+            return Err(err);
+        };
+        info!(step_id = wait.step_id, trigger = %wait.trigger, error = %err, "Run interrupted; pausing session");
+        let pause = self.pause_with(context, wait, item);
+        self.finish_with(context, pause)
     }
 
     fn pause_with(
@@ -336,11 +713,16 @@ impl FlowRunner {
         match action {
             NextAction::Continue | NextAction::End => None,
             NextAction::WaitForInput => {
-                let wait = WaitState {
-                    step_id: current_item.step_id(),
-                    trigger: "task_requested_input".into(),
-                    details: "Task requested human approval before continuing".into(),
-                };
+                // Keep the reason a task recorded for this step, if any.
+                let wait = context
+                    .wait_state
+                    .clone()
+                    .filter(|wait| wait.step_id == current_item.step_id())
+                    .unwrap_or_else(|| WaitState {
+                        step_id: current_item.step_id(),
+                        trigger: "task_requested_input".into(),
+                        details: "Task requested human approval before continuing".into(),
+                    });
                 Some(self.pause_with(context, wait, current_item.clone()))
             }
             NextAction::GoTo(_) => {
@@ -352,15 +734,42 @@ impl FlowRunner {
         }
     }
 
-    fn should_recurse(&self, context: &WorkflowContext, step_id: usize) -> bool {
-        if let Some(step) = context.step(step_id) {
-            if step.depth >= self.options.max_decomposition_depth {
-                return false;
-            }
-            let word_count = step.description.split_whitespace().count();
-            return word_count >= self.options.min_words_for_decomposition;
+    /// Decide whether `step_id` is decomposed further, recording the rationale on the step
+    /// when `explain` is set.
+    fn should_recurse(&self, context: &mut WorkflowContext, step_id: usize) -> bool {
+        let cap_reached = context.step_cap_reached();
+        let spawned_steps = context.spawned_steps;
+        let max_children_total = context.max_children_total.unwrap_or_default();
+        let Some(step) = context.step_mut(step_id) else {
+            return false;
+        };
+        let mut decision = RecursionDecision::evaluate(
+            step.depth,
+            step.description.split_whitespace().count(),
+            self.options.max_decomposition_depth,
+            self.options.min_words_for_decomposition,
+        );
+        if self.options.flat && step.depth > 0 {
+            decision.recursed = false;
+            decision.reason = "not recursed: flat decomposition (--flat)".to_string();
+        } else if decision.recursed && cap_reached {
+            warn!(
+                step_id,
+                spawned_steps,
+                max_children_total,
+                "Total step cap reached; solving step without decomposing it"
+            );
+            decision.recursed = false;
+            decision.reason = format!(
+                "not recursed: total step cap reached ({spawned_steps} >= {max_children_total})"
+            );
+        }
+        debug!(step_id, reason = %decision.reason, "Recursion decision");
+        let recursed = decision.recursed;
+        if self.options.explain {
+            step.recursion = Some(decision);
         }
-        false
+        recursed
     }
 
     fn resolve_k(
@@ -379,12 +788,14 @@ impl FlowRunner {
         {
             let sum: usize = stats.recent_margins.iter().copied().sum();
             let avg = sum as f32 / stats.recent_margins.len() as f32;
-            let mut adjusted = base;
-            if avg < base as f32 * 0.75 {
-                adjusted = base + 1;
-            } else if avg > base as f32 * 1.5 && base > 1 {
-                adjusted = base - 1;
-            }
+            let limits = self.options.adaptive_k_limits;
+            let adjusted = if avg < base as f32 * 0.75 {
+                limits.adjust(base, true)
+            } else if avg > base as f32 * 1.5 {
+                limits.adjust(base, false)
+            } else {
+                base
+            };
             if adjusted != base {
                 debug!(
                     ?agent_kind,
@@ -394,12 +805,59 @@ impl FlowRunner {
                     "Adaptive k adjustment"
                 );
             }
-            return adjusted.max(1);
+            return adjusted;
         }
 
         base
     }
 
+    /// The solver's sample count, scaled by recent solution-vote margins when
+    /// `adaptive_samples` is set: a quarter fewer samples (never below the vote's k) when the
+    /// average margin exceeds 1.5k, a quarter more when it falls below 0.75k.
+    fn resolve_samples(
+        &self,
+        solver: &AgentConfig,
+        agent_configs: &HashMap<AgentKind, AgentConfig>,
+        context: &WorkflowContext,
+    ) -> usize {
+        let base = solver.samples.max(1);
+        if !self.options.adaptive_samples {
+            return base;
+        }
+        let Some(stats) = context
+            .metrics()
+            .vote_stats(AgentKind::SolutionDiscriminator)
+            .filter(|stats| !stats.recent_margins.is_empty())
+        else {
+            return base;
+        };
+
+        let k = agent_configs
+            .get(&AgentKind::SolutionDiscriminator)
+            .map_or(self.options.default_k, |agent| {
+                self.resolve_k(AgentKind::SolutionDiscriminator, agent, context)
+            });
+        let sum: usize = stats.recent_margins.iter().copied().sum();
+        let avg = sum as f32 / stats.recent_margins.len() as f32;
+        let step = (base / 4).max(1);
+        let adjusted = if avg > k as f32 * 1.5 {
+            base.saturating_sub(step).max(k)
+        } else if avg < k as f32 * 0.75 {
+            base + step
+        } else {
+            base
+        };
+        if adjusted != base {
+            debug!(
+                base_samples = base,
+                adjusted_samples = adjusted,
+                avg_margin = avg,
+                "Adaptive samples adjustment"
+            );
+        }
+        adjusted.max(1)
+    }
+
     fn check_sampling_triggers(
         &self,
         context: &WorkflowContext,
@@ -500,6 +958,12 @@ impl FlowRunner {
                 defaults,
             ),
         );
+        if let Some(settings) = domain.agent_settings(AgentKind::Verifier) {
+            map.insert(
+                AgentKind::Verifier,
+                self.build_agent_config(AgentKind::Verifier, settings, defaults),
+            );
+        }
         map
     }
 
@@ -512,21 +976,101 @@ impl FlowRunner {
         settings.as_agent_config(kind, &defaults)
     }
 
+    /// The domain's output encoding with any run-level overrides applied.
+    fn output_encoding(&self, domain: &DomainRuntimeConfig) -> OutputEncoding {
+        let configured = domain.output_encoding;
+        OutputEncoding {
+            line_endings: self.options.line_endings.unwrap_or(configured.line_endings),
+            bom: configured.bom || self.options.bom,
+        }
+    }
+
+    /// Files the run would overwrite, for red flaggers that measure a solution against them.
+    fn workspace(&self, context: &WorkflowContext) -> Workspace {
+        Workspace {
+            file_system: self.file_system.clone(),
+            root: context.output_dir.clone(),
+        }
+    }
+
+    /// Patterns from `.microfactoryignore` in the output root (the working directory when no
+    /// output dir is set); no file means nothing is ignored.
+    fn load_ignore_rules(&self, context: &WorkflowContext) -> Result<IgnoreRules> {
+        let path = context
+            .output_dir
+            .clone()
+            .unwrap_or_default()
+            .join(IGNORE_FILE_NAME);
+        if !self.file_system.exists(&path) {
+            return Ok(IgnoreRules::default());
+        }
+        let source = self
+            .file_system
+            .read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let rules = IgnoreRules::parse(&source)?;
+        debug!(path = %path.display(), "Loaded apply ignore rules");
+        Ok(rules)
+    }
+
     fn finish_with(
         &self,
-        context: &WorkflowContext,
+        context: &mut WorkflowContext,
         outcome: RunnerOutcome,
     ) -> Result<RunnerOutcome> {
+        log_queue_transitions(context);
         self.record_outcome_event(context, &outcome);
+        if let RunnerOutcome::Paused(wait) = &outcome {
+            self.record_pause_event(context, wait);
+        }
         Ok(outcome)
     }
 
+    /// Emit `session_paused` with enough context (step, trigger, metrics snapshot) for a sink
+    /// to notify a human without loading the session.
+    fn record_pause_event(&self, context: &WorkflowContext, wait: &WaitState) {
+        let mut props = HashMap::new();
+        props.insert("step_id".into(), wait.step_id.to_string());
+        props.insert("trigger".into(), wait.trigger.clone());
+        props.insert("details".into(), wait.details.clone());
+        props.insert("prompt".into(), context.prompt.clone());
+        if let Some(step) = context.step(wait.step_id) {
+            props.insert("step_description".into(), step.description.clone());
+            props.insert("step_status".into(), format!("{:?}", step.status));
+        }
+        let completed = context
+            .steps
+            .iter()
+            .filter(|step| step.status == StepStatus::Completed)
+            .count();
+        props.insert("steps_total".into(), context.steps.len().to_string());
+        props.insert("steps_completed".into(), completed.to_string());
+
+        let metrics = &context.metrics;
+        props.insert("samples".into(), metrics.sample_count.to_string());
+        props.insert("resamples".into(), metrics.resample_count.to_string());
+        props.insert("vote_attempts".into(), metrics.vote_attempts.to_string());
+        props.insert("red_flag_hits".into(), metrics.red_flag_hits.to_string());
+        if let Some(margin) = metrics
+            .per_step
+            .get(&wait.step_id)
+            .and_then(|step| step.vote_margin)
+        {
+            props.insert("step_vote_margin".into(), margin.to_string());
+        }
+        self.emit_telemetry(context, "session_paused", props);
+    }
+
     fn record_outcome_event(&self, context: &WorkflowContext, outcome: &RunnerOutcome) {
         let mut props = HashMap::new();
         match outcome {
             RunnerOutcome::Completed => {
                 props.insert("state".into(), "completed".into());
             }
+            RunnerOutcome::CompletedWithFailures { failed_steps } => {
+                props.insert("state".into(), "completed_with_failures".into());
+                props.insert("failed_steps".into(), format_step_ids(failed_steps));
+            }
             RunnerOutcome::Paused(wait) => {
                 props.insert("state".into(), "paused".into());
                 props.insert("wait_trigger".into(), wait.trigger.clone());
@@ -534,6 +1078,8 @@ impl FlowRunner {
             }
         }
         self.emit_telemetry(context, "runner_outcome", props);
+        // The outcome is the last event of this execution; don't leave it sitting in a buffer.
+        self.telemetry.flush();
     }
 
     fn emit_telemetry(
@@ -560,20 +1106,72 @@ impl FlowRunner {
 #[derive(Debug, Clone)]
 pub enum RunnerOutcome {
     Completed,
+    /// The work queue drained but at least one step ended `Failed`.
+    CompletedWithFailures {
+        failed_steps: Vec<usize>,
+    },
     Paused(WaitState),
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Render step ids as a comma-separated list for logs and telemetry.
+pub fn format_step_ids(step_ids: &[usize]) -> String {
+    step_ids
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[derive(Debug, Clone)]
 pub struct RunnerOptions {
     pub default_samples: usize,
     pub default_k: usize,
     pub adaptive_k: bool,
+    /// Bounds and step size for adaptive k adjustments.
+    pub adaptive_k_limits: AdaptiveKLimits,
     pub max_decomposition_depth: usize,
     pub min_words_for_decomposition: usize,
     pub human_red_flag_threshold: usize,
     pub human_resample_threshold: usize,
     pub human_low_margin_threshold: usize,
     pub step_by_step: bool,
+    /// Minimum clean samples a sampling stage must accept before moving on; `None` requires
+    /// every requested sample to survive red-flagging.
+    pub min_samples_accepted: Option<usize>,
+    /// Write every rendered prompt under `<output_dir>/prompts/`.
+    pub dump_prompts: bool,
+    /// Keep each step's rendered prompts on the step so they are saved with the session.
+    pub record_prompts: bool,
+    /// Maximum red-flag evaluations running at once within a sampling batch.
+    pub red_flag_concurrency: usize,
+    /// Evaluate a sample's red flaggers concurrently, stopping at the first rejection.
+    pub parallel_red_flaggers: bool,
+    /// Reject raw samples larger than this many bytes before red-flag evaluation.
+    pub max_response_bytes: Option<usize>,
+    /// Red-flag samples the provider reports as cut off at its output token limit.
+    pub flag_truncated: bool,
+    /// Extra solver batches to request when one returns only empty responses; blank responses
+    /// are dropped before the vote. `0` keeps them as candidates.
+    pub retry_on_empty: usize,
+    /// Custom template variables available to every role's prompt as `{{name}}`.
+    pub prompt_vars: BTreeMap<String, String>,
+    /// Overrides the domain's `output_encoding.line_endings` for applied files.
+    pub line_endings: Option<LineEnding>,
+    /// Forces a UTF-8 BOM on applied files even when the domain does not request one.
+    pub bom: bool,
+    /// Merge whitespace-equivalent solver candidates before voting, weighting votes by count.
+    pub dedupe_candidates: bool,
+    /// Record each step's recursion decision and its inputs on the step.
+    pub explain: bool,
+    /// Solve a step directly when its winning plan is a single subtask that restates it.
+    pub collapse_trivial_decomposition: bool,
+    /// Decompose only the root: every subtask is solved directly, whatever its depth or length.
+    pub flat: bool,
+    /// Scale the solver's sample count with the margins of recent solution votes.
+    pub adaptive_samples: bool,
+    /// Seed for shuffling the options of both votes to counter positional bias; `None` shows
+    /// candidates in sample order.
+    pub shuffle_seed: Option<u64>,
 }
 
 impl RunnerOptions {
@@ -588,28 +1186,97 @@ impl RunnerOptions {
             default_samples: samples.max(1),
             default_k: k.max(1),
             adaptive_k,
+            adaptive_k_limits: AdaptiveKLimits::default(),
             max_decomposition_depth: 2,
             min_words_for_decomposition: 8,
             human_red_flag_threshold: 4,
             human_resample_threshold: 4,
             human_low_margin_threshold,
             step_by_step,
+            min_samples_accepted: None,
+            dump_prompts: false,
+            record_prompts: false,
+            red_flag_concurrency: DEFAULT_RED_FLAG_CONCURRENCY,
+            parallel_red_flaggers: false,
+            max_response_bytes: None,
+            flag_truncated: false,
+            retry_on_empty: 0,
+            prompt_vars: BTreeMap::new(),
+            line_endings: None,
+            bom: false,
+            dedupe_candidates: false,
+            explain: false,
+            collapse_trivial_decomposition: false,
+            flat: false,
+            adaptive_samples: false,
+            shuffle_seed: None,
+        }
+    }
+
+    /// Override `max_decomposition_depth`, clamping to at least 1; `None` keeps the current value.
+    pub fn with_max_depth(mut self, max_depth: Option<usize>) -> Self {
+        if let Some(depth) = max_depth {
+            self.max_decomposition_depth = depth.max(1);
         }
+        self
     }
 }
 
+/// Similarity (normalized Levenshtein over case- and whitespace-normalized text) at or above
+/// which a lone subtask counts as a restatement of its parent.
+const TRIVIAL_PLAN_SIMILARITY: f64 = 0.9;
+
+/// Whether `children` is a single subtask whose description (nearly) matches its parent's.
+fn restates_parent(context: &WorkflowContext, step_id: usize, children: &[usize]) -> bool {
+    let [child] = children else {
+        return false;
+    };
+    let (Some(parent), Some(child)) = (context.step(step_id), context.step(*child)) else {
+        return false;
+    };
+    let normalize = |text: &str| {
+        text.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .trim_end_matches(['.', '!', ';', ':'])
+            .to_lowercase()
+    };
+    strsim::normalized_levenshtein(
+        &normalize(&parent.description),
+        &normalize(&child.description),
+    ) >= TRIVIAL_PLAN_SIMILARITY
+}
+
 impl Default for RunnerOptions {
     fn default() -> Self {
         Self {
             default_samples: 2,
             default_k: 2,
             adaptive_k: false,
+            adaptive_k_limits: AdaptiveKLimits::default(),
             max_decomposition_depth: 2,
             min_words_for_decomposition: 8,
             human_red_flag_threshold: 4,
             human_resample_threshold: 4,
             human_low_margin_threshold: 1,
             step_by_step: false,
+            min_samples_accepted: None,
+            dump_prompts: false,
+            record_prompts: false,
+            red_flag_concurrency: DEFAULT_RED_FLAG_CONCURRENCY,
+            parallel_red_flaggers: false,
+            max_response_bytes: None,
+            flag_truncated: false,
+            retry_on_empty: 0,
+            prompt_vars: BTreeMap::new(),
+            line_endings: None,
+            bom: false,
+            dedupe_candidates: false,
+            explain: false,
+            collapse_trivial_decomposition: false,
+            flat: false,
+            adaptive_samples: false,
+            shuffle_seed: None,
         }
     }
 }
@@ -620,14 +1287,18 @@ mod tests {
     use crate::{
         adapters::{
             outbound::{
-                clock::SystemClock, filesystem::StdFileSystem, telemetry::TracingTelemetrySink,
+                clock::SystemClock, filesystem::StdFileSystem, process::MockCommandRunner,
+                telemetry::TracingTelemetrySink, token_budget::TokenBudgetLlmClient,
             },
             templating::HandlebarsRenderer,
         },
         config::MicrofactoryConfig,
         core::{
             domain::{Context, StepStatus},
-            ports::{Clock, FileSystem, LlmClient, LlmOptions, TelemetrySink},
+            ports::{
+                Clock, CommandOutput, FileSystem, LlmClient, LlmOptions, LlmResponse,
+                TelemetrySink, TokenUsage,
+            },
         },
     };
 
@@ -681,6 +1352,171 @@ mod tests {
         (fs, clock, telemetry)
     }
 
+    #[tokio::test]
+    async fn single_shot_decomposition_skips_the_plan_vote() {
+        /// Answers by model and records every model that was called.
+        #[derive(Default)]
+        struct RoutingLlm {
+            calls: Mutex<Vec<String>>,
+        }
+
+        #[async_trait]
+        impl LlmClient for RoutingLlm {
+            async fn chat_completion(
+                &self,
+                model: &str,
+                _prompt: &str,
+                _options: &LlmOptions,
+            ) -> crate::core::Result<String> {
+                self.calls.lock().unwrap().push(model.to_string());
+                Ok(match model {
+                    "plan" => "- write the parser\n- wire the CLI flag".into(),
+                    "solve" => "done".into(),
+                    _ => "1".into(),
+                })
+            }
+        }
+
+        let yaml = r#"
+        domains:
+          code:
+            single_shot_decomposition: true
+            agents:
+              decomposition:
+                prompt_template: "d"
+                model: "plan"
+                samples: 3
+              decomposition_discriminator:
+                prompt_template: "dv"
+                model: "plan-vote"
+              solver:
+                prompt_template: "s"
+                model: "solve"
+                samples: 1
+              solution_discriminator:
+                prompt_template: "sv"
+                model: "solve-vote"
+                k: 1
+        "#;
+        let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
+        let llm = Arc::new(RoutingLlm::default());
+        let (file_system, clock, telemetry) = test_deps();
+        let runner = FlowRunner::new(
+            config,
+            Some(llm.clone()),
+            Arc::new(HandlebarsRenderer::new()),
+            RunnerOptions {
+                max_decomposition_depth: 1,
+                human_low_margin_threshold: 0,
+                ..RunnerOptions::default()
+            },
+            file_system,
+            clock,
+            telemetry,
+        );
+
+        let mut context = Context::new("Ship the feature", "code");
+        let outcome = runner.execute(&mut context).await.unwrap();
+        assert!(matches!(outcome, RunnerOutcome::Completed), "{outcome:?}");
+
+        let calls = llm.calls.lock().unwrap();
+        assert_eq!(calls.iter().filter(|model| *model == "plan").count(), 1);
+        assert!(!calls.iter().any(|model| model == "plan-vote"), "{calls:?}");
+        let children: Vec<&str> = context.steps[0]
+            .children
+            .iter()
+            .map(|&id| context.step(id).unwrap().description.as_str())
+            .collect();
+        assert_eq!(children, vec!["write the parser", "wire the CLI flag"]);
+    }
+
+    #[tokio::test]
+    async fn domain_min_samples_accepted_applies_unless_the_run_overrides_it() {
+        /// Alternates clean and over-long solver answers so every batch loses half its samples.
+        #[derive(Default)]
+        struct HalfFlaggedLlm {
+            solver_calls: Mutex<usize>,
+        }
+
+        #[async_trait]
+        impl LlmClient for HalfFlaggedLlm {
+            async fn chat_completion(
+                &self,
+                model: &str,
+                _prompt: &str,
+                _options: &LlmOptions,
+            ) -> crate::core::Result<String> {
+                Ok(match model {
+                    "plan" => "- write the parser".into(),
+                    "solve" => {
+                        let mut calls = self.solver_calls.lock().unwrap();
+                        *calls += 1;
+                        if *calls % 2 == 1 {
+                            "done".into()
+                        } else {
+                            "far too many words in this answer".into()
+                        }
+                    }
+                    _ => "1".into(),
+                })
+            }
+        }
+
+        let yaml = r#"
+        domains:
+          code:
+            min_samples_accepted: 1
+            agents:
+              decomposition:
+                prompt_template: "d"
+                model: "plan"
+                samples: 1
+              decomposition_discriminator:
+                prompt_template: "dv"
+                model: "plan-vote"
+                k: 1
+              solver:
+                prompt_template: "s"
+                model: "solve"
+                samples: 2
+                red_flaggers:
+                  - type: "length"
+                    max_tokens: 3
+              solution_discriminator:
+                prompt_template: "sv"
+                model: "solve-vote"
+                k: 1
+        "#;
+        let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
+        let solver_calls = async |override_floor: Option<usize>| {
+            let llm = Arc::new(HalfFlaggedLlm::default());
+            let (file_system, clock, telemetry) = test_deps();
+            let runner = FlowRunner::new(
+                config.clone(),
+                Some(llm.clone()),
+                Arc::new(HandlebarsRenderer::new()),
+                RunnerOptions {
+                    max_decomposition_depth: 1,
+                    human_low_margin_threshold: 0,
+                    min_samples_accepted: override_floor,
+                    ..RunnerOptions::default()
+                },
+                file_system,
+                clock,
+                telemetry,
+            );
+            let mut context = Context::new("Ship the feature", "code");
+            let outcome = runner.execute(&mut context).await.unwrap();
+            assert!(matches!(outcome, RunnerOutcome::Completed), "{outcome:?}");
+            *llm.solver_calls.lock().unwrap()
+        };
+
+        // The domain floor of one accepts the first batch despite its flagged sample
+        assert_eq!(solver_calls(None).await, 2);
+        // The run's own floor wins and tops up for the second clean sample
+        assert_eq!(solver_calls(Some(2)).await, 3);
+    }
+
     #[tokio::test]
     async fn executes_linear_flow_with_scripted_llm() {
         let yaml = r#"#
@@ -732,12 +1568,30 @@ mod tests {
             default_samples: 2,
             default_k: 2,
             adaptive_k: false,
+            adaptive_k_limits: AdaptiveKLimits::default(),
             max_decomposition_depth: 1,
             min_words_for_decomposition: 3,
             human_red_flag_threshold: 5,
             human_resample_threshold: 5,
             human_low_margin_threshold: 1,
             step_by_step: false,
+            min_samples_accepted: None,
+            dump_prompts: false,
+            record_prompts: false,
+            red_flag_concurrency: DEFAULT_RED_FLAG_CONCURRENCY,
+            parallel_red_flaggers: false,
+            max_response_bytes: None,
+            flag_truncated: false,
+            retry_on_empty: 0,
+            prompt_vars: BTreeMap::new(),
+            line_endings: None,
+            bom: false,
+            dedupe_candidates: false,
+            explain: false,
+            collapse_trivial_decomposition: false,
+            flat: false,
+            adaptive_samples: false,
+            shuffle_seed: None,
         };
 
         let renderer = Arc::new(HandlebarsRenderer::new());
@@ -763,37 +1617,916 @@ mod tests {
         assert_eq!(completed, 2, "two subtasks solved");
     }
 
-    #[tokio::test]
-    async fn executes_analysis_domain_with_default_config() {
-        let config = Arc::new(
-            MicrofactoryConfig::from_path("config.yaml").expect("default config.yaml should load"),
-        );
-        // 4 samples requested for solver by default in config? Or 10?
-        // Default RunnerOptions has samples=2.
-        // Config yaml might specify more. Analysis solver usually has samples: 10?
-        // Let's check the test expectation: "solver sampled four times"
-        // So we need 4 responses in that batch.
+    /// Records `name task step_id` for every span opened while installed.
+    #[derive(Clone, Default)]
+    struct SpanLog(Arc<std::sync::Mutex<Vec<String>>>);
 
-        let llm: Arc<dyn LlmClient> = Arc::new(ScriptedLlm::new(vec![
-            // Decomposition
-            vec![
-                "- Produce executive-ready insight".into(),
-                "- Draft alternative plan".into(),
-            ],
-            // Vote
-            vec!["1".into(), "1".into()],
-            // Solver (4 samples expected based on assertion)
-            vec![
-                "Finding A: adoption up 12%".into(),
-                "Finding B: adoption flat".into(),
-                "Finding C: adoption down".into(),
-                "Finding D: inconclusive".into(),
-            ],
-            // Vote
-            vec!["1".into(), "1".into()],
-        ]));
+    #[derive(Default)]
+    struct SpanFields(Vec<String>);
 
-        let mut context = Context::new(
+    impl tracing::field::Visit for SpanFields {
+        fn record_str(&mut self, _field: &tracing::field::Field, value: &str) {
+            self.0.push(value.to_string());
+        }
+
+        fn record_u64(&mut self, _field: &tracing::field::Field, value: u64) {
+            self.0.push(value.to_string());
+        }
+
+        fn record_debug(&mut self, _field: &tracing::field::Field, _value: &dyn std::fmt::Debug) {}
+    }
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for SpanLog {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            _id: &tracing::span::Id,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            let mut fields = SpanFields(vec![attrs.metadata().name().to_string()]);
+            attrs.record(&mut fields);
+            self.0.lock().unwrap().push(fields.0.join(" "));
+        }
+    }
+
+    #[tokio::test]
+    async fn each_dispatched_task_runs_in_its_own_span() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let yaml = r#"#
+        domains:
+          code:
+            agents:
+              decomposition:
+                prompt_template: "decompose"
+                model: "model-a"
+                samples: 1
+              decomposition_discriminator:
+                prompt_template: "vote-decompose"
+                model: "model-b"
+                samples: 1
+                k: 1
+              solver:
+                prompt_template: "solve"
+                model: "model-c"
+                samples: 1
+              solution_discriminator:
+                prompt_template: "vote-solution"
+                model: "model-d"
+                samples: 1
+                k: 1
+        "#;
+        let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
+        let llm: Arc<dyn LlmClient> = Arc::new(ScriptedLlm::new(vec![
+            vec!["- step one\n- step two".into()],
+            vec!["1".into()],
+            vec!["solution one".into()],
+            vec!["1".into()],
+            vec!["solution two".into()],
+            vec!["1".into()],
+        ]));
+        let (file_system, clock, telemetry) = test_deps();
+        let runner = FlowRunner::new(
+            config,
+            Some(llm),
+            Arc::new(HandlebarsRenderer::new()),
+            RunnerOptions {
+                default_samples: 1,
+                default_k: 1,
+                max_decomposition_depth: 1,
+                human_low_margin_threshold: 0,
+                ..RunnerOptions::default()
+            },
+            file_system,
+            clock,
+            telemetry,
+        );
+
+        let spans = SpanLog::default();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(spans.clone()));
+        let mut context = Context::new("Fix the bug", "code");
+        let outcome = runner.execute(&mut context).await.unwrap();
+        assert!(matches!(outcome, RunnerOutcome::Completed));
+
+        let task_spans: Vec<String> = spans
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|span| span.starts_with("micro_task "))
+            .cloned()
+            .collect();
+        assert_eq!(
+            task_spans,
+            [
+                "micro_task decomposition 0",
+                "micro_task decomposition_vote 0",
+                "micro_task solve 1",
+                "micro_task solution_vote 1",
+                "micro_task apply_verify 1",
+                "micro_task solve 2",
+                "micro_task solution_vote 2",
+                "micro_task apply_verify 2",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn max_children_total_stops_further_decomposition() {
+        let yaml = r#"#
+        domains:
+          code:
+            agents:
+              decomposition:
+                prompt_template: "decompose"
+                model: "model-a"
+                samples: 2
+              decomposition_discriminator:
+                prompt_template: "vote-decompose"
+                model: "model-b"
+                samples: 2
+                k: 2
+              solver:
+                prompt_template: "solve"
+                model: "model-c"
+                samples: 2
+              solution_discriminator:
+                prompt_template: "vote-solution"
+                model: "model-d"
+                samples: 2
+                k: 2
+        "#;
+        let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
+        // Both subtasks are long enough to recurse, but the root's plan already hits the cap,
+        // so the script only covers solving them.
+        let llm: Arc<dyn LlmClient> = Arc::new(ScriptedLlm::new(vec![
+            vec![
+                "- write the parser\n- write the lexer".into(),
+                "- write the parser\n- write the lexer".into(),
+            ],
+            vec!["1".into(), "1".into()],
+            vec!["parser".into(), "parser alt".into()],
+            vec!["1".into(), "1".into()],
+            vec!["lexer".into(), "lexer alt".into()],
+            vec!["1".into(), "1".into()],
+        ]));
+        let (file_system, clock, telemetry) = test_deps();
+        let runner = FlowRunner::new(
+            config,
+            Some(llm),
+            Arc::new(HandlebarsRenderer::new()),
+            RunnerOptions {
+                default_samples: 2,
+                default_k: 2,
+                max_decomposition_depth: 3,
+                min_words_for_decomposition: 3,
+                human_low_margin_threshold: 0,
+                explain: true,
+                ..RunnerOptions::default()
+            },
+            file_system,
+            clock,
+            telemetry,
+        );
+        let mut context = Context::new("Fix the bug", "code");
+        context.max_children_total = Some(2);
+        let outcome = runner.execute(&mut context).await.unwrap();
+        assert!(matches!(outcome, RunnerOutcome::Completed), "{outcome:?}");
+
+        assert_eq!(context.spawned_steps, 2);
+        assert_eq!(context.steps.len(), 3, "root plus two leaves");
+        let root = context.root_step_id.unwrap();
+        for step in context.steps.iter().filter(|step| step.id != root) {
+            assert!(step.children.is_empty());
+            assert_eq!(step.status, StepStatus::Completed);
+            let decision = step.recursion.as_ref().expect("explained");
+            assert_eq!(
+                decision.reason,
+                "not recursed: total step cap reached (2 >= 2)"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn flat_solves_subtasks_without_decomposing_them() {
+        let yaml = r#"#
+        domains:
+          code:
+            agents:
+              decomposition:
+                prompt_template: "decompose"
+                model: "model-a"
+                samples: 2
+              decomposition_discriminator:
+                prompt_template: "vote-decompose"
+                model: "model-b"
+                samples: 2
+                k: 2
+              solver:
+                prompt_template: "solve"
+                model: "model-c"
+                samples: 2
+              solution_discriminator:
+                prompt_template: "vote-solution"
+                model: "model-d"
+                samples: 2
+                k: 2
+        "#;
+        let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
+        // Depth and word count would both allow recursing, but the script only covers the root's
+        // plan and solving each subtask.
+        let plan =
+            "- write the recursive descent parser module\n- write the streaming lexer module";
+        let llm: Arc<dyn LlmClient> = Arc::new(ScriptedLlm::new(vec![
+            vec![plan.into(), plan.into()],
+            vec!["1".into(), "1".into()],
+            vec!["parser".into(), "parser alt".into()],
+            vec!["1".into(), "1".into()],
+            vec!["lexer".into(), "lexer alt".into()],
+            vec!["1".into(), "1".into()],
+        ]));
+        let (file_system, clock, telemetry) = test_deps();
+        let runner = FlowRunner::new(
+            config,
+            Some(llm),
+            Arc::new(HandlebarsRenderer::new()),
+            RunnerOptions {
+                default_samples: 2,
+                default_k: 2,
+                max_decomposition_depth: 3,
+                min_words_for_decomposition: 3,
+                human_low_margin_threshold: 0,
+                explain: true,
+                flat: true,
+                ..RunnerOptions::default()
+            },
+            file_system,
+            clock,
+            telemetry,
+        );
+        let mut context = Context::new("Build the front end", "code");
+        let outcome = runner.execute(&mut context).await.unwrap();
+        assert!(matches!(outcome, RunnerOutcome::Completed), "{outcome:?}");
+
+        assert_eq!(context.steps.len(), 3, "root plus two leaves");
+        let root = context.root_step_id.unwrap();
+        for step in context.steps.iter().filter(|step| step.id != root) {
+            assert!(step.children.is_empty());
+            assert_eq!(step.status, StepStatus::Completed);
+            let decision = step.recursion.as_ref().expect("explained");
+            assert!(!decision.recursed);
+            assert_eq!(decision.reason, "not recursed: flat decomposition (--flat)");
+        }
+    }
+
+    #[tokio::test]
+    async fn collapses_single_subtask_plan_that_restates_parent() {
+        let yaml = r#"#
+        domains:
+          code:
+            agents:
+              decomposition:
+                prompt_template: "decompose"
+                model: "model-a"
+                samples: 1
+              decomposition_discriminator:
+                prompt_template: "vote-decompose"
+                model: "model-b"
+                samples: 1
+                k: 1
+              solver:
+                prompt_template: "solve"
+                model: "model-c"
+                samples: 1
+              solution_discriminator:
+                prompt_template: "vote-solution"
+                model: "model-d"
+                samples: 1
+                k: 1
+        "#;
+        let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
+        let llm: Arc<dyn LlmClient> = Arc::new(ScriptedLlm::new(vec![
+            vec!["- fix the  bug.".into()],
+            vec!["1".into()],
+            vec!["patched".into()],
+            vec!["1".into()],
+        ]));
+        let options = RunnerOptions {
+            human_low_margin_threshold: 0,
+            collapse_trivial_decomposition: true,
+            ..RunnerOptions::default()
+        };
+        let (file_system, clock, telemetry) = test_deps();
+        let runner = FlowRunner::new(
+            config,
+            Some(llm),
+            Arc::new(HandlebarsRenderer::new()),
+            options,
+            file_system,
+            clock,
+            telemetry,
+        );
+        let mut context = Context::new("Fix the bug", "code");
+        let outcome = runner.execute(&mut context).await.unwrap();
+        assert!(matches!(outcome, RunnerOutcome::Completed));
+
+        assert_eq!(context.steps.len(), 1, "no redundant child step");
+        let root = &context.steps[0];
+        assert!(root.children.is_empty());
+        assert_eq!(root.status, StepStatus::Completed);
+        assert_eq!(root.winning_solution.as_deref(), Some("patched"));
+    }
+
+    #[tokio::test]
+    async fn empty_plan_guard_pauses_or_fails_on_unparseable_decomposition() {
+        let run = |policy: &'static str| async move {
+            let yaml = format!(
+                r#"
+        domains:
+          code:
+            on_empty_plan: {policy}
+            agents:
+              decomposition:
+                prompt_template: "decompose"
+                model: "model-a"
+                samples: 2
+              decomposition_discriminator:
+                prompt_template: "vote-decompose"
+                model: "model-b"
+                k: 1
+              solver:
+                prompt_template: "solve"
+                model: "model-c"
+                samples: 1
+              solution_discriminator:
+                prompt_template: "vote-solution"
+                model: "model-d"
+                k: 1
+        "#
+            );
+            let config = Arc::new(MicrofactoryConfig::from_yaml_str(&yaml).unwrap());
+            let llm: Arc<dyn LlmClient> =
+                Arc::new(ScriptedLlm::new(vec![vec!["-\n*".into(), "  \n- ".into()]]));
+            let (file_system, clock, telemetry) = test_deps();
+            let runner = FlowRunner::new(
+                config,
+                Some(llm),
+                Arc::new(HandlebarsRenderer::new()),
+                RunnerOptions::default(),
+                file_system,
+                clock,
+                telemetry,
+            );
+            let mut context = Context::new("Fix the bug", "code");
+            let result = runner.execute(&mut context).await;
+            (result, context)
+        };
+
+        let (result, mut context) = run("pause").await;
+        let RunnerOutcome::Paused(wait) = result.unwrap() else {
+            panic!("expected the empty plan to pause the session");
+        };
+        assert_eq!(wait.trigger, "decomposition_empty_plan");
+        assert!(
+            wait.details.contains("no actionable subtasks"),
+            "{}",
+            wait.details
+        );
+        assert!(context.steps[0].children.is_empty());
+        assert!(
+            matches!(
+                context.dequeue_work(),
+                Some(WorkItem::Decomposition { step_id: 0 })
+            ),
+            "resume re-samples the decomposition"
+        );
+
+        let (result, context) = run("fail").await;
+        let err = result.unwrap_err();
+        assert!(
+            err.to_string().contains("no actionable subtasks"),
+            "{err:#}"
+        );
+        assert_eq!(context.steps[0].status, StepStatus::Failed);
+    }
+
+    #[tokio::test]
+    async fn cancellation_pauses_before_the_next_work_item() {
+        /// Answers every call with a one-step plan and cancels the run on the first call.
+        struct CancellingLlm {
+            token: CancellationToken,
+            calls: Mutex<usize>,
+        }
+
+        #[async_trait]
+        impl LlmClient for CancellingLlm {
+            async fn chat_completion(
+                &self,
+                _model: &str,
+                _prompt: &str,
+                _options: &LlmOptions,
+            ) -> crate::core::Result<String> {
+                *self.calls.lock().unwrap() += 1;
+                self.token.cancel();
+                Ok("- Write the fix".into())
+            }
+        }
+
+        let yaml = r#"
+        domains:
+          code:
+            agents:
+              decomposition:
+                prompt_template: "decompose"
+                model: "model-a"
+                samples: 1
+              decomposition_discriminator:
+                prompt_template: "vote-decompose"
+                model: "model-b"
+              solver:
+                prompt_template: "solve"
+                model: "model-c"
+              solution_discriminator:
+                prompt_template: "vote-solution"
+                model: "model-d"
+        "#;
+        let token = CancellationToken::new();
+        let llm = Arc::new(CancellingLlm {
+            token: token.clone(),
+            calls: Mutex::new(0),
+        });
+        let (file_system, clock, telemetry) = test_deps();
+        let runner = FlowRunner::new(
+            Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap()),
+            Some(llm.clone()),
+            Arc::new(HandlebarsRenderer::new()),
+            RunnerOptions::default(),
+            file_system,
+            clock,
+            telemetry,
+        )
+        .with_cancellation(Some(token));
+        let mut context = Context::new("Fix the bug", "code");
+
+        let RunnerOutcome::Paused(wait) = runner.execute(&mut context).await.unwrap() else {
+            panic!("expected cancellation to pause the run");
+        };
+        assert_eq!(wait.trigger, "cancelled");
+        assert_eq!(wait.step_id, 0);
+        assert_eq!(
+            *llm.calls.lock().unwrap(),
+            1,
+            "no LLM batch after cancelling"
+        );
+        assert!(matches!(
+            context.dequeue_work(),
+            Some(WorkItem::DecompositionVote { step_id: 0 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn cancellation_stops_resampling_inside_a_work_item() {
+        /// Answers every call with a one-step plan and cancels the run on the first call.
+        struct CancellingLlm {
+            token: CancellationToken,
+            calls: Mutex<usize>,
+        }
+
+        #[async_trait]
+        impl LlmClient for CancellingLlm {
+            async fn chat_completion(
+                &self,
+                _model: &str,
+                _prompt: &str,
+                _options: &LlmOptions,
+            ) -> crate::core::Result<String> {
+                *self.calls.lock().unwrap() += 1;
+                self.token.cancel();
+                Ok("- Write the fix".into())
+            }
+        }
+
+        let yaml = r#"
+        domains:
+          code:
+            agents:
+              decomposition:
+                prompt_template: "decompose"
+                model: "model-a"
+                samples: 1
+              decomposition_discriminator:
+                prompt_template: "vote-decompose"
+                model: "model-b"
+              solver:
+                prompt_template: "solve"
+                model: "model-c"
+              solution_discriminator:
+                prompt_template: "vote-solution"
+                model: "model-d"
+        "#;
+        let token = CancellationToken::new();
+        let llm = Arc::new(CancellingLlm {
+            token: token.clone(),
+            calls: Mutex::new(0),
+        });
+        // Every sample is over the size limit, so without cancellation the collector tops up
+        let options = RunnerOptions {
+            max_response_bytes: Some(1),
+            ..RunnerOptions::default()
+        };
+        let (file_system, clock, telemetry) = test_deps();
+        let runner = FlowRunner::new(
+            Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap()),
+            Some(llm.clone()),
+            Arc::new(HandlebarsRenderer::new()),
+            options,
+            file_system,
+            clock,
+            telemetry,
+        )
+        .with_cancellation(Some(token));
+        let mut context = Context::new("Fix the bug", "code");
+
+        let RunnerOutcome::Paused(wait) = runner.execute(&mut context).await.unwrap() else {
+            panic!("expected cancellation to pause the run");
+        };
+        assert_eq!(wait.trigger, "cancelled");
+        assert_eq!(
+            *llm.calls.lock().unwrap(),
+            1,
+            "no top-up batch after cancelling"
+        );
+        assert!(matches!(
+            context.dequeue_work(),
+            Some(WorkItem::Decomposition { step_id: 0 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn token_budget_pauses_the_run_once_crossed() {
+        /// Answers every call with a one-step plan billed at 40 tokens.
+        struct MeteredLlm {
+            calls: Mutex<usize>,
+        }
+
+        #[async_trait]
+        impl LlmClient for MeteredLlm {
+            async fn chat_completion(
+                &self,
+                _model: &str,
+                _prompt: &str,
+                _options: &LlmOptions,
+            ) -> crate::core::Result<String> {
+                unreachable!("the budgeted client asks for usage")
+            }
+
+            async fn chat_completion_with_finish_reason(
+                &self,
+                _model: &str,
+                _prompt: &str,
+                _options: &LlmOptions,
+            ) -> crate::core::Result<LlmResponse> {
+                *self.calls.lock().unwrap() += 1;
+                Ok(LlmResponse::new("- Write the fix").with_usage(TokenUsage {
+                    input_tokens: 30,
+                    output_tokens: 10,
+                }))
+            }
+        }
+
+        let yaml = r#"
+        domains:
+          code:
+            agents:
+              decomposition:
+                prompt_template: "decompose"
+                model: "model-a"
+                samples: 4
+              decomposition_discriminator:
+                prompt_template: "vote-decompose"
+                model: "model-b"
+              solver:
+                prompt_template: "solve"
+                model: "model-c"
+              solution_discriminator:
+                prompt_template: "vote-solution"
+                model: "model-d"
+        "#;
+        let llm = Arc::new(MeteredLlm {
+            calls: Mutex::new(0),
+        });
+        let budget = Arc::new(TokenBudget::new(50));
+        let (file_system, clock, telemetry) = test_deps();
+        let runner = FlowRunner::new(
+            Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap()),
+            Some(Arc::new(TokenBudgetLlmClient::new(
+                llm.clone(),
+                budget.clone(),
+            ))),
+            Arc::new(HandlebarsRenderer::new()),
+            RunnerOptions::default(),
+            file_system,
+            clock,
+            telemetry,
+        )
+        .with_token_budget(Some(budget.clone()));
+        let mut context = Context::new("Fix the bug", "code");
+
+        let RunnerOutcome::Paused(wait) = runner.execute(&mut context).await.unwrap() else {
+            panic!("expected the exhausted budget to pause the run");
+        };
+        assert_eq!(wait.trigger, "token_budget_exhausted");
+        assert_eq!(wait.step_id, 0);
+        assert!(wait.details.contains("80 of 50"), "{}", wait.details);
+        assert_eq!(
+            *llm.calls.lock().unwrap(),
+            2,
+            "sampling stops once the budget is crossed"
+        );
+        assert!(matches!(
+            context.dequeue_work(),
+            Some(WorkItem::Decomposition { step_id: 0 })
+        ));
+    }
+
+    #[test]
+    fn adaptive_samples_follow_solution_vote_margins() {
+        let yaml = r#"
+        domains:
+          code:
+            agents:
+              decomposition:
+                prompt_template: "decompose"
+                model: "model-a"
+              decomposition_discriminator:
+                prompt_template: "vote-decompose"
+                model: "model-b"
+              solver:
+                prompt_template: "solve"
+                model: "model-c"
+                samples: 8
+              solution_discriminator:
+                prompt_template: "vote-solution"
+                model: "model-d"
+                k: 2
+        "#;
+        let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
+        let runtime = config.domain("code").unwrap().to_runtime("code").unwrap();
+        let (file_system, clock, telemetry) = test_deps();
+        let runner = |adaptive_samples: bool| {
+            FlowRunner::new(
+                config.clone(),
+                None,
+                Arc::new(HandlebarsRenderer::new()),
+                RunnerOptions {
+                    adaptive_samples,
+                    ..RunnerOptions::default()
+                },
+                file_system.clone(),
+                clock.clone(),
+                telemetry.clone(),
+            )
+        };
+        let adaptive = runner(true);
+        let agents = adaptive.agent_configs(&runtime);
+        let solver = agents[&AgentKind::Solver].clone();
+        let samples_after = |votes: &[(usize, usize)], runner: &FlowRunner| {
+            let mut context = Context::new("Fix the bug", "code");
+            for &(winner, runner_up) in votes {
+                context
+                    .metrics
+                    .record_vote(1, AgentKind::SolutionDiscriminator, winner, runner_up);
+            }
+            runner.resolve_samples(&solver, &agents, &context)
+        };
+
+        let base = solver.samples;
+        assert_eq!(base, 8);
+        assert_eq!(samples_after(&[], &adaptive), base, "no votes yet");
+        let decisive = [(5, 0); 4];
+        let close = [(3, 2); 4];
+        assert!(
+            samples_after(&decisive, &adaptive) < base,
+            "shrinks after decisive votes"
+        );
+        assert!(
+            samples_after(&close, &adaptive) > base,
+            "grows after close votes"
+        );
+        assert_eq!(
+            samples_after(&[(3, 0)], &adaptive),
+            base,
+            "steady margins keep base"
+        );
+        assert_eq!(
+            samples_after(&decisive, &runner(false)),
+            base,
+            "off by default"
+        );
+    }
+
+    #[test]
+    fn adaptive_k_stays_within_configured_bounds() {
+        use crate::core::domain::RECENT_MARGIN_WINDOW;
+
+        let yaml = r#"
+        domains:
+          code:
+            agents:
+              decomposition:
+                prompt_template: "decompose"
+                model: "model-a"
+              decomposition_discriminator:
+                prompt_template: "vote-decompose"
+                model: "model-b"
+              solver:
+                prompt_template: "solve"
+                model: "model-c"
+              solution_discriminator:
+                prompt_template: "vote-solution"
+                model: "model-d"
+                k: 3
+        "#;
+        let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
+        let runtime = config.domain("code").unwrap().to_runtime("code").unwrap();
+        let runner_with = |limits: AdaptiveKLimits| {
+            let (file_system, clock, telemetry) = test_deps();
+            FlowRunner::new(
+                config.clone(),
+                None,
+                Arc::new(HandlebarsRenderer::new()),
+                RunnerOptions {
+                    adaptive_k: true,
+                    adaptive_k_limits: limits,
+                    ..RunnerOptions::default()
+                },
+                file_system,
+                clock,
+                telemetry,
+            )
+        };
+        let k_after = |runner: &FlowRunner, margin: usize| {
+            let discriminator =
+                runner.agent_configs(&runtime)[&AgentKind::SolutionDiscriminator].clone();
+            let mut context = Context::new("Fix the bug", "code");
+            for _ in 0..RECENT_MARGIN_WINDOW {
+                context
+                    .metrics
+                    .record_vote(1, AgentKind::SolutionDiscriminator, margin, 0);
+            }
+            runner.resolve_k(AgentKind::SolutionDiscriminator, &discriminator, &context)
+        };
+
+        let unbounded = runner_with(AdaptiveKLimits {
+            step: 2,
+            ..AdaptiveKLimits::default()
+        });
+        assert_eq!(k_after(&unbounded, 1), 5, "close votes raise k by the step");
+        assert_eq!(
+            k_after(&unbounded, 9),
+            1,
+            "decisive votes lower k, never below 1"
+        );
+
+        let bounded = runner_with(AdaptiveKLimits {
+            min: 2,
+            max: Some(4),
+            step: 5,
+        });
+        for margin in 0..=12 {
+            let k = k_after(&bounded, margin);
+            assert!((2..=4).contains(&k), "margin {margin} gave k {k}");
+        }
+        assert_eq!(k_after(&bounded, 1), 4);
+        assert_eq!(k_after(&bounded, 9), 2);
+        assert_eq!(k_after(&bounded, 3), 3, "steady margins keep the base");
+    }
+
+    #[test]
+    fn adaptive_k_continues_from_persisted_margin_history() {
+        use crate::adapters::outbound::persistence::{
+            SessionEnvelope, SessionMetadata, SessionStatus, SessionStore,
+        };
+        use crate::core::domain::RECENT_MARGIN_WINDOW;
+
+        let yaml = r#"
+        domains:
+          code:
+            agents:
+              decomposition:
+                prompt_template: "decompose"
+                model: "model-a"
+              decomposition_discriminator:
+                prompt_template: "vote-decompose"
+                model: "model-b"
+              solver:
+                prompt_template: "solve"
+                model: "model-c"
+              solution_discriminator:
+                prompt_template: "vote-solution"
+                model: "model-d"
+                k: 3
+        "#;
+        let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
+        let runtime = config.domain("code").unwrap().to_runtime("code").unwrap();
+        let (file_system, clock, telemetry) = test_deps();
+        let runner = FlowRunner::new(
+            config.clone(),
+            None,
+            Arc::new(HandlebarsRenderer::new()),
+            RunnerOptions {
+                adaptive_k: true,
+                ..RunnerOptions::default()
+            },
+            file_system,
+            clock,
+            telemetry,
+        );
+        let agents = runner.agent_configs(&runtime);
+        let discriminator = agents[&AgentKind::SolutionDiscriminator].clone();
+
+        // Decisive early votes fall out of the window; the close ones that follow decide k.
+        let mut context = Context::new("Fix the bug", "code");
+        context.session_id = "adaptive-resume".into();
+        for _ in 0..4 {
+            context
+                .metrics
+                .record_vote(1, AgentKind::SolutionDiscriminator, 9, 0);
+        }
+        for _ in 0..RECENT_MARGIN_WINDOW {
+            context
+                .metrics
+                .record_vote(1, AgentKind::SolutionDiscriminator, 2, 1);
+        }
+        let before = runner.resolve_k(AgentKind::SolutionDiscriminator, &discriminator, &context);
+        assert_eq!(before, 4, "close margins raise k above the base of 3");
+
+        let temp = tempfile::tempdir().unwrap();
+        let store = SessionStore::open(Some(temp.path().to_path_buf())).unwrap();
+        let envelope = SessionEnvelope {
+            context,
+            metadata: SessionMetadata {
+                config_path: "config.yaml".into(),
+                llm_provider: "openai".into(),
+                llm_model: "gpt".into(),
+                max_concurrent_llm: 1,
+                samples: 1,
+                k: 3,
+                adaptive_k: true,
+                human_low_margin_threshold: 1,
+                ..Default::default()
+            },
+        };
+        store.save(&envelope, SessionStatus::Paused).unwrap();
+        let resumed = store.load("adaptive-resume").unwrap().envelope.context;
+
+        let stats = resumed
+            .metrics
+            .vote_stats(AgentKind::SolutionDiscriminator)
+            .expect("vote history persisted");
+        assert_eq!(stats.total_votes, 4 + RECENT_MARGIN_WINDOW);
+        assert_eq!(
+            stats.recent_margins,
+            VecDeque::from(vec![1; RECENT_MARGIN_WINDOW])
+        );
+        assert_eq!(
+            runner.resolve_k(AgentKind::SolutionDiscriminator, &discriminator, &resumed),
+            before
+        );
+    }
+
+    #[tokio::test]
+    async fn executes_analysis_domain_with_default_config() {
+        let config = Arc::new(
+            MicrofactoryConfig::from_path("config.yaml").expect("default config.yaml should load"),
+        );
+        // 4 samples requested for solver by default in config? Or 10?
+        // Default RunnerOptions has samples=2.
+        // Config yaml might specify more. Analysis solver usually has samples: 10?
+        // Let's check the test expectation: "solver sampled four times"
+        // So we need 4 responses in that batch.
+
+        let llm: Arc<dyn LlmClient> = Arc::new(ScriptedLlm::new(vec![
+            // Decomposition
+            vec![
+                "- Produce executive-ready insight".into(),
+                "- Draft alternative plan".into(),
+            ],
+            // Vote
+            vec!["1".into(), "1".into()],
+            // Solver (4 samples expected based on assertion)
+            vec![
+                "Finding A: adoption up 12%".into(),
+                "Finding B: adoption flat".into(),
+                "Finding C: adoption down".into(),
+                "Finding D: inconclusive".into(),
+            ],
+            // Vote
+            vec!["1".into(), "1".into()],
+        ]));
+
+        let mut context = Context::new(
             "Assess quarterly adoption trends for analytics rollout",
             "analysis",
         );
@@ -801,167 +2534,696 @@ mod tests {
         let (file_system, clock, telemetry) = test_deps();
         let runner = FlowRunner::new(
             config,
-            Some(llm),
+            Some(llm),
+            renderer,
+            RunnerOptions::default(),
+            file_system,
+            clock,
+            telemetry,
+        );
+        let outcome = runner.execute(&mut context).await.unwrap();
+        assert!(matches!(outcome, RunnerOutcome::Completed));
+
+        let root = context.root_step_id().expect("root step available");
+        let root_step = context.step(root).expect("root step exists");
+        assert_eq!(root_step.children.len(), 1, "one analysis subtask spawned");
+        let child_id = root_step.children[0];
+        let child = context.step(child_id).expect("child step present");
+        assert_eq!(child.status, StepStatus::Completed);
+        assert_eq!(
+            child.winning_solution.as_deref(),
+            Some("Finding A: adoption up 12%"),
+            "expected winning solution recorded"
+        );
+        let metrics = context
+            .metrics()
+            .step_metrics(child_id)
+            .expect("metrics recorded for child");
+        assert_eq!(metrics.samples_requested, 4, "solver sampled four times");
+        assert_eq!(metrics.vote_margin, Some(2));
+    }
+
+    #[tokio::test]
+    async fn executes_apply_verify_flow() {
+        let yaml = r#"#
+        domains:
+          test_verify:
+            agents:
+              decomposition:
+                prompt_template: "d"
+                model: "m"
+                samples: 1
+              decomposition_discriminator:
+                prompt_template: "dv"
+                model: "m"
+                k: 1
+              solver:
+                prompt_template: "s"
+                model: "m"
+                samples: 1
+              solution_discriminator:
+                prompt_template: "sv"
+                model: "m"
+                k: 1
+            verifier: "true"
+            applier: "patch_file"
+        "#;
+        let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
+        let llm: Arc<dyn LlmClient> = Arc::new(ScriptedLlm::new(vec![
+            vec!["- task".into()], // decomposition
+            vec!["1".into()],      // decomposition vote
+            vec!["sol".into()],    // solve
+            vec!["1".into()],      // solution vote
+        ]));
+
+        let mut context = Context::new("Run verify", "test_verify");
+        let renderer = Arc::new(HandlebarsRenderer::new());
+        let options = RunnerOptions {
+            human_low_margin_threshold: 0,
+            default_samples: 1,
+            ..RunnerOptions::default()
+        };
+        let (file_system, clock, telemetry) = test_deps();
+        let runner = FlowRunner::new(
+            config,
+            Some(llm),
+            renderer,
+            options,
+            file_system,
+            clock,
+            telemetry,
+        )
+        .with_command_runner(Arc::new(MockCommandRunner::new(Vec::new())));
+        let outcome = runner.execute(&mut context).await.unwrap();
+        assert!(matches!(outcome, RunnerOutcome::Completed));
+
+        let root = context.root_step_id().unwrap();
+        let root_step = context.step(root).unwrap();
+        let child_id = root_step.children[0];
+        let child = context.step(child_id).unwrap();
+
+        assert_eq!(child.status, StepStatus::Completed);
+        assert_eq!(child.winning_solution.as_deref(), Some("sol"));
+    }
+
+    #[tokio::test]
+    async fn reports_failed_steps_when_verifier_fails() {
+        let yaml = r#"#
+        domains:
+          test_verify:
+            agents:
+              decomposition:
+                prompt_template: "d"
+                model: "m"
+              decomposition_discriminator:
+                prompt_template: "dv"
+                model: "m"
+              solver:
+                prompt_template: "s"
+                model: "m"
+                samples: 1
+              solution_discriminator:
+                prompt_template: "sv"
+                model: "m"
+                samples: 1
+            verifier: "./verify.sh"
+        "#;
+        let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
+        let llm: Arc<dyn LlmClient> = Arc::new(ScriptedLlm::new(vec![
+            vec!["sol".into()], // solve
+            vec!["1".into()],   // solution vote
+        ]));
+
+        let mut context = Context::new("Run verify", "test_verify");
+        let child_id = context.seed_plan(&["Fix the build".into()])[0];
+        let options = RunnerOptions {
+            human_low_margin_threshold: 0,
+            ..RunnerOptions::default()
+        };
+        let renderer = Arc::new(HandlebarsRenderer::new());
+        let (file_system, clock, telemetry) = test_deps();
+        let command_runner = Arc::new(MockCommandRunner::new(vec![CommandOutput {
+            status: Some(1),
+            ..CommandOutput::default()
+        }]));
+        let runner = FlowRunner::new(
+            config,
+            Some(llm),
+            renderer,
+            options,
+            file_system,
+            clock,
+            telemetry,
+        )
+        .with_command_runner(command_runner.clone());
+        let outcome = runner.execute(&mut context).await.unwrap();
+        assert_eq!(command_runner.commands(), vec!["./verify.sh".to_string()]);
+        let RunnerOutcome::CompletedWithFailures { failed_steps } = outcome else {
+            panic!("expected completed-with-failures, got {outcome:?}");
+        };
+        assert_eq!(failed_steps, vec![child_id]);
+        assert_eq!(context.step(child_id).unwrap().status, StepStatus::Failed);
+    }
+
+    #[tokio::test]
+    async fn respects_agent_specific_red_flaggers() {
+        let yaml = r#"#
+        domains:
+          strict_check:
+            agents:
+              decomposition:
+                prompt_template: "d"
+                model: "m"
+                samples: 1
+                red_flaggers:
+                  - type: "length"
+                    max_tokens: 1  # Strict!
+              decomposition_discriminator:
+                prompt_template: "dv"
+                model: "m"
+              solver:
+                prompt_template: "s"
+                model: "m"
+              solution_discriminator:
+                prompt_template: "sv"
+                model: "m"
+            red_flaggers:
+              - type: "length"
+                max_tokens: 100 # Lax default
+        "#;
+        let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
+        // Response has 3 tokens "too long response", fails strict check (1), passes lax (100)
+        let llm: Arc<dyn LlmClient> = Arc::new(ScriptedLlm::new(vec![
+            vec!["too long response".into()], // decomposition sample 1 (red flagged)
+            vec!["ok".into()],                // decomposition sample 2 (accepted)
+        ]));
+
+        let mut context = Context::new("Check", "strict_check");
+        let options = RunnerOptions {
+            human_red_flag_threshold: 1, // Pause immediately on flag
+            ..RunnerOptions::default()
+        };
+        let renderer = Arc::new(HandlebarsRenderer::new());
+        let (file_system, clock, telemetry) = test_deps();
+        let runner = FlowRunner::new(
+            config,
+            Some(llm),
+            renderer,
+            options,
+            file_system,
+            clock,
+            telemetry,
+        );
+
+        // Execute
+        let outcome = runner.execute(&mut context).await.unwrap();
+
+        // Expect a pause due to red flags
+        match outcome {
+            RunnerOutcome::Paused(wait) => {
+                assert_eq!(wait.trigger, "decomposition sampling_red_flags");
+            }
+            _ => panic!("Expected execution to pause due to red flags, got {outcome:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn paused_step_reports_awaiting_human_until_resumed() {
+        let yaml = r#"#
+        domains:
+          code:
+            agents:
+              decomposition:
+                prompt_template: "d"
+                model: "m"
+                samples: 1
+                red_flaggers:
+                  - type: "length"
+                    max_tokens: 1
+              decomposition_discriminator:
+                prompt_template: "dv"
+                model: "m"
+              solver:
+                prompt_template: "s"
+                model: "m"
+              solution_discriminator:
+                prompt_template: "sv"
+                model: "m"
+        "#;
+        let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
+        let llm: Arc<dyn LlmClient> = Arc::new(ScriptedLlm::new(vec![
+            vec!["too long response".into()],
+            vec!["ok".into()],
+        ]));
+        let options = RunnerOptions {
+            human_red_flag_threshold: 1,
+            ..RunnerOptions::default()
+        };
+        let (file_system, clock, telemetry) = test_deps();
+        let runner = FlowRunner::new(
+            config,
+            Some(llm),
+            Arc::new(HandlebarsRenderer::new()),
+            options,
+            file_system,
+            clock,
+            telemetry,
+        );
+
+        let mut context = Context::new("Check", "code");
+        let RunnerOutcome::Paused(wait) = runner.execute(&mut context).await.unwrap() else {
+            panic!("expected a red-flag pause");
+        };
+        let status = context.step(wait.step_id).unwrap().status;
+        assert_eq!(status, StepStatus::AwaitingHuman);
+        assert_eq!(serde_json::to_string(&status).unwrap(), "\"AwaitingHuman\"");
+
+        context.clear_wait_state();
+        assert_eq!(
+            context.step(wait.step_id).unwrap().status,
+            StepStatus::Running
+        );
+    }
+
+    #[tokio::test]
+    async fn pauses_at_checkpoints_when_step_by_step_enabled() {
+        let _ = tracing_subscriber::fmt().with_test_writer().try_init();
+
+        // Setup minimal config
+        let yaml = r#"#
+        domains:
+          step_check:
+            agents:
+              decomposition:
+                prompt_template: "d"
+                model: "m"
+              decomposition_discriminator:
+                prompt_template: "dv"
+                model: "m"
+              solver:
+                prompt_template: "s"
+                model: "m"
+              solution_discriminator:
+                prompt_template: "sv"
+                model: "m"
+            applier: "patch_file"
+        "#;
+        let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
+
+        // We expect:
+        // 1. Decomposition (1 sample) -> Pause (Checkpoint 1)
+        // 2. Resume -> Solving -> Voting -> Apply -> Pause (Checkpoint 2)
+        let llm: Arc<dyn LlmClient> = Arc::new(ScriptedLlm::new(vec![
+            vec!["- task".into()], // decomposition
+            vec!["1".into()],      // decomposition vote
+            vec!["sol".into()],    // solve
+            vec!["1".into()],      // solution vote
+        ]));
+
+        let mut context = Context::new("Test Stepping", "step_check");
+        let options = RunnerOptions {
+            step_by_step: true,
+            default_samples: 1,
+            default_k: 1,
+            human_low_margin_threshold: 0,
+            ..RunnerOptions::default()
+        };
+        let renderer = Arc::new(HandlebarsRenderer::new());
+        let (file_system, clock, telemetry) = test_deps();
+        let runner = FlowRunner::new(
+            config,
+            Some(llm),
+            renderer,
+            options,
+            file_system,
+            clock,
+            telemetry,
+        );
+
+        // 1. First run: Should reach Decomposition Vote and then Pause
+        let outcome1 = runner.execute(&mut context).await.unwrap();
+        match outcome1 {
+            RunnerOutcome::Paused(wait) => {
+                assert_eq!(wait.trigger, "step_by_step_checkpoint");
+                assert!(wait.details.contains("Decomposition plan ready"));
+            }
+            _ => panic!("Expected first pause at decomposition checkpoint, got {outcome1:?}"),
+        }
+        context.clear_wait_state();
+
+        // 2. Resume: Should execute Solve -> Solution Vote -> Apply -> Pause
+        let outcome2 = runner.execute(&mut context).await.unwrap();
+        match outcome2 {
+            RunnerOutcome::Paused(wait) => {
+                assert_eq!(wait.trigger, "step_by_step_checkpoint");
+                assert!(wait.details.contains("Step finished execution"));
+            }
+            _ => panic!("Expected second pause at step completion checkpoint, got {outcome2:?}"),
+        }
+        context.clear_wait_state();
+
+        // 3. Resume: Should see no more work and complete
+        let outcome3 = runner.execute(&mut context).await.unwrap();
+        assert!(matches!(outcome3, RunnerOutcome::Completed));
+    }
+
+    #[test]
+    fn explain_records_recursion_rationale() {
+        let yaml = r#"#
+        domains:
+          demo:
+            agents:
+              decomposition:
+                prompt_template: "d"
+                model: "m"
+              decomposition_discriminator:
+                prompt_template: "dv"
+                model: "m"
+              solver:
+                prompt_template: "s"
+                model: "m"
+              solution_discriminator:
+                prompt_template: "sv"
+                model: "m"
+        "#;
+        let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
+        let options = RunnerOptions {
+            explain: true,
+            ..RunnerOptions::default()
+        };
+        let (file_system, clock, telemetry) = test_deps();
+        let runner = FlowRunner::new(
+            config,
+            None,
+            Arc::new(HandlebarsRenderer::new()),
+            options,
+            file_system,
+            clock,
+            telemetry,
+        );
+        let long = "write the parser module with error recovery and exhaustive unit tests";
+        let mut ctx = Context::new("demo", "demo");
+        let root = ctx.ensure_root();
+        let short = ctx.add_child_step(root, "add docs");
+        let mid = ctx.add_child_step(root, long);
+        let deep = ctx.add_child_step(mid, long);
+
+        assert!(!runner.should_recurse(&mut ctx, short));
+        assert!(runner.should_recurse(&mut ctx, mid));
+        assert!(!runner.should_recurse(&mut ctx, deep));
+
+        let short_decision = ctx.step(short).unwrap().recursion.clone().unwrap();
+        assert!(
+            short_decision
+                .reason
+                .starts_with("not recursed: below min words"),
+            "{}",
+            short_decision.reason
+        );
+        assert_eq!(short_decision.word_count, 2);
+        assert_eq!(short_decision.min_words, 8);
+
+        let deep_decision = ctx.step(deep).unwrap().recursion.clone().unwrap();
+        assert!(
+            deep_decision
+                .reason
+                .starts_with("not recursed: depth cap reached"),
+            "{}",
+            deep_decision.reason
+        );
+        assert_eq!((deep_decision.depth, deep_decision.max_depth), (2, 2));
+        assert!(ctx.step(mid).unwrap().recursion.as_ref().unwrap().recursed);
+    }
+
+    #[test]
+    fn max_depth_override_is_clamped_and_changes_recursion() {
+        assert_eq!(
+            RunnerOptions::default()
+                .with_max_depth(Some(0))
+                .max_decomposition_depth,
+            1
+        );
+        assert_eq!(
+            RunnerOptions::default()
+                .with_max_depth(None)
+                .max_decomposition_depth,
+            2
+        );
+
+        let config = Arc::new(MicrofactoryConfig::from_path("config.yaml").unwrap());
+        let long = "write the parser module with error recovery and exhaustive unit tests";
+        let mut ctx = Context::new("demo", "code");
+        let root = ctx.ensure_root();
+        let mid = ctx.add_child_step(root, long);
+        let deep = ctx.add_child_step(mid, long);
+
+        let recurses = |options: RunnerOptions, ctx: &mut Context| {
+            let (file_system, clock, telemetry) = test_deps();
+            FlowRunner::new(
+                config.clone(),
+                None,
+                Arc::new(HandlebarsRenderer::new()),
+                options,
+                file_system,
+                clock,
+                telemetry,
+            )
+            .should_recurse(ctx, deep)
+        };
+        assert!(!recurses(RunnerOptions::default(), &mut ctx));
+        assert!(recurses(
+            RunnerOptions::default().with_max_depth(Some(3)),
+            &mut ctx
+        ));
+    }
+
+    #[test]
+    fn low_margin_threshold_zero_disables_pause() {
+        let yaml = r#"#
+        domains:
+          demo:
+            agents:
+              decomposition:
+                prompt_template: "d"
+                model: "m"
+              decomposition_discriminator:
+                prompt_template: "dv"
+                model: "m"
+              solver:
+                prompt_template: "s"
+                model: "m"
+              solution_discriminator:
+                prompt_template: "sv"
+                model: "m"
+        "#;
+        let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
+        let options = RunnerOptions {
+            human_low_margin_threshold: 0,
+            ..RunnerOptions::default()
+        };
+        let renderer = Arc::new(HandlebarsRenderer::new());
+        let (file_system, clock, telemetry) = test_deps();
+        let runner = FlowRunner::new(
+            config,
+            None,
+            renderer,
+            options,
+            file_system,
+            clock,
+            telemetry,
+        );
+        let mut ctx = Context::new("demo", "demo");
+        let step_id = ctx.ensure_root();
+        ctx.metrics
+            .record_vote(step_id, AgentKind::DecompositionDiscriminator, 1, 0);
+
+        let wait = runner.check_vote_triggers(&ctx, step_id, "decomposition vote");
+        assert!(wait.is_none(), "threshold=0 should skip low-margin pauses");
+    }
+
+    #[test]
+    fn positive_threshold_pauses_when_margin_is_low() {
+        let yaml = r#"#
+        domains:
+          demo:
+            agents:
+              decomposition:
+                prompt_template: "d"
+                model: "m"
+              decomposition_discriminator:
+                prompt_template: "dv"
+                model: "m"
+              solver:
+                prompt_template: "s"
+                model: "m"
+              solution_discriminator:
+                prompt_template: "sv"
+                model: "m"
+        "#;
+        let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
+        let options = RunnerOptions {
+            human_low_margin_threshold: 2,
+            ..RunnerOptions::default()
+        };
+        let renderer = Arc::new(HandlebarsRenderer::new());
+        let (file_system, clock, telemetry) = test_deps();
+        let runner = FlowRunner::new(
+            config,
+            None,
             renderer,
-            RunnerOptions::default(),
+            options,
             file_system,
             clock,
             telemetry,
         );
-        let outcome = runner.execute(&mut context).await.unwrap();
-        assert!(matches!(outcome, RunnerOutcome::Completed));
+        let mut ctx = Context::new("demo", "demo");
+        let step_id = ctx.ensure_root();
+        ctx.metrics
+            .record_vote(step_id, AgentKind::DecompositionDiscriminator, 1, 0);
 
-        let root = context.root_step_id().expect("root step available");
-        let root_step = context.step(root).expect("root step exists");
-        assert_eq!(root_step.children.len(), 1, "one analysis subtask spawned");
-        let child_id = root_step.children[0];
-        let child = context.step(child_id).expect("child step present");
-        assert_eq!(child.status, StepStatus::Completed);
-        assert_eq!(
-            child.winning_solution.as_deref(),
-            Some("Finding A: adoption up 12%"),
-            "expected winning solution recorded"
-        );
-        let metrics = context
-            .metrics()
-            .step_metrics(child_id)
-            .expect("metrics recorded for child");
-        assert_eq!(metrics.samples_requested, 4, "solver sampled four times");
-        assert_eq!(metrics.vote_margin, Some(2));
+        let wait = runner.check_vote_triggers(&ctx, step_id, "decomposition vote");
+        assert!(wait.is_some(), "margin 1 <= threshold 2 should pause");
     }
 
-    #[tokio::test]
-    async fn executes_apply_verify_flow() {
+    #[test]
+    fn positive_threshold_allows_decisive_votes() {
         let yaml = r#"#
         domains:
-          test_verify:
+          demo:
             agents:
               decomposition:
                 prompt_template: "d"
                 model: "m"
-                samples: 1
               decomposition_discriminator:
                 prompt_template: "dv"
                 model: "m"
-                k: 1
               solver:
                 prompt_template: "s"
                 model: "m"
-                samples: 1
               solution_discriminator:
                 prompt_template: "sv"
                 model: "m"
-                k: 1
-            verifier: "true"
-            applier: "patch_file"
         "#;
         let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
-        let llm: Arc<dyn LlmClient> = Arc::new(ScriptedLlm::new(vec![
-            vec!["- task".into()], // decomposition
-            vec!["1".into()],      // decomposition vote
-            vec!["sol".into()],    // solve
-            vec!["1".into()],      // solution vote
-        ]));
-
-        let mut context = Context::new("Run verify", "test_verify");
-        let renderer = Arc::new(HandlebarsRenderer::new());
         let options = RunnerOptions {
-            human_low_margin_threshold: 0,
-            default_samples: 1,
+            human_low_margin_threshold: 1,
             ..RunnerOptions::default()
         };
+        let renderer = Arc::new(HandlebarsRenderer::new());
         let (file_system, clock, telemetry) = test_deps();
         let runner = FlowRunner::new(
             config,
-            Some(llm),
+            None,
             renderer,
             options,
             file_system,
             clock,
             telemetry,
         );
-        let outcome = runner.execute(&mut context).await.unwrap();
-        assert!(matches!(outcome, RunnerOutcome::Completed));
+        let mut ctx = Context::new("demo", "demo");
+        let step_id = ctx.ensure_root();
+        ctx.metrics
+            .record_vote(step_id, AgentKind::DecompositionDiscriminator, 3, 1);
 
-        let root = context.root_step_id().unwrap();
-        let root_step = context.step(root).unwrap();
-        let child_id = root_step.children[0];
-        let child = context.step(child_id).unwrap();
+        let wait = runner.check_vote_triggers(&ctx, step_id, "decomposition vote");
+        assert!(wait.is_none(), "margin 2 > threshold 1 should continue");
+    }
 
-        assert_eq!(child.status, StepStatus::Completed);
-        assert_eq!(child.winning_solution.as_deref(), Some("sol"));
+    #[derive(Default)]
+    struct CapturingSink {
+        events: Mutex<Vec<(String, HashMap<String, String>)>>,
+    }
+
+    impl TelemetrySink for CapturingSink {
+        fn record_event(&self, event_name: &str, properties: HashMap<String, String>) {
+            self.events
+                .lock()
+                .unwrap()
+                .push((event_name.to_string(), properties));
+        }
     }
 
     #[tokio::test]
-    async fn respects_agent_specific_red_flaggers() {
+    async fn pause_emits_session_paused_event_with_step_context() {
         let yaml = r#"#
         domains:
-          strict_check:
+          code:
+            applier: "overwrite_file"
             agents:
               decomposition:
                 prompt_template: "d"
                 model: "m"
-                samples: 1
-                red_flaggers:
-                  - type: "length"
-                    max_tokens: 1  # Strict!
               decomposition_discriminator:
                 prompt_template: "dv"
                 model: "m"
               solver:
                 prompt_template: "s"
                 model: "m"
+                samples: 1
               solution_discriminator:
                 prompt_template: "sv"
                 model: "m"
-            red_flaggers:
-              - type: "length"
-                max_tokens: 100 # Lax default
+                samples: 1
         "#;
         let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
-        // Response has 3 tokens "too long response", fails strict check (1), passes lax (100)
         let llm: Arc<dyn LlmClient> = Arc::new(ScriptedLlm::new(vec![
-            vec!["too long response".into()], // decomposition sample 1 (red flagged)
-            vec!["ok".into()],                // decomposition sample 2 (accepted)
+            vec![r#"<file path="notes.txt">hello</file>"#.into()],
+            vec!["1".into()],
         ]));
-
-        let mut context = Context::new("Check", "strict_check");
         let options = RunnerOptions {
-            human_red_flag_threshold: 1, // Pause immediately on flag
+            human_low_margin_threshold: 0,
+            step_by_step: true,
             ..RunnerOptions::default()
         };
-        let renderer = Arc::new(HandlebarsRenderer::new());
-        let (file_system, clock, telemetry) = test_deps();
+        let sink = Arc::new(CapturingSink::default());
+        let (file_system, clock, _) = test_deps();
         let runner = FlowRunner::new(
             config,
             Some(llm),
-            renderer,
+            Arc::new(HandlebarsRenderer::new()),
             options,
             file_system,
             clock,
-            telemetry,
+            sink.clone(),
         );
 
-        // Execute
+        let tmp = tempfile::tempdir().unwrap();
+        let mut context = Context::new("Write notes", "code");
+        context.session_id = "session-1".into();
+        context.output_dir = Some(tmp.path().to_path_buf());
+        context.seed_plan(&["Write notes.txt".into()]);
+
         let outcome = runner.execute(&mut context).await.unwrap();
+        let RunnerOutcome::Paused(wait) = outcome else {
+            panic!("expected pre-apply pause");
+        };
 
-        // Expect a pause due to red flags
-        match outcome {
-            RunnerOutcome::Paused(wait) => {
-                assert_eq!(wait.trigger, "decomposition sampling_red_flags");
-            }
-            _ => panic!("Expected execution to pause due to red flags, got {outcome:?}"),
-        }
+        let events = sink.events.lock().unwrap();
+        let paused: Vec<_> = events
+            .iter()
+            .filter(|(name, _)| name == "session_paused")
+            .map(|(_, props)| props)
+            .collect();
+        assert_eq!(paused.len(), 1);
+        let props = paused[0];
+        assert_eq!(props["session_id"], "session-1");
+        assert_eq!(props["domain"], "code");
+        assert_eq!(props["step_id"], wait.step_id.to_string());
+        assert_eq!(props["trigger"], "step_by_step_pre_apply");
+        assert_eq!(props["step_description"], "Write notes.txt");
+        assert_eq!(props["prompt"], "Write notes");
+        assert!(props["details"].contains("notes.txt"));
+        assert_eq!(props["samples"], "1");
+        assert_eq!(props["steps_total"], "2");
+        assert!(props.contains_key("vote_attempts"));
     }
 
     #[tokio::test]
-    async fn pauses_at_checkpoints_when_step_by_step_enabled() {
-        let _ = tracing_subscriber::fmt().with_test_writer().try_init();
-
-        // Setup minimal config
+    async fn step_by_step_previews_files_before_applying() {
         let yaml = r#"#
         domains:
-          step_check:
+          code:
+            applier: "overwrite_file"
             agents:
               decomposition:
                 prompt_template: "d"
@@ -972,29 +3234,20 @@ mod tests {
               solver:
                 prompt_template: "s"
                 model: "m"
+                samples: 1
               solution_discriminator:
                 prompt_template: "sv"
                 model: "m"
-            applier: "patch_file"
+                samples: 1
         "#;
         let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
-
-        // We expect:
-        // 1. Decomposition (1 sample) -> Pause (Checkpoint 1)
-        // 2. Resume -> Solving -> Voting -> Apply -> Pause (Checkpoint 2)
         let llm: Arc<dyn LlmClient> = Arc::new(ScriptedLlm::new(vec![
-            vec!["- task".into()], // decomposition
-            vec!["1".into()],      // decomposition vote
-            vec!["sol".into()],    // solve
-            vec!["1".into()],      // solution vote
+            vec![r#"<file path="notes.txt">hello</file>"#.into()],
+            vec!["1".into()],
         ]));
-
-        let mut context = Context::new("Test Stepping", "step_check");
         let options = RunnerOptions {
-            step_by_step: true,
-            default_samples: 1,
-            default_k: 1,
             human_low_margin_threshold: 0,
+            step_by_step: true,
             ..RunnerOptions::default()
         };
         let renderer = Arc::new(HandlebarsRenderer::new());
@@ -1009,38 +3262,45 @@ mod tests {
             telemetry,
         );
 
-        // 1. First run: Should reach Decomposition Vote and then Pause
-        let outcome1 = runner.execute(&mut context).await.unwrap();
-        match outcome1 {
-            RunnerOutcome::Paused(wait) => {
-                assert_eq!(wait.trigger, "step_by_step_checkpoint");
-                assert!(wait.details.contains("Decomposition plan ready"));
-            }
-            _ => panic!("Expected first pause at decomposition checkpoint, got {outcome1:?}"),
-        }
+        let tmp = tempfile::tempdir().unwrap();
+        let target = tmp.path().join("notes.txt");
+        let mut context = Context::new("Write notes", "code");
+        context.output_dir = Some(tmp.path().to_path_buf());
+        context.seed_plan(&["Write notes.txt".into()]);
+
+        let outcome = runner.execute(&mut context).await.unwrap();
+        let RunnerOutcome::Paused(wait) = outcome else {
+            panic!("expected pre-apply pause");
+        };
+        assert_eq!(wait.trigger, "step_by_step_pre_apply");
+        assert!(wait.details.contains("notes.txt"), "{}", wait.details);
+        assert!(wait.details.contains("+hello"), "{}", wait.details);
+        assert!(!target.exists(), "nothing written before approval");
+
         context.clear_wait_state();
+        runner.execute(&mut context).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "hello");
+    }
 
-        // 2. Resume: Should execute Solve -> Solution Vote -> Apply -> Pause
-        let outcome2 = runner.execute(&mut context).await.unwrap();
-        match outcome2 {
-            RunnerOutcome::Paused(wait) => {
-                assert_eq!(wait.trigger, "step_by_step_checkpoint");
-                assert!(wait.details.contains("Step finished execution"));
+    #[tokio::test]
+    async fn interactive_review_rejects_then_approves_in_process() {
+        /// Answers checkpoints with the scripted decisions in order, then pauses.
+        struct ScriptedReviewer(Mutex<VecDeque<CheckpointDecision>>);
+
+        impl CheckpointReviewer for ScriptedReviewer {
+            fn review(&self, _context: &Context, _wait: &WaitState) -> CheckpointDecision {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .pop_front()
+                    .unwrap_or(CheckpointDecision::Pause)
             }
-            _ => panic!("Expected second pause at step completion checkpoint, got {outcome2:?}"),
         }
-        context.clear_wait_state();
-
-        // 3. Resume: Should see no more work and complete
-        let outcome3 = runner.execute(&mut context).await.unwrap();
-        assert!(matches!(outcome3, RunnerOutcome::Completed));
-    }
 
-    #[test]
-    fn low_margin_threshold_zero_disables_pause() {
         let yaml = r#"#
         domains:
-          demo:
+          code:
+            applier: "overwrite_file"
             agents:
               decomposition:
                 prompt_template: "d"
@@ -1051,40 +3311,88 @@ mod tests {
               solver:
                 prompt_template: "s"
                 model: "m"
+                samples: 1
               solution_discriminator:
                 prompt_template: "sv"
                 model: "m"
+                samples: 1
         "#;
         let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
+        let llm: Arc<dyn LlmClient> = Arc::new(ScriptedLlm::new(vec![
+            vec![r#"<file path="notes.txt">first</file>"#.into()],
+            vec!["1".into()],
+            vec![r#"<file path="notes.txt">second</file>"#.into()],
+            vec!["1".into()],
+        ]));
         let options = RunnerOptions {
             human_low_margin_threshold: 0,
+            step_by_step: true,
             ..RunnerOptions::default()
         };
+        // Reject the first pre-apply preview, approve the re-solved one, then approve the
+        // post-step checkpoint.
+        let reviewer = Arc::new(ScriptedReviewer(Mutex::new(VecDeque::from([
+            CheckpointDecision::Reject,
+            CheckpointDecision::Approve,
+            CheckpointDecision::Approve,
+        ]))));
         let renderer = Arc::new(HandlebarsRenderer::new());
         let (file_system, clock, telemetry) = test_deps();
         let runner = FlowRunner::new(
             config,
-            None,
+            Some(llm),
             renderer,
             options,
             file_system,
             clock,
             telemetry,
-        );
-        let mut ctx = Context::new("demo", "demo");
-        let step_id = ctx.ensure_root();
-        ctx.metrics
-            .record_vote(step_id, AgentKind::DecompositionDiscriminator, 1, 0);
+        )
+        .with_checkpoint_reviewer(Some(reviewer.clone()));
 
-        let wait = runner.check_vote_triggers(&ctx, step_id, "decomposition vote");
-        assert!(wait.is_none(), "threshold=0 should skip low-margin pauses");
+        let tmp = tempfile::tempdir().unwrap();
+        let mut context = Context::new("Write notes", "code");
+        context.output_dir = Some(tmp.path().to_path_buf());
+        let child = context.seed_plan(&["Write notes.txt".into()])[0];
+
+        let outcome = runner.execute(&mut context).await.unwrap();
+        assert!(matches!(outcome, RunnerOutcome::Completed), "{outcome:?}");
+        assert!(context.wait_state.is_none());
+        assert_eq!(
+            std::fs::read_to_string(tmp.path().join("notes.txt")).unwrap(),
+            "second"
+        );
+        assert_eq!(
+            context.metrics().solve_runs,
+            2,
+            "rejection re-solved the step"
+        );
+        assert_eq!(context.step(child).unwrap().status, StepStatus::Completed);
+        assert!(reviewer.0.lock().unwrap().is_empty(), "every decision used");
     }
 
-    #[test]
-    fn positive_threshold_pauses_when_margin_is_low() {
+    #[tokio::test(flavor = "current_thread")]
+    async fn blocking_reviewer_leaves_the_runtime_free() {
+        /// Approves only once a task on the runner's own runtime sends the answer.
+        struct ChannelReviewer(Mutex<std::sync::mpsc::Receiver<()>>);
+
+        impl CheckpointReviewer for ChannelReviewer {
+            fn review(&self, _context: &Context, _wait: &WaitState) -> CheckpointDecision {
+                let answer = self
+                    .0
+                    .lock()
+                    .unwrap()
+                    .recv_timeout(std::time::Duration::from_secs(5));
+                match answer {
+                    Ok(()) => CheckpointDecision::Approve,
+                    Err(_) => CheckpointDecision::Pause,
+                }
+            }
+        }
+
         let yaml = r#"#
         domains:
-          demo:
+          code:
+            applier: "overwrite_file"
             agents:
               decomposition:
                 prompt_template: "d"
@@ -1095,76 +3403,123 @@ mod tests {
               solver:
                 prompt_template: "s"
                 model: "m"
+                samples: 1
               solution_discriminator:
                 prompt_template: "sv"
                 model: "m"
+                samples: 1
         "#;
         let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
+        let llm: Arc<dyn LlmClient> = Arc::new(ScriptedLlm::new(vec![
+            vec![r#"<file path="notes.txt">hello</file>"#.into()],
+            vec!["1".into()],
+        ]));
         let options = RunnerOptions {
-            human_low_margin_threshold: 2,
+            human_low_margin_threshold: 0,
+            step_by_step: true,
             ..RunnerOptions::default()
         };
+        let (answers, inbox) = std::sync::mpsc::channel();
         let renderer = Arc::new(HandlebarsRenderer::new());
         let (file_system, clock, telemetry) = test_deps();
         let runner = FlowRunner::new(
             config,
-            None,
+            Some(llm),
             renderer,
             options,
             file_system,
             clock,
             telemetry,
-        );
-        let mut ctx = Context::new("demo", "demo");
-        let step_id = ctx.ensure_root();
-        ctx.metrics
-            .record_vote(step_id, AgentKind::DecompositionDiscriminator, 1, 0);
+        )
+        .with_checkpoint_reviewer(Some(Arc::new(ChannelReviewer(Mutex::new(inbox)))));
 
-        let wait = runner.check_vote_triggers(&ctx, step_id, "decomposition vote");
-        assert!(wait.is_some(), "margin 1 <= threshold 2 should pause");
+        // Answers both checkpoints; it only gets to run if the review does not hold the thread
+        tokio::spawn(async move {
+            for _ in 0..2 {
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                let _ = answers.send(());
+            }
+        });
+
+        let tmp = tempfile::tempdir().unwrap();
+        let mut context = Context::new("Write notes", "code");
+        context.output_dir = Some(tmp.path().to_path_buf());
+        context.seed_plan(&["Write notes.txt".into()]);
+
+        let outcome = runner.execute(&mut context).await.unwrap();
+        assert!(matches!(outcome, RunnerOutcome::Completed), "{outcome:?}");
     }
 
-    #[test]
-    fn positive_threshold_allows_decisive_votes() {
+    #[tokio::test]
+    async fn dump_prompts_writes_prompt_files() {
         let yaml = r#"#
         domains:
-          demo:
+          code:
             agents:
               decomposition:
-                prompt_template: "d"
+                prompt_template: "Decompose: {{task}}"
                 model: "m"
+                samples: 1
               decomposition_discriminator:
-                prompt_template: "dv"
+                prompt_template: "Pick plan: {{task}}"
                 model: "m"
+                samples: 1
               solver:
-                prompt_template: "s"
+                prompt_template: "Solve: {{task}}"
                 model: "m"
+                samples: 2
               solution_discriminator:
-                prompt_template: "sv"
+                prompt_template: "Pick solution: {{task}}"
                 model: "m"
+                samples: 1
         "#;
         let config = Arc::new(MicrofactoryConfig::from_yaml_str(yaml).unwrap());
+        let llm: Arc<dyn LlmClient> = Arc::new(ScriptedLlm::new(vec![
+            vec!["- write docs".into()],
+            vec!["1".into()],
+            vec!["docs".into(), "docs alt".into()],
+            vec!["1".into()],
+        ]));
         let options = RunnerOptions {
-            human_low_margin_threshold: 1,
+            human_low_margin_threshold: 0,
+            dump_prompts: true,
             ..RunnerOptions::default()
         };
         let renderer = Arc::new(HandlebarsRenderer::new());
         let (file_system, clock, telemetry) = test_deps();
         let runner = FlowRunner::new(
             config,
-            None,
+            Some(llm),
             renderer,
             options,
             file_system,
             clock,
             telemetry,
         );
-        let mut ctx = Context::new("demo", "demo");
-        let step_id = ctx.ensure_root();
-        ctx.metrics
-            .record_vote(step_id, AgentKind::DecompositionDiscriminator, 3, 1);
 
-        let wait = runner.check_vote_triggers(&ctx, step_id, "decomposition vote");
-        assert!(wait.is_none(), "margin 2 > threshold 1 should continue");
+        let tmp = tempfile::tempdir().unwrap();
+        let mut context = Context::new("Document it", "code");
+        context.output_dir = Some(tmp.path().to_path_buf());
+        let outcome = runner.execute(&mut context).await.unwrap();
+        assert!(matches!(outcome, RunnerOutcome::Completed));
+
+        let prompts = tmp.path().join("prompts");
+        let read = |name: &str| std::fs::read_to_string(prompts.join(name)).unwrap();
+        assert_eq!(
+            read("step-000-decomposition-attempt-01.txt"),
+            "Decompose: Document it"
+        );
+        assert!(
+            prompts
+                .join("step-000-decomposition_vote-attempt-01.txt")
+                .exists()
+        );
+        assert_eq!(read("step-001-solve-attempt-01.txt"), "Solve: write docs");
+        assert!(!prompts.join("step-001-solve-attempt-02.txt").exists());
+        assert!(
+            prompts
+                .join("step-001-solution_vote-attempt-01.txt")
+                .exists()
+        );
     }
 }
@@ -1,7 +1,7 @@
 //! Application service implementation that provides the `WorkflowService` trait.
 //! This is the primary use-case port implementation that driving adapters consume.
 
-use std::sync::Arc;
+use std::{collections::HashMap, path::Path, sync::Arc};
 
 use anyhow::anyhow;
 use async_trait::async_trait;
@@ -9,26 +9,43 @@ use uuid::Uuid;
 
 use crate::{
     adapters::outbound::persistence::{
-        SessionEnvelope, SessionMetadata, SessionStatus, SessionStore,
+        SessionEnvelope, SessionMetadata, SessionRecord, SessionStatus,
     },
     config::MicrofactoryConfig,
     core::{
         domain::{Context, WorkItem},
         error::{Error as CoreError, Result as CoreResult},
         ports::{
-            Clock, DryRunResult, FileSystem, LlmClient, LlmOptions, PauseInfo, PromptRenderer,
-            ResumeSessionRequest, RunSessionRequest, SessionDetail, SessionMetadataInfo,
-            SessionOutcome, SessionSummary, SubprocessMetrics, SubprocessOutcome,
-            SubprocessRequest, TelemetrySink, WorkflowService,
+            CheckpointReviewer, Clock, CommandRunner, ConfigCheck, DryRunResult, FileSystem,
+            FlaggerRejections, ListModelsRequest, LlmClient, LlmOptions, NoCommandRunner,
+            PauseInfo, PromptRenderer, RecursionExplanation, ResumeSessionRequest,
+            RunSessionRequest, SessionDetail, SessionExportSink, SessionFilter,
+            SessionMetadataInfo, SessionNote, SessionNoteRecord, SessionOutcome, SessionRepository,
+            SessionRunMetrics, SessionSummary, StageTiming, StepApplyPreview, StepDetail,
+            StepSolution, SubprocessMetrics, SubprocessOutcome, SubprocessRequest, SummaryFormat,
+            TelemetrySink, TokenBudget, VerifyOutcome, VerifyRequest, WorkflowService,
         },
     },
-    runner::{FlowRunner, RunnerOptions, RunnerOutcome},
-    status_export::count_completed_steps,
+    paths::expand_output_dir,
+    runner::{FlowRunner, RunnerOptions, RunnerOutcome, format_step_ids},
+    status_export::{SessionDetailExport, count_completed_steps, count_running_steps},
+    tasks::DEFAULT_RED_FLAG_CONCURRENCY,
 };
 
-/// Factory function type for creating LLM clients.
-pub type LlmClientFactory =
-    Arc<dyn Fn(&str, &str, usize, String) -> anyhow::Result<Arc<dyn LlmClient>> + Send + Sync>;
+/// Factory function type for creating LLM clients: provider, model, max concurrency, API key,
+/// extra HTTP headers, and the run's token budget, which the returned client must charge.
+pub type LlmClientFactory = Arc<
+    dyn Fn(
+            &str,
+            &str,
+            usize,
+            String,
+            &[(String, String)],
+            Option<Arc<TokenBudget>>,
+        ) -> anyhow::Result<Arc<dyn LlmClient>>
+        + Send
+        + Sync,
+>;
 
 /// Factory function type for resolving API keys.
 pub type ApiKeyResolver = Arc<dyn Fn(Option<String>, &str) -> anyhow::Result<String> + Send + Sync>;
@@ -38,18 +55,20 @@ pub type ApiKeyResolver = Arc<dyn Fn(Option<String>, &str) -> anyhow::Result<Str
 /// This struct holds all the dependencies needed to execute workflow operations
 /// and is injected into driving adapters (CLI, HTTP server).
 pub struct AppService {
-    store: SessionStore,
+    store: Arc<dyn SessionRepository>,
     renderer: Arc<dyn PromptRenderer>,
     file_system: Arc<dyn FileSystem>,
     clock: Arc<dyn Clock>,
     telemetry: Arc<dyn TelemetrySink>,
     llm_factory: LlmClientFactory,
     api_key_resolver: ApiKeyResolver,
+    command_runner: Arc<dyn CommandRunner>,
+    checkpoint_reviewer: Option<Arc<dyn CheckpointReviewer>>,
 }
 
 impl AppService {
     pub fn new(
-        store: SessionStore,
+        store: Arc<dyn SessionRepository>,
         renderer: Arc<dyn PromptRenderer>,
         file_system: Arc<dyn FileSystem>,
         clock: Arc<dyn Clock>,
@@ -65,11 +84,66 @@ impl AppService {
             telemetry,
             llm_factory,
             api_key_resolver,
+            command_runner: Arc::new(NoCommandRunner),
+            checkpoint_reviewer: None,
         }
     }
 
-    fn load_config(&self, path: &std::path::Path) -> anyhow::Result<Arc<MicrofactoryConfig>> {
-        Ok(Arc::new(MicrofactoryConfig::from_path(path)?))
+    /// Set the process runner handed to every `FlowRunner`; without one, verifier and applier
+    /// commands fail.
+    pub fn with_command_runner(mut self, command_runner: Arc<dyn CommandRunner>) -> Self {
+        self.command_runner = command_runner;
+        self
+    }
+
+    /// Review step-by-step checkpoints in-process (e.g. `run --interactive`) instead of pausing.
+    pub fn with_checkpoint_reviewer(
+        mut self,
+        reviewer: Option<Arc<dyn CheckpointReviewer>>,
+    ) -> Self {
+        self.checkpoint_reviewer = reviewer;
+        self
+    }
+
+    async fn save_envelope(
+        &self,
+        envelope: &SessionEnvelope,
+        status: SessionStatus,
+    ) -> CoreResult<()> {
+        let request = envelope
+            .to_save_request(status)
+            .map_err(|e| CoreError::Persistence(e.to_string()))?;
+        self.store.save_session(&request).await
+    }
+
+    /// First save of a new session; refuses to overwrite an existing session with the same ID.
+    async fn insert_envelope(&self, envelope: &SessionEnvelope) -> CoreResult<()> {
+        let request = envelope
+            .to_save_request(SessionStatus::Running)
+            .map_err(|e| CoreError::Persistence(e.to_string()))?;
+        self.store.insert_session(&request).await
+    }
+
+    async fn load_record(&self, session_id: &str) -> CoreResult<Option<SessionRecord>> {
+        self.store
+            .load_session(session_id)
+            .await?
+            .map(|row| {
+                SessionRecord::from_response(&row)
+                    .map_err(|e| CoreError::Persistence(e.to_string()))
+            })
+            .transpose()
+    }
+
+    fn load_config(
+        &self,
+        path: &std::path::Path,
+        template_dir: Option<&std::path::Path>,
+    ) -> anyhow::Result<Arc<MicrofactoryConfig>> {
+        Ok(Arc::new(MicrofactoryConfig::from_path_with_template_dir(
+            path,
+            template_dir,
+        )?))
     }
 
     fn ensure_domain_exists(
@@ -77,7 +151,7 @@ impl AppService {
         config: &MicrofactoryConfig,
         domain: &str,
     ) -> anyhow::Result<()> {
-        if config.domain(domain).is_none() {
+        let Some((resolved, _)) = config.resolve_domain(domain) else {
             let available = if config.domains.is_empty() {
                 "<none>".to_string()
             } else {
@@ -91,42 +165,340 @@ impl AppService {
             return Err(anyhow!(
                 "Domain '{domain}' not defined in provided configuration. Available domains: {available}"
             ));
+        };
+        if resolved != domain {
+            tracing::warn!(
+                requested = domain,
+                fallback = resolved,
+                "Domain not defined; using default_domain"
+            );
         }
         Ok(())
     }
 
+    /// Load the config a session resumes with, failing fast if the file is gone or no longer
+    /// defines the session's domain.
+    fn resume_config(
+        &self,
+        context: &Context,
+        config_path: &std::path::Path,
+        template_dir: Option<&std::path::Path>,
+    ) -> CoreResult<Arc<MicrofactoryConfig>> {
+        if !self.file_system.exists(config_path) {
+            return Err(CoreError::Config(format!(
+                "Config file {} for session {} no longer exists; pass --config <path> to resume with its new location",
+                config_path.display(),
+                context.session_id
+            )));
+        }
+        let config = self
+            .load_config(config_path, template_dir)
+            .map_err(|e| CoreError::Config(format!("{e:#}")))?;
+        self.ensure_domain_exists(&config, &context.domain)
+            .map_err(|e| {
+                CoreError::Config(format!(
+                    "Cannot resume session {} from {}: {e}; pass --config <path> with a configuration that defines it",
+                    context.session_id,
+                    config_path.display()
+                ))
+            })?;
+        Ok(config)
+    }
+
     fn create_llm_client(
         &self,
         provider: &str,
         model: &str,
         max_concurrent: usize,
         api_key: Option<String>,
+        headers: &[(String, String)],
+        token_budget: Option<Arc<TokenBudget>>,
     ) -> anyhow::Result<Arc<dyn LlmClient>> {
         let resolved_key = (self.api_key_resolver)(api_key, provider)?;
-        (self.llm_factory)(provider, model, max_concurrent, resolved_key)
+        (self.llm_factory)(
+            provider,
+            model,
+            max_concurrent,
+            resolved_key,
+            headers,
+            token_budget,
+        )
     }
 
-    fn runner_options_from_request(&self, req: &RunSessionRequest) -> RunnerOptions {
-        RunnerOptions::from_cli(
-            req.samples,
-            req.k,
-            req.adaptive_k,
-            req.step_by_step,
-            req.human_low_margin_threshold,
+    /// Write the whole `Context` as pretty JSON, creating parent directories as needed.
+    fn dump_context(&self, path: &Path, context: &Context) -> CoreResult<()> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            self.file_system.create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(context)
+            .map_err(|e| CoreError::System(format!("Failed to serialize context: {e}")))?;
+        self.file_system.write(path, &json)?;
+        tracing::info!("Wrote final context to {}", path.display());
+        Ok(())
+    }
+
+    /// Expanded file name and format of the run summary, when `--summary-format` or
+    /// `--summary-name` asks for one. The name is expanded up front so a bad template fails
+    /// before any LLM work.
+    fn summary_target(
+        &self,
+        request: &RunSessionRequest,
+        context: &Context,
+    ) -> CoreResult<Option<(String, SummaryFormat)>> {
+        if request.summary_format.is_none() && request.summary_name.is_none() {
+            return Ok(None);
+        }
+        let format = request.summary_format.unwrap_or_default();
+        let name = request
+            .summary_name
+            .clone()
+            .unwrap_or_else(|| format!("summary.{}", format.extension()));
+        let name = expand_output_dir(
+            Path::new(&name),
+            "summary name",
+            &context.session_id,
+            self.clock.now_ms(),
+        )
+        .map_err(CoreError::Config)?;
+        Ok(Some((name.to_string_lossy().into_owned(), format)))
+    }
+
+    /// Write the summary recorded in `metadata`, if any, under the output directory. The
+    /// session is already saved, so a summary that cannot be written is not a failed run.
+    fn write_saved_summary(
+        &self,
+        metadata: &SessionMetadata,
+        context: &Context,
+        outcome: &SessionOutcome,
+    ) {
+        let (Some(name), Some(format)) = (&metadata.summary_name, metadata.summary_format) else {
+            return;
+        };
+        let path = context.output_dir.clone().unwrap_or_default().join(name);
+        if let Err(err) = self.write_summary(&path, format, outcome) {
+            tracing::warn!("Failed to write run summary to {}: {err}", path.display());
+        }
+    }
+
+    /// Write `outcome` to `path` in `format`, creating parent directories as needed.
+    fn write_summary(
+        &self,
+        path: &Path,
+        format: SummaryFormat,
+        outcome: &SessionOutcome,
+    ) -> CoreResult<()> {
+        let rendered = match format {
+            SummaryFormat::Json => serde_json::to_string_pretty(outcome)
+                .map_err(|e| CoreError::System(format!("Failed to serialize summary: {e}")))?,
+            SummaryFormat::Yaml => serde_yaml::to_string(outcome)
+                .map_err(|e| CoreError::System(format!("Failed to serialize summary: {e}")))?,
+            SummaryFormat::Markdown => summary_markdown(outcome),
+        };
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            self.file_system.create_dir_all(parent)?;
+        }
+        self.file_system.write(path, &rendered)?;
+        tracing::info!("Wrote run summary to {}", path.display());
+        Ok(())
+    }
+
+    /// Load the config a subprocess request names and check its domain exists.
+    fn subprocess_config(
+        &self,
+        request: &SubprocessRequest,
+    ) -> CoreResult<Arc<MicrofactoryConfig>> {
+        let config = self
+            .load_config(&request.config_path, None)
+            .map_err(|e| CoreError::Config(e.to_string()))?;
+        self.ensure_domain_exists(&config, &request.domain)
+            .map_err(|e| CoreError::Config(e.to_string()))?;
+        Ok(config)
+    }
+
+    /// Solve `request.step` in isolation with an already constructed LLM client.
+    async fn run_subprocess_with(
+        &self,
+        request: SubprocessRequest,
+        config: Arc<MicrofactoryConfig>,
+        llm_client: Arc<dyn LlmClient>,
+    ) -> CoreResult<SubprocessOutcome> {
+        let session_id = format!("subprocess-{}", Uuid::new_v4());
+        let mut context = Context::new(&request.step, &request.domain);
+        context.session_id = session_id.clone();
+
+        if let Some(extra) = &request.context_json {
+            context
+                .domain_data
+                .insert("context_json".into(), extra.clone());
+            // Surface top-level fields individually so templates can use `{{context.<key>}}`.
+            if let Ok(serde_json::Value::Object(fields)) = serde_json::from_str(extra) {
+                for (key, value) in fields {
+                    let value = match value {
+                        serde_json::Value::String(text) => text,
+                        other => other.to_string(),
+                    };
+                    context.domain_data.entry(key).or_insert(value);
+                }
+            }
+        }
+
+        let root_id = context.ensure_root();
+        context.work_queue.clear();
+        context.enqueue_work(WorkItem::Solve { step_id: root_id });
+        context.enqueue_work(WorkItem::SolutionVote { step_id: root_id });
+
+        let runner_options = RunnerOptions::from_cli(request.samples, request.k, false, false, 1);
+        let runner = FlowRunner::new(
+            config,
+            Some(llm_client),
+            self.renderer.clone(),
+            runner_options,
+            self.file_system.clone(),
+            self.clock.clone(),
+            self.telemetry.clone(),
         )
+        .with_command_runner(self.command_runner.clone())
+        .with_checkpoint_reviewer(self.checkpoint_reviewer.clone());
+
+        match runner
+            .execute(&mut context)
+            .await
+            .map_err(|e| CoreError::System(e.to_string()))?
+        {
+            RunnerOutcome::Completed | RunnerOutcome::CompletedWithFailures { .. } => {
+                let step = context.step(root_id).ok_or_else(|| {
+                    CoreError::System("Root step missing after subprocess run".into())
+                })?;
+                let metrics = context.metrics().step_metrics(root_id).cloned();
+
+                Ok(SubprocessOutcome {
+                    session_id,
+                    step_id: root_id,
+                    candidate_solutions: step.candidate_solutions.clone(),
+                    winning_solution: step.winning_solution.clone(),
+                    metrics: metrics.map(|m| SubprocessMetrics {
+                        samples_requested: m.samples_requested,
+                        samples_accepted: m.samples_retained,
+                        vote_margin: m.vote_margin,
+                    }),
+                })
+            }
+            RunnerOutcome::Paused(wait) => Err(CoreError::System(format!(
+                "Subprocess paused at step {} ({}) - {}",
+                wait.step_id, wait.trigger, wait.details
+            ))),
+        }
+    }
+
+    fn runner_options_from_request(&self, req: &RunSessionRequest) -> RunnerOptions {
+        RunnerOptions {
+            dump_prompts: req.dump_prompts,
+            record_prompts: req.record_prompts,
+            red_flag_concurrency: req.red_flag_concurrency.max(1),
+            parallel_red_flaggers: req.parallel_red_flaggers,
+            max_response_bytes: req.max_response_bytes,
+            flag_truncated: req.flag_truncated,
+            retry_on_empty: req.retry_on_empty,
+            min_samples_accepted: req.min_samples_accepted,
+            prompt_vars: req.prompt_vars.iter().cloned().collect(),
+            line_endings: req.line_endings,
+            bom: req.bom,
+            dedupe_candidates: req.dedupe_candidates,
+            explain: req.explain,
+            collapse_trivial_decomposition: req.collapse_trivial_decomposition,
+            flat: req.flat,
+            adaptive_samples: req.adaptive_samples,
+            adaptive_k_limits: req.adaptive_k_limits,
+            shuffle_seed: req.shuffle_candidates.then(|| {
+                req.shuffle_seed
+                    .unwrap_or_else(|| self.clock.now_ms() as u64)
+            }),
+            ..RunnerOptions::from_cli(
+                req.samples,
+                req.k,
+                req.adaptive_k,
+                req.step_by_step,
+                req.human_low_margin_threshold,
+            )
+            .with_max_depth(req.max_depth)
+        }
     }
 
     fn outcome_from_runner_result(
         &self,
-        session_id: &str,
+        context: &Context,
         result: RunnerOutcome,
     ) -> SessionOutcome {
+        let session_id = context.session_id.as_str();
+        let metrics = SessionRunMetrics {
+            total_steps: context.steps.len(),
+            completed_steps: count_completed_steps(context),
+            samples: context.metrics.sample_count,
+            resamples: context.metrics.resample_count,
+            vote_attempts: context.metrics.vote_attempts,
+            red_flag_hits: context.metrics.red_flag_hits,
+            top_flaggers: FlaggerRejections::from_counts(context.metrics.top_flaggers()),
+            stage_timings: StageTiming::from_durations(context.metrics.stage_timings()),
+        };
+        let recursion: Vec<RecursionExplanation> = context
+            .steps
+            .iter()
+            .filter_map(|step| {
+                step.recursion.clone().map(|decision| RecursionExplanation {
+                    step_id: step.id,
+                    description: step.description.clone(),
+                    decision,
+                })
+            })
+            .collect();
+        let solutions: Vec<StepSolution> = context
+            .steps
+            .iter()
+            .filter_map(|step| {
+                step.winning_solution.clone().map(|solution| StepSolution {
+                    step_id: step.id,
+                    description: step.description.clone(),
+                    solution,
+                })
+            })
+            .collect();
+        let apply_previews: Vec<StepApplyPreview> = context
+            .steps
+            .iter()
+            .filter_map(|step| {
+                step.pending_apply.as_ref().map(|pending| StepApplyPreview {
+                    step_id: step.id,
+                    files: pending
+                        .files
+                        .iter()
+                        .map(|path| path.display().to_string())
+                        .collect(),
+                    diff: pending.diff.clone(),
+                })
+            })
+            .collect();
         match result {
             RunnerOutcome::Completed => SessionOutcome {
                 session_id: session_id.to_string(),
                 completed: true,
                 paused: false,
                 pause_reason: None,
+                failed_steps: Vec::new(),
+                metrics: metrics.clone(),
+                recursion,
+                solutions,
+                apply_previews,
+            },
+            RunnerOutcome::CompletedWithFailures { failed_steps } => SessionOutcome {
+                session_id: session_id.to_string(),
+                completed: true,
+                paused: false,
+                pause_reason: None,
+                failed_steps,
+                metrics,
+                recursion,
+                solutions,
+                apply_previews,
             },
             RunnerOutcome::Paused(wait) => SessionOutcome {
                 session_id: session_id.to_string(),
@@ -137,6 +509,11 @@ impl AppService {
                     trigger: wait.trigger,
                     details: wait.details,
                 }),
+                failed_steps: Vec::new(),
+                metrics,
+                recursion,
+                solutions,
+                apply_previews,
             },
         }
     }
@@ -146,18 +523,23 @@ impl AppService {
 impl WorkflowService for AppService {
     async fn run_session(&self, request: RunSessionRequest) -> CoreResult<SessionOutcome> {
         let config = self
-            .load_config(&request.config_path)
+            .load_config(&request.config_path, request.template_dir.as_deref())
             .map_err(|e| CoreError::Config(e.to_string()))?;
 
         self.ensure_domain_exists(&config, &request.domain)
             .map_err(|e| CoreError::Config(e.to_string()))?;
 
+        let token_budget = request
+            .max_total_tokens
+            .map(|limit| Arc::new(TokenBudget::new(limit)));
         let llm_client = self
             .create_llm_client(
                 &request.llm_provider,
                 &request.llm_model,
                 request.max_concurrent_llm,
                 request.api_key.clone(),
+                &llm_headers(&config, &request.domain, &request.llm_headers),
+                token_budget.clone(),
             )
             .map_err(|e| CoreError::System(e.to_string()))?;
 
@@ -165,7 +547,31 @@ impl WorkflowService for AppService {
         let mut context = Context::new(&request.prompt, &request.domain);
         context.session_id = session_id.clone();
         context.dry_run = request.dry_run;
-        context.output_dir = request.output_dir.clone();
+        context.dry_run_apply = request.dry_run_apply;
+        context.max_children_total = request.max_children_total;
+        if let Some(template) = &request.output_dir {
+            let output_dir =
+                expand_output_dir(template, "output dir", &session_id, self.clock.now_ms())
+                    .map_err(CoreError::Config)?;
+            if output_dir != *template {
+                tracing::info!("Writing output to {}", output_dir.display());
+            }
+            context.output_dir = Some(output_dir);
+        }
+        let summary = self.summary_target(&request, &context)?;
+
+        if let Some(plan_path) = &request.plan_file {
+            let raw = self.file_system.read_to_string(plan_path)?;
+            let subtasks = parse_plan(&raw).map_err(|e| {
+                CoreError::Config(format!("Invalid plan file {}: {e}", plan_path.display()))
+            })?;
+            tracing::info!(
+                "Seeding {} subtask(s) from plan file {}",
+                subtasks.len(),
+                plan_path.display()
+            );
+            context.seed_plan(&subtasks);
+        }
 
         tracing::info!(
             "Starting session {} (domain: {})",
@@ -173,6 +579,7 @@ impl WorkflowService for AppService {
             context.domain
         );
 
+        let runner_options = self.runner_options_from_request(&request);
         let metadata = SessionMetadata {
             config_path: request.config_path.to_string_lossy().to_string(),
             llm_provider: request.llm_provider.clone(),
@@ -181,7 +588,33 @@ impl WorkflowService for AppService {
             samples: request.samples,
             k: request.k,
             adaptive_k: request.adaptive_k,
+            adaptive_k_limits: request.adaptive_k_limits,
             human_low_margin_threshold: request.human_low_margin_threshold,
+            max_depth: request.max_depth.map(|depth| depth.max(1)),
+            adaptive_samples: request.adaptive_samples,
+            prompt_vars: request.prompt_vars.iter().cloned().collect(),
+            config_hash: config.content_hash().map(str::to_string),
+            template_dir: request
+                .template_dir
+                .as_ref()
+                .map(|dir| dir.to_string_lossy().to_string()),
+            flat: request.flat,
+            min_samples_accepted: request.min_samples_accepted,
+            line_endings: request.line_endings,
+            bom: request.bom,
+            record_prompts: request.record_prompts,
+            max_response_bytes: request.max_response_bytes,
+            dedupe_candidates: request.dedupe_candidates,
+            collapse_trivial_decomposition: request.collapse_trivial_decomposition,
+            explain: request.explain,
+            parallel_red_flaggers: request.parallel_red_flaggers,
+            shuffle_seed: runner_options.shuffle_seed,
+            retry_on_empty: request.retry_on_empty,
+            flag_truncated: request.flag_truncated,
+            red_flag_concurrency: Some(runner_options.red_flag_concurrency),
+            summary_format: summary.as_ref().map(|(_, format)| *format),
+            summary_name: summary.map(|(name, _)| name),
+            max_total_tokens: request.max_total_tokens,
         };
 
         let mut envelope = SessionEnvelope {
@@ -189,11 +622,16 @@ impl WorkflowService for AppService {
             metadata,
         };
 
-        self.store
-            .save(&envelope, SessionStatus::Running)
-            .map_err(|e| CoreError::Persistence(e.to_string()))?;
+        let persist = !request.no_persist;
+        if persist {
+            self.insert_envelope(&envelope).await?;
+        } else {
+            tracing::info!(
+                "Session {} will not be persisted (--no-persist); it cannot be resumed.",
+                context.session_id
+            );
+        }
 
-        let runner_options = self.runner_options_from_request(&request);
         let runner = FlowRunner::new(
             config,
             Some(llm_client),
@@ -202,13 +640,35 @@ impl WorkflowService for AppService {
             self.file_system.clone(),
             self.clock.clone(),
             self.telemetry.clone(),
-        );
+        )
+        .with_command_runner(self.command_runner.clone())
+        .with_checkpoint_reviewer(self.checkpoint_reviewer.clone())
+        .with_token_budget(token_budget.clone());
+
+        let result = runner.execute(&mut context).await;
+        if let Some(budget) = &token_budget {
+            context.metrics.tokens_used = budget.used();
+        }
+        // A failed dump must not keep the session from being saved below
+        if let Some(path) = &request.dump_context
+            && let Err(err) = self.dump_context(path, &context)
+        {
+            tracing::warn!("Failed to dump context to {}: {err}", path.display());
+        }
 
-        match runner.execute(&mut context).await {
+        match result {
             Ok(outcome) => {
                 envelope.context = context.clone();
                 let status = match &outcome {
                     RunnerOutcome::Completed => SessionStatus::Completed,
+                    RunnerOutcome::CompletedWithFailures { failed_steps } => {
+                        tracing::warn!(
+                            "Session {} finished with failed step(s): {}",
+                            context.session_id,
+                            format_step_ids(failed_steps)
+                        );
+                        SessionStatus::CompletedWithFailures
+                    }
                     RunnerOutcome::Paused(wait) => {
                         tracing::info!(
                             "Session {} paused at step {} ({}) - {}",
@@ -220,26 +680,38 @@ impl WorkflowService for AppService {
                         SessionStatus::Paused
                     }
                 };
-                self.store
-                    .save(&envelope, status)
-                    .map_err(|e| CoreError::Persistence(e.to_string()))?;
+                if persist {
+                    self.save_envelope(&envelope, status).await?;
+                }
 
-                if matches!(outcome, RunnerOutcome::Completed) {
-                    tracing::info!("Session {} completed successfully.", context.session_id);
-                } else {
-                    tracing::info!(
-                        "Use `microfactory resume --session-id {}` after resolving the issue.",
-                        context.session_id
-                    );
+                match &outcome {
+                    RunnerOutcome::Completed => {
+                        tracing::info!("Session {} completed successfully.", context.session_id);
+                    }
+                    RunnerOutcome::CompletedWithFailures { .. } => {}
+                    RunnerOutcome::Paused(_) if persist => {
+                        tracing::info!(
+                            "Use `microfactory resume --session-id {}` after resolving the issue.",
+                            context.session_id
+                        );
+                    }
+                    RunnerOutcome::Paused(_) => {
+                        tracing::info!(
+                            "Session {} was not persisted (--no-persist), so it cannot be resumed.",
+                            context.session_id
+                        );
+                    }
                 }
 
-                Ok(self.outcome_from_runner_result(&session_id, outcome))
+                let outcome = self.outcome_from_runner_result(&context, outcome);
+                self.write_saved_summary(&envelope.metadata, &context, &outcome);
+                Ok(outcome)
             }
             Err(err) => {
-                envelope.context = context;
-                self.store
-                    .save(&envelope, SessionStatus::Failed)
-                    .map_err(|e| CoreError::Persistence(e.to_string()))?;
+                if persist {
+                    envelope.context = context;
+                    self.save_envelope(&envelope, SessionStatus::Failed).await?;
+                }
                 Err(CoreError::System(err.to_string()))
             }
         }
@@ -247,9 +719,11 @@ impl WorkflowService for AppService {
 
     async fn resume_session(&self, request: ResumeSessionRequest) -> CoreResult<SessionOutcome> {
         let record = self
-            .store
-            .load(&request.session_id)
-            .map_err(|e| CoreError::Persistence(e.to_string()))?;
+            .load_record(&request.session_id)
+            .await?
+            .ok_or_else(|| {
+                CoreError::Persistence(format!("Session {} not found", request.session_id))
+            })?;
 
         let mut context = record.envelope.context;
         let prev_metadata = record.envelope.metadata;
@@ -267,7 +741,6 @@ impl WorkflowService for AppService {
             .unwrap_or(prev_metadata.max_concurrent_llm);
         let samples = request.samples.unwrap_or(prev_metadata.samples);
         let k = request.k.unwrap_or(prev_metadata.k);
-        let adaptive = prev_metadata.adaptive_k;
         let human_low_margin_threshold = request
             .human_low_margin_threshold
             .unwrap_or(prev_metadata.human_low_margin_threshold);
@@ -277,12 +750,21 @@ impl WorkflowService for AppService {
             .clone()
             .unwrap_or_else(|| std::path::PathBuf::from(&prev_metadata.config_path));
 
-        let config = self
-            .load_config(&config_path)
-            .map_err(|e| CoreError::Config(e.to_string()))?;
+        let template_dir = request.template_dir.clone().or_else(|| {
+            prev_metadata
+                .template_dir
+                .as_ref()
+                .map(std::path::PathBuf::from)
+        });
 
-        self.ensure_domain_exists(&config, &context.domain)
-            .map_err(|e| CoreError::Config(e.to_string()))?;
+        let config = self.resume_config(&context, &config_path, template_dir.as_deref())?;
+        check_config_drift(
+            &context.session_id,
+            &config_path,
+            prev_metadata.config_hash.as_deref(),
+            config.content_hash(),
+            request.strict,
+        )?;
 
         if let Some(wait) = &context.wait_state {
             tracing::info!(
@@ -295,12 +777,38 @@ impl WorkflowService for AppService {
         }
         context.clear_wait_state();
 
-        let llm_client = self
-            .create_llm_client(&provider, &model, max_concurrent, request.api_key.clone())
-            .map_err(|e| CoreError::System(e.to_string()))?;
+        if let Some(step_id) = request.from_step {
+            let reset = context.reset_subtree(step_id).ok_or_else(|| {
+                CoreError::InvalidState(format!(
+                    "Session {} has no step {step_id}",
+                    context.session_id
+                ))
+            })?;
+            tracing::info!(
+                "Restarting session {} from step {step_id} ({} step(s) reset)",
+                context.session_id,
+                reset.len()
+            );
+        }
 
-        let runner_options =
-            RunnerOptions::from_cli(samples, k, adaptive, false, human_low_margin_threshold);
+        let max_total_tokens = request.max_total_tokens.or(prev_metadata.max_total_tokens);
+        let token_budget = max_total_tokens
+            .map(|limit| Arc::new(TokenBudget::with_used(limit, context.metrics.tokens_used)));
+        let llm_client = self
+            .create_llm_client(
+                &provider,
+                &model,
+                max_concurrent,
+                request.api_key.clone(),
+                &llm_headers(&config, &context.domain, &request.llm_headers),
+                token_budget.clone(),
+            )
+            .map_err(|e| {
+                CoreError::System(format!(
+                    "Cannot resume session {} with provider '{provider}': {e:#}",
+                    context.session_id
+                ))
+            })?;
 
         let metadata = SessionMetadata {
             config_path: config_path.to_string_lossy().to_string(),
@@ -309,18 +817,21 @@ impl WorkflowService for AppService {
             max_concurrent_llm: max_concurrent,
             samples,
             k,
-            adaptive_k: adaptive,
             human_low_margin_threshold,
+            config_hash: config.content_hash().map(str::to_string),
+            template_dir: template_dir.map(|dir| dir.to_string_lossy().to_string()),
+            max_total_tokens,
+            ..prev_metadata
         };
+        let runner_options = resumed_runner_options(&metadata);
 
         let mut envelope = SessionEnvelope {
             context: context.clone(),
             metadata,
         };
 
-        self.store
-            .save(&envelope, SessionStatus::Running)
-            .map_err(|e| CoreError::Persistence(e.to_string()))?;
+        self.save_envelope(&envelope, SessionStatus::Running)
+            .await?;
 
         let runner = FlowRunner::new(
             config,
@@ -330,14 +841,28 @@ impl WorkflowService for AppService {
             self.file_system.clone(),
             self.clock.clone(),
             self.telemetry.clone(),
-        );
+        )
+        .with_command_runner(self.command_runner.clone())
+        .with_checkpoint_reviewer(self.checkpoint_reviewer.clone())
+        .with_token_budget(token_budget.clone());
 
-        let session_id = context.session_id.clone();
-        match runner.execute(&mut context).await {
+        let result = runner.execute(&mut context).await;
+        if let Some(budget) = &token_budget {
+            context.metrics.tokens_used = budget.used();
+        }
+        match result {
             Ok(outcome) => {
                 envelope.context = context.clone();
                 let status = match &outcome {
                     RunnerOutcome::Completed => SessionStatus::Completed,
+                    RunnerOutcome::CompletedWithFailures { failed_steps } => {
+                        tracing::warn!(
+                            "Session {} finished with failed step(s): {}",
+                            context.session_id,
+                            format_step_ids(failed_steps)
+                        );
+                        SessionStatus::CompletedWithFailures
+                    }
                     RunnerOutcome::Paused(wait) => {
                         tracing::info!(
                             "Session {} paused again at step {} ({}) - {}",
@@ -349,154 +874,232 @@ impl WorkflowService for AppService {
                         SessionStatus::Paused
                     }
                 };
-                self.store
-                    .save(&envelope, status)
-                    .map_err(|e| CoreError::Persistence(e.to_string()))?;
+                self.save_envelope(&envelope, status).await?;
 
-                if matches!(outcome, RunnerOutcome::Completed) {
-                    tracing::info!("Session {} completed.", context.session_id);
-                } else {
-                    tracing::info!(
-                        "Use `microfactory resume --session-id {}` once resolved.",
-                        context.session_id
-                    );
+                match &outcome {
+                    RunnerOutcome::Completed => {
+                        tracing::info!("Session {} completed.", context.session_id);
+                    }
+                    RunnerOutcome::CompletedWithFailures { .. } => {}
+                    RunnerOutcome::Paused(_) => {
+                        tracing::info!(
+                            "Use `microfactory resume --session-id {}` once resolved.",
+                            context.session_id
+                        );
+                    }
                 }
 
-                Ok(self.outcome_from_runner_result(&session_id, outcome))
+                let outcome = self.outcome_from_runner_result(&context, outcome);
+                self.write_saved_summary(&envelope.metadata, &context, &outcome);
+                Ok(outcome)
             }
             Err(err) => {
                 envelope.context = context;
-                self.store
-                    .save(&envelope, SessionStatus::Failed)
-                    .map_err(|e| CoreError::Persistence(e.to_string()))?;
+                self.save_envelope(&envelope, SessionStatus::Failed).await?;
                 Err(CoreError::System(err.to_string()))
             }
         }
     }
 
     async fn run_subprocess(&self, request: SubprocessRequest) -> CoreResult<SubprocessOutcome> {
-        let config = self
-            .load_config(&request.config_path)
-            .map_err(|e| CoreError::Config(e.to_string()))?;
-
-        self.ensure_domain_exists(&config, &request.domain)
-            .map_err(|e| CoreError::Config(e.to_string()))?;
-
+        let config = self.subprocess_config(&request)?;
         let llm_client = self
             .create_llm_client(
                 &request.llm_provider,
                 &request.llm_model,
                 request.max_concurrent_llm,
                 request.api_key.clone(),
+                &llm_headers(&config, &request.domain, &request.llm_headers),
+                None,
             )
             .map_err(|e| CoreError::System(e.to_string()))?;
+        self.run_subprocess_with(request, config, llm_client).await
+    }
 
-        let session_id = format!("subprocess-{}", Uuid::new_v4());
-        let mut context = Context::new(&request.step, &request.domain);
-        context.session_id = session_id.clone();
-
-        if let Some(extra) = &request.context_json {
-            context
-                .domain_data
-                .insert("context_json".into(), extra.clone());
+    async fn run_subprocess_batch(
+        &self,
+        requests: Vec<SubprocessRequest>,
+    ) -> CoreResult<Vec<SubprocessOutcome>> {
+        let mut clients: HashMap<(String, String, Vec<(String, String)>), Arc<dyn LlmClient>> =
+            HashMap::new();
+        let mut outcomes = Vec::with_capacity(requests.len());
+        for (idx, request) in requests.into_iter().enumerate() {
+            let config = self
+                .subprocess_config(&request)
+                .map_err(|e| CoreError::Config(format!("Batch item {}: {e}", idx + 1)))?;
+            let headers = llm_headers(&config, &request.domain, &request.llm_headers);
+            let key = (
+                request.llm_provider.clone(),
+                request.llm_model.clone(),
+                headers,
+            );
+            let llm_client = match clients.get(&key) {
+                Some(client) => client.clone(),
+                None => {
+                    let client = self
+                        .create_llm_client(
+                            &request.llm_provider,
+                            &request.llm_model,
+                            request.max_concurrent_llm,
+                            request.api_key.clone(),
+                            &key.2,
+                            None,
+                        )
+                        .map_err(|e| CoreError::System(format!("Batch item {}: {e}", idx + 1)))?;
+                    clients.insert(key, client.clone());
+                    client
+                }
+            };
+            let outcome = self
+                .run_subprocess_with(request, config, llm_client)
+                .await
+                .map_err(|e| CoreError::System(format!("Batch item {}: {e}", idx + 1)))?;
+            outcomes.push(outcome);
         }
+        Ok(outcomes)
+    }
 
-        let root_id = context.ensure_root();
-        context.work_queue.clear();
-        context.enqueue_work(WorkItem::Solve { step_id: root_id });
-        context.enqueue_work(WorkItem::SolutionVote { step_id: root_id });
+    async fn get_session(&self, session_id: &str) -> CoreResult<Option<SessionDetail>> {
+        match self.load_record(session_id).await? {
+            Some(record) => {
+                let context = &record.envelope.context;
+                let wait_state = context.wait_state.as_ref().map(|w| PauseInfo {
+                    step_id: w.step_id,
+                    trigger: w.trigger.clone(),
+                    details: w.details.clone(),
+                });
 
-        let runner_options = RunnerOptions::from_cli(request.samples, request.k, false, false, 1);
-        let runner = FlowRunner::new(
-            config,
-            Some(llm_client),
-            self.renderer.clone(),
-            runner_options,
-            self.file_system.clone(),
-            self.clock.clone(),
-            self.telemetry.clone(),
-        );
+                Ok(Some(SessionDetail {
+                    session_id: context.session_id.clone(),
+                    domain: context.domain.clone(),
+                    prompt: context.prompt.clone(),
+                    status: record.status.as_str().to_string(),
+                    updated_at: record.updated_at.to_string(),
+                    steps_completed: count_completed_steps(context),
+                    partial: record.status == SessionStatus::Running,
+                    steps_running: count_running_steps(context),
+                    top_flaggers: FlaggerRejections::from_counts(context.metrics.top_flaggers()),
+                    stage_timings: StageTiming::from_durations(context.metrics.stage_timings()),
+                    wait_state,
+                    metadata: SessionMetadataInfo {
+                        config_path: record.envelope.metadata.config_path.clone(),
+                        llm_provider: record.envelope.metadata.llm_provider.clone(),
+                        llm_model: record.envelope.metadata.llm_model.clone(),
+                        samples: record.envelope.metadata.samples,
+                        k: record.envelope.metadata.k,
+                    },
+                    notes: self
+                        .store
+                        .list_notes(session_id)
+                        .await?
+                        .into_iter()
+                        .map(session_note)
+                        .collect(),
+                }))
+            }
+            None => Ok(None),
+        }
+    }
 
-        match runner
-            .execute(&mut context)
-            .await
-            .map_err(|e| CoreError::System(e.to_string()))?
-        {
-            RunnerOutcome::Completed => {
-                let step = context.step(root_id).ok_or_else(|| {
-                    CoreError::System("Root step missing after subprocess run".into())
-                })?;
-                let metrics = context.metrics().step_metrics(root_id).cloned();
-
-                Ok(SubprocessOutcome {
-                    session_id,
-                    step_id: root_id,
-                    candidate_solutions: step.candidate_solutions.clone(),
-                    winning_solution: step.winning_solution.clone(),
-                    metrics: metrics.map(|m| SubprocessMetrics {
-                        samples_requested: m.samples_requested,
-                        samples_accepted: m.samples_retained,
-                        vote_margin: m.vote_margin,
-                    }),
-                })
-            }
-            RunnerOutcome::Paused(wait) => Err(CoreError::System(format!(
-                "Subprocess paused at step {} ({}) - {}",
-                wait.step_id, wait.trigger, wait.details
-            ))),
+    async fn add_session_note(
+        &self,
+        session_id: &str,
+        note: &str,
+    ) -> CoreResult<Option<SessionNote>> {
+        let note = note.trim();
+        if note.is_empty() {
+            return Err(CoreError::InvalidState(
+                "Session notes cannot be empty".into(),
+            ));
         }
+        Ok(self
+            .store
+            .add_note(session_id, note)
+            .await?
+            .map(session_note))
     }
 
-    async fn get_session(&self, session_id: &str) -> CoreResult<Option<SessionDetail>> {
-        match self.store.load(session_id) {
-            Ok(record) => {
-                let context = &record.envelope.context;
-                let wait_state = context.wait_state.as_ref().map(|w| PauseInfo {
-                    step_id: w.step_id,
-                    trigger: w.trigger.clone(),
-                    details: w.details.clone(),
-                });
-
-                Ok(Some(SessionDetail {
-                    session_id: context.session_id.clone(),
-                    domain: context.domain.clone(),
-                    prompt: context.prompt.clone(),
-                    status: record.status.as_str().to_string(),
-                    updated_at: record.updated_at.to_string(),
-                    steps_completed: count_completed_steps(context),
-                    wait_state,
-                    metadata: SessionMetadataInfo {
-                        config_path: record.envelope.metadata.config_path.clone(),
-                        llm_provider: record.envelope.metadata.llm_provider.clone(),
-                        llm_model: record.envelope.metadata.llm_model.clone(),
-                        samples: record.envelope.metadata.samples,
-                        k: record.envelope.metadata.k,
-                    },
-                }))
-            }
-            Err(e) if e.to_string().contains("not found") => Ok(None),
-            Err(e) => Err(CoreError::Persistence(e.to_string())),
-        }
+    async fn get_step(&self, session_id: &str, step_id: usize) -> CoreResult<Option<StepDetail>> {
+        let Some(record) = self.load_record(session_id).await? else {
+            return Ok(None);
+        };
+        let context = &record.envelope.context;
+        let Some(step) = context.step(step_id) else {
+            return Ok(None);
+        };
+        let metrics = context.metrics().step_metrics(step_id);
+        Ok(Some(StepDetail {
+            session_id: context.session_id.clone(),
+            step_id,
+            description: step.description.clone(),
+            status: format!("{:?}", step.status),
+            parent: step.parent,
+            depth: step.depth,
+            children: step.children.clone(),
+            candidate_solutions: step.candidate_solutions.clone(),
+            winning_solution: step.winning_solution.clone(),
+            vote_histogram: metrics
+                .map(|m| m.vote_histogram.clone())
+                .unwrap_or_default(),
+            vote_margin: metrics.and_then(|m| m.vote_margin),
+        }))
     }
 
     async fn list_sessions(&self, limit: usize) -> CoreResult<Vec<SessionSummary>> {
-        let summaries = self
+        let rows = self.store.list_sessions(limit).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|s| SessionSummary {
+                session_id: s.session_id,
+                domain: s.domain,
+                prompt: s.prompt,
+                status: s.status,
+                pause_trigger: s.pause_trigger,
+                updated_at: s.updated_at.to_string(),
+            })
+            .collect())
+    }
+
+    async fn search_sessions(&self, query: &str, limit: usize) -> CoreResult<Vec<SessionSummary>> {
+        let rows = self
             .store
-            .list(limit)
-            .map_err(|e| CoreError::Persistence(e.to_string()))?;
+            .find_sessions(&SessionFilter {
+                limit: Some(limit),
+                prompt_contains: Some(query.to_string()),
+                ..SessionFilter::default()
+            })
+            .await?;
 
-        Ok(summaries
+        Ok(rows
             .into_iter()
             .map(|s| SessionSummary {
                 session_id: s.session_id,
                 domain: s.domain,
                 prompt: s.prompt,
-                status: s.status.as_str().to_string(),
+                status: s.status,
+                pause_trigger: s.pause_trigger,
                 updated_at: s.updated_at.to_string(),
             })
             .collect())
     }
 
+    async fn export_sessions(
+        &self,
+        limit: Option<usize>,
+        sink: &mut SessionExportSink<'_>,
+    ) -> CoreResult<usize> {
+        self.store
+            .for_each_session(limit, &mut |row| {
+                let record = SessionRecord::from_response(&row)
+                    .map_err(|e| CoreError::Persistence(e.to_string()))?;
+                let line = serde_json::to_string(&SessionDetailExport::from_record(&record))
+                    .map_err(|e| CoreError::Persistence(e.to_string()))?;
+                sink(&line)
+            })
+            .await
+    }
+
     async fn dry_run_probe(&self, request: &RunSessionRequest) -> CoreResult<DryRunResult> {
         tracing::info!("[dry-run] probing model '{}'...", request.llm_model);
 
@@ -506,6 +1109,8 @@ impl WorkflowService for AppService {
                 &request.llm_model,
                 request.max_concurrent_llm,
                 request.api_key.clone(),
+                &request.llm_headers,
+                None,
             )
             .map_err(|e| CoreError::System(e.to_string()))?;
 
@@ -518,4 +1123,1875 @@ impl WorkflowService for AppService {
             response,
         })
     }
+
+    async fn check_run_config(&self, request: &RunSessionRequest) -> CoreResult<ConfigCheck> {
+        let config = self
+            .load_config(&request.config_path, request.template_dir.as_deref())
+            .map_err(|e| CoreError::Config(e.to_string()))?;
+        self.ensure_domain_exists(&config, &request.domain)
+            .map_err(|e| CoreError::Config(e.to_string()))?;
+        let runtime = config
+            .runtime_domain(&request.domain)
+            .map_err(|e| CoreError::Config(e.to_string()))?;
+
+        let mut context = Context::new(&request.prompt, &request.domain);
+        if let Some(template) = &request.output_dir {
+            context.output_dir = Some(
+                expand_output_dir(
+                    template,
+                    "output dir",
+                    &context.session_id,
+                    self.clock.now_ms(),
+                )
+                .map_err(CoreError::Config)?,
+            );
+        }
+        self.summary_target(request, &context)?;
+        if let Some(plan_path) = &request.plan_file {
+            let raw = self.file_system.read_to_string(plan_path)?;
+            parse_plan(&raw).map_err(|e| {
+                CoreError::Config(format!("Invalid plan file {}: {e}", plan_path.display()))
+            })?;
+        }
+
+        // Constructing the client resolves the API key and validates the provider without
+        // sending a request.
+        self.create_llm_client(
+            &request.llm_provider,
+            &request.llm_model,
+            request.max_concurrent_llm,
+            request.api_key.clone(),
+            &llm_headers(&config, &request.domain, &request.llm_headers),
+            None,
+        )
+        .map_err(|e| CoreError::System(e.to_string()))?;
+
+        Ok(ConfigCheck {
+            domain: runtime.name,
+            provider: request.llm_provider.clone(),
+            model: request.llm_model.clone(),
+        })
+    }
+
+    async fn verify(&self, request: VerifyRequest) -> CoreResult<VerifyOutcome> {
+        let config = self
+            .load_config(&request.config_path, None)
+            .map_err(|e| CoreError::Config(e.to_string()))?;
+        self.ensure_domain_exists(&config, &request.domain)
+            .map_err(|e| CoreError::Config(e.to_string()))?;
+        let command = config
+            .domain(&request.domain)
+            .and_then(|domain| domain.verifier.clone())
+            .ok_or_else(|| {
+                CoreError::Config(format!(
+                    "Domain '{}' has no verifier configured",
+                    request.domain
+                ))
+            })?;
+
+        tracing::info!(command = %command, "Running verification");
+        let output = self.command_runner.run(&command)?;
+        Ok(VerifyOutcome {
+            domain: request.domain,
+            command,
+            passed: output.success(),
+            status: output.status,
+            stdout: output.stdout,
+            stderr: output.stderr,
+        })
+    }
+
+    async fn list_models(&self, request: ListModelsRequest) -> CoreResult<Vec<String>> {
+        // Listing never sends a completion, so the client's default model is a placeholder.
+        let client = self
+            .create_llm_client(
+                &request.llm_provider,
+                "list-models",
+                1,
+                request.api_key,
+                &request.llm_headers,
+                None,
+            )
+            .map_err(|e| CoreError::Config(e.to_string()))?;
+        client.list_models().await
+    }
+}
+
+/// Human-readable run summary: status, headline metrics, and each step's winning solution.
+fn summary_markdown(outcome: &SessionOutcome) -> String {
+    let status = if outcome.paused {
+        "paused"
+    } else if !outcome.failed_steps.is_empty() {
+        "completed with failures"
+    } else {
+        "completed"
+    };
+    let metrics = &outcome.metrics;
+    let mut out = format!("# Session {}\n\n", outcome.session_id);
+    out.push_str(&format!("- Status: {status}\n"));
+    out.push_str(&format!(
+        "- Steps completed: {}/{}\n",
+        metrics.completed_steps, metrics.total_steps
+    ));
+    out.push_str(&format!(
+        "- Samples: {} ({} resamples)\n",
+        metrics.samples, metrics.resamples
+    ));
+    out.push_str(&format!("- Vote attempts: {}\n", metrics.vote_attempts));
+    out.push_str(&format!("- Red-flag hits: {}\n", metrics.red_flag_hits));
+    if !metrics.stage_timings.is_empty() {
+        out.push_str(&format!(
+            "- Stage timing: {}\n",
+            StageTiming::summarize(&metrics.stage_timings)
+        ));
+    }
+    if !outcome.failed_steps.is_empty() {
+        out.push_str(&format!(
+            "- Failed steps: {}\n",
+            format_step_ids(&outcome.failed_steps)
+        ));
+    }
+    if let Some(pause) = &outcome.pause_reason {
+        out.push_str(&format!(
+            "- Paused at step {} ({}): {}\n",
+            pause.step_id, pause.trigger, pause.details
+        ));
+    }
+    if !outcome.solutions.is_empty() {
+        out.push_str("\n## Solutions\n");
+        for step in &outcome.solutions {
+            out.push_str(&format!(
+                "\n### Step {}: {}\n\n```\n{}\n```\n",
+                step.step_id,
+                step.description,
+                step.solution.trim_end()
+            ));
+        }
+    }
+    out
+}
+
+fn session_note(record: SessionNoteRecord) -> SessionNote {
+    SessionNote {
+        note: record.note,
+        created_at: record.created_at.to_string(),
+    }
+}
+
+/// Runner options for a resumed session: the run-time choices saved in `metadata`.
+fn resumed_runner_options(metadata: &SessionMetadata) -> RunnerOptions {
+    RunnerOptions {
+        adaptive_samples: metadata.adaptive_samples,
+        adaptive_k_limits: metadata.adaptive_k_limits,
+        prompt_vars: metadata.prompt_vars.clone(),
+        flat: metadata.flat,
+        min_samples_accepted: metadata.min_samples_accepted,
+        line_endings: metadata.line_endings,
+        bom: metadata.bom,
+        record_prompts: metadata.record_prompts,
+        max_response_bytes: metadata.max_response_bytes,
+        dedupe_candidates: metadata.dedupe_candidates,
+        collapse_trivial_decomposition: metadata.collapse_trivial_decomposition,
+        explain: metadata.explain,
+        parallel_red_flaggers: metadata.parallel_red_flaggers,
+        shuffle_seed: metadata.shuffle_seed,
+        retry_on_empty: metadata.retry_on_empty,
+        flag_truncated: metadata.flag_truncated,
+        red_flag_concurrency: metadata
+            .red_flag_concurrency
+            .map_or(DEFAULT_RED_FLAG_CONCURRENCY, |limit| limit.max(1)),
+        ..RunnerOptions::from_cli(
+            metadata.samples,
+            metadata.k,
+            metadata.adaptive_k,
+            false,
+            metadata.human_low_margin_threshold,
+        )
+        .with_max_depth(metadata.max_depth)
+    }
+}
+
+/// Compare the config hash recorded with a session against the config being resumed with.
+/// A mismatch is a warning, or an error when `strict` is set; sessions saved before hashes
+/// were recorded are never reported.
+fn check_config_drift(
+    session_id: &str,
+    config_path: &std::path::Path,
+    recorded: Option<&str>,
+    current: Option<&str>,
+    strict: bool,
+) -> CoreResult<()> {
+    let (Some(recorded), Some(current)) = (recorded, current) else {
+        return Ok(());
+    };
+    if recorded == current {
+        return Ok(());
+    }
+    if strict {
+        return Err(CoreError::Config(format!(
+            "Config {} changed since session {session_id} was saved; resume without --strict to continue anyway",
+            config_path.display()
+        )));
+    }
+    tracing::warn!(
+        "Config {} changed since session {session_id} was saved; prompts and settings may differ from the original run",
+        config_path.display()
+    );
+    Ok(())
+}
+
+/// The domain's configured `llm_headers` followed by `overrides`; an override replaces a
+/// configured header with the same (case-insensitive) name.
+fn llm_headers(
+    config: &MicrofactoryConfig,
+    domain: &str,
+    overrides: &[(String, String)],
+) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = config
+        .domain(domain)
+        .map(|domain| {
+            domain
+                .llm_headers
+                .iter()
+                .filter(|(name, _)| {
+                    !overrides
+                        .iter()
+                        .any(|(other, _)| other.eq_ignore_ascii_case(name))
+                })
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect()
+        })
+        .unwrap_or_default();
+    headers.extend(overrides.iter().cloned());
+    headers
+}
+
+/// Parse a plan file into subtasks. Accepts a JSON array of strings or one subtask per line
+/// (blank lines and `#` comments are ignored).
+fn parse_plan(raw: &str) -> anyhow::Result<Vec<String>> {
+    let trimmed = raw.trim();
+    let subtasks: Vec<String> = if trimmed.starts_with('[') {
+        serde_json::from_str::<Vec<String>>(trimmed)?
+            .into_iter()
+            .map(|task| task.trim().to_string())
+            .filter(|task| !task.is_empty())
+            .collect()
+    } else {
+        trimmed
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect()
+    };
+    if subtasks.is_empty() {
+        return Err(anyhow!("plan contains no subtasks"));
+    }
+    Ok(subtasks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    use crate::{
+        adapters::{
+            outbound::{
+                clock::SystemClock,
+                filesystem::StdFileSystem,
+                llm_trace::{RecordingLlmClient, ReplayLlmClient},
+                memory::InMemorySessionRepository,
+                persistence::SessionStore,
+                process::MockCommandRunner,
+                telemetry::TracingTelemetrySink,
+                token_budget::TokenBudgetLlmClient,
+            },
+            templating::HandlebarsRenderer,
+        },
+        core::{
+            domain::{LineEnding, StepStatus},
+            ports::{CommandOutput, LlmOptions, LlmResponse, TokenUsage},
+        },
+    };
+
+    const MINI_CONFIG: &str = r#"
+domains:
+  mini:
+    agents:
+      decomposition:
+        prompt_template: "Decompose: {{task}}"
+        model: "mock-decompose"
+        samples: 1
+      decomposition_discriminator:
+        prompt_template: "Vote: {{task}}"
+        model: "mock-decompose-vote"
+        samples: 1
+      solver:
+        prompt_template: "Solve: {{task}} (repo: {{context.repo}})"
+        model: "mock-solve"
+        samples: 1
+      solution_discriminator:
+        prompt_template: "Pick: {{task}}"
+        model: "mock-solve-vote"
+        samples: 2
+"#;
+
+    /// LLM stub that answers solver/vote prompts, records every prompt, and panics if
+    /// decomposition is attempted.
+    #[derive(Default)]
+    struct SolveOnlyLlm {
+        prompts: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl LlmClient for SolveOnlyLlm {
+        async fn chat_completion(
+            &self,
+            model: &str,
+            prompt: &str,
+            _options: &LlmOptions,
+        ) -> CoreResult<String> {
+            self.prompts.lock().unwrap().push(prompt.to_string());
+            match model {
+                "mock-solve" => Ok("solution".into()),
+                "mock-solve-vote" => Ok("1".into()),
+                other => panic!("unexpected LLM call for model {other}"),
+            }
+        }
+    }
+
+    fn test_service(home: &std::path::Path, llm: Arc<SolveOnlyLlm>) -> AppService {
+        let store = SessionStore::open(Some(home.to_path_buf())).unwrap();
+        test_service_with_store(Arc::new(store), llm)
+    }
+
+    fn test_service_with_store(
+        store: Arc<dyn SessionRepository>,
+        llm: Arc<SolveOnlyLlm>,
+    ) -> AppService {
+        let llm_factory: LlmClientFactory =
+            Arc::new(move |_, _, _, _, _, _| Ok(llm.clone() as Arc<dyn LlmClient>));
+        let api_key_resolver: ApiKeyResolver = Arc::new(|_, _| Ok("test-key".into()));
+        AppService::new(
+            store,
+            Arc::new(HandlebarsRenderer::new()),
+            Arc::new(StdFileSystem::new()),
+            Arc::new(SystemClock::new()),
+            Arc::new(TracingTelemetrySink::new()),
+            llm_factory,
+            api_key_resolver,
+        )
+    }
+
+    /// `run` request for the `mini` domain with every option at its CLI default.
+    fn run_request(config_path: PathBuf, plan_file: Option<PathBuf>) -> RunSessionRequest {
+        RunSessionRequest {
+            prompt: "Ship the feature".into(),
+            domain: "mini".into(),
+            config_path,
+            template_dir: None,
+            llm_provider: "openai".into(),
+            llm_model: "mock".into(),
+            api_key: None,
+            llm_headers: Vec::new(),
+            samples: 1,
+            k: 1,
+            adaptive_k: false,
+            adaptive_k_limits: Default::default(),
+            adaptive_samples: false,
+            max_concurrent_llm: 1,
+            dry_run: false,
+            dry_run_apply: false,
+            step_by_step: false,
+            human_low_margin_threshold: 0,
+            max_depth: None,
+            max_children_total: None,
+            output_dir: None,
+            plan_file,
+            dump_prompts: false,
+            record_prompts: false,
+            red_flag_concurrency: 4,
+            parallel_red_flaggers: false,
+            max_response_bytes: None,
+            flag_truncated: false,
+            retry_on_empty: 0,
+            min_samples_accepted: None,
+            prompt_vars: Vec::new(),
+            line_endings: None,
+            bom: false,
+            dump_context: None,
+            summary_format: None,
+            summary_name: None,
+            dedupe_candidates: false,
+            no_persist: false,
+            explain: false,
+            collapse_trivial_decomposition: false,
+            flat: false,
+            shuffle_candidates: false,
+            shuffle_seed: None,
+            max_total_tokens: None,
+        }
+    }
+
+    #[test]
+    fn cli_llm_headers_override_configured_ones() {
+        let config = MicrofactoryConfig::from_yaml_str(
+            r#"
+        domains:
+          code:
+            llm_headers:
+              OpenAI-Organization: org-config
+              X-Route: eu
+            agents:
+              decomposition: { prompt_template: "a", model: "m" }
+              decomposition_discriminator: { prompt_template: "b", model: "m" }
+              solver: { prompt_template: "c", model: "m" }
+              solution_discriminator: { prompt_template: "d", model: "m" }
+        "#,
+        )
+        .unwrap();
+        let headers = llm_headers(
+            &config,
+            "code",
+            &[("openai-organization".into(), "org-cli".into())],
+        );
+        assert_eq!(
+            headers,
+            vec![
+                ("X-Route".to_string(), "eu".to_string()),
+                ("openai-organization".to_string(), "org-cli".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_plan_accepts_lines_and_json() {
+        assert_eq!(
+            parse_plan("# plan\nfirst\n\n  second  \n").unwrap(),
+            vec!["first", "second"]
+        );
+        assert_eq!(
+            parse_plan(r#"["first", "second"]"#).unwrap(),
+            vec!["first", "second"]
+        );
+        assert!(parse_plan("\n# only comments\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn run_session_with_plan_file_skips_decomposition() {
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG).unwrap();
+        let plan_path = temp.path().join("plan.txt");
+        std::fs::write(&plan_path, "write the parser\nwire the CLI flag\n").unwrap();
+
+        let service = test_service(&temp.path().join("store"), Arc::default());
+        let outcome = service
+            .run_session(run_request(config_path, Some(plan_path)))
+            .await
+            .unwrap();
+        assert!(outcome.completed);
+
+        let record = service
+            .load_record(&outcome.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        let context = record.envelope.context;
+        let planned: Vec<_> = context
+            .steps
+            .iter()
+            .filter(|step| step.parent.is_some())
+            .map(|step| (step.description.as_str(), step.status))
+            .collect();
+        assert_eq!(
+            planned,
+            vec![
+                ("write the parser", StepStatus::Completed),
+                ("wire the CLI flag", StepStatus::Completed),
+            ]
+        );
+        assert_eq!(context.metrics.decomposition_runs, 0);
+    }
+
+    #[tokio::test]
+    async fn get_step_returns_candidates_winner_and_votes() {
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG).unwrap();
+        let plan_path = temp.path().join("plan.txt");
+        std::fs::write(&plan_path, "write the parser\n").unwrap();
+
+        let service = test_service(&temp.path().join("store"), Arc::default());
+        let outcome = service
+            .run_session(run_request(config_path, Some(plan_path)))
+            .await
+            .unwrap();
+        let record = service
+            .load_record(&outcome.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        let step_id = record
+            .envelope
+            .context
+            .steps
+            .iter()
+            .find(|step| step.parent.is_some())
+            .unwrap()
+            .id;
+
+        let detail = service
+            .get_step(&outcome.session_id, step_id)
+            .await
+            .unwrap()
+            .expect("step detail");
+        assert_eq!(detail.description, "write the parser");
+        assert_eq!(detail.status, "Completed");
+        assert_eq!(detail.candidate_solutions, vec!["solution"]);
+        assert_eq!(detail.winning_solution.as_deref(), Some("solution"));
+        assert_eq!(detail.vote_histogram, vec![2]);
+        assert!(
+            service
+                .get_step(&outcome.session_id, 999)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            service
+                .get_step("missing", step_id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn run_session_dumps_final_context() {
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG).unwrap();
+        let plan_path = temp.path().join("plan.txt");
+        std::fs::write(&plan_path, "write the parser\n").unwrap();
+        let dump_path = temp.path().join("debug").join("context.json");
+
+        let service = test_service(&temp.path().join("store"), Arc::default());
+        let outcome = service
+            .run_session(RunSessionRequest {
+                dump_context: Some(dump_path.clone()),
+                ..run_request(config_path, Some(plan_path))
+            })
+            .await
+            .unwrap();
+
+        let dumped: Context =
+            serde_json::from_str(&std::fs::read_to_string(&dump_path).unwrap()).unwrap();
+        let stored = service
+            .load_record(&outcome.session_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .envelope
+            .context;
+        assert_eq!(dumped.session_id, outcome.session_id);
+        assert_eq!(
+            serde_json::to_value(&dumped).unwrap(),
+            serde_json::to_value(&stored).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn context_dump_failure_keeps_the_saved_run() {
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG).unwrap();
+        let plan_path = temp.path().join("plan.txt");
+        std::fs::write(&plan_path, "write the parser\n").unwrap();
+        let blocker = temp.path().join("debug");
+        std::fs::write(&blocker, "not a directory").unwrap();
+
+        let service = test_service(&temp.path().join("store"), Arc::default());
+        let outcome = service
+            .run_session(RunSessionRequest {
+                dump_context: Some(blocker.join("context.json")),
+                ..run_request(config_path, Some(plan_path))
+            })
+            .await
+            .unwrap();
+
+        assert!(outcome.completed);
+        let record = service
+            .load_record(&outcome.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.status, SessionStatus::Completed);
+    }
+
+    #[tokio::test]
+    async fn run_session_writes_summary_with_templated_name() {
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG).unwrap();
+        let plan_path = temp.path().join("plan.txt");
+        std::fs::write(&plan_path, "write the parser\n").unwrap();
+        let output_dir = temp.path().join("out");
+
+        let service = test_service(&temp.path().join("store"), Arc::default());
+        let outcome = service
+            .run_session(RunSessionRequest {
+                output_dir: Some(output_dir.clone()),
+                summary_format: Some(SummaryFormat::Markdown),
+                summary_name: Some("reports/run-{session_id}.md".into()),
+                ..run_request(config_path, Some(plan_path))
+            })
+            .await
+            .unwrap();
+
+        let summary_path = output_dir
+            .join("reports")
+            .join(format!("run-{}.md", outcome.session_id));
+        let summary = std::fs::read_to_string(&summary_path).unwrap();
+        assert!(summary.starts_with(&format!("# Session {}", outcome.session_id)));
+        assert!(summary.contains("- Status: completed"));
+        assert!(summary.contains("### Step 1: write the parser"));
+    }
+
+    #[tokio::test]
+    async fn resume_rewrites_the_summary_of_a_paused_run() {
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG).unwrap();
+        let plan_path = temp.path().join("plan.txt");
+        std::fs::write(&plan_path, "write the parser\nwire the CLI flag\n").unwrap();
+        let output_dir = temp.path().join("out");
+        let store_dir = temp.path().join("store");
+
+        let outcome = test_service(&store_dir, Arc::default())
+            .run_session(RunSessionRequest {
+                output_dir: Some(output_dir.clone()),
+                step_by_step: true,
+                summary_format: Some(SummaryFormat::Markdown),
+                summary_name: Some("run-{timestamp}.md".into()),
+                ..run_request(config_path, Some(plan_path))
+            })
+            .await
+            .unwrap();
+        assert!(outcome.paused);
+        let written: Vec<_> = std::fs::read_dir(&output_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(written.len(), 1, "{written:?}");
+        let summary = std::fs::read_to_string(&written[0]).unwrap();
+        assert!(summary.contains("- Status: paused"), "{summary}");
+
+        let resumed = test_service(&store_dir, Arc::default())
+            .resume_session(resume_request(&outcome.session_id))
+            .await
+            .unwrap();
+        assert!(resumed.completed);
+        let summary = std::fs::read_to_string(&written[0]).unwrap();
+        assert!(summary.contains("- Status: completed"), "{summary}");
+        assert_eq!(std::fs::read_dir(&output_dir).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn summary_write_failure_keeps_the_saved_run() {
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG).unwrap();
+        let plan_path = temp.path().join("plan.txt");
+        std::fs::write(&plan_path, "write the parser\n").unwrap();
+        let output_dir = temp.path().join("out");
+        std::fs::create_dir_all(&output_dir).unwrap();
+        std::fs::write(output_dir.join("reports"), "not a directory").unwrap();
+
+        let service = test_service(&temp.path().join("store"), Arc::default());
+        let outcome = service
+            .run_session(RunSessionRequest {
+                output_dir: Some(output_dir),
+                summary_name: Some("reports/summary.md".into()),
+                ..run_request(config_path, Some(plan_path))
+            })
+            .await
+            .unwrap();
+
+        assert!(outcome.completed);
+        let record = service
+            .load_record(&outcome.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.status, SessionStatus::Completed);
+    }
+
+    #[tokio::test]
+    async fn check_run_config_validates_setup_without_running() {
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG).unwrap();
+        let request = |domain: &str, plan_file: Option<std::path::PathBuf>| RunSessionRequest {
+            domain: domain.into(),
+            ..run_request(config_path.clone(), plan_file)
+        };
+
+        let llm = Arc::new(SolveOnlyLlm::default());
+        let service = test_service(&temp.path().join("store"), llm.clone());
+        let check = service
+            .check_run_config(&request("mini", None))
+            .await
+            .unwrap();
+        assert_eq!(check.domain, "mini");
+        assert_eq!(check.model, "mock");
+        assert!(llm.prompts.lock().unwrap().is_empty(), "no LLM call");
+        assert!(service.list_sessions(10).await.unwrap().is_empty());
+
+        let err = service
+            .check_run_config(&request("missing", None))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Available domains: mini"), "{err}");
+
+        let plan_path = temp.path().join("plan.txt");
+        std::fs::write(&plan_path, "# nothing planned\n").unwrap();
+        let err = service
+            .check_run_config(&request("mini", Some(plan_path)))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Invalid plan file"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn recorded_prompts_are_persisted_with_the_session() {
+        struct PlanningLlm;
+
+        #[async_trait]
+        impl LlmClient for PlanningLlm {
+            async fn chat_completion(
+                &self,
+                model: &str,
+                _prompt: &str,
+                _options: &LlmOptions,
+            ) -> CoreResult<String> {
+                Ok(match model {
+                    "mock-decompose" => "- write the parser".into(),
+                    "mock-solve" => "solution".into(),
+                    _ => "1".into(),
+                })
+            }
+        }
+
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(
+            &config_path,
+            format!("{MINI_CONFIG}    prompt_redactions: ['feat\\w+']\n"),
+        )
+        .unwrap();
+        let llm_factory: LlmClientFactory =
+            Arc::new(|_, _, _, _, _, _| Ok(Arc::new(PlanningLlm) as Arc<dyn LlmClient>));
+        let service = AppService::new(
+            Arc::new(SessionStore::open(Some(temp.path().join("store"))).unwrap()),
+            Arc::new(HandlebarsRenderer::new()),
+            Arc::new(StdFileSystem::new()),
+            Arc::new(SystemClock::new()),
+            Arc::new(TracingTelemetrySink::new()),
+            llm_factory,
+            Arc::new(|_, _| Ok("test-key".into())),
+        );
+
+        let outcome = service
+            .run_session(RunSessionRequest {
+                record_prompts: true,
+                ..run_request(config_path, None)
+            })
+            .await
+            .unwrap();
+        assert!(outcome.completed);
+
+        let stored = service
+            .load_record(&outcome.session_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .envelope
+            .context;
+        let root = stored.step(stored.root_step_id().unwrap()).unwrap();
+        assert_eq!(
+            root.rendered_prompts
+                .get("decomposition")
+                .map(String::as_str),
+            Some("Decompose: Ship the [REDACTED]")
+        );
+        assert!(root.rendered_prompts.contains_key("decomposition_vote"));
+        let child = stored.step(root.children[0]).unwrap();
+        assert!(
+            child.rendered_prompts["solve"].starts_with("Solve: write the parser"),
+            "{:?}",
+            child.rendered_prompts
+        );
+    }
+
+    #[tokio::test]
+    async fn run_subprocess_renders_context_json_into_prompts() {
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG).unwrap();
+
+        let llm = Arc::new(SolveOnlyLlm::default());
+        let service = test_service(&temp.path().join("store"), llm.clone());
+        let outcome = service
+            .run_subprocess(SubprocessRequest {
+                domain: "mini".into(),
+                config_path,
+                step: "Fix the parser".into(),
+                context_json: Some(r#"{"repo": "microfactory"}"#.into()),
+                llm_provider: "openai".into(),
+                llm_model: "mock".into(),
+                api_key: None,
+                llm_headers: Vec::new(),
+                samples: 1,
+                k: 1,
+                max_concurrent_llm: 1,
+            })
+            .await
+            .unwrap();
+        assert_eq!(outcome.winning_solution.as_deref(), Some("solution"));
+
+        let prompts = llm.prompts.lock().unwrap();
+        assert!(
+            prompts
+                .iter()
+                .any(|prompt| prompt == "Solve: Fix the parser (repo: microfactory)"),
+            "{prompts:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn run_subprocess_batch_shares_the_llm_client_across_items() {
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG).unwrap();
+
+        let llm = Arc::new(SolveOnlyLlm::default());
+        let created = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let llm_factory: LlmClientFactory = {
+            let (llm, created) = (llm.clone(), created.clone());
+            Arc::new(move |_, _, _, _, _, _| {
+                created.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                Ok(llm.clone() as Arc<dyn LlmClient>)
+            })
+        };
+        let service = AppService::new(
+            Arc::new(SessionStore::open(Some(temp.path().join("store"))).unwrap()),
+            Arc::new(HandlebarsRenderer::new()),
+            Arc::new(StdFileSystem::new()),
+            Arc::new(SystemClock::new()),
+            Arc::new(TracingTelemetrySink::new()),
+            llm_factory,
+            Arc::new(|_, _| Ok("test-key".into())),
+        );
+        let request = |step: &str| SubprocessRequest {
+            domain: "mini".into(),
+            config_path: config_path.clone(),
+            step: step.into(),
+            context_json: None,
+            llm_provider: "openai".into(),
+            llm_model: "mock".into(),
+            api_key: None,
+            llm_headers: Vec::new(),
+            samples: 1,
+            k: 1,
+            max_concurrent_llm: 1,
+        };
+
+        let outcomes = service
+            .run_subprocess_batch(vec![request("Fix the parser"), request("Add a test")])
+            .await
+            .unwrap();
+        assert_eq!(outcomes.len(), 2);
+        assert!(
+            outcomes
+                .iter()
+                .all(|outcome| outcome.winning_solution.as_deref() == Some("solution"))
+        );
+        assert_ne!(outcomes[0].session_id, outcomes[1].session_id);
+        assert_eq!(created.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        let mut broken = request("Nope");
+        broken.domain = "missing".into();
+        let err = service
+            .run_subprocess_batch(vec![request("Fix the parser"), broken])
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Batch item 2"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn resume_from_step_reruns_only_the_targeted_subtree() {
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG).unwrap();
+        let plan_path = temp.path().join("plan.txt");
+        std::fs::write(&plan_path, "write the parser\nwire the CLI flag\n").unwrap();
+        let store_dir = temp.path().join("store");
+
+        let outcome = test_service(&store_dir, Arc::default())
+            .run_session(run_request(config_path, Some(plan_path)))
+            .await
+            .unwrap();
+        assert!(outcome.completed);
+        let before = test_service(&store_dir, Arc::default())
+            .load_record(&outcome.session_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .envelope
+            .context;
+        let step_id = |context: &Context, description: &str| {
+            context
+                .steps
+                .iter()
+                .find(|step| step.description == description)
+                .map(|step| step.id)
+                .unwrap()
+        };
+        let parser = step_id(&before, "write the parser");
+        let flag = step_id(&before, "wire the CLI flag");
+
+        let llm = Arc::new(SolveOnlyLlm::default());
+        let service = test_service(&store_dir, llm.clone());
+        let resumed = service
+            .resume_session(ResumeSessionRequest {
+                session_id: outcome.session_id.clone(),
+                config_path: None,
+                template_dir: None,
+                llm_provider: None,
+                llm_model: None,
+                api_key: None,
+                llm_headers: Vec::new(),
+                samples: None,
+                k: None,
+                max_concurrent_llm: None,
+                human_low_margin_threshold: None,
+                from_step: Some(flag),
+                strict: false,
+                max_total_tokens: None,
+            })
+            .await
+            .unwrap();
+        assert!(resumed.completed);
+
+        let solve_prompts: Vec<String> = llm
+            .prompts
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|prompt| prompt.starts_with("Solve:"))
+            .cloned()
+            .collect();
+        assert_eq!(solve_prompts.len(), 1, "{solve_prompts:?}");
+        assert!(solve_prompts[0].contains("wire the CLI flag"));
+
+        let after = service
+            .load_record(&outcome.session_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .envelope
+            .context;
+        let parser_before = before.step(parser).unwrap();
+        let parser_after = after.step(parser).unwrap();
+        assert_eq!(parser_after.status, StepStatus::Completed);
+        assert_eq!(
+            parser_after.winning_solution,
+            parser_before.winning_solution
+        );
+        assert_eq!(
+            after.metrics.per_step[&parser].samples_requested,
+            before.metrics.per_step[&parser].samples_requested
+        );
+        assert_eq!(after.step(flag).unwrap().status, StepStatus::Completed);
+
+        let missing = service
+            .resume_session(ResumeSessionRequest {
+                session_id: outcome.session_id,
+                config_path: None,
+                template_dir: None,
+                llm_provider: None,
+                llm_model: None,
+                api_key: None,
+                llm_headers: Vec::new(),
+                samples: None,
+                k: None,
+                max_concurrent_llm: None,
+                human_low_margin_threshold: None,
+                from_step: Some(999),
+                strict: false,
+                max_total_tokens: None,
+            })
+            .await;
+        assert!(matches!(missing, Err(CoreError::InvalidState(_))));
+    }
+
+    #[tokio::test]
+    async fn resume_keeps_the_run_output_encoding() {
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG).unwrap();
+        let plan_path = temp.path().join("plan.txt");
+        std::fs::write(&plan_path, "write the parser\n").unwrap();
+        let store_dir = temp.path().join("store");
+
+        let outcome = test_service(&store_dir, Arc::default())
+            .run_session(RunSessionRequest {
+                line_endings: Some(LineEnding::Crlf),
+                bom: true,
+                ..run_request(config_path, Some(plan_path))
+            })
+            .await
+            .unwrap();
+        let service = test_service(&store_dir, Arc::default());
+        let root = service
+            .load_record(&outcome.session_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .envelope
+            .context
+            .steps[0]
+            .id;
+        service
+            .resume_session(ResumeSessionRequest {
+                from_step: Some(root),
+                ..resume_request(&outcome.session_id)
+            })
+            .await
+            .unwrap();
+
+        let metadata = service
+            .load_record(&outcome.session_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .envelope
+            .metadata;
+        assert_eq!(metadata.line_endings, Some(LineEnding::Crlf));
+        assert!(metadata.bom);
+        let options = resumed_runner_options(&metadata);
+        assert_eq!(options.line_endings, Some(LineEnding::Crlf));
+        assert!(options.bom);
+    }
+
+    #[tokio::test]
+    async fn resume_sends_the_given_llm_headers() {
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG).unwrap();
+        let plan_path = temp.path().join("plan.txt");
+        std::fs::write(&plan_path, "write the parser\n").unwrap();
+        let store_dir = temp.path().join("store");
+        let outcome = test_service(&store_dir, Arc::default())
+            .run_session(run_request(config_path, Some(plan_path)))
+            .await
+            .unwrap();
+
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let llm = Arc::new(SolveOnlyLlm::default());
+        let llm_factory: LlmClientFactory = {
+            let seen = seen.clone();
+            Arc::new(move |_, _, _, _, headers, _| {
+                seen.lock().unwrap().extend_from_slice(headers);
+                Ok(llm.clone() as Arc<dyn LlmClient>)
+            })
+        };
+        let service = AppService::new(
+            Arc::new(SessionStore::open(Some(store_dir)).unwrap()),
+            Arc::new(HandlebarsRenderer::new()),
+            Arc::new(StdFileSystem::new()),
+            Arc::new(SystemClock::new()),
+            Arc::new(TracingTelemetrySink::new()),
+            llm_factory,
+            Arc::new(|_, _| Ok("test-key".into())),
+        );
+        let root = service
+            .load_record(&outcome.session_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .envelope
+            .context
+            .steps[0]
+            .id;
+        service
+            .resume_session(ResumeSessionRequest {
+                llm_headers: vec![("X-Trace".into(), "abc".into())],
+                from_step: Some(root),
+                ..resume_request(&outcome.session_id)
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![("X-Trace".to_string(), "abc".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn resume_keeps_the_token_budget_of_a_budget_paused_run() {
+        /// Bills every call of the wrapped stub at 40 tokens.
+        struct MeteredLlm(Arc<SolveOnlyLlm>);
+
+        #[async_trait]
+        impl LlmClient for MeteredLlm {
+            async fn chat_completion(
+                &self,
+                model: &str,
+                prompt: &str,
+                options: &LlmOptions,
+            ) -> CoreResult<String> {
+                self.0.chat_completion(model, prompt, options).await
+            }
+
+            async fn chat_completion_with_finish_reason(
+                &self,
+                model: &str,
+                prompt: &str,
+                options: &LlmOptions,
+            ) -> CoreResult<LlmResponse> {
+                let content = self.0.chat_completion(model, prompt, options).await?;
+                Ok(LlmResponse::new(content).with_usage(TokenUsage {
+                    input_tokens: 30,
+                    output_tokens: 10,
+                }))
+            }
+        }
+
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG).unwrap();
+        let plan_path = temp.path().join("plan.txt");
+        std::fs::write(&plan_path, "write the parser\nwire the CLI flag\n").unwrap();
+        let store_dir = temp.path().join("store");
+        let llm = Arc::new(SolveOnlyLlm::default());
+        let service = {
+            let llm = llm.clone();
+            let llm_factory: LlmClientFactory = Arc::new(move |_, _, _, _, _, budget| {
+                let metered = Arc::new(MeteredLlm(llm.clone())) as Arc<dyn LlmClient>;
+                Ok(match budget {
+                    Some(budget) => Arc::new(TokenBudgetLlmClient::new(metered, budget)),
+                    None => metered,
+                })
+            });
+            AppService::new(
+                Arc::new(SessionStore::open(Some(store_dir)).unwrap()),
+                Arc::new(HandlebarsRenderer::new()),
+                Arc::new(StdFileSystem::new()),
+                Arc::new(SystemClock::new()),
+                Arc::new(TracingTelemetrySink::new()),
+                llm_factory,
+                Arc::new(|_, _| Ok("test-key".into())),
+            )
+        };
+        let budget_wait = |outcome: &SessionOutcome| {
+            outcome
+                .pause_reason
+                .as_ref()
+                .filter(|reason| reason.trigger == "token_budget_exhausted")
+                .map(|reason| reason.details.clone())
+        };
+
+        let outcome = service
+            .run_session(RunSessionRequest {
+                max_total_tokens: Some(50),
+                ..run_request(config_path, Some(plan_path))
+            })
+            .await
+            .unwrap();
+        let details = budget_wait(&outcome).expect("run pauses on the budget");
+        let record = service
+            .load_record(&outcome.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        let spent = record.envelope.context.metrics.tokens_used;
+        assert!(spent > 50, "{spent}");
+        assert_eq!(record.envelope.metadata.max_total_tokens, Some(50));
+        assert!(details.contains(&format!("{spent} of 50")), "{details}");
+
+        let calls = llm.prompts.lock().unwrap().len();
+        let again = service
+            .resume_session(resume_request(&outcome.session_id))
+            .await
+            .unwrap();
+        let details = budget_wait(&again).expect("the saved budget is still spent");
+        assert!(details.contains(&format!("{spent} of 50")), "{details}");
+        assert_eq!(llm.prompts.lock().unwrap().len(), calls, "no LLM call");
+
+        let resumed = service
+            .resume_session(ResumeSessionRequest {
+                max_total_tokens: Some(1_000),
+                ..resume_request(&outcome.session_id)
+            })
+            .await
+            .unwrap();
+        assert!(resumed.completed);
+        let record = service
+            .load_record(&outcome.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(record.envelope.context.metrics.tokens_used > spent);
+        assert_eq!(record.envelope.metadata.max_total_tokens, Some(1_000));
+    }
+
+    #[test]
+    fn resumed_runner_options_restore_saved_run_choices() {
+        let metadata = SessionMetadata {
+            samples: 3,
+            k: 2,
+            record_prompts: true,
+            max_response_bytes: Some(4096),
+            dedupe_candidates: true,
+            collapse_trivial_decomposition: true,
+            explain: true,
+            parallel_red_flaggers: true,
+            shuffle_seed: Some(7),
+            retry_on_empty: 2,
+            flag_truncated: true,
+            red_flag_concurrency: Some(9),
+            ..Default::default()
+        };
+
+        let options = resumed_runner_options(&metadata);
+
+        assert_eq!((options.default_samples, options.default_k), (3, 2));
+        assert!(options.record_prompts);
+        assert_eq!(options.max_response_bytes, Some(4096));
+        assert!(options.dedupe_candidates);
+        assert!(options.collapse_trivial_decomposition);
+        assert!(options.explain);
+        assert!(options.parallel_red_flaggers);
+        assert_eq!(options.shuffle_seed, Some(7));
+        assert_eq!(options.retry_on_empty, 2);
+        assert!(options.flag_truncated);
+        assert_eq!(options.red_flag_concurrency, 9);
+        let legacy = resumed_runner_options(&SessionMetadata::default());
+        assert_eq!(legacy.red_flag_concurrency, DEFAULT_RED_FLAG_CONCURRENCY);
+    }
+
+    #[tokio::test]
+    async fn run_session_without_persistence_writes_no_rows() {
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG).unwrap();
+        let plan_path = temp.path().join("plan.txt");
+        std::fs::write(&plan_path, "write the parser\n").unwrap();
+
+        let repository = Arc::new(InMemorySessionRepository::new());
+        let service = test_service_with_store(repository.clone(), Arc::default());
+        let outcome = service
+            .run_session(RunSessionRequest {
+                no_persist: true,
+                ..run_request(config_path, Some(plan_path))
+            })
+            .await
+            .unwrap();
+        assert!(outcome.completed);
+        assert!(repository.list_sessions(10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn stage_timings_add_up_to_the_recorded_step_durations() {
+        /// Advances 10 ms on every reading, so each measured stage takes a known, non-zero time.
+        #[derive(Default)]
+        struct SteppingClock(std::sync::atomic::AtomicU64);
+
+        impl Clock for SteppingClock {
+            fn now_ms(&self) -> u128 {
+                u128::from(self.0.fetch_add(10, std::sync::atomic::Ordering::SeqCst))
+            }
+        }
+
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG).unwrap();
+        let plan_path = temp.path().join("plan.txt");
+        std::fs::write(&plan_path, "write the parser\nwire the CLI flag\n").unwrap();
+        let llm = Arc::new(SolveOnlyLlm::default());
+        let clock = Arc::new(SteppingClock::default());
+        let repository = Arc::new(InMemorySessionRepository::new());
+        let service = AppService::new(
+            repository,
+            Arc::new(HandlebarsRenderer::new()),
+            Arc::new(StdFileSystem::new()),
+            clock.clone(),
+            Arc::new(TracingTelemetrySink::new()),
+            Arc::new(move |_, _, _, _, _, _| Ok(llm.clone() as Arc<dyn LlmClient>)),
+            Arc::new(|_, _| Ok("test-key".into())),
+        );
+
+        let started_ms = clock.now_ms();
+        let outcome = service
+            .run_session(run_request(config_path, Some(plan_path)))
+            .await
+            .unwrap();
+        let elapsed_ms = clock.now_ms() - started_ms;
+        assert!(outcome.completed);
+
+        let timings = &outcome.metrics.stage_timings;
+        assert!(
+            timings.iter().any(|timing| timing.stage == "solve"),
+            "{timings:?}"
+        );
+        let stage_total: u128 = timings.iter().map(|timing| timing.duration_ms).sum();
+        let record = service
+            .load_record(&outcome.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        let step_total: u128 = record
+            .envelope
+            .context
+            .metrics
+            .per_step
+            .values()
+            .filter_map(|metrics| metrics.duration_ms)
+            .sum();
+        assert_eq!(stage_total, step_total);
+        assert!(stage_total >= 20, "two solver tasks took {stage_total} ms");
+        assert!(stage_total <= elapsed_ms, "{stage_total} > {elapsed_ms}");
+
+        let detail = service
+            .get_session(&outcome.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&detail.stage_timings, timings);
+    }
+
+    #[tokio::test]
+    async fn replaying_a_recorded_trace_reproduces_the_run_without_a_provider() {
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG).unwrap();
+        let plan_path = temp.path().join("plan.txt");
+        std::fs::write(&plan_path, "write the parser\nwire the CLI flag\n").unwrap();
+        let trace_path = temp.path().join("llm.jsonl");
+        let request = || RunSessionRequest {
+            no_persist: true,
+            ..run_request(config_path.clone(), Some(plan_path.clone()))
+        };
+        let service = |llm_factory: LlmClientFactory| {
+            AppService::new(
+                Arc::new(InMemorySessionRepository::new()),
+                Arc::new(HandlebarsRenderer::new()),
+                Arc::new(StdFileSystem::new()),
+                Arc::new(SystemClock::new()),
+                Arc::new(TracingTelemetrySink::new()),
+                llm_factory,
+                Arc::new(|_, _| Ok("test-key".into())),
+            )
+        };
+
+        let llm = Arc::new(SolveOnlyLlm::default());
+        let recording_factory: LlmClientFactory = {
+            let llm = llm.clone();
+            let trace_path = trace_path.clone();
+            Arc::new(move |_, _, _, _, _, _| {
+                Ok(Arc::new(RecordingLlmClient::new(
+                    llm.clone(),
+                    Arc::new(StdFileSystem::new()),
+                    Arc::new(SystemClock::new()),
+                    trace_path.clone(),
+                )) as Arc<dyn LlmClient>)
+            })
+        };
+        let recorded = service(recording_factory)
+            .run_session(request())
+            .await
+            .unwrap();
+        let recorded_calls = llm.prompts.lock().unwrap().len();
+        assert!(recorded_calls > 0);
+
+        let replay_factory: LlmClientFactory = Arc::new(move |_, _, _, _, _, _| {
+            let client = ReplayLlmClient::open(&StdFileSystem::new(), &trace_path)?;
+            Ok(Arc::new(client) as Arc<dyn LlmClient>)
+        });
+        let replayed = service(replay_factory)
+            .run_session(request())
+            .await
+            .unwrap();
+
+        assert!(replayed.completed);
+        assert_eq!(replayed.completed, recorded.completed);
+        assert_eq!(replayed.failed_steps, recorded.failed_steps);
+        let solutions = |outcome: &SessionOutcome| {
+            outcome
+                .solutions
+                .iter()
+                .map(|s| (s.step_id, s.description.clone(), s.solution.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(solutions(&replayed), solutions(&recorded));
+        assert_eq!(solutions(&replayed).len(), 2);
+        assert_eq!(llm.prompts.lock().unwrap().len(), recorded_calls);
+    }
+
+    #[tokio::test]
+    async fn output_dir_placeholders_expand_per_run() {
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG).unwrap();
+        let plan_path = temp.path().join("plan.txt");
+        std::fs::write(&plan_path, "write the parser\n").unwrap();
+        let template = temp.path().join("runs").join("{session_id}").join("{date}");
+
+        let service = test_service(&temp.path().join("store"), Arc::default());
+        let mut output_dirs = Vec::new();
+        for _ in 0..2 {
+            let outcome = service
+                .run_session(RunSessionRequest {
+                    output_dir: Some(template.clone()),
+                    ..run_request(config_path.clone(), Some(plan_path.clone()))
+                })
+                .await
+                .unwrap();
+            let context = service
+                .load_record(&outcome.session_id)
+                .await
+                .unwrap()
+                .unwrap()
+                .envelope
+                .context;
+            let output_dir = context.output_dir.expect("output dir recorded");
+            let session_dir = output_dir.parent().unwrap();
+            assert_eq!(
+                session_dir,
+                temp.path().join("runs").join(&outcome.session_id)
+            );
+            let date = output_dir
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .to_string();
+            assert_eq!(date.len(), 10, "unexpected date segment {date}");
+            assert!(!output_dir.to_string_lossy().contains('{'));
+            output_dirs.push(output_dir);
+        }
+        assert_ne!(output_dirs[0], output_dirs[1]);
+    }
+
+    /// Store a paused `mini` session whose metadata points at `config_path`, using a service
+    /// whose LLM factory panics, so resume must fail before any client is built.
+    async fn paused_session_without_llm(
+        config_path: &std::path::Path,
+        api_key_resolver: ApiKeyResolver,
+    ) -> AppService {
+        let llm_factory: LlmClientFactory =
+            Arc::new(|_, _, _, _, _, _| panic!("LLM client built before resume validation"));
+        let service = AppService::new(
+            Arc::new(InMemorySessionRepository::new()),
+            Arc::new(HandlebarsRenderer::new()),
+            Arc::new(StdFileSystem::new()),
+            Arc::new(SystemClock::new()),
+            Arc::new(TracingTelemetrySink::new()),
+            llm_factory,
+            api_key_resolver,
+        );
+        let mut context = Context::new("Ship the feature", "mini");
+        context.session_id = "paused-session".into();
+        context.ensure_root();
+        let envelope = SessionEnvelope {
+            context,
+            metadata: SessionMetadata {
+                config_path: config_path.to_string_lossy().to_string(),
+                llm_provider: "openai".into(),
+                llm_model: "mock".into(),
+                max_concurrent_llm: 1,
+                samples: 1,
+                k: 1,
+                human_low_margin_threshold: 1,
+                ..Default::default()
+            },
+        };
+        service
+            .save_envelope(&envelope, SessionStatus::Paused)
+            .await
+            .unwrap();
+        service
+    }
+
+    fn resume_request(session_id: &str) -> ResumeSessionRequest {
+        ResumeSessionRequest {
+            session_id: session_id.into(),
+            config_path: None,
+            template_dir: None,
+            llm_provider: None,
+            llm_model: None,
+            api_key: None,
+            llm_headers: Vec::new(),
+            samples: None,
+            k: None,
+            max_concurrent_llm: None,
+            human_low_margin_threshold: None,
+            from_step: None,
+            strict: false,
+            max_total_tokens: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn resume_reports_a_moved_config_before_building_the_llm_client() {
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        let service =
+            paused_session_without_llm(&config_path, Arc::new(|_, _| Ok("test-key".into()))).await;
+
+        let err = service
+            .resume_session(resume_request("paused-session"))
+            .await
+            .unwrap_err();
+
+        let CoreError::Config(message) = err else {
+            panic!("expected a config error, got {err:?}");
+        };
+        assert!(message.contains("no longer exists"), "{message}");
+        assert!(message.contains("--config"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn resume_missing_key_error_names_provider_and_env_var() {
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG).unwrap();
+        let resolver: ApiKeyResolver = Arc::new(|cli_value, provider: &str| {
+            assert!(cli_value.is_none());
+            Err(anyhow!(
+                "Missing API key for provider '{provider}': pass --api-key or set OPENAI_API_KEY"
+            ))
+        });
+        let service = paused_session_without_llm(&config_path, resolver).await;
+
+        let err = service
+            .resume_session(resume_request("paused-session"))
+            .await
+            .unwrap_err();
+
+        let message = err.to_string();
+        assert!(message.contains("paused-session"), "{message}");
+        assert!(message.contains("provider 'openai'"), "{message}");
+        assert!(message.contains("OPENAI_API_KEY"), "{message}");
+        assert!(message.contains("--api-key"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn resume_reports_a_removed_domain_before_building_the_llm_client() {
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG.replace("  mini:", "  renamed:")).unwrap();
+        let service =
+            paused_session_without_llm(&config_path, Arc::new(|_, _| Ok("test-key".into()))).await;
+
+        let err = service
+            .resume_session(resume_request("paused-session"))
+            .await
+            .unwrap_err();
+
+        let CoreError::Config(message) = err else {
+            panic!("expected a config error, got {err:?}");
+        };
+        assert!(message.contains("Domain 'mini' not defined"), "{message}");
+        assert!(message.contains("renamed"), "{message}");
+        assert!(message.contains("--config"), "{message}");
+    }
+
+    /// Pretend the paused session was saved against a different config.
+    async fn record_stale_config_hash(service: &AppService) {
+        let mut envelope = service
+            .load_record("paused-session")
+            .await
+            .unwrap()
+            .unwrap()
+            .envelope;
+        envelope.metadata.config_hash = Some("stale".into());
+        service
+            .save_envelope(&envelope, SessionStatus::Paused)
+            .await
+            .unwrap();
+    }
+
+    #[derive(Clone, Default)]
+    struct WarnEvents(Arc<std::sync::Mutex<Vec<String>>>);
+
+    struct WarnMessage(String);
+
+    impl tracing::field::Visit for WarnMessage {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            if field.name() == "message" {
+                self.0 = format!("{value:?}");
+            }
+        }
+    }
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for WarnEvents {
+        fn on_event(
+            &self,
+            event: &tracing::Event<'_>,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            if *event.metadata().level() == tracing::Level::WARN {
+                let mut message = WarnMessage(String::new());
+                event.record(&mut message);
+                self.0.lock().unwrap().push(message.0);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn resume_warns_when_the_config_changed_since_the_session_was_saved() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG).unwrap();
+        // A failing key lookup stops the resume right after the drift check.
+        let resolver: ApiKeyResolver = Arc::new(|_, _| Err(anyhow!("no key in tests")));
+        let service = paused_session_without_llm(&config_path, resolver).await;
+        record_stale_config_hash(&service).await;
+
+        let warnings = WarnEvents::default();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(warnings.clone()));
+        let err = service
+            .resume_session(resume_request("paused-session"))
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("no key in tests"), "{err}");
+        let warnings = warnings.0.lock().unwrap();
+        assert!(
+            warnings
+                .iter()
+                .any(|warning| warning.contains("changed since session paused-session")),
+            "{warnings:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn strict_resume_rejects_a_changed_config() {
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG).unwrap();
+        let service =
+            paused_session_without_llm(&config_path, Arc::new(|_, _| Ok("test-key".into()))).await;
+        record_stale_config_hash(&service).await;
+
+        let err = service
+            .resume_session(ResumeSessionRequest {
+                strict: true,
+                ..resume_request("paused-session")
+            })
+            .await
+            .unwrap_err();
+
+        let CoreError::Config(message) = err else {
+            panic!("expected a config error, got {err:?}");
+        };
+        assert!(
+            message.contains("changed since session paused-session"),
+            "{message}"
+        );
+        assert!(message.contains("--strict"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn running_session_detail_is_marked_partial() {
+        let repository = Arc::new(InMemorySessionRepository::new());
+        let service = test_service_with_store(repository, Arc::default());
+        let mut context = Context::new("Ship the feature", "mini");
+        context.session_id = "running-session".into();
+        let root = context.ensure_root();
+        let first = context.add_child_step(root, "write the parser");
+        context.add_child_step(root, "wire the CLI flag");
+        context.mark_step_status(root, StepStatus::Running);
+        context.mark_step_status(first, StepStatus::Running);
+        let envelope = SessionEnvelope {
+            context,
+            metadata: SessionMetadata {
+                config_path: "config.yaml".into(),
+                llm_provider: "openai".into(),
+                llm_model: "mock".into(),
+                max_concurrent_llm: 1,
+                samples: 1,
+                k: 1,
+                human_low_margin_threshold: 1,
+                ..Default::default()
+            },
+        };
+
+        service
+            .save_envelope(&envelope, SessionStatus::Running)
+            .await
+            .unwrap();
+        let detail = service
+            .get_session("running-session")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(detail.partial);
+        assert_eq!(detail.steps_running, 2);
+
+        service
+            .save_envelope(&envelope, SessionStatus::Paused)
+            .await
+            .unwrap();
+        let detail = service
+            .get_session("running-session")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!detail.partial);
+    }
+
+    #[tokio::test]
+    async fn session_detail_lists_notes_oldest_first() {
+        let temp = tempfile::tempdir().unwrap();
+        let service = paused_session_without_llm(
+            &temp.path().join("config.yaml"),
+            Arc::new(|_, _| Ok("test-key".into())),
+        )
+        .await;
+
+        service
+            .add_session_note("paused-session", "  known flaky  ")
+            .await
+            .unwrap()
+            .unwrap();
+        service
+            .add_session_note("paused-session", "approved by ops")
+            .await
+            .unwrap();
+        assert!(
+            service
+                .add_session_note("missing", "lost")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        let err = service
+            .add_session_note("paused-session", " ")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CoreError::InvalidState(_)), "{err:?}");
+
+        let detail = service
+            .get_session("paused-session")
+            .await
+            .unwrap()
+            .unwrap();
+        let notes: Vec<&str> = detail.notes.iter().map(|n| n.note.as_str()).collect();
+        assert_eq!(notes, ["known flaky", "approved by ops"]);
+    }
+
+    #[tokio::test]
+    async fn service_runs_against_an_injected_repository() {
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(&config_path, MINI_CONFIG).unwrap();
+        let plan_path = temp.path().join("plan.txt");
+        std::fs::write(&plan_path, "write the parser\n").unwrap();
+
+        let repository = Arc::new(InMemorySessionRepository::new());
+        let service = test_service_with_store(repository.clone(), Arc::default());
+        let outcome = service
+            .run_session(run_request(config_path, Some(plan_path)))
+            .await
+            .unwrap();
+        assert!(outcome.completed);
+
+        let stored = repository
+            .load_session(&outcome.session_id)
+            .await
+            .unwrap()
+            .expect("session saved through the port");
+        assert_eq!(stored.status, "completed");
+
+        let detail = service
+            .get_session(&outcome.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(detail.steps_completed, 1);
+        let listed = service.list_sessions(10).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].session_id, outcome.session_id);
+
+        let mut lines = Vec::new();
+        let exported = service
+            .export_sessions(None, &mut |line| {
+                lines.push(line.to_string());
+                Ok(())
+            })
+            .await
+            .unwrap();
+        assert_eq!(exported, 1);
+        assert!(lines[0].contains(&outcome.session_id));
+
+        assert!(service.get_session("missing").await.unwrap().is_none());
+        assert!(
+            repository
+                .delete_session(&outcome.session_id)
+                .await
+                .unwrap()
+        );
+        assert!(service.list_sessions(10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn verify_runs_domain_verifier_without_llm() {
+        let temp = tempfile::tempdir().unwrap();
+        let config_path = temp.path().join("config.yaml");
+        std::fs::write(
+            &config_path,
+            format!("{MINI_CONFIG}    verifier: \"./verify.sh\"\n"),
+        )
+        .unwrap();
+
+        let runner = Arc::new(MockCommandRunner::new(vec![
+            CommandOutput {
+                status: Some(0),
+                stdout: "ok\n".into(),
+                stderr: String::new(),
+            },
+            CommandOutput {
+                status: Some(2),
+                stdout: String::new(),
+                stderr: "1 test failed\n".into(),
+            },
+        ]));
+        let llm = Arc::new(SolveOnlyLlm::default());
+        let service = test_service(&temp.path().join("store"), llm.clone())
+            .with_command_runner(runner.clone());
+        let request = VerifyRequest {
+            domain: "mini".into(),
+            config_path: config_path.clone(),
+        };
+
+        let passed = service.verify(request.clone()).await.unwrap();
+        assert!(passed.passed);
+        assert_eq!(passed.stdout, "ok\n");
+
+        let failed = service.verify(request).await.unwrap();
+        assert!(!failed.passed);
+        assert_eq!(failed.status, Some(2));
+        assert_eq!(failed.stderr, "1 test failed\n");
+
+        assert_eq!(runner.commands(), vec!["./verify.sh", "./verify.sh"]);
+        assert!(llm.prompts.lock().unwrap().is_empty());
+    }
 }
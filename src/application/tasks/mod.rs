@@ -1,19 +1,32 @@
-use std::{collections::HashMap, fmt::Write as _, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    sync::Arc,
+};
 
 use anyhow::{Context as AnyhowContext, Result, anyhow};
 use async_trait::async_trait;
 use serde_json::json;
 use tracing::{debug, info, warn};
 
-use tokio::task::JoinSet;
+use tokio::{sync::Semaphore, task::JoinSet};
+use tokio_util::sync::CancellationToken;
 
 use crate::{
+    apply_ignore::{IGNORE_FILE_NAME, IgnoreRules},
     core::{
+        config::DEFAULT_MAX_FILE_BYTES,
         domain::{
-            AgentConfig, AgentKind, Context, DecompositionProposal, RedFlagIncident, StepStatus,
+            AgentConfig, AgentKind, Context, DecompositionProposal, EmptyPlanPolicy,
+            OutputEncoding, PathExtraction, PendingApply, RedFlagIncident, StepStatus,
+            TemperatureSpread, VoteEnumeration, WorkflowStep,
+        },
+        ports::{
+            Clock, CommandOutput, CommandRunner, FileSystem, LlmClient, LlmOptions, LlmResponse,
+            PromptRenderer,
         },
-        ports::{Clock, FileSystem, LlmClient, LlmOptions, PromptRenderer},
     },
+    diff::{DEFAULT_CONTEXT, unified_diff},
     red_flaggers::{RedFlagMatch, RedFlagPipeline},
     utils::extract_xml_files,
 };
@@ -57,6 +70,15 @@ impl Default for TaskResult {
     }
 }
 
+/// Default cap on simultaneous red-flag evaluations within one sampling batch.
+pub const DEFAULT_RED_FLAG_CONCURRENCY: usize = 4;
+
+/// Flagger name recorded on incidents for samples rejected by `--max-response-bytes`.
+pub const MAX_RESPONSE_BYTES_FLAGGER: &str = "max_response_bytes";
+
+/// Flagger name recorded on incidents for samples the provider cut off at its token limit.
+pub const TRUNCATED_FLAGGER: &str = "truncated";
+
 #[async_trait]
 pub trait MicroTask: Send + Sync {
     async fn run(&self, ctx: &mut Context) -> Result<TaskResult>;
@@ -70,6 +92,15 @@ pub struct DecompositionTask {
     red_flags: Arc<RedFlagPipeline>,
     renderer: Arc<dyn PromptRenderer>,
     clock: Arc<dyn Clock>,
+    min_samples_accepted: Option<usize>,
+    red_flag_concurrency: usize,
+    max_response_bytes: Option<usize>,
+    flag_truncated: bool,
+    prompt_dump: Option<Arc<PromptDump>>,
+    prompt_recorder: Option<Arc<PromptRecorder>>,
+    prompt_vars: BTreeMap<String, String>,
+    empty_plan: EmptyPlanPolicy,
+    cancellation: Option<CancellationToken>,
 }
 
 impl DecompositionTask {
@@ -90,8 +121,72 @@ impl DecompositionTask {
             red_flags,
             renderer,
             clock,
+            min_samples_accepted: None,
+            red_flag_concurrency: DEFAULT_RED_FLAG_CONCURRENCY,
+            max_response_bytes: None,
+            flag_truncated: false,
+            prompt_dump: None,
+            prompt_recorder: None,
+            prompt_vars: BTreeMap::new(),
+            empty_plan: EmptyPlanPolicy::default(),
+            cancellation: None,
         }
     }
+
+    /// Write each rendered prompt to disk for auditing; `None` disables dumping.
+    pub fn with_prompt_dump(mut self, dump: Option<Arc<PromptDump>>) -> Self {
+        self.prompt_dump = dump;
+        self
+    }
+
+    /// Keep each rendered prompt on its step so it is persisted with the session.
+    pub fn with_prompt_recorder(mut self, recorder: Option<Arc<PromptRecorder>>) -> Self {
+        self.prompt_recorder = recorder;
+        self
+    }
+
+    /// Custom template variables (`--prompt-var`), rendered as top-level `{{name}}` values.
+    pub fn with_prompt_vars(mut self, vars: BTreeMap<String, String>) -> Self {
+        self.prompt_vars = vars;
+        self
+    }
+
+    /// Decide what happens when no proposal contains a parseable subtask.
+    pub fn with_empty_plan_policy(mut self, policy: EmptyPlanPolicy) -> Self {
+        self.empty_plan = policy;
+        self
+    }
+
+    /// Accept fewer clean samples than requested, as long as at least `floor` survive red-flagging.
+    pub fn with_min_samples_accepted(mut self, floor: Option<usize>) -> Self {
+        self.min_samples_accepted = floor;
+        self
+    }
+
+    /// Bound how many red-flag evaluations run at once for each sampling batch.
+    pub fn with_red_flag_concurrency(mut self, limit: usize) -> Self {
+        self.red_flag_concurrency = limit;
+        self
+    }
+
+    /// Reject samples longer than `limit` bytes before any red flagger sees them.
+    pub fn with_max_response_bytes(mut self, limit: Option<usize>) -> Self {
+        self.max_response_bytes = limit;
+        self
+    }
+
+    /// Red-flag samples whose generation stopped at the output token limit.
+    pub fn with_flag_truncated(mut self, enabled: bool) -> Self {
+        self.flag_truncated = enabled;
+        self
+    }
+
+    /// Stop before the next LLM batch once `token` is cancelled; the task then fails and the
+    /// runner pauses the session.
+    pub fn with_cancellation(mut self, token: Option<CancellationToken>) -> Self {
+        self.cancellation = token;
+        self
+    }
 }
 
 #[async_trait]
@@ -104,7 +199,15 @@ impl MicroTask for DecompositionTask {
             &self.agent.prompt_template,
             &self.prompt,
             "decomposition",
+            &ctx.domain_data,
+            &self.prompt_vars,
         )?;
+        if let Some(dump) = &self.prompt_dump {
+            dump.record(self.step_id, "decomposition", &rendered_prompt);
+        }
+        if let Some(recorder) = &self.prompt_recorder {
+            recorder.record(ctx, self.step_id, "decomposition", &rendered_prompt);
+        }
         ctx.mark_step_status(self.step_id, StepStatus::Running);
         let responses = SampleCollector::new(
             ctx,
@@ -113,9 +216,18 @@ impl MicroTask for DecompositionTask {
             self.red_flags.clone(),
             "decomposition",
         )
+        .with_min_accepted(self.min_samples_accepted)
+        .with_red_flag_concurrency(self.red_flag_concurrency)
+        .with_max_response_bytes(self.max_response_bytes)
+        .with_flag_truncated(self.flag_truncated)
+        .with_llm_options(llm_options(&self.agent))
+        .with_temperature_spread(self.agent.temperature_spread)
+        .with_max_concurrent(self.agent.max_concurrent)
+        .with_cancellation(self.cancellation.clone())
         .collect(rendered_prompt, samples, &self.agent.model)
         .await?;
 
+        let mut actionable = false;
         let proposals = responses
             .into_iter()
             .enumerate()
@@ -123,6 +235,8 @@ impl MicroTask for DecompositionTask {
                 let mut subtasks = parse_subtasks(&raw);
                 if subtasks.is_empty() {
                     subtasks.push(self.prompt.clone());
+                } else {
+                    actionable = true;
                 }
                 DecompositionProposal::new(idx, raw, subtasks)
             })
@@ -132,8 +246,31 @@ impl MicroTask for DecompositionTask {
             return Err(anyhow!("LLM returned no decomposition proposals"));
         }
 
+        if !actionable {
+            let details = format!(
+                "Decomposition produced no actionable subtasks for step {} ({} proposal(s) fell back to the original prompt)",
+                self.step_id,
+                proposals.len()
+            );
+            match self.empty_plan {
+                EmptyPlanPolicy::Proceed => {}
+                EmptyPlanPolicy::Pause => {
+                    ctx.set_wait_state(self.step_id, "decomposition_empty_plan", details);
+                    return Ok(TaskResult {
+                        action: NextAction::WaitForInput,
+                        effect: TaskEffect::None,
+                    });
+                }
+                EmptyPlanPolicy::Fail => {
+                    ctx.mark_step_status(self.step_id, StepStatus::Failed);
+                    return Err(anyhow!(details));
+                }
+            }
+        }
+
         let duration = self.clock.now_ms().saturating_sub(start_ms);
-        ctx.metrics.record_duration_ms(self.step_id, duration);
+        ctx.metrics
+            .record_duration_ms(self.step_id, "decomposition", duration);
         ctx.register_decomposition(self.step_id, proposals);
         if let Some(step) = ctx.step(self.step_id) {
             debug!(
@@ -146,6 +283,29 @@ impl MicroTask for DecompositionTask {
     }
 }
 
+/// Single-shot decomposition: adopt the step's only proposal as its plan without a vote and
+/// spawn one child per subtask. Returns the new child ids.
+pub fn adopt_sole_proposal(ctx: &mut Context, step_id: usize) -> Result<Vec<usize>> {
+    let proposals = ctx
+        .take_decomposition(step_id)
+        .with_context(|| format!("No proposals available for step {step_id}"))?;
+    let plan = proposals
+        .into_iter()
+        .next()
+        .with_context(|| format!("No proposals available for step {step_id}"))?;
+    let children = plan
+        .subtasks
+        .into_iter()
+        .map(|subtask| ctx.add_child_step(step_id, subtask))
+        .collect::<Vec<_>>();
+    debug!(
+        step_id,
+        children = children.len(),
+        "Single-shot plan adopted"
+    );
+    Ok(children)
+}
+
 pub struct DecompositionVoteTask {
     step_id: usize,
     agent: AgentConfig,
@@ -153,6 +313,12 @@ pub struct DecompositionVoteTask {
     vote_k: usize,
     renderer: Arc<dyn PromptRenderer>,
     clock: Arc<dyn Clock>,
+    prompt_dump: Option<Arc<PromptDump>>,
+    prompt_recorder: Option<Arc<PromptRecorder>>,
+    prompt_vars: BTreeMap<String, String>,
+    enumeration: VoteEnumeration,
+    shuffle_seed: Option<u64>,
+    cancellation: Option<CancellationToken>,
 }
 
 impl DecompositionVoteTask {
@@ -171,44 +337,120 @@ impl DecompositionVoteTask {
             vote_k,
             renderer,
             clock,
+            prompt_dump: None,
+            prompt_recorder: None,
+            prompt_vars: BTreeMap::new(),
+            enumeration: VoteEnumeration::default(),
+            shuffle_seed: None,
+            cancellation: None,
         }
     }
+
+    /// Write each rendered prompt to disk for auditing; `None` disables dumping.
+    pub fn with_prompt_dump(mut self, dump: Option<Arc<PromptDump>>) -> Self {
+        self.prompt_dump = dump;
+        self
+    }
+
+    /// Keep each rendered prompt on its step so it is persisted with the session.
+    pub fn with_prompt_recorder(mut self, recorder: Option<Arc<PromptRecorder>>) -> Self {
+        self.prompt_recorder = recorder;
+        self
+    }
+
+    /// Custom template variables (`--prompt-var`), rendered as top-level `{{name}}` values.
+    pub fn with_prompt_vars(mut self, vars: BTreeMap<String, String>) -> Self {
+        self.prompt_vars = vars;
+        self
+    }
+
+    /// Label options numerically or alphabetically; responses are parsed in the same style.
+    pub fn with_enumeration(mut self, enumeration: VoteEnumeration) -> Self {
+        self.enumeration = enumeration;
+        self
+    }
+
+    /// Present options in an order shuffled from `seed` (mixed with the step id) to counter
+    /// positional bias; votes are mapped back to the original candidates. `None` keeps order.
+    pub fn with_shuffle_seed(mut self, seed: Option<u64>) -> Self {
+        self.shuffle_seed = seed;
+        self
+    }
+
+    /// Stop before the next LLM batch once `token` is cancelled; the task then fails and the
+    /// runner pauses the session.
+    pub fn with_cancellation(mut self, token: Option<CancellationToken>) -> Self {
+        self.cancellation = token;
+        self
+    }
 }
 
 #[async_trait]
 impl MicroTask for DecompositionVoteTask {
     async fn run(&self, ctx: &mut Context) -> Result<TaskResult> {
         let start_ms = self.clock.now_ms();
+        // Check before taking the candidates, so a cancelled vote can be retried on resume
+        ensure_not_cancelled(self.cancellation.as_ref(), self.step_id)?;
         let proposals = ctx
             .take_decomposition(self.step_id)
             .with_context(|| format!("No proposals available for step {}", self.step_id))?;
 
-        let prompt_body = enumerate_options(
-            proposals
-                .iter()
-                .map(|p| p.subtasks.join("\n"))
-                .collect::<Vec<_>>(),
-        );
-        let rendered_prompt = render_prompt(
-            &self.renderer,
-            &self.agent.prompt_template,
-            &prompt_body,
-            "decomposition_vote",
-        )?;
         let samples = self.agent.samples.max(1);
-        let raw_votes = sample_n(
+        let orders = ballot_orders(proposals.len(), self.shuffle_seed, self.step_id, samples);
+        let mut prompts = Vec::with_capacity(samples);
+        for order in &orders {
+            let prompt_body = enumerate_options(
+                order
+                    .iter()
+                    .map(|&idx| proposals[idx].subtasks.join("\n"))
+                    .collect::<Vec<_>>(),
+                self.enumeration,
+            );
+            prompts.push(render_prompt(
+                &self.renderer,
+                &self.agent.prompt_template,
+                &prompt_body,
+                "decomposition_vote",
+                &ctx.domain_data,
+                &self.prompt_vars,
+            )?);
+        }
+        if let Some(dump) = &self.prompt_dump {
+            for prompt in &prompts {
+                dump.record(self.step_id, "decomposition_vote", prompt);
+            }
+        }
+        if let Some(recorder) = &self.prompt_recorder {
+            recorder.record(ctx, self.step_id, "decomposition_vote", &prompts[0]);
+        }
+        let options = llm_options(&self.agent);
+        let raw_votes = match sample_prompts(
             &self.llm,
-            &rendered_prompt,
-            samples,
             self.agent.model.as_str(),
+            prompts.into_iter().map(|prompt| (prompt, options.clone())),
+            self.agent.max_concurrent,
         )
-        .await?;
+        .await
+        {
+            Ok(raw_votes) => raw_votes,
+            Err(err) => {
+                // Put the proposals back, so a vote interrupted mid-sampling can be retried
+                ctx.pending_decompositions.insert(self.step_id, proposals);
+                return Err(err);
+            }
+        };
         let mut votes = Vec::new();
-        for raw in raw_votes {
-            if let Some(choice) = parse_vote_response(&raw, proposals.len()) {
-                votes.push(choice);
+        for (order, raw) in orders.iter().zip(raw_votes) {
+            if let Some(shown) =
+                parse_vote_response(&raw.content, proposals.len(), self.enumeration)
+            {
+                votes.push(order[shown]);
             }
         }
+        if self.shuffle_seed.is_some() {
+            ctx.metrics
+                .record_vote_orders(self.step_id, "decomposition_vote", orders);
+        }
 
         let k = self.vote_k.max(1);
         let winner_idx = first_to_ahead_by_k(&votes, k)
@@ -223,7 +465,8 @@ impl MicroTask for DecompositionVoteTask {
             runner_up_votes,
         );
         let duration = self.clock.now_ms().saturating_sub(start_ms);
-        ctx.metrics.record_duration_ms(self.step_id, duration);
+        ctx.metrics
+            .record_duration_ms(self.step_id, "decomposition_vote", duration);
         let winner = proposals[winner_idx].clone();
         let mut new_steps = Vec::new();
         for subtask in winner.subtasks.iter() {
@@ -253,6 +496,15 @@ pub struct SolveTask {
     red_flags: Arc<RedFlagPipeline>,
     renderer: Arc<dyn PromptRenderer>,
     clock: Arc<dyn Clock>,
+    min_samples_accepted: Option<usize>,
+    red_flag_concurrency: usize,
+    max_response_bytes: Option<usize>,
+    flag_truncated: bool,
+    retry_on_empty: usize,
+    prompt_dump: Option<Arc<PromptDump>>,
+    prompt_recorder: Option<Arc<PromptRecorder>>,
+    prompt_vars: BTreeMap<String, String>,
+    cancellation: Option<CancellationToken>,
 }
 
 impl SolveTask {
@@ -271,8 +523,74 @@ impl SolveTask {
             red_flags,
             renderer,
             clock,
+            min_samples_accepted: None,
+            red_flag_concurrency: DEFAULT_RED_FLAG_CONCURRENCY,
+            max_response_bytes: None,
+            flag_truncated: false,
+            retry_on_empty: 0,
+            prompt_dump: None,
+            prompt_recorder: None,
+            prompt_vars: BTreeMap::new(),
+            cancellation: None,
         }
     }
+
+    /// Write each rendered prompt to disk for auditing; `None` disables dumping.
+    pub fn with_prompt_dump(mut self, dump: Option<Arc<PromptDump>>) -> Self {
+        self.prompt_dump = dump;
+        self
+    }
+
+    /// Keep each rendered prompt on its step so it is persisted with the session.
+    pub fn with_prompt_recorder(mut self, recorder: Option<Arc<PromptRecorder>>) -> Self {
+        self.prompt_recorder = recorder;
+        self
+    }
+
+    /// Custom template variables (`--prompt-var`), rendered as top-level `{{name}}` values.
+    pub fn with_prompt_vars(mut self, vars: BTreeMap<String, String>) -> Self {
+        self.prompt_vars = vars;
+        self
+    }
+
+    /// Drop blank responses and request up to `retries` more batches when none are left, then
+    /// fail the step. `0` (the default) keeps blank responses as candidates. Red-flagged samples
+    /// are resampled separately.
+    pub fn with_retry_on_empty(mut self, retries: usize) -> Self {
+        self.retry_on_empty = retries;
+        self
+    }
+
+    /// Accept fewer clean samples than requested, as long as at least `floor` survive red-flagging.
+    pub fn with_min_samples_accepted(mut self, floor: Option<usize>) -> Self {
+        self.min_samples_accepted = floor;
+        self
+    }
+
+    /// Bound how many red-flag evaluations run at once for each sampling batch.
+    pub fn with_red_flag_concurrency(mut self, limit: usize) -> Self {
+        self.red_flag_concurrency = limit;
+        self
+    }
+
+    /// Reject samples longer than `limit` bytes before any red flagger sees them.
+    pub fn with_max_response_bytes(mut self, limit: Option<usize>) -> Self {
+        self.max_response_bytes = limit;
+        self
+    }
+
+    /// Red-flag samples whose generation stopped at the output token limit.
+    pub fn with_flag_truncated(mut self, enabled: bool) -> Self {
+        self.flag_truncated = enabled;
+        self
+    }
+
+    /// Stop before the next LLM batch once `token` is cancelled; the task then fails and the
+    /// runner pauses the session.
+    pub fn with_cancellation(mut self, token: Option<CancellationToken>) -> Self {
+        self.cancellation = token;
+        self
+    }
 }
 
 #[async_trait]
@@ -282,27 +600,74 @@ impl MicroTask for SolveTask {
         let step = ctx
             .step(self.step_id)
             .with_context(|| format!("Unknown step {}", self.step_id))?;
-        let prompt = render_prompt(
-            &self.renderer,
-            &self.agent.prompt_template,
+        let mut data = prompt_data(
             &step.description,
             "solve",
-        )?;
+            &ctx.domain_data,
+            &self.prompt_vars,
+        );
+        if let Some(feedback) = &step.verifier_feedback {
+            data["verifier_feedback"] = json!(feedback);
+        }
+        let prompt = self
+            .renderer
+            .render(&self.agent.prompt_template, &data)
+            .context("Failed to render prompt template for role 'solve'")?;
         let samples = self.agent.samples.max(1);
-        let responses = SampleCollector::new(
-            ctx,
-            self.step_id,
-            self.llm.clone(),
-            self.red_flags.clone(),
-            "solve",
-        )
-        .collect(prompt, samples, &self.agent.model)
-        .await?;
-        if responses.is_empty() {
-            return Err(anyhow!("Solver agent produced no candidates"));
+        if let Some(dump) = &self.prompt_dump {
+            dump.record(self.step_id, "solve", &prompt);
+        }
+        if let Some(recorder) = &self.prompt_recorder {
+            recorder.record(ctx, self.step_id, "solve", &prompt);
         }
+        let mut empty_batches = 0usize;
+        let responses = loop {
+            let responses = SampleCollector::new(
+                ctx,
+                self.step_id,
+                self.llm.clone(),
+                self.red_flags.clone(),
+                "solve",
+            )
+            .with_min_accepted(self.min_samples_accepted)
+            .with_red_flag_concurrency(self.red_flag_concurrency)
+            .with_max_response_bytes(self.max_response_bytes)
+            .with_flag_truncated(self.flag_truncated)
+            .with_llm_options(llm_options(&self.agent))
+            .with_temperature_spread(self.agent.temperature_spread)
+            .with_max_concurrent(self.agent.max_concurrent)
+            .with_cancellation(self.cancellation.clone())
+            .collect(prompt.clone(), samples, &self.agent.model)
+            .await?;
+            // Without the guard, blank answers are candidates like any other
+            if self.retry_on_empty == 0 {
+                break responses;
+            }
+            let responses: Vec<String> = responses
+                .into_iter()
+                .filter(|response| !response.trim().is_empty())
+                .collect();
+            // Red-flagged batches never get here empty; this is the LLM answering with nothing.
+            if !responses.is_empty() {
+                break responses;
+            }
+            empty_batches += 1;
+            if empty_batches > self.retry_on_empty {
+                return Err(anyhow!(
+                    "Solver agent produced no candidates: {empty_batches} batch(es) came back empty"
+                ));
+            }
+            warn!(
+                step_id = self.step_id,
+                retry = empty_batches,
+                retry_on_empty = self.retry_on_empty,
+                "Solver returned only empty responses; resampling"
+            );
+            ctx.metrics.record_resample(self.step_id);
+        };
         let duration = self.clock.now_ms().saturating_sub(start_ms);
-        ctx.metrics.record_duration_ms(self.step_id, duration);
+        ctx.metrics
+            .record_duration_ms(self.step_id, "solve", duration);
         ctx.register_solutions(self.step_id, responses);
         debug!(
             step_id = self.step_id,
@@ -321,6 +686,13 @@ pub struct SolutionVoteTask {
     vote_k: usize,
     renderer: Arc<dyn PromptRenderer>,
     clock: Arc<dyn Clock>,
+    prompt_dump: Option<Arc<PromptDump>>,
+    prompt_recorder: Option<Arc<PromptRecorder>>,
+    prompt_vars: BTreeMap<String, String>,
+    dedupe_candidates: bool,
+    enumeration: VoteEnumeration,
+    shuffle_seed: Option<u64>,
+    cancellation: Option<CancellationToken>,
 }
 
 impl SolutionVoteTask {
@@ -339,33 +711,131 @@ impl SolutionVoteTask {
             vote_k,
             renderer,
             clock,
+            prompt_dump: None,
+            prompt_recorder: None,
+            prompt_vars: BTreeMap::new(),
+            dedupe_candidates: false,
+            enumeration: VoteEnumeration::default(),
+            shuffle_seed: None,
+            cancellation: None,
         }
     }
+
+    /// Write each rendered prompt to disk for auditing; `None` disables dumping.
+    pub fn with_prompt_dump(mut self, dump: Option<Arc<PromptDump>>) -> Self {
+        self.prompt_dump = dump;
+        self
+    }
+
+    /// Keep each rendered prompt on its step so it is persisted with the session.
+    pub fn with_prompt_recorder(mut self, recorder: Option<Arc<PromptRecorder>>) -> Self {
+        self.prompt_recorder = recorder;
+        self
+    }
+
+    /// Custom template variables (`--prompt-var`), rendered as top-level `{{name}}` values.
+    pub fn with_prompt_vars(mut self, vars: BTreeMap<String, String>) -> Self {
+        self.prompt_vars = vars;
+        self
+    }
+
+    /// Present candidates that match after whitespace normalization as a single option whose
+    /// votes count once per duplicate.
+    pub fn with_dedupe_candidates(mut self, dedupe: bool) -> Self {
+        self.dedupe_candidates = dedupe;
+        self
+    }
+
+    /// Label options numerically or alphabetically; responses are parsed in the same style.
+    pub fn with_enumeration(mut self, enumeration: VoteEnumeration) -> Self {
+        self.enumeration = enumeration;
+        self
+    }
+
+    /// Present options in an order shuffled from `seed` (mixed with the step id) to counter
+    /// positional bias; votes are mapped back to the original candidates. `None` keeps order.
+    pub fn with_shuffle_seed(mut self, seed: Option<u64>) -> Self {
+        self.shuffle_seed = seed;
+        self
+    }
+
+    /// Stop before the next LLM batch once `token` is cancelled; the task then fails and the
+    /// runner pauses the session.
+    pub fn with_cancellation(mut self, token: Option<CancellationToken>) -> Self {
+        self.cancellation = token;
+        self
+    }
 }
 
 #[async_trait]
 impl MicroTask for SolutionVoteTask {
     async fn run(&self, ctx: &mut Context) -> Result<TaskResult> {
         let start_ms = self.clock.now_ms();
-        let solutions = ctx
+        // Check before taking the candidates, so a cancelled vote can be retried on resume
+        ensure_not_cancelled(self.cancellation.as_ref(), self.step_id)?;
+        let candidates = ctx
             .take_solutions(self.step_id)
             .with_context(|| format!("No solutions queued for step {}", self.step_id))?;
-        let prompt_body = enumerate_options(solutions.clone());
-        let vote_prompt = render_prompt(
-            &self.renderer,
-            &self.agent.prompt_template,
-            &prompt_body,
-            "solution_vote",
-        )?;
+        let (solutions, weights) = if self.dedupe_candidates {
+            dedupe_candidates(candidates.clone())
+        } else {
+            let weights = vec![1; candidates.len()];
+            (candidates.clone(), weights)
+        };
         let samples = self.agent.samples.max(1);
-        let raw_votes =
-            sample_n(&self.llm, &vote_prompt, samples, self.agent.model.as_str()).await?;
+        let orders = ballot_orders(solutions.len(), self.shuffle_seed, self.step_id, samples);
+        let mut prompts = Vec::with_capacity(samples);
+        for order in &orders {
+            let prompt_body = enumerate_options(
+                order.iter().map(|&idx| solutions[idx].clone()).collect(),
+                self.enumeration,
+            );
+            prompts.push(render_prompt(
+                &self.renderer,
+                &self.agent.prompt_template,
+                &prompt_body,
+                "solution_vote",
+                &ctx.domain_data,
+                &self.prompt_vars,
+            )?);
+        }
+        if let Some(dump) = &self.prompt_dump {
+            for prompt in &prompts {
+                dump.record(self.step_id, "solution_vote", prompt);
+            }
+        }
+        if let Some(recorder) = &self.prompt_recorder {
+            recorder.record(ctx, self.step_id, "solution_vote", &prompts[0]);
+        }
+        let options = llm_options(&self.agent);
+        let raw_votes = match sample_prompts(
+            &self.llm,
+            self.agent.model.as_str(),
+            prompts.into_iter().map(|prompt| (prompt, options.clone())),
+            self.agent.max_concurrent,
+        )
+        .await
+        {
+            Ok(raw_votes) => raw_votes,
+            Err(err) => {
+                // Put the candidates back, so a vote interrupted mid-sampling can be retried
+                ctx.pending_solutions.insert(self.step_id, candidates);
+                return Err(err);
+            }
+        };
         let mut votes = Vec::new();
-        for raw in raw_votes {
-            if let Some(choice) = parse_vote_response(&raw, solutions.len()) {
-                votes.push(choice);
+        for (order, raw) in orders.iter().zip(raw_votes) {
+            if let Some(shown) =
+                parse_vote_response(&raw.content, solutions.len(), self.enumeration)
+            {
+                let choice = order[shown];
+                votes.extend(std::iter::repeat_n(choice, weights[choice]));
             }
         }
+        if self.shuffle_seed.is_some() {
+            ctx.metrics
+                .record_vote_orders(self.step_id, "solution_vote", orders);
+        }
         let k = self.vote_k.max(1);
         let winner_idx = first_to_ahead_by_k(&votes, k)
             .or_else(|| majority_vote(&votes))
@@ -378,8 +848,19 @@ impl MicroTask for SolutionVoteTask {
             winner_votes,
             runner_up_votes,
         );
+        let histogram = if self.dedupe_candidates {
+            candidate_histogram(
+                &candidates,
+                &solutions,
+                &tally_votes(&votes, solutions.len()),
+            )
+        } else {
+            tally_votes(&votes, solutions.len())
+        };
+        ctx.metrics.record_vote_histogram(self.step_id, histogram);
         let duration = self.clock.now_ms().saturating_sub(start_ms);
-        ctx.metrics.record_duration_ms(self.step_id, duration);
+        ctx.metrics
+            .record_duration_ms(self.step_id, "solution_vote", duration);
         let winner = solutions[winner_idx].clone();
         ctx.mark_step_solution(self.step_id, winner);
         debug!(
@@ -396,12 +877,44 @@ impl MicroTask for SolutionVoteTask {
     }
 }
 
+/// Files an apply would write, with a line diff against their current contents.
+#[derive(Debug, Clone, Default)]
+pub struct ApplyPreview {
+    pub files: Vec<std::path::PathBuf>,
+    pub diff: String,
+}
+
+impl ApplyPreview {
+    /// Human-readable summary suitable for wait-state details.
+    pub fn describe(&self) -> String {
+        let files = self
+            .files
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!(
+            "Pending apply will write {} file(s): {files}\n\n{}",
+            self.files.len(),
+            self.diff
+        )
+    }
+}
+
 pub struct ApplyVerifyTask {
     step_id: usize,
     applier: Option<String>,
     verifier: Option<String>,
+    pre_verifier: Option<String>,
     file_system: Arc<dyn FileSystem>,
     clock: Arc<dyn Clock>,
+    command_runner: Arc<dyn CommandRunner>,
+    output_encoding: OutputEncoding,
+    path_extraction: PathExtraction,
+    path_pattern: Option<String>,
+    llm_verifier: Option<Arc<LlmVerifier>>,
+    ignore_rules: Arc<IgnoreRules>,
+    max_file_bytes: usize,
 }
 
 impl ApplyVerifyTask {
@@ -411,14 +924,177 @@ impl ApplyVerifyTask {
         verifier: Option<String>,
         file_system: Arc<dyn FileSystem>,
         clock: Arc<dyn Clock>,
+        command_runner: Arc<dyn CommandRunner>,
     ) -> Self {
         Self {
             step_id,
             applier,
             verifier,
+            pre_verifier: None,
             file_system,
             clock,
+            command_runner,
+            output_encoding: OutputEncoding::default(),
+            path_extraction: PathExtraction::default(),
+            path_pattern: None,
+            llm_verifier: None,
+            ignore_rules: Arc::default(),
+            max_file_bytes: DEFAULT_MAX_FILE_BYTES,
+        }
+    }
+
+    /// Largest file `overwrite_file` may write; a bigger one fails the step before any write.
+    pub fn with_max_file_bytes(mut self, max_file_bytes: usize) -> Self {
+        self.max_file_bytes = max_file_bytes;
+        self
+    }
+
+    /// Line endings and BOM applied to files written by `overwrite_file`.
+    pub fn with_output_encoding(mut self, encoding: OutputEncoding) -> Self {
+        self.output_encoding = encoding;
+        self
+    }
+
+    /// How `overwrite_file` finds the target path when a solution has no `<file>` blocks;
+    /// `pattern` is only used by `PathExtraction::Regex`.
+    pub fn with_path_extraction(
+        mut self,
+        strategy: PathExtraction,
+        pattern: Option<String>,
+    ) -> Self {
+        self.path_extraction = strategy;
+        self.path_pattern = pattern;
+        self
+    }
+
+    /// Command that must succeed before anything is applied; on failure the step fails untouched.
+    pub fn with_pre_verifier(mut self, command: Option<String>) -> Self {
+        self.pre_verifier = command;
+        self
+    }
+
+    /// Ask an LLM judge to pass or fail the applied solution; runs after any shell verifier.
+    pub fn with_llm_verifier(mut self, verifier: Option<Arc<LlmVerifier>>) -> Self {
+        self.llm_verifier = verifier;
+        self
+    }
+
+    /// Paths `overwrite_file` must leave alone (from `.microfactoryignore`); matching targets
+    /// are skipped and listed in the step's `skipped_paths`.
+    pub fn with_ignore_rules(mut self, rules: Arc<IgnoreRules>) -> Self {
+        self.ignore_rules = rules;
+        self
+    }
+
+    /// Compute the writes `run` would perform for `overwrite_file` without touching the disk.
+    pub fn preview(&self, ctx: &Context) -> Result<ApplyPreview> {
+        let step = ctx
+            .step(self.step_id)
+            .with_context(|| format!("Unknown step {}", self.step_id))?;
+        if self.applier.as_deref() != Some("overwrite_file") {
+            return Ok(ApplyPreview::default());
+        }
+
+        let mut preview = ApplyPreview::default();
+        for (path_str, content) in self.planned_file_writes(step) {
+            let safe_path = match validate_target_path(&path_str) {
+                Ok(path) => path,
+                Err(err) => {
+                    let _ = writeln!(preview.diff, "! {path_str}: {err}");
+                    continue;
+                }
+            };
+            if self.ignore_rules.is_ignored(&safe_path) {
+                let _ = writeln!(preview.diff, "! {path_str}: ignored by {IGNORE_FILE_NAME}");
+                continue;
+            }
+            if content.len() > self.max_file_bytes {
+                let _ = writeln!(
+                    preview.diff,
+                    "! {path_str}: {} bytes exceeds max_file_bytes ({})",
+                    content.len(),
+                    self.max_file_bytes
+                );
+                continue;
+            }
+            let real_path = match &ctx.output_dir {
+                Some(root) => root.join(&safe_path),
+                None => safe_path,
+            };
+            // A file that cannot be read is previewed as new, like a missing one
+            let current = if self.file_system.exists(&real_path) {
+                self.file_system
+                    .read_to_string(&real_path)
+                    .unwrap_or_else(|err| {
+                        warn!(
+                            step_id = self.step_id,
+                            path = %real_path.display(),
+                            error = %err,
+                            "Cannot read the current file; previewing it as new"
+                        );
+                        String::new()
+                    })
+            } else {
+                String::new()
+            };
+            let _ = writeln!(preview.diff, "--- {}", real_path.display());
+            let _ = writeln!(preview.diff, "+++ {}", real_path.display());
+            preview
+                .diff
+                .push_str(&unified_diff(&current, &content, DEFAULT_CONTEXT));
+            preview.files.push(real_path);
+        }
+        Ok(preview)
+    }
+
+    /// Files `overwrite_file` would write for a step: XML `<file>` blocks when present, otherwise
+    /// a single file whose path comes from the step description.
+    fn planned_file_writes(&self, step: &WorkflowStep) -> Vec<(String, String)> {
+        let Some(solution) = step.winning_solution.as_deref() else {
+            return Vec::new();
+        };
+        let files = extract_xml_files(solution);
+        if !files.is_empty() {
+            return files;
+        }
+        self.description_target_path(&step.description)
+            .map(|path| vec![(path, extract_code_content(solution))])
+            .unwrap_or_default()
+    }
+
+    /// Target path for a solution without `<file>` blocks, per the configured strategy.
+    fn description_target_path(&self, description: &str) -> Option<String> {
+        match self.path_extraction {
+            PathExtraction::Xml => None,
+            PathExtraction::Heuristic => extract_target_path(description),
+            PathExtraction::Regex => {
+                extract_target_path_with(self.path_pattern.as_deref()?, description)
+            }
+        }
+    }
+
+    /// Whether `content` is too large to write to `path`; logs the path and size when it is.
+    fn exceeds_max_file_bytes(&self, path: &str, content: &str) -> bool {
+        if content.len() <= self.max_file_bytes {
+            return false;
         }
+        warn!(
+            step_id = self.step_id,
+            path,
+            bytes = content.len(),
+            max_file_bytes = self.max_file_bytes,
+            "File block exceeds max_file_bytes; refusing to apply the step"
+        );
+        true
+    }
+
+    fn skip_ignored(&self, path: std::path::PathBuf, skipped: &mut Vec<std::path::PathBuf>) {
+        info!(
+            step_id = self.step_id,
+            path = %path.display(),
+            "Skipping write matched by {IGNORE_FILE_NAME}"
+        );
+        skipped.push(path);
     }
 
     fn resolve_real_path(&self, ctx: &Context, safe_path: &std::path::Path) -> std::path::PathBuf {
@@ -446,10 +1122,11 @@ impl MicroTask for ApplyVerifyTask {
             .step(self.step_id)
             .with_context(|| format!("Unknown step {}", self.step_id))?;
 
-        let _solution = step
+        let solution = step
             .winning_solution
             .clone()
             .ok_or_else(|| anyhow!("No winning solution to apply for step {}", self.step_id))?;
+        let description = step.description.clone();
 
         if ctx.dry_run {
             info!(step_id = self.step_id, "Dry run: skipping apply/verify");
@@ -459,7 +1136,62 @@ impl MicroTask for ApplyVerifyTask {
             }));
         }
 
+        if ctx.dry_run_apply {
+            let preview = self.preview(ctx)?;
+            info!(
+                step_id = self.step_id,
+                files = preview.files.len(),
+                "Dry-run apply: recording diff instead of writing"
+            );
+            if let Some(step) = ctx.step_mut(self.step_id) {
+                step.pending_apply = Some(PendingApply {
+                    files: preview.files,
+                    diff: preview.diff,
+                });
+            }
+            ctx.mark_step_status(self.step_id, StepStatus::Completed);
+            return Ok(TaskResult::continue_with(TaskEffect::StepCompleted {
+                step_id: self.step_id,
+            }));
+        }
+
+        ctx.mark_step_status(self.step_id, StepStatus::Applying);
+        if let Some(pre_cmd) = &self.pre_verifier {
+            info!(
+                step_id = self.step_id,
+                command = pre_cmd,
+                "Running pre-apply check"
+            );
+            let failure = match self.command_runner.run(pre_cmd) {
+                Ok(output) if output.success() => None,
+                Ok(output) => {
+                    warn!(
+                        step_id = self.step_id,
+                        status = ?output.status,
+                        stderr = %output.stderr,
+                        "Pre-apply check failed; skipping apply"
+                    );
+                    Some(format!(
+                        "Pre-verifier `{pre_cmd}` exited with {:?}",
+                        output.status
+                    ))
+                }
+                Err(e) => {
+                    warn!(step_id = self.step_id, error = ?e, "Failed to execute pre-verifier");
+                    Some(format!("Failed to execute pre-verifier `{pre_cmd}`: {e}"))
+                }
+            };
+            let metrics = ctx.step_metrics_mut(self.step_id);
+            metrics.pre_verification_passed = Some(failure.is_none());
+            if let Some(reason) = failure {
+                metrics.pre_verification_reason = Some(reason);
+                ctx.mark_step_status(self.step_id, StepStatus::Failed);
+                return Ok(TaskResult::continue_with(TaskEffect::None));
+            }
+        }
+
         // Apply
+        let mut skipped_paths = Vec::new();
         if let Some(applier_cmd) = &self.applier {
             if applier_cmd == "patch_file" {
                 info!(
@@ -467,13 +1199,27 @@ impl MicroTask for ApplyVerifyTask {
                     "Applying solution via built-in patch_file (mock)"
                 );
             } else if applier_cmd == "overwrite_file" {
-                let solution = step.winning_solution.as_ref().unwrap();
-                let files = extract_xml_files(solution);
+                let files = extract_xml_files(&solution);
+                // Ignored targets are never written, so only the others count against the cap.
+                let oversized = files
+                    .iter()
+                    .filter(|(path, _)| {
+                        !validate_target_path(path).is_ok_and(|p| self.ignore_rules.is_ignored(&p))
+                    })
+                    .filter(|(path, content)| self.exceeds_max_file_bytes(path, content))
+                    .count();
+                if oversized > 0 {
+                    ctx.mark_step_status(self.step_id, StepStatus::Failed);
+                    return Ok(TaskResult::continue_with(TaskEffect::None));
+                }
 
                 if !files.is_empty() {
                     let mut success = true;
                     for (path_str, content) in files {
                         match validate_target_path(&path_str) {
+                            Ok(safe_path) if self.ignore_rules.is_ignored(&safe_path) => {
+                                self.skip_ignored(safe_path, &mut skipped_paths);
+                            }
                             Ok(safe_path) => {
                                 let real_path = self.resolve_real_path(ctx, &safe_path);
                                 if let Some(parent) = real_path.parent()
@@ -488,7 +1234,8 @@ impl MicroTask for ApplyVerifyTask {
                                     success = false;
                                     continue;
                                 }
-                                match self.file_system.write(&real_path, &content) {
+                                let encoded = self.output_encoding.encode(&content);
+                                match self.file_system.write(&real_path, &encoded) {
                                     Ok(_) => {
                                         info!(
                                             step_id = self.step_id,
@@ -523,13 +1270,19 @@ impl MicroTask for ApplyVerifyTask {
                         return Ok(TaskResult::continue_with(TaskEffect::None));
                     }
                 } else {
-                    // Fallback to legacy single-file heuristic
-                    let target_path = extract_target_path(&step.description);
+                    // No `<file>` blocks: fall back to the domain's path extraction strategy
+                    let target_path = self.description_target_path(&description);
                     if let Some(path_str) = target_path {
                         match validate_target_path(&path_str) {
+                            Ok(safe_path) if self.ignore_rules.is_ignored(&safe_path) => {
+                                self.skip_ignored(safe_path, &mut skipped_paths);
+                            }
                             Ok(safe_path) => {
-                                let content =
-                                    extract_code_content(step.winning_solution.as_ref().unwrap());
+                                let content = extract_code_content(&solution);
+                                if self.exceeds_max_file_bytes(&path_str, &content) {
+                                    ctx.mark_step_status(self.step_id, StepStatus::Failed);
+                                    return Ok(TaskResult::continue_with(TaskEffect::None));
+                                }
 
                                 let real_path = self.resolve_real_path(ctx, &safe_path);
                                 if let Some(parent) = real_path.parent()
@@ -544,9 +1297,10 @@ impl MicroTask for ApplyVerifyTask {
                                     ctx.mark_step_status(self.step_id, StepStatus::Failed);
                                     return Ok(TaskResult::continue_with(TaskEffect::None));
                                 }
-                                match self.file_system.write(&real_path, &content) {
+                                let encoded = self.output_encoding.encode(&content);
+                                match self.file_system.write(&real_path, &encoded) {
                                     Ok(_) => {
-                                        info!(step_id = self.step_id, path = %real_path.display(), "Overwrote file (legacy heuristic)");
+                                        info!(step_id = self.step_id, path = %real_path.display(), strategy = ?self.path_extraction, "Overwrote file (description path)");
                                     }
                                     Err(err) => {
                                         warn!(step_id = self.step_id, path = %real_path.display(), error = ?err, "Failed to overwrite file");
@@ -569,7 +1323,8 @@ impl MicroTask for ApplyVerifyTask {
                     } else {
                         warn!(
                             step_id = self.step_id,
-                            description = %step.description,
+                            description = %description,
+                            strategy = ?self.path_extraction,
                             "Could not determine target file path from description for overwrite_file"
                         );
                         ctx.mark_step_status(self.step_id, StepStatus::Failed);
@@ -584,39 +1339,62 @@ impl MicroTask for ApplyVerifyTask {
                 );
             }
         }
+        if let Some(step) = ctx.step_mut(self.step_id) {
+            step.skipped_paths = skipped_paths;
+        }
 
         let mut verified = true;
+        let mut reason = None;
+        let mut feedback = None;
         if let Some(verifier_cmd) = &self.verifier {
             info!(
                 step_id = self.step_id,
                 command = verifier_cmd,
                 "Running verification"
             );
-            match std::process::Command::new("sh")
-                .arg("-c")
-                .arg(verifier_cmd)
-                .output()
-            {
+            match self.command_runner.run(verifier_cmd) {
                 Ok(output) => {
-                    verified = output.status.success();
+                    verified = output.success();
                     if !verified {
                         warn!(
                             step_id = self.step_id,
-                            stderr = ?String::from_utf8_lossy(&output.stderr),
+                            status = ?output.status,
+                            stderr = %output.stderr,
                             "Verification failed"
                         );
+                        reason = Some(format!("`{verifier_cmd}` exited with {:?}", output.status));
+                        feedback = Some(verifier_feedback(verifier_cmd, &output));
                     }
                 }
                 Err(e) => {
                     warn!(step_id = self.step_id, error = ?e, "Failed to execute verifier");
                     verified = false;
+                    reason = Some(format!("Failed to execute `{verifier_cmd}`: {e}"));
                 }
             }
         }
 
+        if verified && let Some(llm_verifier) = &self.llm_verifier {
+            let verdict = llm_verifier
+                .verify(&description, &solution, &ctx.domain_data)
+                .await?;
+            if let Err(why) = verdict {
+                warn!(step_id = self.step_id, reason = %why, "LLM verifier rejected the solution");
+                verified = false;
+                feedback = Some(format!("The LLM verifier rejected the solution: {why}"));
+                reason = Some(why);
+            }
+        }
+
         let duration = self.clock.now_ms().saturating_sub(start_ms);
-        ctx.metrics.record_duration_ms(self.step_id, duration);
-        ctx.step_metrics_mut(self.step_id).verification_passed = Some(verified);
+        ctx.metrics
+            .record_duration_ms(self.step_id, "apply_verify", duration);
+        let metrics = ctx.step_metrics_mut(self.step_id);
+        metrics.verification_passed = Some(verified);
+        metrics.verification_reason = reason;
+        if let Some(step) = ctx.step_mut(self.step_id) {
+            step.verifier_feedback = feedback;
+        }
 
         if verified {
             ctx.mark_step_status(self.step_id, StepStatus::Completed);
@@ -630,47 +1408,320 @@ impl MicroTask for ApplyVerifyTask {
     }
 }
 
-fn render_prompt(
-    renderer: &Arc<dyn PromptRenderer>,
-    template: &str,
-    body: &str,
-    role: &str,
-) -> Result<String> {
-    let data = json!({
-        "prompt": body,
-        "task": body,
-        "role": role,
-    });
+/// Largest verifier output kept as feedback; longer output keeps its tail, where failures
+/// are usually reported.
+const MAX_VERIFIER_FEEDBACK_BYTES: usize = 4 * 1024;
+
+/// Failed verifier command, exit status, and captured output for the next solve prompt.
+fn verifier_feedback(command: &str, output: &CommandOutput) -> String {
+    let mut captured = String::new();
+    for stream in [output.stderr.trim(), output.stdout.trim()] {
+        if !stream.is_empty() {
+            if !captured.is_empty() {
+                captured.push('\n');
+            }
+            captured.push_str(stream);
+        }
+    }
+    if captured.len() > MAX_VERIFIER_FEEDBACK_BYTES {
+        let mut start = captured.len() - MAX_VERIFIER_FEEDBACK_BYTES;
+        while !captured.is_char_boundary(start) {
+            start += 1;
+        }
+        captured = format!("[... truncated]\n{}", &captured[start..]);
+    }
+    format!("`{command}` exited with {:?}\n{captured}", output.status)
+        .trim_end()
+        .to_string()
+}
 
-    renderer
-        .render(template, &data)
-        .with_context(|| format!("Failed to render prompt template for role '{role}'"))
-        .map_err(|e| anyhow!(e))
+/// LLM judge for applied solutions. The verifier agent's template sees the step description as
+/// `{{task}}` and the winning solution as `{{solution}}`, and must answer `PASS` or `FAIL: <reason>`.
+pub struct LlmVerifier {
+    agent: AgentConfig,
+    llm: Arc<dyn LlmClient>,
+    renderer: Arc<dyn PromptRenderer>,
+    prompt_vars: BTreeMap<String, String>,
 }
 
-async fn sample_n(
-    llm: &Arc<dyn LlmClient>,
-    prompt: &str,
-    n: usize,
-    model: &str,
-) -> Result<Vec<String>> {
-    let mut join_set = JoinSet::new();
-    for _ in 0..n {
-        let llm = llm.clone();
-        let prompt = prompt.to_string();
-        let model = model.to_string();
-        join_set.spawn(async move {
-            llm.chat_completion(&model, &prompt, &LlmOptions::default())
-                .await
-        });
+impl LlmVerifier {
+    pub fn new(
+        agent: AgentConfig,
+        llm: Arc<dyn LlmClient>,
+        renderer: Arc<dyn PromptRenderer>,
+    ) -> Self {
+        Self {
+            agent,
+            llm,
+            renderer,
+            prompt_vars: BTreeMap::new(),
+        }
+    }
+
+    /// Custom template variables (`--prompt-var`), rendered as top-level `{{name}}` values.
+    pub fn with_prompt_vars(mut self, vars: BTreeMap<String, String>) -> Self {
+        self.prompt_vars = vars;
+        self
+    }
+
+    /// `Ok(Ok(()))` on PASS, `Ok(Err(reason))` on FAIL or an unparseable verdict.
+    async fn verify(
+        &self,
+        task: &str,
+        solution: &str,
+        domain_data: &HashMap<String, String>,
+    ) -> Result<std::result::Result<(), String>> {
+        let mut data = prompt_data(task, "verify", domain_data, &self.prompt_vars);
+        data["solution"] = json!(solution);
+        let prompt = self
+            .renderer
+            .render(&self.agent.prompt_template, &data)
+            .context("Failed to render prompt template for role 'verify'")?;
+        let response = self
+            .llm
+            .chat_completion(&self.agent.model, &prompt, &llm_options(&self.agent))
+            .await?;
+        Ok(parse_verdict(&response))
+    }
+}
+
+/// Read the first non-empty line as `PASS[ED]` or `FAIL[ED][: reason]`; anything else fails.
+fn parse_verdict(response: &str) -> std::result::Result<(), String> {
+    let line = response
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    let word_end = line
+        .find(|ch: char| !ch.is_ascii_alphabetic())
+        .unwrap_or(line.len());
+    let (verdict, rest) = line.split_at(word_end);
+    match verdict.to_ascii_uppercase().as_str() {
+        "PASS" | "PASSED" => Ok(()),
+        "FAIL" | "FAILED" => {
+            let reason = rest.trim_start_matches([':', '-', ' ']).trim();
+            Err(if reason.is_empty() {
+                "LLM verifier reported FAIL".to_string()
+            } else {
+                reason.to_string()
+            })
+        }
+        _ => Err(format!("Unrecognized verifier verdict: {line}")),
+    }
+}
+
+/// Writes each distinct rendered prompt once, as `step-<id>-<role>-attempt-<n>.txt`, so runs can
+/// be audited. Top-up and retry batches reuse their prompt's file; a prompt that differs from
+/// every file already written for the step and role (a re-solve with verifier feedback, another
+/// voter's shuffled options) takes the next attempt number. Write failures are logged and never
+/// abort the workflow.
+pub struct PromptDump {
+    file_system: Arc<dyn FileSystem>,
+    dir: std::path::PathBuf,
+}
+
+impl PromptDump {
+    pub fn new(file_system: Arc<dyn FileSystem>, dir: std::path::PathBuf) -> Self {
+        Self { file_system, dir }
+    }
+
+    fn record(&self, step_id: usize, role: &str, prompt: &str) {
+        if let Err(err) = self.file_system.create_dir_all(&self.dir) {
+            warn!(path = %self.dir.display(), error = %err, "Failed to create prompt dump directory");
+            return;
+        }
+        for attempt in 1.. {
+            let path = self
+                .dir
+                .join(format!("step-{step_id:03}-{role}-attempt-{attempt:02}.txt"));
+            if !self.file_system.exists(&path) {
+                if let Err(err) = self.file_system.write(&path, prompt) {
+                    warn!(path = %path.display(), error = %err, "Failed to dump prompt");
+                }
+                return;
+            }
+            // Files survive a resume, so an earlier process may already have written this one
+            if self
+                .file_system
+                .read_to_string(&path)
+                .is_ok_and(|dumped| dumped == prompt)
+            {
+                return;
+            }
+        }
+    }
+}
+
+/// Largest prompt kept per step and role by `PromptRecorder`; longer prompts are truncated.
+pub const MAX_RECORDED_PROMPT_BYTES: usize = 32 * 1024;
+
+/// Stores rendered prompts on their steps (`WorkflowStep::rendered_prompts`) so the saved session
+/// documents what was sent. Matches of the redaction patterns are replaced before storing.
+pub struct PromptRecorder {
+    redactions: Vec<regex::Regex>,
+    max_bytes: usize,
+}
+
+impl PromptRecorder {
+    /// Compile `redactions`; fails on the first invalid pattern.
+    pub fn new(redactions: &[String]) -> Result<Self> {
+        let redactions = redactions
+            .iter()
+            .map(|pattern| {
+                regex::Regex::new(pattern)
+                    .with_context(|| format!("Invalid prompt redaction pattern '{pattern}'"))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            redactions,
+            max_bytes: MAX_RECORDED_PROMPT_BYTES,
+        })
+    }
+
+    fn record(&self, ctx: &mut Context, step_id: usize, role: &str, prompt: &str) {
+        let mut stored = prompt.to_string();
+        for pattern in &self.redactions {
+            stored = pattern.replace_all(&stored, "[REDACTED]").into_owned();
+        }
+        if stored.len() > self.max_bytes {
+            let mut cut = self.max_bytes;
+            while !stored.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            let dropped = stored.len() - cut;
+            stored.truncate(cut);
+            let _ = write!(stored, "\n[truncated {dropped} bytes]");
+        }
+        ctx.record_prompt(step_id, role, stored);
+    }
+}
+
+fn render_prompt(
+    renderer: &Arc<dyn PromptRenderer>,
+    template: &str,
+    body: &str,
+    role: &str,
+    domain_data: &HashMap<String, String>,
+    vars: &BTreeMap<String, String>,
+) -> Result<String> {
+    let data = prompt_data(body, role, domain_data, vars);
+    renderer
+        .render(template, &data)
+        .with_context(|| format!("Failed to render prompt template for role '{role}'"))
+        .map_err(|e| anyhow!(e))
+}
+
+fn prompt_data(
+    body: &str,
+    role: &str,
+    domain_data: &HashMap<String, String>,
+    vars: &BTreeMap<String, String>,
+) -> serde_json::Value {
+    // Expose domain data as `{{context.<key>}}`; values holding JSON are passed structured.
+    let context: serde_json::Map<String, serde_json::Value> = domain_data
+        .iter()
+        .map(|(key, value)| {
+            let parsed = serde_json::from_str(value)
+                .unwrap_or_else(|_| serde_json::Value::String(value.clone()));
+            (key.clone(), parsed)
+        })
+        .collect();
+    // Custom variables sit beside the built-ins, which win on a name clash.
+    let mut data: serde_json::Map<String, serde_json::Value> = vars
+        .iter()
+        .map(|(key, value)| (key.clone(), json!(value)))
+        .collect();
+    data.insert("prompt".into(), json!(body));
+    data.insert("task".into(), json!(body));
+    data.insert("role".into(), json!(role));
+    data.insert("context".into(), serde_json::Value::Object(context));
+    serde_json::Value::Object(data)
+}
+
+/// Provider options derived from an agent's configuration.
+fn llm_options(agent: &AgentConfig) -> LlmOptions {
+    LlmOptions {
+        stop_sequences: agent.stop_sequences.clone(),
+        ..LlmOptions::default()
+    }
+}
+
+/// Fail before an LLM batch once the run has been cancelled, so no new calls start.
+fn ensure_not_cancelled(token: Option<&CancellationToken>, step_id: usize) -> Result<()> {
+    if token.is_some_and(CancellationToken::is_cancelled) {
+        return Err(anyhow!(
+            "Run cancelled before the next LLM batch for step {step_id}"
+        ));
+    }
+    Ok(())
+}
+
+async fn sample_n(
+    llm: &Arc<dyn LlmClient>,
+    prompt: &str,
+    n: usize,
+    model: &str,
+    options: &LlmOptions,
+    max_concurrent: Option<usize>,
+) -> Result<Vec<LlmResponse>> {
+    sample_each(
+        llm,
+        prompt,
+        model,
+        std::iter::repeat_n(options.clone(), n),
+        max_concurrent,
+    )
+    .await
+}
+
+/// Issue one concurrent request per entry in `options`, at most `max_concurrent` in flight
+/// (on top of the client's global limit).
+async fn sample_each(
+    llm: &Arc<dyn LlmClient>,
+    prompt: &str,
+    model: &str,
+    options: impl IntoIterator<Item = LlmOptions>,
+    max_concurrent: Option<usize>,
+) -> Result<Vec<LlmResponse>> {
+    let requests = options
+        .into_iter()
+        .map(|options| (prompt.to_string(), options));
+    sample_prompts(llm, model, requests, max_concurrent).await
+}
+
+/// Issue one concurrent request per `(prompt, options)` pair, at most `max_concurrent` in
+/// flight (on top of the client's global limit). Responses come back in request order.
+async fn sample_prompts(
+    llm: &Arc<dyn LlmClient>,
+    model: &str,
+    requests: impl IntoIterator<Item = (String, LlmOptions)>,
+    max_concurrent: Option<usize>,
+) -> Result<Vec<LlmResponse>> {
+    let permits = max_concurrent.map(|limit| Arc::new(Semaphore::new(limit.max(1))));
+    let mut join_set = JoinSet::new();
+    for (idx, (prompt, options)) in requests.into_iter().enumerate() {
+        let llm = llm.clone();
+        let model = model.to_string();
+        let permits = permits.clone();
+        join_set.spawn(async move {
+            let _permit = match permits {
+                Some(permits) => permits.acquire_owned().await.ok(),
+                None => None,
+            };
+            let response = llm
+                .chat_completion_with_finish_reason(&model, &prompt, &options)
+                .await;
+            (idx, response)
+        });
     }
 
     let mut results = Vec::new();
     while let Some(res) = join_set.join_next().await {
-        let val = res.context("LLM task panic")?.map_err(|e| anyhow!(e))?;
-        results.push(val);
+        let (idx, val) = res.context("LLM task panic")?;
+        results.push((idx, val.map_err(|e| anyhow!(e))?));
     }
-    Ok(results)
+    results.sort_by_key(|(idx, _)| *idx);
+    Ok(results.into_iter().map(|(_, response)| response).collect())
 }
 
 fn parse_subtasks(raw: &str) -> Vec<String> {
@@ -694,42 +1745,122 @@ fn parse_subtasks(raw: &str) -> Vec<String> {
         .collect()
 }
 
-fn parse_vote_response(raw: &str, max_index: usize) -> Option<usize> {
-    if max_index == 0 {
-        return None;
-    }
-    for token in raw.split_whitespace() {
-        let digits: String = token.chars().filter(|c| c.is_ascii_digit()).collect();
-        if digits.is_empty() {
-            continue;
+/// Zero-based option a voter chose. Numeric votes take the first in-range number anywhere in
+/// the response. A lone capital letter is often an ordinary word ("A", "I"), so alpha votes
+/// count only a label that is the whole response or directly follows "Option".
+fn parse_vote_response(raw: &str, max_index: usize, style: VoteEnumeration) -> Option<usize> {
+    let in_range = |index: &usize| *index < max_index;
+    match style {
+        VoteEnumeration::Numeric => raw
+            .split_whitespace()
+            .filter_map(|token| style.index_of(token))
+            .find(in_range),
+        VoteEnumeration::Alpha => {
+            if let Some(index) = style.index_of(raw.trim()).filter(in_range) {
+                return Some(index);
+            }
+            let tokens: Vec<&str> = raw.split_whitespace().collect();
+            tokens
+                .windows(2)
+                .filter(|pair| {
+                    pair[0]
+                        .trim_matches(|c: char| !c.is_ascii_alphanumeric())
+                        .eq_ignore_ascii_case("option")
+                })
+                .filter_map(|pair| style.index_of(pair[1]))
+                .find(in_range)
         }
-        if let Ok(value) = digits.parse::<usize>()
-            && (1..=max_index).contains(&value)
-        {
-            return Some(value - 1);
+    }
+}
+
+/// Collapse candidates that are equal after whitespace normalization, keeping the first
+/// spelling of each. Returns the unique candidates and how many samples each one stands for.
+fn dedupe_candidates(candidates: Vec<String>) -> (Vec<String>, Vec<usize>) {
+    let mut unique: Vec<String> = Vec::new();
+    let mut weights: Vec<usize> = Vec::new();
+    let mut index_by_key: HashMap<String, usize> = HashMap::new();
+    for candidate in candidates {
+        let key = candidate.split_whitespace().collect::<Vec<_>>().join(" ");
+        match index_by_key.get(&key) {
+            Some(&idx) => weights[idx] += 1,
+            None => {
+                index_by_key.insert(key, unique.len());
+                unique.push(candidate);
+                weights.push(1);
+            }
         }
     }
-    None
+    (unique, weights)
+}
+
+/// Option order shown to each of `ballots` voters, one [`option_order`] per ballot.
+fn ballot_orders(len: usize, seed: Option<u64>, step_id: usize, ballots: usize) -> Vec<Vec<usize>> {
+    (0..ballots)
+        .map(|ballot| option_order(len, seed, step_id, ballot))
+        .collect()
 }
 
-fn enumerate_options(options: Vec<String>) -> String {
+/// Order in which vote options are shown: `order[shown] = original`. Identity without a seed;
+/// otherwise a Fisher-Yates shuffle driven by splitmix64 over `seed`, `step_id` and the
+/// `ballot` index, so every voter sees its own order and a given seed reproduces them all.
+fn option_order(len: usize, seed: Option<u64>, step_id: usize, ballot: usize) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..len).collect();
+    let Some(seed) = seed else {
+        return order;
+    };
+    let mut state = seed
+        ^ (step_id as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (ballot as u64).wrapping_mul(0xD1B5_4A32_D192_ED03);
+    let mut next = || {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    };
+    for idx in (1..len).rev() {
+        let swap = (next() % (idx as u64 + 1)) as usize;
+        order.swap(idx, swap);
+    }
+    order
+}
+
+fn enumerate_options(options: Vec<String>, style: VoteEnumeration) -> String {
     let mut body = String::new();
     for (idx, option) in options.iter().enumerate() {
-        let _ = writeln!(&mut body, "Option {}:\n{option}\n", idx + 1);
+        let _ = writeln!(&mut body, "Option {}:\n{option}\n", style.label(idx));
     }
     body
 }
 
-fn vote_counts(votes: &[usize], candidate_count: usize, winner_idx: usize) -> (usize, usize) {
-    if candidate_count == 0 {
-        return (0, 0);
-    }
+/// Count the votes each option received; out-of-range votes are ignored.
+fn tally_votes(votes: &[usize], candidate_count: usize) -> Vec<usize> {
     let mut counts = vec![0usize; candidate_count];
     for &vote in votes {
         if vote < candidate_count {
             counts[vote] += 1;
         }
     }
+    counts
+}
+
+/// Map per-option counts of deduplicated candidates back onto the original candidate list,
+/// crediting each merged group to the spelling that was shown to the voter.
+fn candidate_histogram(candidates: &[String], options: &[String], counts: &[usize]) -> Vec<usize> {
+    let mut histogram = vec![0usize; candidates.len()];
+    for (option, &count) in options.iter().zip(counts) {
+        if let Some(idx) = candidates.iter().position(|candidate| candidate == option) {
+            histogram[idx] += count;
+        }
+    }
+    histogram
+}
+
+fn vote_counts(votes: &[usize], candidate_count: usize, winner_idx: usize) -> (usize, usize) {
+    if candidate_count == 0 {
+        return (0, 0);
+    }
+    let counts = tally_votes(votes, candidate_count);
     let winner = counts.get(winner_idx).copied().unwrap_or(0);
     let mut runner_up = 0;
     for (idx, count) in counts.iter().enumerate() {
@@ -749,6 +1880,14 @@ struct SampleCollector<'ctx> {
     llm: Arc<dyn LlmClient>,
     pipeline: Arc<RedFlagPipeline>,
     stage: &'static str,
+    min_accepted: Option<usize>,
+    options: LlmOptions,
+    temperature_spread: Option<TemperatureSpread>,
+    red_flag_concurrency: usize,
+    max_response_bytes: Option<usize>,
+    flag_truncated: bool,
+    max_concurrent: Option<usize>,
+    cancellation: Option<CancellationToken>,
 }
 
 impl<'ctx> SampleCollector<'ctx> {
@@ -765,9 +1904,125 @@ impl<'ctx> SampleCollector<'ctx> {
             llm,
             pipeline,
             stage,
+            min_accepted: None,
+            options: LlmOptions::default(),
+            temperature_spread: None,
+            red_flag_concurrency: DEFAULT_RED_FLAG_CONCURRENCY,
+            max_response_bytes: None,
+            flag_truncated: false,
+            max_concurrent: None,
+            cancellation: None,
+        }
+    }
+
+    /// Refuse to start another batch, top-ups included, once `token` is cancelled.
+    fn with_cancellation(mut self, token: Option<CancellationToken>) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Cap this agent's in-flight sample requests; `None` leaves only the client's global limit.
+    fn with_max_concurrent(mut self, limit: Option<usize>) -> Self {
+        self.max_concurrent = limit;
+        self
+    }
+
+    /// Stop resampling once `floor` clean samples are accepted (clamped to `1..=target`).
+    /// Defaults to requiring every requested sample.
+    fn with_min_accepted(mut self, floor: Option<usize>) -> Self {
+        self.min_accepted = floor;
+        self
+    }
+
+    /// Cap simultaneous red-flag evaluations (clamped to at least one).
+    fn with_red_flag_concurrency(mut self, limit: usize) -> Self {
+        self.red_flag_concurrency = limit.max(1);
+        self
+    }
+
+    /// Discard samples larger than `limit` bytes as a built-in red flag, without running the
+    /// pipeline on them. `None` accepts any size.
+    fn with_max_response_bytes(mut self, limit: Option<usize>) -> Self {
+        self.max_response_bytes = limit;
+        self
+    }
+
+    /// Discard samples the provider reports as cut off at the token limit (`length`,
+    /// `max_tokens`), as a built-in red flag.
+    fn with_flag_truncated(mut self, enabled: bool) -> Self {
+        self.flag_truncated = enabled;
+        self
+    }
+
+    /// Per-request provider options (e.g. stop sequences) forwarded with every sample.
+    fn with_llm_options(mut self, options: LlmOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Request each sample of a batch at its own temperature across `spread`.
+    fn with_temperature_spread(mut self, spread: Option<TemperatureSpread>) -> Self {
+        self.temperature_spread = spread;
+        self
+    }
+
+    /// Request `n` samples, either with the shared options or one temperature per sample.
+    /// `requested` counts the samples already requested for this step out of the `planned`
+    /// batch, so top-ups continue the spread where earlier batches stopped.
+    async fn sample(
+        &self,
+        prompt: &str,
+        n: usize,
+        requested: usize,
+        planned: usize,
+        model: &str,
+    ) -> Result<Vec<LlmResponse>> {
+        ensure_not_cancelled(self.cancellation.as_ref(), self.step_id)?;
+        match self.temperature_spread {
+            Some(spread) => {
+                let options = (requested..requested + n).map(|index| LlmOptions {
+                    temperature: Some(spread.temperature(index, planned)),
+                    ..self.options.clone()
+                });
+                sample_each(&self.llm, prompt, model, options, self.max_concurrent).await
+            }
+            None => {
+                sample_n(
+                    &self.llm,
+                    prompt,
+                    n,
+                    model,
+                    &self.options,
+                    self.max_concurrent,
+                )
+                .await
+            }
         }
     }
 
+    fn oversized(&self, raw: &str) -> Option<RedFlagIncident> {
+        let limit = self.max_response_bytes?;
+        (raw.len() > limit).then(|| RedFlagIncident {
+            flagger: MAX_RESPONSE_BYTES_FLAGGER.into(),
+            reason: format!(
+                "Response is {} bytes, over the {limit}-byte limit",
+                raw.len()
+            ),
+            sample_preview: preview_sample(raw),
+        })
+    }
+
+    fn truncated(&self, response: &LlmResponse) -> Option<RedFlagIncident> {
+        (self.flag_truncated && response.truncated()).then(|| RedFlagIncident {
+            flagger: TRUNCATED_FLAGGER.into(),
+            reason: format!(
+                "Response was cut off (finish reason '{}')",
+                response.finish_reason.as_deref().unwrap_or_default()
+            ),
+            sample_preview: preview_sample(&response.content),
+        })
+    }
+
     async fn collect(
         self,
         prompt: String,
@@ -787,8 +2042,13 @@ impl<'ctx> SampleCollector<'ctx> {
             return Ok(Vec::new());
         }
 
-        if self.pipeline.is_empty() {
-            let responses = sample_n(&self.llm, &prompt, target_samples, model).await?;
+        if self.pipeline.is_empty() && self.max_response_bytes.is_none() && !self.flag_truncated {
+            let responses: Vec<String> = self
+                .sample(&prompt, target_samples, 0, target_samples, model)
+                .await?
+                .into_iter()
+                .map(|response| response.content)
+                .collect();
             self.ctx
                 .metrics
                 .record_samples(self.step_id, responses.len(), responses.len());
@@ -801,25 +2061,66 @@ impl<'ctx> SampleCollector<'ctx> {
             return Ok(responses);
         }
 
+        let required = self
+            .min_accepted
+            .map_or(target_samples, |floor| floor.clamp(1, target_samples));
         let mut accepted = Vec::new();
         let mut attempts = 0usize;
+        let mut requested = 0usize;
+        let permits = Arc::new(Semaphore::new(self.red_flag_concurrency));
         let max_attempts = target_samples.max(1) * 4;
-        while accepted.len() < target_samples {
+        while accepted.len() < required {
             attempts += 1;
-            let remaining = target_samples - accepted.len();
-            let batch = sample_n(&self.llm, &prompt, remaining, model).await?;
+            // The first batch asks for the full request; later batches only top up to the floor.
+            let remaining = if attempts == 1 {
+                target_samples
+            } else {
+                required - accepted.len()
+            };
+            let batch = self
+                .sample(&prompt, remaining, requested, target_samples, model)
+                .await?;
+            requested += remaining;
             let batch_len = batch.len();
             let before = accepted.len();
             let mut flagged_this_round = 0usize;
 
-            // Evaluate red flags in parallel
+            // Evaluate red flags in parallel, at most `red_flag_concurrency` at a time
             let mut join_set = JoinSet::new();
-            for raw in batch {
+            for response in batch {
+                if let Some(incident) = self.truncated(&response) {
+                    flagged_this_round += 1;
+                    warn!(
+                        step_id = self.step_id,
+                        stage = self.stage,
+                        finish_reason = response.finish_reason.as_deref().unwrap_or_default(),
+                        "Truncated sample discarded"
+                    );
+                    self.ctx
+                        .metrics
+                        .record_red_flags(self.step_id, vec![incident]);
+                    continue;
+                }
+                let raw = response.content;
+                if let Some(incident) = self.oversized(&raw) {
+                    flagged_this_round += 1;
+                    warn!(
+                        step_id = self.step_id,
+                        stage = self.stage,
+                        bytes = raw.len(),
+                        "Oversized sample discarded"
+                    );
+                    self.ctx
+                        .metrics
+                        .record_red_flags(self.step_id, vec![incident]);
+                    continue;
+                }
                 let pipeline = self.pipeline.clone();
-                let raw_owned = raw.clone();
+                let permits = permits.clone();
                 join_set.spawn(async move {
-                    let matches = pipeline.evaluate(&raw_owned).await;
-                    (raw_owned, matches)
+                    let _permit = permits.acquire_owned().await;
+                    let matches = pipeline.evaluate(&raw).await;
+                    (raw, matches)
                 });
             }
 
@@ -847,7 +2148,7 @@ impl<'ctx> SampleCollector<'ctx> {
             self.ctx
                 .metrics
                 .record_samples(self.step_id, batch_len, accepted_delta);
-            if accepted.len() < target_samples {
+            if accepted.len() < required {
                 self.ctx.metrics.record_resample(self.step_id);
                 if attempts >= max_attempts {
                     return Err(anyhow!(
@@ -955,10 +2256,8 @@ fn validate_target_path(raw: &str) -> Result<std::path::PathBuf> {
             std::path::Component::ParentDir => {
                 return Err(anyhow!("Path traversal (..) is forbidden: {raw}"));
             }
-            std::path::Component::Normal(os_str) => {
-                if os_str == ".git" {
-                    return Err(anyhow!("Modifying .git directory is forbidden: {raw}"));
-                }
+            std::path::Component::Normal(os_str) if os_str == ".git" => {
+                return Err(anyhow!("Modifying .git directory is forbidden: {raw}"));
             }
             _ => {}
         }
@@ -981,6 +2280,22 @@ fn extract_target_path(description: &str) -> Option<String> {
     None
 }
 
+/// First capture group of `pattern` in `description`; `None` for an invalid pattern or no match.
+fn extract_target_path_with(pattern: &str, description: &str) -> Option<String> {
+    let regex = match regex::Regex::new(pattern) {
+        Ok(regex) => regex,
+        Err(err) => {
+            warn!(pattern, error = %err, "Invalid path_pattern");
+            return None;
+        }
+    };
+    regex
+        .captures(description)?
+        .get(1)
+        .map(|path| path.as_str().trim().to_string())
+        .filter(|path| !path.is_empty())
+}
+
 fn extract_code_content(raw: &str) -> String {
     if let Some(start) = raw.find("```") {
         let rest = &raw[start + 3..];
@@ -999,20 +2314,96 @@ fn extract_code_content(raw: &str) -> String {
 mod tests {
     use super::*;
     use crate::{
-        adapters::outbound::{clock::SystemClock, filesystem::StdFileSystem},
+        adapters::outbound::{
+            clock::SystemClock,
+            filesystem::StdFileSystem,
+            process::{MockCommandRunner, StdCommandRunner},
+        },
         core::{
-            domain::{Context, RedFlaggerDescriptor},
-            ports::{Clock, FileSystem},
+            domain::{Context, LineEnding, RedFlaggerDescriptor},
+            ports::{Clock, CommandOutput, FileSystem},
         },
         red_flaggers::RedFlagPipeline,
     };
     use async_trait::async_trait;
+    use mock_llm::{CapturingLlm, QueueLlm};
     use serde_json::json;
     use std::{
         collections::{HashMap, VecDeque},
         sync::Mutex,
     };
 
+    /// Mock LLM clients shared by the tests below.
+    mod mock_llm {
+        use super::*;
+
+        /// Answers every request with `reply`, recording each prompt and its options.
+        pub struct CapturingLlm {
+            reply: String,
+            calls: Mutex<Vec<(String, LlmOptions)>>,
+        }
+
+        impl CapturingLlm {
+            pub fn new(reply: impl Into<String>) -> Self {
+                Self {
+                    reply: reply.into(),
+                    calls: Mutex::new(Vec::new()),
+                }
+            }
+
+            pub fn prompts(&self) -> Vec<String> {
+                let calls = self.calls.lock().unwrap();
+                calls.iter().map(|(prompt, _)| prompt.clone()).collect()
+            }
+
+            pub fn options(&self) -> Vec<LlmOptions> {
+                let calls = self.calls.lock().unwrap();
+                calls.iter().map(|(_, options)| options.clone()).collect()
+            }
+        }
+
+        #[async_trait]
+        impl LlmClient for CapturingLlm {
+            async fn chat_completion(
+                &self,
+                _model: &str,
+                prompt: &str,
+                options: &LlmOptions,
+            ) -> crate::core::Result<String> {
+                let mut calls = self.calls.lock().unwrap();
+                calls.push((prompt.to_string(), options.clone()));
+                Ok(self.reply.clone())
+            }
+        }
+
+        /// Answers with the scripted responses in order and fails once they run out.
+        pub struct QueueLlm {
+            responses: Mutex<VecDeque<String>>,
+        }
+
+        impl QueueLlm {
+            pub fn new(responses: impl IntoIterator<Item = impl Into<String>>) -> Self {
+                Self {
+                    responses: Mutex::new(responses.into_iter().map(Into::into).collect()),
+                }
+            }
+        }
+
+        #[async_trait]
+        impl LlmClient for QueueLlm {
+            async fn chat_completion(
+                &self,
+                _model: &str,
+                _prompt: &str,
+                _options: &LlmOptions,
+            ) -> crate::core::Result<String> {
+                self.responses.lock().unwrap().pop_front().ok_or_else(|| {
+                    crate::core::error::Error::System("No scripted responses left".into())
+                })
+            }
+        }
+    }
+
     #[test]
     fn parses_subtasks_from_bullets() {
         let raw = "- step one\n* step two";
@@ -1022,9 +2413,43 @@ mod tests {
 
     #[test]
     fn vote_parser_handles_digits() {
-        assert_eq!(parse_vote_response("Option 2", 3), Some(1));
-        assert_eq!(parse_vote_response("choice #1", 1), Some(0));
-        assert_eq!(parse_vote_response("invalid", 2), None);
+        let numeric = VoteEnumeration::Numeric;
+        assert_eq!(parse_vote_response("Option 2", 3, numeric), Some(1));
+        assert_eq!(parse_vote_response("choice #1", 1, numeric), Some(0));
+        assert_eq!(parse_vote_response("invalid", 2, numeric), None);
+    }
+
+    #[test]
+    fn alpha_enumeration_labels_and_parses_letters() {
+        let alpha = VoteEnumeration::Alpha;
+        let body = enumerate_options(vec!["first".into(), "second".into()], alpha);
+        assert!(body.starts_with("Option A:\nfirst\n"), "{body}");
+        assert!(body.contains("Option B:\nsecond\n"), "{body}");
+
+        assert_eq!(parse_vote_response("A", 2, alpha), Some(0));
+        assert_eq!(parse_vote_response("I pick option (B).", 2, alpha), Some(1));
+        assert_eq!(parse_vote_response("Option 2", 2, alpha), None);
+        assert_eq!(parse_vote_response("C", 2, alpha), None);
+        assert_eq!(alpha.label(26), "AA");
+        assert_eq!(alpha.index_of("AA"), Some(26));
+    }
+
+    #[test]
+    fn alpha_votes_ignore_capital_words_outside_a_label() {
+        let alpha = VoteEnumeration::Alpha;
+        assert_eq!(
+            parse_vote_response("I pick option (B).", 10, alpha),
+            Some(1)
+        );
+        assert_eq!(
+            parse_vote_response("A close call, but Option C is best.", 10, alpha),
+            Some(2)
+        );
+        assert_eq!(parse_vote_response(" (D) ", 10, alpha), Some(3));
+        assert_eq!(
+            parse_vote_response("I like the second one", 10, alpha),
+            None
+        );
     }
 
     #[test]
@@ -1145,32 +2570,1334 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn apply_verify_writes_to_output_dir() {
-        use tempfile::tempdir;
+    async fn min_samples_floor_forces_extra_batches() {
+        let configs = vec![RedFlaggerDescriptor {
+            kind: "length".into(),
+            params: HashMap::from([(String::from("max_tokens"), json!(2))]),
+        }];
+        let pipeline = Arc::new(RedFlagPipeline::from_configs(&configs, None).unwrap());
+        let flagged = "far too many words";
+        let llm: Arc<dyn LlmClient> =
+            Arc::new(QueueLlm::new([flagged, "ok", flagged, "ok", flagged, "ok"]));
+        let mut ctx = Context::new("demo", "code");
+        let root_id = ctx.ensure_root();
+        let responses = SampleCollector::new(&mut ctx, root_id, llm, pipeline, "test")
+            .with_min_accepted(Some(3))
+            .collect("prompt".to_string(), 4, "model")
+            .await
+            .expect("floor reached within budget");
 
-        let tmp = tempdir().unwrap();
-        let output_dir = tmp.path().join("subdir");
-        let mut ctx = Context::new("Write file.txt", "code");
-        ctx.output_dir = Some(output_dir.clone());
+        assert_eq!(responses.len(), 3);
+        assert_eq!(ctx.metrics.red_flag_hits, 3);
+        assert_eq!(ctx.metrics.resample_count, 2);
+    }
 
-        let root = ctx.ensure_root();
-        ctx.mark_step_solution(root, "content".to_string());
+    #[tokio::test]
+    async fn red_flag_evaluation_respects_concurrency_limit() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
 
-        let file_system: Arc<dyn FileSystem> = Arc::new(StdFileSystem::new());
-        let clock: Arc<dyn Clock> = Arc::new(SystemClock::new());
-        let task = ApplyVerifyTask::new(
-            root,
-            Some("overwrite_file".into()),
-            None,
-            file_system,
-            clock,
-        );
+        struct EchoLlm;
 
-        // The description "Write file.txt" should trigger heuristic extraction of "file.txt"
-        task.run(&mut ctx).await.unwrap();
+        #[async_trait]
+        impl LlmClient for EchoLlm {
+            async fn chat_completion(
+                &self,
+                _model: &str,
+                _prompt: &str,
+                _options: &LlmOptions,
+            ) -> crate::core::Result<String> {
+                Ok("candidate".into())
+            }
+        }
 
-        let expected_path = output_dir.join("file.txt");
-        assert!(expected_path.exists());
-        assert_eq!(std::fs::read_to_string(expected_path).unwrap(), "content");
+        /// Critique client that records how many evaluations overlap.
+        #[derive(Default)]
+        struct PeakCritic {
+            active: AtomicUsize,
+            peak: AtomicUsize,
+        }
+
+        #[async_trait]
+        impl LlmClient for PeakCritic {
+            async fn chat_completion(
+                &self,
+                _model: &str,
+                _prompt: &str,
+                _options: &LlmOptions,
+            ) -> crate::core::Result<String> {
+                let now = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+                self.peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                self.active.fetch_sub(1, Ordering::SeqCst);
+                Ok("no".into())
+            }
+        }
+
+        let critic = Arc::new(PeakCritic::default());
+        let configs = vec![RedFlaggerDescriptor {
+            kind: "llm_critique".into(),
+            params: HashMap::from([
+                (String::from("model"), json!("critic")),
+                (String::from("prompt_template"), json!("{{candidate}}")),
+            ]),
+        }];
+        let pipeline = Arc::new(
+            RedFlagPipeline::from_configs(&configs, Some(critic.clone() as Arc<dyn LlmClient>))
+                .unwrap(),
+        );
+        let mut ctx = Context::new("demo", "code");
+        let root_id = ctx.ensure_root();
+        let responses =
+            SampleCollector::new(&mut ctx, root_id, Arc::new(EchoLlm), pipeline, "test")
+                .with_red_flag_concurrency(2)
+                .collect("prompt".to_string(), 6, "model")
+                .await
+                .expect("all samples accepted");
+
+        assert_eq!(responses.len(), 6);
+        assert_eq!(critic.peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn truncated_samples_are_flagged_and_resampled() {
+        /// Reports the first response as cut off at the token limit, the rest as complete.
+        struct TruncatingLlm {
+            responses: Mutex<VecDeque<LlmResponse>>,
+        }
+
+        #[async_trait]
+        impl LlmClient for TruncatingLlm {
+            async fn chat_completion(
+                &self,
+                _model: &str,
+                _prompt: &str,
+                _options: &LlmOptions,
+            ) -> crate::core::Result<String> {
+                unreachable!("the collector asks for finish reasons")
+            }
+
+            async fn chat_completion_with_finish_reason(
+                &self,
+                _model: &str,
+                _prompt: &str,
+                _options: &LlmOptions,
+            ) -> crate::core::Result<LlmResponse> {
+                self.responses.lock().unwrap().pop_front().ok_or_else(|| {
+                    crate::core::error::Error::System("No scripted responses left".into())
+                })
+            }
+        }
+
+        let llm: Arc<dyn LlmClient> = Arc::new(TruncatingLlm {
+            responses: Mutex::new(
+                [
+                    LlmResponse::new("fn main() {").with_finish_reason("length"),
+                    LlmResponse::new("fn main() {}").with_finish_reason("stop"),
+                ]
+                .into_iter()
+                .collect(),
+            ),
+        });
+        let pipeline = Arc::new(RedFlagPipeline::from_configs(&[], None).unwrap());
+        let mut ctx = Context::new("demo", "code");
+        let root_id = ctx.ensure_root();
+        let responses = SampleCollector::new(&mut ctx, root_id, llm, pipeline, "test")
+            .with_flag_truncated(true)
+            .collect("prompt".to_string(), 1, "model")
+            .await
+            .expect("complete sample accepted");
+
+        assert_eq!(responses, vec!["fn main() {}".to_string()]);
+        let step_metrics = ctx.metrics.step_metrics(root_id).unwrap();
+        assert_eq!(step_metrics.red_flags.len(), 1);
+        assert_eq!(step_metrics.red_flags[0].flagger, TRUNCATED_FLAGGER);
+        assert!(
+            step_metrics.red_flags[0].reason.contains("'length'"),
+            "{}",
+            step_metrics.red_flags[0].reason
+        );
+        assert_eq!(step_metrics.red_flags[0].sample_preview, "fn main() {");
+        assert_eq!(ctx.metrics.red_flag_hits, 1);
+    }
+
+    #[tokio::test]
+    async fn oversized_samples_are_rejected_before_flaggers_run() {
+        let critic = Arc::new(CapturingLlm::new("no"));
+        let configs = vec![RedFlaggerDescriptor {
+            kind: "llm_critique".into(),
+            params: HashMap::from([
+                (String::from("model"), json!("critic")),
+                (String::from("prompt_template"), json!("{{candidate}}")),
+            ]),
+        }];
+        let pipeline = Arc::new(
+            RedFlagPipeline::from_configs(&configs, Some(critic.clone() as Arc<dyn LlmClient>))
+                .unwrap(),
+        );
+        let llm: Arc<dyn LlmClient> = Arc::new(QueueLlm::new(["x".repeat(64), "ok".to_string()]));
+        let mut ctx = Context::new("demo", "code");
+        let root_id = ctx.ensure_root();
+        let responses = SampleCollector::new(&mut ctx, root_id, llm, pipeline, "test")
+            .with_max_response_bytes(Some(16))
+            .collect("prompt".to_string(), 1, "model")
+            .await
+            .expect("small sample accepted");
+
+        assert_eq!(responses, vec!["ok".to_string()]);
+        assert_eq!(critic.prompts().as_slice(), ["ok".to_string()]);
+        let incidents = &ctx.metrics.step_metrics(root_id).unwrap().red_flags;
+        assert_eq!(incidents.len(), 1);
+        assert_eq!(incidents[0].flagger, MAX_RESPONSE_BYTES_FLAGGER);
+        assert!(
+            incidents[0].reason.contains("64 bytes"),
+            "{}",
+            incidents[0].reason
+        );
+        assert_eq!(ctx.metrics.red_flag_hits, 1);
+    }
+
+    #[tokio::test]
+    async fn apply_verify_writes_to_output_dir() {
+        use tempfile::tempdir;
+
+        let tmp = tempdir().unwrap();
+        let output_dir = tmp.path().join("subdir");
+        let mut ctx = Context::new("Write file.txt", "code");
+        ctx.output_dir = Some(output_dir.clone());
+
+        let root = ctx.ensure_root();
+        ctx.mark_step_solution(root, "content".to_string());
+
+        let file_system: Arc<dyn FileSystem> = Arc::new(StdFileSystem::new());
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock::new());
+        let task = ApplyVerifyTask::new(
+            root,
+            Some("overwrite_file".into()),
+            None,
+            file_system,
+            clock,
+            Arc::new(StdCommandRunner::new()),
+        );
+
+        // The description "Write file.txt" should trigger heuristic extraction of "file.txt"
+        task.run(&mut ctx).await.unwrap();
+
+        let expected_path = output_dir.join("file.txt");
+        assert!(expected_path.exists());
+        assert_eq!(std::fs::read_to_string(expected_path).unwrap(), "content");
+    }
+
+    #[tokio::test]
+    async fn apply_verify_rejects_oversized_file_blocks() {
+        use tempfile::tempdir;
+
+        let tmp = tempdir().unwrap();
+        let mut ctx = Context::new("Update the sources", "code");
+        ctx.output_dir = Some(tmp.path().to_path_buf());
+        let root = ctx.ensure_root();
+        let huge = "x".repeat(64);
+        ctx.mark_step_solution(
+            root,
+            format!(
+                r#"<file path="src/small.rs">fn ok() {{}}</file><file path="src/huge.rs">{huge}</file>"#
+            ),
+        );
+
+        let task = ApplyVerifyTask::new(
+            root,
+            Some("overwrite_file".into()),
+            None,
+            Arc::new(StdFileSystem::new()),
+            Arc::new(SystemClock::new()),
+            Arc::new(StdCommandRunner::new()),
+        )
+        .with_max_file_bytes(32);
+
+        let preview = task.preview(&ctx).unwrap();
+        assert_eq!(preview.files, vec![tmp.path().join("src/small.rs")]);
+        assert!(
+            preview
+                .diff
+                .contains("! src/huge.rs: 64 bytes exceeds max_file_bytes (32)"),
+            "{}",
+            preview.diff
+        );
+
+        task.run(&mut ctx).await.unwrap();
+        assert_eq!(ctx.step(root).unwrap().status, StepStatus::Failed);
+        // The whole step is rejected, so even the small file stays unwritten.
+        assert!(!tmp.path().join("src").exists());
+    }
+
+    #[tokio::test]
+    async fn apply_verify_skips_paths_matched_by_ignore_rules() {
+        use tempfile::tempdir;
+
+        let tmp = tempdir().unwrap();
+        let mut ctx = Context::new("Update the sources", "code");
+        ctx.output_dir = Some(tmp.path().to_path_buf());
+        std::fs::write(tmp.path().join("Cargo.lock"), "original").unwrap();
+
+        let root = ctx.ensure_root();
+        ctx.mark_step_solution(
+            root,
+            concat!(
+                r#"<file path="src/lib.rs">pub fn answer() -> u8 { 42 }</file>"#,
+                r#"<file path="Cargo.lock">regenerated</file>"#,
+                r#"<file path="gen/api/client.rs">// generated</file>"#,
+            )
+            .to_string(),
+        );
+
+        let rules = IgnoreRules::parse("*.lock\ngen/\n").unwrap();
+        let task = ApplyVerifyTask::new(
+            root,
+            Some("overwrite_file".into()),
+            None,
+            Arc::new(StdFileSystem::new()),
+            Arc::new(SystemClock::new()),
+            Arc::new(StdCommandRunner::new()),
+        )
+        .with_ignore_rules(Arc::new(rules));
+        task.run(&mut ctx).await.unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(tmp.path().join("src/lib.rs")).unwrap(),
+            "pub fn answer() -> u8 { 42 }"
+        );
+        assert_eq!(
+            std::fs::read_to_string(tmp.path().join("Cargo.lock")).unwrap(),
+            "original"
+        );
+        assert!(!tmp.path().join("gen").exists());
+        let step = ctx.step(root).unwrap();
+        assert_eq!(step.status, StepStatus::Completed);
+        assert_eq!(
+            step.skipped_paths,
+            vec![
+                std::path::PathBuf::from("Cargo.lock"),
+                std::path::PathBuf::from("gen/api/client.rs"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn apply_verify_encodes_written_files() {
+        use tempfile::tempdir;
+
+        let tmp = tempdir().unwrap();
+        let mut ctx = Context::new("Write notes", "code");
+        ctx.output_dir = Some(tmp.path().to_path_buf());
+        let root = ctx.ensure_root();
+        ctx.mark_step_solution(
+            root,
+            "<file path=\"notes.txt\">one\ntwo\r\nthree\n</file>".to_string(),
+        );
+
+        let task = ApplyVerifyTask::new(
+            root,
+            Some("overwrite_file".into()),
+            None,
+            Arc::new(StdFileSystem::new()),
+            Arc::new(SystemClock::new()),
+            Arc::new(StdCommandRunner::new()),
+        )
+        .with_output_encoding(OutputEncoding {
+            line_endings: LineEnding::Crlf,
+            bom: true,
+        });
+        task.run(&mut ctx).await.unwrap();
+
+        let bytes = std::fs::read(tmp.path().join("notes.txt")).unwrap();
+        assert_eq!(bytes, b"\xEF\xBB\xBFone\r\ntwo\r\nthree");
+    }
+
+    #[tokio::test]
+    async fn apply_verify_runs_verifier_through_command_runner() {
+        let mut ctx = Context::new("Fix the build", "code");
+        let root = ctx.ensure_root();
+        ctx.mark_step_solution(root, "patch".to_string());
+
+        let runner = Arc::new(MockCommandRunner::new(vec![CommandOutput {
+            status: Some(2),
+            stdout: String::new(),
+            stderr: "2 tests failed".into(),
+        }]));
+        let task = ApplyVerifyTask::new(
+            root,
+            None,
+            Some("cargo test".into()),
+            Arc::new(StdFileSystem::new()),
+            Arc::new(SystemClock::new()),
+            runner.clone(),
+        );
+
+        let result = task.run(&mut ctx).await.unwrap();
+
+        assert_eq!(runner.commands(), vec!["cargo test".to_string()]);
+        assert!(matches!(result.effect, TaskEffect::None));
+        assert_eq!(ctx.step(root).unwrap().status, StepStatus::Failed);
+        assert_eq!(
+            ctx.metrics()
+                .step_metrics(root)
+                .unwrap()
+                .verification_passed,
+            Some(false)
+        );
+    }
+
+    #[tokio::test]
+    async fn prompt_vars_are_rendered_next_to_the_task() {
+        let mut ctx = Context::new("Write a parser", "code");
+        let root = ctx.ensure_root();
+        let llm = Arc::new(CapturingLlm::new("fn parse() {}"));
+        let agent = AgentConfig {
+            kind: AgentKind::Solver,
+            prompt_template: "{{task}} in {{language}}, {{style}} style.".into(),
+            model: "m".into(),
+            samples: 1,
+            k: None,
+            red_flaggers: None,
+            stop_sequences: Vec::new(),
+            temperature_spread: None,
+            max_concurrent: None,
+        };
+        SolveTask::new(
+            root,
+            agent,
+            llm.clone(),
+            Arc::new(RedFlagPipeline::from_configs(&[], None).unwrap()),
+            Arc::new(crate::adapters::templating::HandlebarsRenderer::new()),
+            Arc::new(SystemClock::new()),
+        )
+        .with_prompt_vars(BTreeMap::from([
+            ("language".to_string(), "Rust".to_string()),
+            ("style".to_string(), "functional".to_string()),
+        ]))
+        .run(&mut ctx)
+        .await
+        .unwrap();
+
+        let prompts = llm.prompts();
+        assert_eq!(prompts, vec!["Write a parser in Rust, functional style."]);
+    }
+
+    #[tokio::test]
+    async fn resolved_step_prompt_includes_prior_verifier_failure() {
+        let mut ctx = Context::new("Fix the build", "code");
+        let root = ctx.ensure_root();
+        ctx.mark_step_solution(root, "patch".to_string());
+        let runner = Arc::new(MockCommandRunner::new(vec![CommandOutput {
+            status: Some(101),
+            stdout: "running 3 tests".into(),
+            stderr: "error[E0308]: mismatched types in src/lib.rs".into(),
+        }]));
+        ApplyVerifyTask::new(
+            root,
+            None,
+            Some("cargo test".into()),
+            Arc::new(StdFileSystem::new()),
+            Arc::new(SystemClock::new()),
+            runner,
+        )
+        .run(&mut ctx)
+        .await
+        .unwrap();
+        assert_eq!(ctx.step(root).unwrap().status, StepStatus::Failed);
+
+        // Retrying the step keeps the feedback for the next solve.
+        ctx.reset_subtree(root).unwrap();
+        let llm = Arc::new(CapturingLlm::new("patch v2"));
+        let agent = AgentConfig {
+            kind: AgentKind::Solver,
+            prompt_template:
+                "{{task}}{{#if verifier_feedback}}\nPrevious attempt failed:\n{{{verifier_feedback}}}{{/if}}"
+                    .into(),
+            model: "m".into(),
+            samples: 1,
+            k: None,
+            red_flaggers: None,
+            stop_sequences: Vec::new(),
+            temperature_spread: None,
+            max_concurrent: None,
+        };
+        SolveTask::new(
+            root,
+            agent,
+            llm.clone(),
+            Arc::new(RedFlagPipeline::from_configs(&[], None).unwrap()),
+            Arc::new(crate::adapters::templating::HandlebarsRenderer::new()),
+            Arc::new(SystemClock::new()),
+        )
+        .run(&mut ctx)
+        .await
+        .unwrap();
+
+        let prompts = llm.prompts();
+        assert_eq!(prompts.len(), 1);
+        assert!(
+            prompts[0].contains("Previous attempt failed:\n`cargo test` exited with Some(101)"),
+            "{}",
+            prompts[0]
+        );
+        assert!(prompts[0].contains("mismatched types"), "{}", prompts[0]);
+        assert!(prompts[0].contains("running 3 tests"), "{}", prompts[0]);
+
+        // A passing verification clears it again.
+        ctx.mark_step_solution(root, "patch v2".to_string());
+        ApplyVerifyTask::new(
+            root,
+            None,
+            Some("cargo test".into()),
+            Arc::new(StdFileSystem::new()),
+            Arc::new(SystemClock::new()),
+            Arc::new(MockCommandRunner::new(Vec::new())),
+        )
+        .run(&mut ctx)
+        .await
+        .unwrap();
+        assert_eq!(ctx.step(root).unwrap().verifier_feedback, None);
+    }
+
+    #[tokio::test]
+    async fn failing_pre_verifier_skips_apply_and_fails_step() {
+        use tempfile::tempdir;
+
+        let tmp = tempdir().unwrap();
+        let mut ctx = Context::new("Write notes", "code");
+        ctx.output_dir = Some(tmp.path().to_path_buf());
+        let root = ctx.ensure_root();
+        ctx.mark_step_solution(root, "<file path=\"notes.txt\">hi</file>".to_string());
+
+        let runner = Arc::new(MockCommandRunner::new(vec![CommandOutput {
+            status: Some(1),
+            stdout: String::new(),
+            stderr: "working tree is dirty".into(),
+        }]));
+        let task = ApplyVerifyTask::new(
+            root,
+            Some("overwrite_file".into()),
+            Some("cargo test".into()),
+            Arc::new(StdFileSystem::new()),
+            Arc::new(SystemClock::new()),
+            runner.clone(),
+        )
+        .with_pre_verifier(Some("git diff --quiet".into()));
+
+        let result = task.run(&mut ctx).await.unwrap();
+
+        assert!(matches!(result.effect, TaskEffect::None));
+        assert_eq!(runner.commands(), vec!["git diff --quiet".to_string()]);
+        assert!(!tmp.path().join("notes.txt").exists());
+        assert_eq!(ctx.step(root).unwrap().status, StepStatus::Failed);
+        let metrics = ctx.metrics().step_metrics(root).unwrap();
+        assert_eq!(metrics.pre_verification_passed, Some(false));
+        assert!(
+            metrics
+                .pre_verification_reason
+                .as_deref()
+                .is_some_and(|reason| reason.contains("git diff --quiet")),
+            "{:?}",
+            metrics.pre_verification_reason
+        );
+        assert_eq!(metrics.verification_passed, None);
+        assert_eq!(metrics.verification_reason, None);
+    }
+
+    #[test]
+    fn prompt_recorder_truncates_long_prompts_on_a_char_boundary() {
+        let mut ctx = Context::new("Write notes", "code");
+        let root = ctx.ensure_root();
+        let recorder = PromptRecorder {
+            max_bytes: 5,
+            ..PromptRecorder::new(&[]).unwrap()
+        };
+
+        recorder.record(&mut ctx, root, "solve", "abcdé fin");
+
+        assert_eq!(
+            ctx.step(root).unwrap().rendered_prompts["solve"],
+            "abcd\n[truncated 6 bytes]"
+        );
+    }
+
+    #[test]
+    fn prompt_dump_writes_each_distinct_prompt_once() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("prompts");
+        let dump = PromptDump::new(Arc::new(StdFileSystem::new()), dir.clone());
+
+        dump.record(1, "solve", "Solve: a");
+        dump.record(1, "solve", "Solve: a");
+        dump.record(1, "solve", "Solve: a\nFeedback: tests failed");
+        // A resumed run starts a new dump over the same directory
+        PromptDump::new(Arc::new(StdFileSystem::new()), dir.clone()).record(1, "solve", "Solve: a");
+
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("step-001-solve-attempt-01.txt"), "Solve: a");
+        assert_eq!(
+            read("step-001-solve-attempt-02.txt"),
+            "Solve: a\nFeedback: tests failed"
+        );
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn dry_run_apply_records_diff_without_writing() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(tmp.path().join("notes.txt"), "keep\nold\n").unwrap();
+        let mut ctx = Context::new("Write notes", "code");
+        ctx.output_dir = Some(tmp.path().to_path_buf());
+        ctx.dry_run_apply = true;
+        let root = ctx.ensure_root();
+        ctx.mark_step_solution(
+            root,
+            "<file path=\"notes.txt\">keep\nnew\n</file><file path=\"src/added.rs\">fn a() {}</file>"
+                .to_string(),
+        );
+
+        let runner = Arc::new(MockCommandRunner::new(Vec::new()));
+        let task = ApplyVerifyTask::new(
+            root,
+            Some("overwrite_file".into()),
+            Some("cargo test".into()),
+            Arc::new(StdFileSystem::new()),
+            Arc::new(SystemClock::new()),
+            runner.clone(),
+        )
+        .with_pre_verifier(Some("git diff --quiet".into()));
+
+        let result = task.run(&mut ctx).await.unwrap();
+
+        assert!(matches!(
+            result.effect,
+            TaskEffect::StepCompleted { step_id } if step_id == root
+        ));
+        assert!(runner.commands().is_empty());
+        assert_eq!(
+            std::fs::read_to_string(tmp.path().join("notes.txt")).unwrap(),
+            "keep\nold\n"
+        );
+        assert!(!tmp.path().join("src").exists());
+        let step = ctx.step(root).unwrap();
+        assert_eq!(step.status, StepStatus::Completed);
+        let pending = step.pending_apply.as_ref().expect("diff recorded");
+        assert_eq!(
+            pending.files,
+            vec![
+                tmp.path().join("notes.txt"),
+                tmp.path().join("src/added.rs")
+            ]
+        );
+        assert!(
+            pending
+                .diff
+                .contains("@@ -1,2 +1,2 @@\n keep\n-old\n+new\n"),
+            "{}",
+            pending.diff
+        );
+        assert!(pending.diff.contains("+fn a() {}"), "{}", pending.diff);
+    }
+
+    #[test]
+    fn preview_treats_an_unreadable_target_as_a_new_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        // A directory exists at the path but cannot be read as text
+        std::fs::create_dir(tmp.path().join("notes.txt")).unwrap();
+        let mut ctx = Context::new("Write notes", "code");
+        ctx.output_dir = Some(tmp.path().to_path_buf());
+        let root = ctx.ensure_root();
+        ctx.mark_step_solution(root, "<file path=\"notes.txt\">hello</file>".to_string());
+
+        let task = ApplyVerifyTask::new(
+            root,
+            Some("overwrite_file".into()),
+            None,
+            Arc::new(StdFileSystem::new()),
+            Arc::new(SystemClock::new()),
+            Arc::new(MockCommandRunner::new(Vec::new())),
+        );
+        let preview = task.preview(&ctx).unwrap();
+
+        assert!(
+            preview.diff.ends_with("@@ -0,0 +1 @@\n+hello\n"),
+            "{}",
+            preview.diff
+        );
+    }
+
+    async fn apply_with_strategy(
+        description: &str,
+        solution: &str,
+        strategy: PathExtraction,
+        pattern: Option<&str>,
+    ) -> (StepStatus, tempfile::TempDir) {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut ctx = Context::new(description, "code");
+        ctx.output_dir = Some(tmp.path().to_path_buf());
+        let root = ctx.ensure_root();
+        ctx.mark_step_solution(root, solution.to_string());
+
+        let task = ApplyVerifyTask::new(
+            root,
+            Some("overwrite_file".into()),
+            None,
+            Arc::new(StdFileSystem::new()),
+            Arc::new(SystemClock::new()),
+            Arc::new(MockCommandRunner::new(Vec::new())),
+        )
+        .with_path_extraction(strategy, pattern.map(str::to_string));
+        task.run(&mut ctx).await.unwrap();
+        (ctx.step(root).unwrap().status, tmp)
+    }
+
+    #[tokio::test]
+    async fn heuristic_path_extraction_uses_first_path_like_token() {
+        let (status, tmp) = apply_with_strategy(
+            "Create src/lib.rs with a greeting",
+            "```rust\npub fn hi() {}\n```",
+            PathExtraction::Heuristic,
+            None,
+        )
+        .await;
+
+        assert_ne!(status, StepStatus::Failed);
+        let written = std::fs::read_to_string(tmp.path().join("src/lib.rs")).unwrap();
+        assert_eq!(written, "pub fn hi() {}\n");
+    }
+
+    #[tokio::test]
+    async fn regex_path_extraction_uses_the_first_capture_group() {
+        let (status, tmp) = apply_with_strategy(
+            "Update docs (see README.md) and write target=notes/todo.txt",
+            "remember the milk",
+            PathExtraction::Regex,
+            Some(r"target=(\S+)"),
+        )
+        .await;
+
+        assert_ne!(status, StepStatus::Failed);
+        assert!(!tmp.path().join("README.md").exists());
+        let written = std::fs::read_to_string(tmp.path().join("notes/todo.txt")).unwrap();
+        assert_eq!(written, "remember the milk");
+    }
+
+    #[tokio::test]
+    async fn regex_path_extraction_fails_the_step_without_a_match() {
+        let (status, _tmp) = apply_with_strategy(
+            "Create src/lib.rs",
+            "pub fn hi() {}",
+            PathExtraction::Regex,
+            Some(r"target=(\S+)"),
+        )
+        .await;
+
+        assert_eq!(status, StepStatus::Failed);
+    }
+
+    #[tokio::test]
+    async fn xml_path_extraction_applies_file_blocks() {
+        let (status, tmp) = apply_with_strategy(
+            "Create src/lib.rs",
+            "<file path=\"src/main.rs\">fn main() {}</file>",
+            PathExtraction::Xml,
+            None,
+        )
+        .await;
+
+        assert_ne!(status, StepStatus::Failed);
+        assert!(tmp.path().join("src/main.rs").exists());
+        assert!(!tmp.path().join("src/lib.rs").exists());
+    }
+
+    #[tokio::test]
+    async fn xml_path_extraction_fails_without_file_blocks() {
+        let (status, tmp) = apply_with_strategy(
+            "Create src/lib.rs",
+            "```rust\npub fn hi() {}\n```",
+            PathExtraction::Xml,
+            None,
+        )
+        .await;
+
+        assert_eq!(status, StepStatus::Failed);
+        assert!(!tmp.path().join("src/lib.rs").exists());
+    }
+
+    #[tokio::test]
+    async fn llm_verifier_fail_marks_step_failed_with_reason() {
+        let llm = Arc::new(CapturingLlm::new("FAIL: the function never returns"));
+        let agent = AgentConfig {
+            kind: AgentKind::Verifier,
+            prompt_template: "Task: {{task}}\nSolution: {{solution}}".into(),
+            model: "judge".into(),
+            samples: 1,
+            k: None,
+            red_flaggers: None,
+            stop_sequences: Vec::new(),
+            temperature_spread: None,
+            max_concurrent: None,
+        };
+        let mut ctx = Context::new("Write loop", "code");
+        let root = ctx.ensure_root();
+        ctx.mark_step_solution(root, "loop {}".to_string());
+
+        let task = ApplyVerifyTask::new(
+            root,
+            None,
+            None,
+            Arc::new(StdFileSystem::new()),
+            Arc::new(SystemClock::new()),
+            Arc::new(StdCommandRunner::new()),
+        )
+        .with_llm_verifier(Some(Arc::new(LlmVerifier::new(
+            agent,
+            llm.clone(),
+            Arc::new(crate::adapters::templating::HandlebarsRenderer::new()),
+        ))));
+        let result = task.run(&mut ctx).await.unwrap();
+
+        assert_eq!(
+            llm.prompts().as_slice(),
+            ["Task: Write loop\nSolution: loop {}".to_string()]
+        );
+        assert!(matches!(result.effect, TaskEffect::None));
+        assert_eq!(ctx.step(root).unwrap().status, StepStatus::Failed);
+        let metrics = ctx.metrics().step_metrics(root).unwrap();
+        assert_eq!(metrics.verification_passed, Some(false));
+        assert_eq!(
+            metrics.verification_reason.as_deref(),
+            Some("the function never returns")
+        );
+    }
+
+    #[test]
+    fn parses_verifier_verdicts() {
+        assert_eq!(parse_verdict("PASS"), Ok(()));
+        assert_eq!(parse_verdict("\npass - looks good"), Ok(()));
+        assert_eq!(parse_verdict("FAIL: off by one"), Err("off by one".into()));
+        assert!(parse_verdict("FAIL").is_err());
+        assert!(parse_verdict("maybe?").is_err());
+        assert_eq!(parse_verdict("Passed."), Ok(()));
+        assert_eq!(
+            parse_verdict("FAILED: missing test"),
+            Err("missing test".into())
+        );
+        assert!(parse_verdict("PASSWORD leaked").is_err());
+    }
+
+    #[tokio::test]
+    async fn dedupe_candidates_merges_duplicates_and_weights_votes() {
+        let llm = Arc::new(CapturingLlm::new("1"));
+        let agent = AgentConfig {
+            kind: AgentKind::SolutionDiscriminator,
+            prompt_template: "{{task}}".into(),
+            model: "m".into(),
+            samples: 1,
+            k: None,
+            red_flaggers: None,
+            stop_sequences: Vec::new(),
+            temperature_spread: None,
+            max_concurrent: None,
+        };
+        let mut ctx = Context::new("Fix it", "code");
+        let root = ctx.ensure_root();
+        ctx.register_solutions(
+            root,
+            vec!["fix  a".into(), "fix b".into(), "fix a\n".into()],
+        );
+
+        let task = SolutionVoteTask::new(
+            root,
+            agent,
+            llm.clone(),
+            2,
+            Arc::new(crate::adapters::templating::HandlebarsRenderer::new()),
+            Arc::new(SystemClock::new()),
+        )
+        .with_dedupe_candidates(true);
+        task.run(&mut ctx).await.unwrap();
+
+        let prompts = llm.prompts();
+        assert!(prompts[0].contains("Option 2:"));
+        assert!(!prompts[0].contains("Option 3:"));
+        assert_eq!(
+            ctx.step(root).unwrap().winning_solution.as_deref(),
+            Some("fix  a")
+        );
+        assert_eq!(
+            ctx.metrics().step_metrics(root).unwrap().vote_margin,
+            Some(2)
+        );
+        assert_eq!(
+            ctx.metrics().step_metrics(root).unwrap().vote_histogram,
+            vec![2, 0, 0]
+        );
+    }
+
+    #[tokio::test]
+    async fn shuffled_vote_maps_the_choice_back_to_the_original_candidate() {
+        let seed = Some(7);
+        let mut ctx = Context::new("Fix it", "code");
+        let root = ctx.ensure_root();
+        ctx.register_solutions(root, vec!["fix a".into(), "fix b".into(), "fix c".into()]);
+
+        let order = option_order(3, seed, root, 0);
+        assert_ne!(order, vec![0, 1, 2], "seed should reorder the options");
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, vec![0, 1, 2]);
+        let shown = order.iter().position(|&original| original == 2).unwrap();
+
+        let llm = Arc::new(CapturingLlm::new((shown + 1).to_string()));
+        let agent = AgentConfig {
+            kind: AgentKind::SolutionDiscriminator,
+            prompt_template: "{{task}}".into(),
+            model: "m".into(),
+            samples: 1,
+            k: None,
+            red_flaggers: None,
+            stop_sequences: Vec::new(),
+            temperature_spread: None,
+            max_concurrent: None,
+        };
+        let task = SolutionVoteTask::new(
+            root,
+            agent,
+            llm.clone(),
+            2,
+            Arc::new(crate::adapters::templating::HandlebarsRenderer::new()),
+            Arc::new(SystemClock::new()),
+        )
+        .with_shuffle_seed(seed);
+        task.run(&mut ctx).await.unwrap();
+
+        let prompts = llm.prompts();
+        assert!(prompts[0].contains(&format!("Option {}:\nfix c", shown + 1)));
+        assert_eq!(
+            ctx.step(root).unwrap().winning_solution.as_deref(),
+            Some("fix c")
+        );
+        assert_eq!(
+            ctx.metrics().step_metrics(root).unwrap().vote_histogram,
+            vec![0, 0, 1]
+        );
+    }
+
+    #[tokio::test]
+    async fn shuffled_vote_gives_every_voter_its_own_order() {
+        /// Always votes for "fix c", wherever its prompt shows it.
+        #[derive(Default)]
+        struct FindingVoter {
+            prompts: Mutex<Vec<String>>,
+        }
+
+        #[async_trait]
+        impl LlmClient for FindingVoter {
+            async fn chat_completion(
+                &self,
+                _model: &str,
+                prompt: &str,
+                _options: &LlmOptions,
+            ) -> crate::core::Result<String> {
+                self.prompts.lock().unwrap().push(prompt.to_string());
+                let shown = (1..=4)
+                    .find(|label| prompt.contains(&format!("Option {label}:\nfix c")))
+                    .unwrap();
+                Ok(shown.to_string())
+            }
+        }
+
+        let seed = Some(11);
+        let mut ctx = Context::new("Fix it", "code");
+        let root = ctx.ensure_root();
+        ctx.register_solutions(
+            root,
+            vec![
+                "fix a".into(),
+                "fix b".into(),
+                "fix c".into(),
+                "fix d".into(),
+            ],
+        );
+        let llm = Arc::new(FindingVoter::default());
+        let agent = AgentConfig {
+            kind: AgentKind::SolutionDiscriminator,
+            prompt_template: "{{task}}".into(),
+            model: "m".into(),
+            samples: 4,
+            k: None,
+            red_flaggers: None,
+            stop_sequences: Vec::new(),
+            temperature_spread: None,
+            max_concurrent: None,
+        };
+        let task = SolutionVoteTask::new(
+            root,
+            agent,
+            llm.clone(),
+            4,
+            Arc::new(crate::adapters::templating::HandlebarsRenderer::new()),
+            Arc::new(SystemClock::new()),
+        )
+        .with_shuffle_seed(seed);
+        task.run(&mut ctx).await.unwrap();
+
+        let prompts = llm.prompts.lock().unwrap();
+        assert!(
+            prompts.iter().any(|prompt| prompt != &prompts[0]),
+            "every voter saw the same order"
+        );
+        let metrics = ctx.metrics().step_metrics(root).unwrap();
+        assert_eq!(metrics.vote_histogram, vec![0, 0, 4, 0]);
+        let orders = &metrics.vote_orders["solution_vote"];
+        assert_eq!(*orders, ballot_orders(4, seed, root, 4));
+        assert_ne!(orders[0], orders[1]);
+    }
+
+    #[tokio::test]
+    async fn solve_forwards_agent_stop_sequences_to_client() {
+        let llm = Arc::new(CapturingLlm::new("<file path=\"a.txt\">a"));
+        let agent = AgentConfig {
+            kind: AgentKind::Solver,
+            prompt_template: "{{task}}".into(),
+            model: "m".into(),
+            samples: 2,
+            k: None,
+            red_flaggers: None,
+            stop_sequences: vec!["</file>".into()],
+            temperature_spread: None,
+            max_concurrent: None,
+        };
+        let mut ctx = Context::new("Write a.txt", "code");
+        let root = ctx.ensure_root();
+        let task = SolveTask::new(
+            root,
+            agent,
+            llm.clone(),
+            Arc::new(RedFlagPipeline::from_configs(&[], None).unwrap()),
+            Arc::new(crate::adapters::templating::HandlebarsRenderer::new()),
+            Arc::new(SystemClock::new()),
+        );
+
+        task.run(&mut ctx).await.unwrap();
+
+        let options = llm.options();
+        assert_eq!(options.len(), 2);
+        assert!(
+            options
+                .iter()
+                .all(|o| o.stop_sequences == vec!["</file>".to_string()])
+        );
+    }
+
+    #[tokio::test]
+    async fn temperature_spread_requests_distinct_temperatures() {
+        let llm = Arc::new(CapturingLlm::new("<file path=\"a.txt\">a</file>"));
+        let agent = AgentConfig {
+            kind: AgentKind::Solver,
+            prompt_template: "{{task}}".into(),
+            model: "m".into(),
+            samples: 5,
+            k: None,
+            red_flaggers: None,
+            stop_sequences: Vec::new(),
+            temperature_spread: Some(TemperatureSpread { min: 0.2, max: 1.0 }),
+            max_concurrent: None,
+        };
+        let mut ctx = Context::new("Write a.txt", "code");
+        let root = ctx.ensure_root();
+        let task = SolveTask::new(
+            root,
+            agent,
+            llm.clone(),
+            Arc::new(RedFlagPipeline::from_configs(&[], None).unwrap()),
+            Arc::new(crate::adapters::templating::HandlebarsRenderer::new()),
+            Arc::new(SystemClock::new()),
+        );
+
+        task.run(&mut ctx).await.unwrap();
+
+        let mut temperatures: Vec<f32> = llm
+            .options()
+            .iter()
+            .map(|o| o.temperature.expect("every sample sets a temperature"))
+            .collect();
+        temperatures.sort_by(f32::total_cmp);
+        let expected = [0.2, 0.4, 0.6, 0.8, 1.0];
+        assert_eq!(temperatures.len(), expected.len());
+        for (actual, expected) in temperatures.iter().zip(expected) {
+            assert!((actual - expected).abs() < 1e-6, "{temperatures:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn temperature_spread_continues_across_top_up_batches() {
+        /// Answers too verbosely at the coldest temperature, which the length flagger rejects.
+        #[derive(Default)]
+        struct ColdVerboseLlm {
+            temperatures: Mutex<Vec<f32>>,
+        }
+
+        #[async_trait]
+        impl LlmClient for ColdVerboseLlm {
+            async fn chat_completion(
+                &self,
+                _model: &str,
+                _prompt: &str,
+                options: &LlmOptions,
+            ) -> crate::core::Result<String> {
+                let temperature = options.temperature.unwrap_or_default();
+                self.temperatures.lock().unwrap().push(temperature);
+                Ok(if temperature == 0.0 {
+                    "far too many words".into()
+                } else {
+                    "ok".into()
+                })
+            }
+        }
+
+        let configs = vec![RedFlaggerDescriptor {
+            kind: "length".into(),
+            params: HashMap::from([(String::from("max_tokens"), json!(2))]),
+        }];
+        let pipeline = Arc::new(RedFlagPipeline::from_configs(&configs, None).unwrap());
+        let llm = Arc::new(ColdVerboseLlm::default());
+        let mut ctx = Context::new("demo", "code");
+        let root_id = ctx.ensure_root();
+        let responses = SampleCollector::new(&mut ctx, root_id, llm.clone(), pipeline, "test")
+            .with_temperature_spread(Some(TemperatureSpread { min: 0.0, max: 1.0 }))
+            .collect("prompt".to_string(), 2, "model")
+            .await
+            .expect("the top-up samples away from the rejected temperature");
+
+        assert_eq!(responses, vec!["ok".to_string(), "ok".to_string()]);
+        let mut temperatures = llm.temperatures.lock().unwrap().clone();
+        temperatures.sort_by(f32::total_cmp);
+        assert_eq!(temperatures, vec![0.0, 0.5, 1.0]);
+    }
+
+    #[tokio::test]
+    async fn retry_on_empty_resamples_a_blank_solver_batch() {
+        /// Answers the first `blank_calls` requests with whitespace, then with a file block.
+        struct FlakyLlm {
+            blank_calls: usize,
+            calls: Mutex<usize>,
+        }
+
+        #[async_trait]
+        impl LlmClient for FlakyLlm {
+            async fn chat_completion(
+                &self,
+                _model: &str,
+                _prompt: &str,
+                _options: &LlmOptions,
+            ) -> crate::core::Result<String> {
+                let mut calls = self.calls.lock().unwrap();
+                *calls += 1;
+                if *calls <= self.blank_calls {
+                    Ok("  \n".into())
+                } else {
+                    Ok("<file path=\"a.txt\">a</file>".into())
+                }
+            }
+        }
+
+        let solve = |retries: usize| async move {
+            let llm = Arc::new(FlakyLlm {
+                blank_calls: 2,
+                calls: Mutex::new(0),
+            });
+            let agent = AgentConfig {
+                kind: AgentKind::Solver,
+                prompt_template: "{{task}}".into(),
+                model: "m".into(),
+                samples: 2,
+                k: None,
+                red_flaggers: None,
+                stop_sequences: Vec::new(),
+                temperature_spread: None,
+                max_concurrent: None,
+            };
+            let mut ctx = Context::new("Write a.txt", "code");
+            let root = ctx.ensure_root();
+            let result = SolveTask::new(
+                root,
+                agent,
+                llm.clone(),
+                Arc::new(RedFlagPipeline::from_configs(&[], None).unwrap()),
+                Arc::new(crate::adapters::templating::HandlebarsRenderer::new()),
+                Arc::new(SystemClock::new()),
+            )
+            .with_retry_on_empty(retries)
+            .run(&mut ctx)
+            .await;
+            (result, ctx, *llm.calls.lock().unwrap())
+        };
+
+        let (result, ctx, calls) = solve(1).await;
+        result.unwrap();
+        assert_eq!(calls, 4, "one blank batch, then one good batch");
+        let step = ctx.step(0).unwrap();
+        assert_eq!(step.candidate_solutions.len(), 2);
+        assert_eq!(ctx.metrics.resample_count, 1);
+
+        let (result, ctx, calls) = solve(0).await;
+        result.unwrap();
+        assert_eq!(calls, 2, "the guard is off by default");
+        assert_eq!(ctx.step(0).unwrap().candidate_solutions, vec!["  \n"; 2]);
+
+        let (result, _, calls) = solve(2).await;
+        result.unwrap();
+        assert_eq!(calls, 4);
+    }
+
+    #[tokio::test]
+    async fn retry_on_empty_drops_blank_answers_and_fails_after_the_last_retry() {
+        /// Answers every other request with whitespace, or every request when `always_blank`.
+        struct BlankLlm {
+            always_blank: bool,
+            calls: Mutex<usize>,
+        }
+
+        #[async_trait]
+        impl LlmClient for BlankLlm {
+            async fn chat_completion(
+                &self,
+                _model: &str,
+                _prompt: &str,
+                _options: &LlmOptions,
+            ) -> crate::core::Result<String> {
+                let mut calls = self.calls.lock().unwrap();
+                *calls += 1;
+                if self.always_blank || calls.is_multiple_of(2) {
+                    Ok(" ".into())
+                } else {
+                    Ok("<file path=\"a.txt\">a</file>".into())
+                }
+            }
+        }
+
+        let solve = |always_blank: bool| async move {
+            let llm = Arc::new(BlankLlm {
+                always_blank,
+                calls: Mutex::new(0),
+            });
+            let agent = AgentConfig {
+                kind: AgentKind::Solver,
+                prompt_template: "{{task}}".into(),
+                model: "m".into(),
+                samples: 2,
+                k: None,
+                red_flaggers: None,
+                stop_sequences: Vec::new(),
+                temperature_spread: None,
+                max_concurrent: None,
+            };
+            let mut ctx = Context::new("Write a.txt", "code");
+            let root = ctx.ensure_root();
+            let result = SolveTask::new(
+                root,
+                agent,
+                llm.clone(),
+                Arc::new(RedFlagPipeline::from_configs(&[], None).unwrap()),
+                Arc::new(crate::adapters::templating::HandlebarsRenderer::new()),
+                Arc::new(SystemClock::new()),
+            )
+            .with_retry_on_empty(1)
+            .run(&mut ctx)
+            .await;
+            (result, ctx)
+        };
+
+        let (result, ctx) = solve(false).await;
+        result.unwrap();
+        assert_eq!(
+            ctx.step(0).unwrap().candidate_solutions,
+            vec!["<file path=\"a.txt\">a</file>"]
+        );
+
+        let (result, _) = solve(true).await;
+        let err = result.unwrap_err();
+        assert!(
+            err.to_string().contains("2 batch(es) came back empty"),
+            "{err:#}"
+        );
+    }
+
+    #[tokio::test]
+    async fn agent_max_concurrent_caps_only_that_agents_samples() {
+        /// Records the most requests in flight at once for each model.
+        #[derive(Default)]
+        struct PeakTracker {
+            in_flight: Mutex<HashMap<String, usize>>,
+            peaks: Mutex<HashMap<String, usize>>,
+        }
+
+        #[async_trait]
+        impl LlmClient for PeakTracker {
+            async fn chat_completion(
+                &self,
+                model: &str,
+                _prompt: &str,
+                _options: &LlmOptions,
+            ) -> crate::core::Result<String> {
+                {
+                    let mut in_flight = self.in_flight.lock().unwrap();
+                    let current = in_flight.entry(model.to_string()).or_default();
+                    *current += 1;
+                    let mut peaks = self.peaks.lock().unwrap();
+                    let peak = peaks.entry(model.to_string()).or_default();
+                    *peak = (*peak).max(*current);
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                *self.in_flight.lock().unwrap().get_mut(model).unwrap() -= 1;
+                Ok(if model == "voter" { "1" } else { "answer" }.into())
+            }
+        }
+
+        let agent = |kind, model: &str, max_concurrent| AgentConfig {
+            kind,
+            prompt_template: "{{task}}".into(),
+            model: model.into(),
+            samples: 4,
+            k: None,
+            red_flaggers: None,
+            stop_sequences: Vec::new(),
+            temperature_spread: None,
+            max_concurrent,
+        };
+        let llm = Arc::new(PeakTracker::default());
+        let renderer: Arc<dyn PromptRenderer> =
+            Arc::new(crate::adapters::templating::HandlebarsRenderer::new());
+        let mut ctx = Context::new("Answer", "code");
+        let root = ctx.ensure_root();
+
+        SolveTask::new(
+            root,
+            agent(AgentKind::Solver, "solver", Some(1)),
+            llm.clone(),
+            Arc::new(RedFlagPipeline::from_configs(&[], None).unwrap()),
+            renderer.clone(),
+            Arc::new(SystemClock::new()),
+        )
+        .run(&mut ctx)
+        .await
+        .unwrap();
+        SolutionVoteTask::new(
+            root,
+            agent(AgentKind::SolutionDiscriminator, "voter", None),
+            llm.clone(),
+            2,
+            renderer,
+            Arc::new(SystemClock::new()),
+        )
+        .run(&mut ctx)
+        .await
+        .unwrap();
+
+        let peaks = llm.peaks.lock().unwrap();
+        assert_eq!(peaks["solver"], 1);
+        assert_eq!(peaks["voter"], 4);
     }
 }
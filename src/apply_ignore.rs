@@ -0,0 +1,166 @@
+//! `.microfactoryignore`: gitignore-style patterns naming files the applier must never write.
+
+use std::path::{Component, Path};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+
+/// File name looked up in the output root (or the working directory) at run start.
+pub const IGNORE_FILE_NAME: &str = ".microfactoryignore";
+
+/// Parsed ignore patterns, evaluated in file order with the last match winning.
+///
+/// Supported syntax: blank lines and `#` comments are skipped, `!` re-includes, a trailing `/`
+/// only matches directories, a leading or inner `/` anchors the pattern to the root, and `*`,
+/// `?`, `[...]`, and `**` behave as in `.gitignore`. A matching directory covers every path
+/// below it.
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    rules: Vec<IgnoreRule>,
+}
+
+#[derive(Debug, Clone)]
+struct IgnoreRule {
+    pattern: Regex,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl IgnoreRules {
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut rules = Vec::new();
+        for (idx, line) in source.lines().enumerate() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let anchored = line.contains('/');
+            let body = glob_to_regex(line.trim_start_matches('/'));
+            let source = if anchored {
+                format!("^{body}$")
+            } else {
+                format!("^(?:.*/)?{body}$")
+            };
+            let pattern = Regex::new(&source).with_context(|| {
+                format!("Invalid {IGNORE_FILE_NAME} pattern on line {}", idx + 1)
+            })?;
+            rules.push(IgnoreRule {
+                pattern,
+                negated,
+                dir_only,
+            });
+        }
+        Ok(Self { rules })
+    }
+
+    /// Whether `path` (relative to the output root) is excluded from apply writes.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let segments: Vec<String> = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(segment) => Some(segment.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect();
+        if segments.is_empty() {
+            return false;
+        }
+        // Every ancestor directory, then the file itself.
+        let candidates: Vec<(String, bool)> = (1..=segments.len())
+            .map(|len| (segments[..len].join("/"), len < segments.len()))
+            .collect();
+
+        let mut ignored = false;
+        for rule in &self.rules {
+            let matched = candidates.iter().any(|(candidate, is_dir)| {
+                (*is_dir || !rule.dir_only) && rule.pattern.is_match(candidate)
+            });
+            if matched {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut out = String::new();
+    let mut idx = 0;
+    while idx < chars.len() {
+        match chars[idx] {
+            '*' if chars.get(idx + 1) == Some(&'*') => {
+                if chars.get(idx + 2) == Some(&'/') {
+                    out.push_str("(?:.*/)?");
+                    idx += 3;
+                } else {
+                    out.push_str(".*");
+                    idx += 2;
+                }
+                continue;
+            }
+            '*' => out.push_str("[^/]*"),
+            '?' => out.push_str("[^/]"),
+            '[' => match chars[idx + 1..].iter().position(|&ch| ch == ']') {
+                Some(len) => {
+                    let class: String = chars[idx + 1..idx + 1 + len].iter().collect();
+                    let class = class
+                        .strip_prefix('!')
+                        .map_or(class.clone(), |rest| format!("^{rest}"));
+                    out.push('[');
+                    out.push_str(&class.replace('\\', "\\\\"));
+                    out.push(']');
+                    idx += len + 2;
+                    continue;
+                }
+                None => out.push_str(r"\["),
+            },
+            ch => out.push_str(&regex::escape(&ch.to_string())),
+        }
+        idx += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ignored(rules: &str, path: &str) -> bool {
+        IgnoreRules::parse(rules)
+            .unwrap()
+            .is_ignored(Path::new(path))
+    }
+
+    #[test]
+    fn matches_gitignore_style_patterns() {
+        let rules = "# generated\n*.lock\n/build/\ngen/**/*.rs\n!gen/keep/*.rs\ndocs/?.md\n";
+        assert!(ignored(rules, "Cargo.lock"));
+        assert!(ignored(rules, "nested/deep/yarn.lock"));
+        assert!(ignored(rules, "build/out.txt"));
+        assert!(!ignored(rules, "src/build/out.txt"), "/build/ is anchored");
+        assert!(ignored(rules, "gen/a/b/model.rs"));
+        assert!(ignored(rules, "gen/model.rs"));
+        assert!(!ignored(rules, "gen/keep/model.rs"), "negation re-includes");
+        assert!(ignored(rules, "docs/a.md"));
+        assert!(!ignored(rules, "docs/ab.md"));
+        assert!(!ignored(rules, "src/main.rs"));
+    }
+
+    #[test]
+    fn directory_patterns_skip_files_of_the_same_name() {
+        assert!(ignored("vendor/", "vendor/lib.rs"));
+        assert!(!ignored("vendor/", "vendor"));
+        assert!(ignored("vendor", "vendor"));
+        assert!(ignored("[ab]*.txt", "b1.txt"));
+        assert!(!ignored("[!ab]*.txt", "b1.txt"));
+    }
+}
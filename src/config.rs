@@ -5,30 +5,63 @@ use std::{
     str::FromStr,
 };
 
-use anyhow::{Context, Result, anyhow, ensure};
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_yaml::Value;
+use sha2::{Digest, Sha256};
 
-use crate::core::config::{AgentSettings, DomainRuntimeConfig};
-use crate::core::domain::{AgentKind, RedFlaggerDescriptor};
+use crate::core::config::{AgentSettings, DEFAULT_MAX_FILE_BYTES, DomainRuntimeConfig};
+use crate::core::domain::{
+    AgentKind, EmptyPlanPolicy, OutputEncoding, PathExtraction, RedFlaggerDescriptor,
+    TemperatureSpread, VoteEnumeration,
+};
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct MicrofactoryConfig {
     pub domains: HashMap<String, DomainConfig>,
+    /// Domain used in place of any requested domain the config does not define.
+    #[serde(default)]
+    pub default_domain: Option<String>,
+    /// Base directory for relative prompt template paths, itself relative to the config file's
+    /// directory. Defaults to the config file's directory.
+    #[serde(default)]
+    pub template_dir: Option<PathBuf>,
+    /// SHA-256 of the file and its hydrated templates; set by [`Self::from_path`].
+    #[serde(skip)]
+    content_hash: Option<String>,
 }
 
 impl MicrofactoryConfig {
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_path_with_template_dir(path, None)
+    }
+
+    /// Like [`Self::from_path`], but resolves relative prompt template paths against
+    /// `template_dir` when given, taking precedence over the config's own `template_dir`.
+    pub fn from_path_with_template_dir<P: AsRef<Path>>(
+        path: P,
+        template_dir: Option<&Path>,
+    ) -> Result<Self> {
         let path_ref = path.as_ref();
         let raw = fs::read_to_string(path_ref)
             .with_context(|| format!("Failed to read config file at {}", path_ref.display()))?;
         let mut config = Self::from_yaml_str(&raw)
             .with_context(|| format!("Invalid configuration in {}", path_ref.display()))?;
-        let base_dir = path_ref.parent().unwrap_or_else(|| Path::new("."));
+        let config_dir = path_ref.parent().unwrap_or_else(|| Path::new("."));
+        let base_dir = match (template_dir, &config.template_dir) {
+            (Some(dir), _) => dir.to_path_buf(),
+            (None, Some(dir)) => config_dir.join(dir),
+            (None, None) => config_dir.to_path_buf(),
+        };
         config
-            .hydrate_templates(base_dir)
+            .hydrate_templates(&base_dir)
             .with_context(|| format!("Failed to hydrate templates for {}", path_ref.display()))?;
         config.validate()?;
+        for warning in config.template_warnings() {
+            tracing::warn!(config = %path_ref.display(), "{warning}");
+        }
+        config.content_hash = Some(config.hash_with_templates(&raw));
         Ok(config)
     }
 
@@ -38,15 +71,66 @@ impl MicrofactoryConfig {
         Ok(config)
     }
 
+    /// Hex SHA-256 of the loaded file plus every hydrated prompt template, so editing either
+    /// changes it. `None` for configs not loaded through [`Self::from_path`].
+    pub fn content_hash(&self) -> Option<&str> {
+        self.content_hash.as_deref()
+    }
+
+    fn hash_with_templates(&self, raw: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(raw.as_bytes());
+        let mut names: Vec<&String> = self.domains.keys().collect();
+        names.sort();
+        for name in names {
+            let domain = &self.domains[name];
+            let agents = &domain.agents;
+            let templates = [
+                &agents.decomposition,
+                &agents.decomposition_discriminator,
+                &agents.solver,
+                &agents.solution_discriminator,
+            ]
+            .into_iter()
+            .chain(domain.verifier_agent.as_ref())
+            .map(|agent| agent.prompt_template.as_str());
+            for template in std::iter::once(name.as_str()).chain(templates) {
+                // Length-prefix each part so moving text between templates changes the hash.
+                hasher.update((template.len() as u64).to_le_bytes());
+                hasher.update(template.as_bytes());
+            }
+        }
+        hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    /// The domain named `name`, or the `default_domain` when `name` is not defined.
     pub fn domain(&self, name: &str) -> Option<&DomainConfig> {
-        self.domains.get(name)
+        self.domains.get(name).or_else(|| {
+            self.default_domain
+                .as_deref()
+                .and_then(|fallback| self.domains.get(fallback))
+        })
+    }
+
+    /// Name and config of the domain `name` resolves to, following `default_domain` for
+    /// undefined names.
+    pub fn resolve_domain<'a>(&'a self, name: &'a str) -> Option<(&'a str, &'a DomainConfig)> {
+        if let Some(domain) = self.domains.get(name) {
+            return Some((name, domain));
+        }
+        let fallback = self.default_domain.as_deref()?;
+        self.domains.get(fallback).map(|domain| (fallback, domain))
     }
 
     pub fn runtime_domain(&self, name: &str) -> Result<DomainRuntimeConfig> {
-        let domain = self
-            .domain(name)
+        let (resolved, domain) = self
+            .resolve_domain(name)
             .ok_or_else(|| anyhow!("Unknown domain: {name}"))?;
-        domain.to_runtime(name)
+        domain.to_runtime(resolved)
     }
 
     pub fn validate(&self) -> Result<()> {
@@ -54,6 +138,12 @@ impl MicrofactoryConfig {
             !self.domains.is_empty(),
             "Configuration must contain at least one domain"
         );
+        if let Some(fallback) = &self.default_domain {
+            ensure!(
+                self.domains.contains_key(fallback),
+                "default_domain '{fallback}' is not a defined domain"
+            );
+        }
         for (name, domain) in &self.domains {
             domain
                 .validate(name)
@@ -62,6 +152,17 @@ impl MicrofactoryConfig {
         Ok(())
     }
 
+    /// Lint the hydrated prompt templates, returning one message per agent whose template never
+    /// interpolates the task (`{{prompt}}` or `{{task}}`), so every sample would ignore the input.
+    pub fn template_warnings(&self) -> Vec<String> {
+        let mut names: Vec<&String> = self.domains.keys().collect();
+        names.sort();
+        names
+            .into_iter()
+            .flat_map(|name| self.domains[name].agents.template_warnings(name))
+            .collect()
+    }
+
     fn hydrate_templates<P: AsRef<Path>>(&mut self, base_dir: P) -> Result<()> {
         let base = base_dir.as_ref();
         for domain in self.domains.values_mut() {
@@ -86,23 +187,105 @@ pub struct DomainConfig {
     pub step_granularity: StepGranularity,
     #[serde(default)]
     pub verifier: Option<String>,
+    /// Command that must succeed before the applier runs (e.g. a clean working tree check).
+    #[serde(default)]
+    pub pre_verifier: Option<String>,
     #[serde(default)]
     pub applier: Option<String>,
     #[serde(default)]
     pub red_flaggers: Vec<RedFlaggerConfig>,
+    /// Line endings and BOM used when the `overwrite_file` applier writes files.
+    #[serde(default)]
+    pub output_encoding: OutputEncoding,
+    /// LLM judge run after apply, alongside any `verifier` command.
+    #[serde(default)]
+    pub verifier_agent: Option<AgentDefinition>,
+    /// Option labels used by both discriminators' vote prompts and their response parsing.
+    #[serde(default)]
+    pub vote_enumeration: VoteEnumeration,
+    /// Whether a decomposition whose proposals contain no parseable subtasks proceeds, pauses,
+    /// or fails the run.
+    #[serde(default)]
+    pub on_empty_plan: EmptyPlanPolicy,
+    /// Sample one decomposition and adopt its subtasks directly, skipping the decomposition vote.
+    #[serde(default)]
+    pub single_shot_decomposition: bool,
+    /// How `overwrite_file` finds the target path of a solution without `<file>` blocks.
+    #[serde(default)]
+    pub path_extraction: PathExtraction,
+    /// Pattern whose first capture group is the target path when `path_extraction: regex`.
+    #[serde(default)]
+    pub path_pattern: Option<String>,
+    /// Largest file, in bytes, `overwrite_file` may write. A step whose solution holds a bigger
+    /// file fails before anything is written.
+    #[serde(default = "default_max_file_bytes")]
+    pub max_file_bytes: usize,
+    /// Clean samples the decomposition and solver stages must accept before voting; unset
+    /// requires every requested sample. `--min-samples-accepted` overrides it.
+    #[serde(default)]
+    pub min_samples_accepted: Option<usize>,
+    /// Regexes whose matches are replaced with `[REDACTED]` in prompts saved by `--record-prompts`.
+    #[serde(default)]
+    pub prompt_redactions: Vec<String>,
+    /// Extra HTTP headers sent with every LLM request (e.g. `OpenAI-Organization`).
+    #[serde(default)]
+    pub llm_headers: BTreeMap<String, String>,
 }
 
 impl DomainConfig {
     fn hydrate_templates(&mut self, base_dir: &Path) -> Result<()> {
-        self.agents.hydrate_templates(base_dir)
+        self.agents.hydrate_templates(base_dir)?;
+        if let Some(verifier) = &mut self.verifier_agent {
+            verifier.hydrate_template(base_dir)?;
+        }
+        Ok(())
     }
 
     fn validate(&self, name: &str) -> Result<()> {
         self.agents.validate(name)?;
+        if let Some(verifier) = &self.verifier_agent {
+            verifier.validate(name, "verifier_agent")?;
+        }
         self.step_granularity.validate(name)?;
         for (idx, flagger) in self.red_flaggers.iter().enumerate() {
             validate_red_flagger(name, idx, flagger)?;
         }
+        self.validate_path_extraction(name)?;
+        ensure!(
+            self.max_file_bytes > 0,
+            "Domain '{name}' max_file_bytes must be at least 1"
+        );
+        ensure!(
+            self.min_samples_accepted != Some(0),
+            "Domain '{name}' min_samples_accepted must be at least 1"
+        );
+        for (idx, pattern) in self.prompt_redactions.iter().enumerate() {
+            Regex::new(pattern).with_context(|| {
+                format!("Domain '{name}' prompt_redactions[{idx}] is not a valid regex")
+            })?;
+        }
+        Ok(())
+    }
+
+    fn validate_path_extraction(&self, name: &str) -> Result<()> {
+        match (self.path_extraction, self.path_pattern.as_deref()) {
+            (PathExtraction::Regex, Some(pattern)) => {
+                let regex = Regex::new(pattern).with_context(|| {
+                    format!("Domain '{name}' path_pattern is not a valid regex")
+                })?;
+                ensure!(
+                    regex.captures_len() > 1,
+                    "Domain '{name}' path_pattern must contain a capture group"
+                );
+            }
+            (PathExtraction::Regex, None) => {
+                bail!("Domain '{name}' sets path_extraction: regex but no path_pattern")
+            }
+            (_, Some(_)) => {
+                bail!("Domain '{name}' path_pattern requires path_extraction: regex")
+            }
+            (_, None) => {}
+        }
         Ok(())
     }
 
@@ -131,16 +314,38 @@ impl DomainConfig {
             )?,
         );
 
+        if let Some(verifier) = &self.verifier_agent {
+            agents.insert(
+                AgentKind::Verifier,
+                agent_settings(AgentKind::Verifier, verifier)?,
+            );
+        }
+
         Ok(DomainRuntimeConfig {
             name: name.to_string(),
             agents,
             applier: self.applier.clone(),
             verifier: self.verifier.clone(),
+            pre_verifier: self.pre_verifier.clone(),
             red_flaggers: convert_red_flaggers(&self.red_flaggers)?,
+            output_encoding: self.output_encoding,
+            vote_enumeration: self.vote_enumeration,
+            on_empty_plan: self.on_empty_plan,
+            single_shot_decomposition: self.single_shot_decomposition,
+            path_extraction: self.path_extraction,
+            path_pattern: self.path_pattern.clone(),
+            max_file_bytes: self.max_file_bytes,
+            min_samples_accepted: self.min_samples_accepted,
+            prompt_redactions: self.prompt_redactions.clone(),
+            granularity_flagger: self.step_granularity.red_flagger(),
         })
     }
 }
 
+fn default_max_file_bytes() -> usize {
+    DEFAULT_MAX_FILE_BYTES
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct AgentsConfig {
     pub decomposition: AgentDefinition,
@@ -159,6 +364,23 @@ impl AgentsConfig {
         Ok(())
     }
 
+    fn template_warnings(&self, domain: &str) -> Vec<String> {
+        [
+            ("decomposition", &self.decomposition),
+            ("decomposition_discriminator", &self.decomposition_discriminator),
+            ("solver", &self.solver),
+            ("solution_discriminator", &self.solution_discriminator),
+        ]
+        .into_iter()
+        .filter(|(_, agent)| !references_task_variable(&agent.prompt_template))
+        .map(|(role, _)| {
+            format!(
+                "Domain '{domain}' role '{role}' prompt_template never uses {{{{prompt}}}} or {{{{task}}}}; samples will ignore the task"
+            )
+        })
+        .collect()
+    }
+
     fn validate(&self, domain: &str) -> Result<()> {
         self.decomposition.validate(domain, "decomposition")?;
         self.decomposition_discriminator
@@ -180,6 +402,15 @@ pub struct AgentDefinition {
     pub k: Option<usize>,
     #[serde(default)]
     pub red_flaggers: Option<Vec<RedFlaggerConfig>>,
+    #[serde(default)]
+    pub stop_sequences: Vec<String>,
+    /// Request each sample at a different temperature across this range instead of one shared setting.
+    #[serde(default)]
+    pub temperature_spread: Option<TemperatureSpread>,
+    /// Most sample requests this agent keeps in flight; the global `max_concurrent_llm` still
+    /// caps the whole run.
+    #[serde(default)]
+    pub max_concurrent: Option<usize>,
 }
 
 impl AgentDefinition {
@@ -209,12 +440,30 @@ impl AgentDefinition {
         if let Some(k) = self.k {
             ensure!(k > 0, "Domain '{domain}' role '{role}' k must be > 0");
         }
+        if let Some(limit) = self.max_concurrent {
+            ensure!(
+                limit > 0,
+                "Domain '{domain}' role '{role}' max_concurrent must be > 0"
+            );
+        }
         if let Some(flaggers) = &self.red_flaggers {
             for (idx, flagger) in flaggers.iter().enumerate() {
                 validate_red_flagger(domain, idx, flagger)
                     .with_context(|| format!("Invalid red_flagger for role '{role}'"))?;
             }
         }
+        for (idx, stop) in self.stop_sequences.iter().enumerate() {
+            ensure!(
+                !stop.is_empty(),
+                "Domain '{domain}' role '{role}' stop_sequences[{idx}] must not be empty"
+            );
+        }
+        if let Some(spread) = self.temperature_spread {
+            ensure!(
+                spread.min >= 0.0 && spread.min <= spread.max && spread.max <= 2.0,
+                "Domain '{domain}' role '{role}' temperature_spread must satisfy 0 <= min <= max <= 2"
+            );
+        }
         Ok(())
     }
 }
@@ -228,6 +477,24 @@ pub struct StepGranularity {
 }
 
 impl StepGranularity {
+    /// The built-in `granularity` red flagger enforcing these limits, if any are set.
+    pub fn red_flagger(&self) -> Option<RedFlaggerDescriptor> {
+        if self.max_files.is_none() && self.max_lines_changed.is_none() {
+            return None;
+        }
+        let mut params = HashMap::new();
+        if let Some(files) = self.max_files {
+            params.insert("max_files".to_string(), files.into());
+        }
+        if let Some(lines) = self.max_lines_changed {
+            params.insert("max_lines_changed".to_string(), lines.into());
+        }
+        Some(RedFlaggerDescriptor {
+            kind: "granularity".into(),
+            params,
+        })
+    }
+
     fn validate(&self, domain: &str) -> Result<()> {
         if let Some(files) = self.max_files {
             ensure!(
@@ -313,6 +580,22 @@ fn validate_red_flagger(domain: &str, idx: usize, cfg: &RedFlaggerConfig) -> Res
                 "Domain '{domain}' red_flaggers[{idx}] prompt_template must not be blank"
             );
         }
+        "granularity" => {
+            let limits = ["max_files", "max_lines_changed"]
+                .iter()
+                .filter_map(|key| cfg.params.get(*key))
+                .collect::<Vec<_>>();
+            ensure!(
+                !limits.is_empty(),
+                "Domain '{domain}' red_flaggers[{idx}] of type 'granularity' must set max_files or max_lines_changed"
+            );
+            ensure!(
+                limits
+                    .iter()
+                    .all(|value| value.as_u64().is_some_and(|limit| limit > 0)),
+                "Domain '{domain}' red_flaggers[{idx}] granularity limits must be integers > 0"
+            );
+        }
         other => {
             return Err(anyhow!(
                 "Domain '{domain}' references unknown red flagger type '{other}'"
@@ -334,6 +617,9 @@ fn agent_settings(kind: AgentKind, definition: &AgentDefinition) -> Result<Agent
             .map(convert_red_flaggers)
             .transpose()
             .with_context(|| format!("Failed to convert red flaggers for {kind:?}"))?,
+        stop_sequences: definition.stop_sequences.clone(),
+        temperature_spread: definition.temperature_spread,
+        max_concurrent: definition.max_concurrent,
     })
 }
 
@@ -392,6 +678,21 @@ fn resolve_prompt_template(raw: &str, base_dir: &Path) -> Result<String> {
     Ok(raw.to_string())
 }
 
+/// Whether any Handlebars expression in `template` mentions the `prompt` or `task` variable,
+/// including block helpers such as `{{#if task}}` and triple-stash `{{{prompt}}}`.
+fn references_task_variable(template: &str) -> bool {
+    template
+        .split("{{")
+        .skip(1)
+        .filter_map(|rest| rest.split_once("}}").map(|(expr, _)| expr))
+        .flat_map(|expr| {
+            expr.split(|c: char| {
+                c.is_whitespace() || matches!(c, '{' | '}' | '~' | '#' | '(' | ')')
+            })
+        })
+        .any(|token| matches!(token, "prompt" | "task"))
+}
+
 fn looks_like_template_path(value: &str) -> bool {
     value.contains('/')
         || value.contains('\\')
@@ -431,6 +732,13 @@ mod tests {
             step_granularity:
               max_files: 1
             verifier: "pytest"
+            verifier_agent:
+              prompt_template: "judge {{task}}: {{solution}}"
+              model: "m5"
+            vote_enumeration: alpha
+            on_empty_plan: pause
+            single_shot_decomposition: true
+            min_samples_accepted: 2
             applier: "patch"
             red_flaggers:
               - type: "length"
@@ -448,6 +756,19 @@ mod tests {
             Some(1),
             "step granularity parsed"
         );
+        let runtime = domain.to_runtime("code").expect("runtime config");
+        let verifier = runtime
+            .agent_settings(AgentKind::Verifier)
+            .expect("verifier agent");
+        assert_eq!(verifier.model, "m5");
+        assert_eq!(runtime.vote_enumeration, VoteEnumeration::Alpha);
+        assert_eq!(runtime.on_empty_plan, EmptyPlanPolicy::Pause);
+        assert!(runtime.single_shot_decomposition);
+        assert_eq!(runtime.min_samples_accepted, Some(2));
+        let granularity = runtime.granularity_flagger.expect("granularity flagger");
+        assert_eq!(granularity.kind, "granularity");
+        assert_eq!(granularity.params["max_files"], 1);
+        assert!(!granularity.params.contains_key("max_lines_changed"));
     }
 
     #[test]
@@ -491,6 +812,74 @@ domains:
             domain.agents.decomposition.prompt_template,
             "Hydrated {{prompt}}"
         );
+
+        // The content hash is stable across loads and follows edits to template files.
+        let hash = config.content_hash().expect("hash recorded").to_string();
+        assert_eq!(hash.len(), 64);
+        let reloaded = MicrofactoryConfig::from_path(&config_path).unwrap();
+        assert_eq!(reloaded.content_hash(), Some(hash.as_str()));
+        fs::write(&template_path, "Edited {{prompt}}").unwrap();
+        let edited = MicrofactoryConfig::from_path(&config_path).unwrap();
+        assert_ne!(edited.content_hash(), Some(hash.as_str()));
+    }
+
+    #[test]
+    fn templates_resolve_from_the_template_dir_override() {
+        let temp = tempdir().unwrap();
+        let shared = temp.path().join("shared");
+        let configured = temp.path().join("project").join("prompts");
+        fs::create_dir_all(&shared).unwrap();
+        fs::create_dir_all(&configured).unwrap();
+        fs::write(shared.join("demo.hbs"), "Shared {{prompt}}").unwrap();
+        fs::write(configured.join("demo.hbs"), "Configured {{prompt}}").unwrap();
+
+        let config_path = temp.path().join("project").join("config.yaml");
+        fs::write(
+            &config_path,
+            r#"
+template_dir: prompts
+domains:
+  code:
+    agents:
+      decomposition:
+        prompt_template: "demo.hbs"
+        model: "m1"
+        samples: 1
+      decomposition_discriminator:
+        prompt_template: "demo.hbs"
+        model: "m2"
+        k: 2
+      solver:
+        prompt_template: "demo.hbs"
+        model: "m3"
+        samples: 1
+      solution_discriminator:
+        prompt_template: "demo.hbs"
+        model: "m4"
+        k: 2
+"#,
+        )
+        .unwrap();
+
+        let from_config = MicrofactoryConfig::from_path(&config_path).unwrap();
+        assert_eq!(
+            from_config.domains["code"].agents.solver.prompt_template,
+            "Configured {{prompt}}"
+        );
+
+        let overridden =
+            MicrofactoryConfig::from_path_with_template_dir(&config_path, Some(&shared)).unwrap();
+        assert_eq!(
+            overridden.domains["code"].agents.solver.prompt_template,
+            "Shared {{prompt}}"
+        );
+
+        let err = MicrofactoryConfig::from_path_with_template_dir(
+            &config_path,
+            Some(&temp.path().join("missing")),
+        )
+        .unwrap_err();
+        assert!(format!("{err:#}").contains("missing"), "{err:#}");
     }
 
     #[test]
@@ -524,4 +913,163 @@ domains:
             "error chain missing max_tokens context: {messages:?}"
         );
     }
+
+    #[test]
+    fn path_extraction_regex_requires_a_capturing_pattern() {
+        let domain = |extra: &str| {
+            format!(
+                r#"
+        domains:
+          code:
+            agents:
+              decomposition:
+                prompt_template: "p"
+                model: "m1"
+              decomposition_discriminator:
+                prompt_template: "p"
+                model: "m2"
+                k: 1
+              solver:
+                prompt_template: "p"
+                model: "m3"
+              solution_discriminator:
+                prompt_template: "p"
+                model: "m4"
+                k: 1
+{extra}
+        "#
+            )
+        };
+        let error_chain = |yaml: String| -> Vec<String> {
+            let err = MicrofactoryConfig::from_yaml_str(&yaml).unwrap_err();
+            err.chain().map(|cause| cause.to_string()).collect()
+        };
+
+        let missing = error_chain(domain("            path_extraction: regex"));
+        assert!(
+            missing.iter().any(|msg| msg.contains("no path_pattern")),
+            "{missing:?}"
+        );
+        let no_group = error_chain(domain(
+            "            path_extraction: regex\n            path_pattern: 'src/\\S+'",
+        ));
+        assert!(
+            no_group.iter().any(|msg| msg.contains("capture group")),
+            "{no_group:?}"
+        );
+
+        let config = MicrofactoryConfig::from_yaml_str(&domain(
+            "            path_extraction: regex\n            path_pattern: 'file=(\\S+)'",
+        ))
+        .expect("valid config");
+        let runtime = config.domain("code").unwrap().to_runtime("code").unwrap();
+        assert_eq!(runtime.path_extraction, PathExtraction::Regex);
+        assert_eq!(runtime.path_pattern.as_deref(), Some(r"file=(\S+)"));
+    }
+
+    #[test]
+    fn unknown_domain_falls_back_to_default_domain_when_configured() {
+        let yaml = |default_domain: &str| {
+            format!(
+                r#"
+        {default_domain}
+        domains:
+          generic:
+            agents:
+              decomposition:
+                prompt_template: "p"
+                model: "m1"
+              decomposition_discriminator:
+                prompt_template: "p"
+                model: "m2"
+                k: 1
+              solver:
+                prompt_template: "p"
+                model: "m3"
+              solution_discriminator:
+                prompt_template: "p"
+                model: "m4"
+                k: 1
+        "#
+            )
+        };
+
+        let strict = MicrofactoryConfig::from_yaml_str(&yaml("")).expect("valid config");
+        assert!(strict.domain("docs").is_none());
+        let err = strict.runtime_domain("docs").unwrap_err();
+        assert!(err.to_string().contains("Unknown domain: docs"), "{err}");
+
+        let lenient = MicrofactoryConfig::from_yaml_str(&yaml("default_domain: generic"))
+            .expect("valid config");
+        assert_eq!(
+            lenient.resolve_domain("docs").map(|(name, _)| name),
+            Some("generic")
+        );
+        let runtime = lenient.runtime_domain("docs").expect("falls back");
+        assert_eq!(runtime.name, "generic");
+
+        let err = MicrofactoryConfig::from_yaml_str(&yaml("default_domain: missing")).unwrap_err();
+        assert!(
+            err.to_string().contains("default_domain 'missing'"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn warns_when_template_ignores_task() {
+        let yaml = r#"
+        domains:
+          code:
+            agents:
+              decomposition:
+                prompt_template: "Split {{ task }} into steps"
+                model: "m1"
+              decomposition_discriminator:
+                prompt_template: "{{#if prompt}}Pick a plan{{/if}}"
+                model: "m2"
+              solver:
+                prompt_template: "Write the code for {{role}}"
+                model: "m3"
+              solution_discriminator:
+                prompt_template: "Judge: {{{prompt}}}"
+                model: "m4"
+        "#;
+
+        let config = MicrofactoryConfig::from_yaml_str(yaml).expect("lint does not fail loading");
+        let warnings = config.template_warnings();
+        assert_eq!(warnings.len(), 1, "unexpected warnings: {warnings:?}");
+        assert!(warnings[0].contains("role 'solver'"));
+        assert!(warnings[0].contains("{{prompt}}"));
+    }
+
+    #[test]
+    fn rejects_empty_stop_sequence() {
+        let yaml = r#"
+        domains:
+          code:
+            agents:
+              decomposition:
+                prompt_template: "p"
+                model: "m1"
+              decomposition_discriminator:
+                prompt_template: "p"
+                model: "m2"
+              solver:
+                prompt_template: "p"
+                model: "m3"
+                stop_sequences: ["</file>", ""]
+              solution_discriminator:
+                prompt_template: "p"
+                model: "m4"
+        "#;
+
+        let err = MicrofactoryConfig::from_yaml_str(yaml).unwrap_err();
+        let messages: Vec<String> = err.chain().map(|cause| cause.to_string()).collect();
+        assert!(
+            messages
+                .iter()
+                .any(|msg| msg.contains("stop_sequences[1] must not be empty")),
+            "error chain missing stop_sequences context: {messages:?}"
+        );
+    }
 }
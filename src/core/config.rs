@@ -1,6 +1,9 @@
 use std::collections::HashMap;
 
-use crate::core::domain::{AgentConfig, AgentKind, RedFlaggerDescriptor};
+use crate::core::domain::{
+    AgentConfig, AgentKind, EmptyPlanPolicy, OutputEncoding, PathExtraction, RedFlaggerDescriptor,
+    TemperatureSpread, VoteEnumeration,
+};
 
 /// Runtime settings for a single agent role within a domain.
 #[derive(Debug, Clone)]
@@ -10,6 +13,9 @@ pub struct AgentSettings {
     pub samples: Option<usize>,
     pub k: Option<usize>,
     pub red_flaggers: Option<Vec<RedFlaggerDescriptor>>,
+    pub stop_sequences: Vec<String>,
+    pub temperature_spread: Option<TemperatureSpread>,
+    pub max_concurrent: Option<usize>,
 }
 
 impl AgentSettings {
@@ -21,6 +27,9 @@ impl AgentSettings {
             samples: self.samples.unwrap_or(defaults.samples).max(1),
             k: self.k.or(Some(defaults.k)),
             red_flaggers: self.red_flaggers.clone(),
+            stop_sequences: self.stop_sequences.clone(),
+            temperature_spread: self.temperature_spread,
+            max_concurrent: self.max_concurrent,
         }
     }
 }
@@ -32,6 +41,9 @@ pub struct AgentDefaults {
     pub k: usize,
 }
 
+/// Default for a domain's `max_file_bytes`: large enough for any hand-written source file.
+pub const DEFAULT_MAX_FILE_BYTES: usize = 1024 * 1024;
+
 /// Runtime representation of a domain after configuration parsing.
 #[derive(Debug, Clone)]
 pub struct DomainRuntimeConfig {
@@ -39,7 +51,27 @@ pub struct DomainRuntimeConfig {
     pub agents: HashMap<AgentKind, AgentSettings>,
     pub applier: Option<String>,
     pub verifier: Option<String>,
+    /// Command run before applying a solution; a failure fails the step without writing.
+    pub pre_verifier: Option<String>,
     pub red_flaggers: Vec<RedFlaggerDescriptor>,
+    pub output_encoding: OutputEncoding,
+    pub vote_enumeration: VoteEnumeration,
+    pub on_empty_plan: EmptyPlanPolicy,
+    /// Adopt the single decomposition sample's subtasks without a decomposition vote.
+    pub single_shot_decomposition: bool,
+    /// How `overwrite_file` picks a target path when a solution has no `<file>` blocks.
+    pub path_extraction: PathExtraction,
+    /// Capture pattern used by `PathExtraction::Regex`.
+    pub path_pattern: Option<String>,
+    /// Largest file `overwrite_file` may write; a bigger one fails the step unwritten.
+    pub max_file_bytes: usize,
+    /// Clean samples a sampling stage must accept; `RunnerOptions` takes precedence when set.
+    pub min_samples_accepted: Option<usize>,
+    /// Patterns redacted from prompts recorded on steps.
+    pub prompt_redactions: Vec<String>,
+    /// Built-in `granularity` red flagger derived from `step_granularity`; always added to the
+    /// solver's pipeline, even when the solver overrides `red_flaggers`.
+    pub granularity_flagger: Option<RedFlaggerDescriptor>,
 }
 
 impl DomainRuntimeConfig {
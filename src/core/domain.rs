@@ -1,5 +1,5 @@
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{BTreeMap, HashMap, VecDeque},
     path::PathBuf,
 };
 
@@ -17,14 +17,26 @@ pub struct Context {
     pub metrics: WorkflowMetrics,
     pub domain_data: HashMap<String, String>,
     pub dry_run: bool,
+    /// Run the full flow but record apply diffs on each step instead of writing files.
+    #[serde(default)]
+    pub dry_run_apply: bool,
     pub next_step_id: usize,
     pub root_step_id: Option<usize>,
     pub pending_decompositions: HashMap<usize, Vec<DecompositionProposal>>,
     pub pending_solutions: HashMap<usize, Vec<String>>,
     pub work_queue: VecDeque<WorkItem>,
+    /// Queue changes not yet reported; see [`Context::drain_queue_transitions`].
+    #[serde(skip)]
+    queue_transitions: Vec<QueueTransition>,
     pub wait_state: Option<WaitState>,
     #[serde(default)]
     pub output_dir: Option<PathBuf>,
+    /// Child steps spawned so far, including any later discarded; never decremented.
+    #[serde(default)]
+    pub spawned_steps: usize,
+    /// Once `spawned_steps` reaches this cap, new children are solved as leaves.
+    #[serde(default)]
+    pub max_children_total: Option<usize>,
 }
 
 impl Context {
@@ -66,12 +78,87 @@ impl Context {
             .map(|step| step.depth + 1)
             .unwrap_or(0);
         let id = self.create_step(description.into(), Some(parent), depth);
+        self.spawned_steps += 1;
         if let Some(parent_step) = self.steps.iter_mut().find(|step| step.id == parent) {
             parent_step.children.push(id);
         }
         id
     }
 
+    /// Whether `max_children_total` is set and the spawned-step count has reached it.
+    pub fn step_cap_reached(&self) -> bool {
+        self.max_children_total
+            .is_some_and(|cap| self.spawned_steps >= cap)
+    }
+
+    /// Replace the root decomposition with a fixed list of subtasks, queueing each one for
+    /// solving directly. Returns the ids of the seeded child steps.
+    pub fn seed_plan(&mut self, subtasks: &[String]) -> Vec<usize> {
+        let root = self.ensure_root();
+        self.work_queue.clear();
+        let mut children = Vec::with_capacity(subtasks.len());
+        for subtask in subtasks {
+            let child = self.add_child_step(root, subtask.clone());
+            self.enqueue_work(WorkItem::Solve { step_id: child });
+            children.push(child);
+        }
+        children
+    }
+
+    /// Drop the children spawned for `step_id`, along with any queued work for the step or its
+    /// children, so the step can be decomposed again.
+    pub fn discard_children(&mut self, step_id: usize) {
+        let children = self
+            .step_mut(step_id)
+            .map(|step| std::mem::take(&mut step.children))
+            .unwrap_or_default();
+        self.steps.retain(|step| !children.contains(&step.id));
+        self.work_queue.retain(|item| {
+            let id = item.step_id();
+            id != step_id && !children.contains(&id)
+        });
+    }
+
+    /// Forget the solutions produced for `step_id` and drop its queued work so it can be solved
+    /// again from scratch.
+    pub fn reset_solution(&mut self, step_id: usize) {
+        if let Some(step) = self.step_mut(step_id) {
+            step.candidate_solutions.clear();
+            step.winning_solution = None;
+            step.status = StepStatus::Pending;
+        }
+        self.pending_solutions.remove(&step_id);
+        self.work_queue.retain(|item| item.step_id() != step_id);
+    }
+
+    /// Reset `step_id` and every descendant to `Pending`, clearing their solutions, pending
+    /// candidates, per-step metrics, and queued work, then enqueue a `Solve` for each leaf so the
+    /// subtree runs again with its existing plan. Returns the reset ids, or `None` for an unknown step.
+    pub fn reset_subtree(&mut self, step_id: usize) -> Option<Vec<usize>> {
+        self.step(step_id)?;
+        let mut subtree = vec![step_id];
+        let mut idx = 0;
+        while let Some(&id) = subtree.get(idx) {
+            if let Some(step) = self.step(id) {
+                subtree.extend(step.children.iter().copied());
+            }
+            idx += 1;
+        }
+        subtree.sort_unstable();
+
+        self.work_queue
+            .retain(|item| !subtree.contains(&item.step_id()));
+        for &id in &subtree {
+            self.reset_solution(id);
+            self.pending_decompositions.remove(&id);
+            self.metrics.per_step.remove(&id);
+            if self.step(id).is_some_and(|step| step.children.is_empty()) {
+                self.enqueue_work(WorkItem::Solve { step_id: id });
+            }
+        }
+        Some(subtree)
+    }
+
     pub fn step(&self, step_id: usize) -> Option<&WorkflowStep> {
         self.steps.iter().find(|step| step.id == step_id)
     }
@@ -127,6 +214,13 @@ impl Context {
         }
     }
 
+    /// Keep the prompt rendered for `role` on the step so it is saved with the session.
+    pub fn record_prompt(&mut self, step_id: usize, role: &str, prompt: String) {
+        if let Some(step) = self.step_mut(step_id) {
+            step.rendered_prompts.insert(role.to_string(), prompt);
+        }
+    }
+
     pub fn mark_step_status(&mut self, step_id: usize, status: StepStatus) {
         if let Some(step) = self.step_mut(step_id) {
             step.status = status;
@@ -138,27 +232,53 @@ impl Context {
     }
 
     pub fn dequeue_work(&mut self) -> Option<WorkItem> {
-        self.work_queue.pop_front()
+        let item = self.work_queue.pop_front()?;
+        self.record_queue_transition("dequeue", &item);
+        Some(item)
     }
 
     pub fn enqueue_work(&mut self, item: WorkItem) {
-        self.work_queue.push_back(item);
+        self.work_queue.push_back(item.clone());
+        self.record_queue_transition("enqueue", &item);
     }
 
     pub fn enqueue_work_front(&mut self, item: WorkItem) {
-        self.work_queue.push_front(item);
+        self.work_queue.push_front(item.clone());
+        self.record_queue_transition("enqueue_front", &item);
+    }
+
+    fn record_queue_transition(&mut self, op: &'static str, item: &WorkItem) {
+        self.queue_transitions.push(QueueTransition {
+            op,
+            kind: item.kind(),
+            step_id: item.step_id(),
+            queue_len: self.work_queue.len(),
+        });
+    }
+
+    /// Queue changes since the last call, oldest first, for the caller to log.
+    pub fn drain_queue_transitions(&mut self) -> Vec<QueueTransition> {
+        std::mem::take(&mut self.queue_transitions)
     }
 
     pub fn has_pending_work(&self) -> bool {
         !self.work_queue.is_empty()
     }
 
+    /// Whether a solution vote for `step_id` is already waiting in the queue.
+    pub fn has_queued_solution_vote(&self, step_id: usize) -> bool {
+        self.work_queue.iter().any(
+            |item| matches!(item, WorkItem::SolutionVote { step_id: queued } if *queued == step_id),
+        )
+    }
+
     pub fn clear_wait_state(&mut self) {
         if let Some(wait) = self.wait_state.take()
             && let Some(step) = self.step_mut(wait.step_id)
-            && matches!(step.status, StepStatus::WaitingOnInput)
+            && matches!(step.status, StepStatus::AwaitingHuman)
         {
-            step.status = StepStatus::Pending;
+            // The paused work item is still queued, so the step picks up where it stopped.
+            step.status = StepStatus::Running;
         }
     }
 
@@ -173,7 +293,7 @@ impl Context {
             trigger: trigger.into(),
             details: details.into(),
         });
-        self.mark_step_status(step_id, StepStatus::WaitingOnInput);
+        self.mark_step_status(step_id, StepStatus::AwaitingHuman);
     }
 
     pub fn set_checkpoint(
@@ -187,7 +307,12 @@ impl Context {
             trigger: trigger.into(),
             details: details.into(),
         });
-        // Do not change step status
+        // A step that already finished keeps its status; anything else waits on the reviewer.
+        if let Some(step) = self.step_mut(step_id)
+            && !matches!(step.status, StepStatus::Completed | StepStatus::Failed)
+        {
+            step.status = StepStatus::AwaitingHuman;
+        }
     }
 
     fn create_step(&mut self, description: String, parent: Option<usize>, depth: usize) -> usize {
@@ -211,6 +336,72 @@ pub struct WorkflowStep {
     #[serde(default)]
     pub candidate_decompositions: Vec<DecompositionProposal>,
     pub winning_solution: Option<String>,
+    /// Why the runner did or did not decompose this step further; recorded with `--explain`.
+    #[serde(default)]
+    pub recursion: Option<RecursionDecision>,
+    /// Last prompt rendered for each role (`decomposition`, `solve`, ...); recorded with
+    /// `--record-prompts`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub rendered_prompts: BTreeMap<String, String>,
+    /// What apply would have written; recorded instead of writing under `--dry-run-apply`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_apply: Option<PendingApply>,
+    /// Output of the last failed verification, offered to the next solve as
+    /// `{{verifier_feedback}}`; cleared once a solution verifies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verifier_feedback: Option<String>,
+    /// Targets of the last apply that `.microfactoryignore` kept from being written.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped_paths: Vec<PathBuf>,
+}
+
+/// Files an apply would write and a line diff against their current contents.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingApply {
+    pub files: Vec<PathBuf>,
+    pub diff: String,
+}
+
+/// Inputs and outcome of the runner's "decompose further or solve?" heuristic for one step.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecursionDecision {
+    pub recursed: bool,
+    pub depth: usize,
+    pub word_count: usize,
+    pub max_depth: usize,
+    pub min_words: usize,
+    pub reason: String,
+}
+
+impl RecursionDecision {
+    pub fn evaluate(depth: usize, word_count: usize, max_depth: usize, min_words: usize) -> Self {
+        let (recursed, reason) = if depth >= max_depth {
+            (
+                false,
+                format!("not recursed: depth cap reached (depth {depth} >= max {max_depth})"),
+            )
+        } else if word_count < min_words {
+            (
+                false,
+                format!("not recursed: below min words ({word_count} < {min_words})"),
+            )
+        } else {
+            (
+                true,
+                format!(
+                    "recursed: {word_count} words >= {min_words} and depth {depth} < max {max_depth}"
+                ),
+            )
+        };
+        Self {
+            recursed,
+            depth,
+            word_count,
+            max_depth,
+            min_words,
+            reason,
+        }
+    }
 }
 
 impl WorkflowStep {
@@ -225,6 +416,11 @@ impl WorkflowStep {
             candidate_solutions: Vec::new(),
             candidate_decompositions: Vec::new(),
             winning_solution: None,
+            recursion: None,
+            rendered_prompts: BTreeMap::new(),
+            pending_apply: None,
+            verifier_feedback: None,
+            skipped_paths: Vec::new(),
         }
     }
 }
@@ -232,8 +428,13 @@ impl WorkflowStep {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum StepStatus {
     Pending,
+    /// Sampling, voting, or otherwise waiting on the LLM.
     Running,
-    WaitingOnInput,
+    /// Paused for a human decision (a pause trigger or step-by-step checkpoint).
+    #[serde(alias = "WaitingOnInput")]
+    AwaitingHuman,
+    /// Writing the winning solution and running the verifier.
+    Applying,
     Completed,
     Failed,
 }
@@ -251,6 +452,9 @@ impl DecompositionProposal {
     }
 }
 
+/// How many recent vote margins each discriminator keeps for adaptive k and adaptive samples.
+pub const RECENT_MARGIN_WINDOW: usize = 8;
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct WorkflowMetrics {
     pub sample_count: usize,
@@ -259,8 +463,21 @@ pub struct WorkflowMetrics {
     pub decomposition_runs: usize,
     pub solve_runs: usize,
     pub red_flag_hits: usize,
+    /// Rejections per red flagger name across the whole session.
+    #[serde(default)]
+    pub red_flags_by_flagger: BTreeMap<String, usize>,
     pub per_step: HashMap<usize, StepMetrics>,
+    /// Recent vote margins per discriminator; persisted so adaptive k and adaptive samples keep
+    /// their history across resumes.
+    #[serde(default)]
     pub vote_history: HashMap<AgentKind, VoteStats>,
+    /// Milliseconds spent per stage (`decomposition`, `decomposition_vote`, `solve`,
+    /// `solution_vote`, `apply_verify`) across all steps.
+    #[serde(default)]
+    pub stage_durations_ms: BTreeMap<String, u128>,
+    /// Tokens spent against `--max-total-tokens` so far; resume keeps counting from here.
+    #[serde(default)]
+    pub tokens_used: u64,
 }
 
 impl WorkflowMetrics {
@@ -290,16 +507,28 @@ impl WorkflowMetrics {
         incidents: impl IntoIterator<Item = RedFlagIncident>,
     ) {
         let mut added = 0usize;
-        {
-            let metrics = self.step_metrics_mut(step_id);
-            for incident in incidents {
-                metrics.red_flags.push(incident);
-                added += 1;
-            }
+        for incident in incidents {
+            *self
+                .red_flags_by_flagger
+                .entry(incident.flagger.clone())
+                .or_default() += 1;
+            self.step_metrics_mut(step_id).red_flags.push(incident);
+            added += 1;
         }
         self.red_flag_hits += added;
     }
 
+    /// Red flaggers ordered by rejection count (highest first, ties by name).
+    pub fn top_flaggers(&self) -> Vec<(String, usize)> {
+        let mut counts: Vec<(String, usize)> = self
+            .red_flags_by_flagger
+            .iter()
+            .map(|(name, count)| (name.clone(), *count))
+            .collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts
+    }
+
     pub fn record_vote(
         &mut self,
         step_id: usize,
@@ -312,18 +541,45 @@ impl WorkflowMetrics {
         self.step_metrics_mut(step_id).vote_margin = Some(margin);
         let stats = self.vote_history.entry(agent_kind).or_default();
         stats.total_votes += 1;
-        if stats.recent_margins.len() >= 8 {
+        if stats.recent_margins.len() >= RECENT_MARGIN_WINDOW {
             stats.recent_margins.pop_front();
         }
         stats.recent_margins.push_back(margin);
     }
 
-    pub fn record_duration_ms(&mut self, step_id: usize, duration_ms: u128) {
+    pub fn record_vote_histogram(&mut self, step_id: usize, histogram: Vec<usize>) {
+        self.step_metrics_mut(step_id).vote_histogram = histogram;
+    }
+
+    /// Keep the shuffled option order of every ballot cast in a `role` vote.
+    pub fn record_vote_orders(&mut self, step_id: usize, role: &str, orders: Vec<Vec<usize>>) {
+        self.step_metrics_mut(step_id)
+            .vote_orders
+            .insert(role.to_string(), orders);
+    }
+
+    /// Add one task's run time to its step and to the totals of its `stage`.
+    pub fn record_duration_ms(&mut self, step_id: usize, stage: &str, duration_ms: u128) {
+        *self
+            .stage_durations_ms
+            .entry(stage.to_string())
+            .or_default() += duration_ms;
         let metrics = self.step_metrics_mut(step_id);
         let accumulated = metrics.duration_ms.unwrap_or(0) + duration_ms;
         metrics.duration_ms = Some(accumulated);
     }
 
+    /// Stages ordered by time spent (slowest first, ties by name).
+    pub fn stage_timings(&self) -> Vec<(String, u128)> {
+        let mut timings: Vec<(String, u128)> = self
+            .stage_durations_ms
+            .iter()
+            .map(|(stage, ms)| (stage.clone(), *ms))
+            .collect();
+        timings.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        timings
+    }
+
     pub fn vote_stats(&self, agent_kind: AgentKind) -> Option<&VoteStats> {
         self.vote_history.get(&agent_kind)
     }
@@ -335,6 +591,8 @@ pub enum AgentKind {
     DecompositionDiscriminator,
     Solver,
     SolutionDiscriminator,
+    /// Optional LLM judge that passes or fails an applied solution.
+    Verifier,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -345,6 +603,208 @@ pub struct AgentConfig {
     pub samples: usize,
     pub k: Option<usize>,
     pub red_flaggers: Option<Vec<RedFlaggerDescriptor>>,
+    #[serde(default)]
+    pub stop_sequences: Vec<String>,
+    #[serde(default)]
+    pub temperature_spread: Option<TemperatureSpread>,
+    /// Cap on this agent's in-flight sample requests, below the global `max_concurrent_llm`.
+    #[serde(default)]
+    pub max_concurrent: Option<usize>,
+}
+
+/// Temperature range that sampled candidates are spread across, lowest to highest.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TemperatureSpread {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl TemperatureSpread {
+    /// Temperature for sample `index` of a batch planned as `planned` samples. The planned
+    /// samples are evenly spaced from `min` to `max` inclusive (`min` alone when `planned == 1`);
+    /// later top-up samples bisect the range (middle, then quarters, eighths, ...) so resampling
+    /// keeps exploring the spread instead of repeating one end of it.
+    pub fn temperature(&self, index: usize, planned: usize) -> f32 {
+        let fraction = if index < planned {
+            if planned == 1 {
+                0.0
+            } else {
+                index as f32 / (planned - 1) as f32
+            }
+        } else {
+            // Van der Corput sequence: 1/2, 1/4, 3/4, 1/8, 5/8, ...
+            let mut rest = index - planned + 1;
+            let (mut fraction, mut weight) = (0.0, 0.5);
+            while rest > 0 {
+                if rest & 1 == 1 {
+                    fraction += weight;
+                }
+                weight /= 2.0;
+                rest >>= 1;
+            }
+            fraction
+        };
+        self.min + (self.max - self.min) * fraction
+    }
+}
+
+/// Bounds and step size for `--adaptive-k` adjustments of a discriminator's k.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdaptiveKLimits {
+    /// Smallest k an adjustment may produce; values below 1 count as 1.
+    pub min: usize,
+    /// Largest k an adjustment may produce (raised to `min` if lower); `None` is unbounded.
+    pub max: Option<usize>,
+    /// How far one adjustment moves k away from the agent's base k.
+    pub step: usize,
+}
+
+impl Default for AdaptiveKLimits {
+    fn default() -> Self {
+        Self {
+            min: 1,
+            max: None,
+            step: 1,
+        }
+    }
+}
+
+impl AdaptiveKLimits {
+    /// `base` moved up (`raise`) or down by `step`, clamped into `min..=max`.
+    pub fn adjust(&self, base: usize, raise: bool) -> usize {
+        let step = self.step.max(1);
+        let adjusted = if raise {
+            base.saturating_add(step)
+        } else {
+            base.saturating_sub(step)
+        };
+        let min = self.min.max(1);
+        let max = self.max.map_or(usize::MAX, |max| max.max(min));
+        adjusted.clamp(min, max)
+    }
+}
+
+/// Line terminator used when the `overwrite_file` applier writes files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+/// How applied file contents are encoded on disk. The default (LF, no BOM) writes the
+/// solution text unchanged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputEncoding {
+    #[serde(default)]
+    pub line_endings: LineEnding,
+    /// Prefix written files with a UTF-8 byte order mark.
+    #[serde(default)]
+    pub bom: bool,
+}
+
+impl OutputEncoding {
+    const BOM: char = '\u{feff}';
+
+    /// Normalize `content` to the configured line endings and add or strip the BOM. The
+    /// default encoding returns `content` as-is.
+    pub fn encode(&self, content: &str) -> String {
+        if *self == Self::default() {
+            return content.to_string();
+        }
+        let body = content.strip_prefix(Self::BOM).unwrap_or(content);
+        let normalized = body.replace("\r\n", "\n");
+        let mut encoded = String::with_capacity(normalized.len() + 3);
+        if self.bom {
+            encoded.push(Self::BOM);
+        }
+        match self.line_endings {
+            LineEnding::Lf => encoded.push_str(&normalized),
+            LineEnding::Crlf => encoded.push_str(&normalized.replace('\n', "\r\n")),
+        }
+        encoded
+    }
+}
+
+/// What happens when no decomposition proposal yields a parseable subtask, so every plan falls
+/// back to the step's own prompt.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmptyPlanPolicy {
+    /// Keep the fallback plan and carry on (the historical behavior).
+    #[default]
+    Proceed,
+    /// Pause the session for human input; resuming re-samples the decomposition.
+    Pause,
+    /// Abort the run with an error.
+    Fail,
+}
+
+/// How the `overwrite_file` applier finds the target file of a solution without `<file>` blocks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathExtraction {
+    /// Only `<file path="...">` blocks are applied; a solution without them fails the step.
+    Xml,
+    /// The first capture group of the domain's `path_pattern`, matched against the description.
+    Regex,
+    /// The first path-like token of the step description (the historical behavior).
+    #[default]
+    Heuristic,
+}
+
+/// How vote prompts label their options: `Option 1:` (numeric) or `Option A:` (alpha).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VoteEnumeration {
+    #[default]
+    Numeric,
+    Alpha,
+}
+
+impl VoteEnumeration {
+    /// Label for the zero-based option `index`. Alpha labels continue past `Z` as `AA`, `AB`, ...
+    pub fn label(self, index: usize) -> String {
+        match self {
+            Self::Numeric => (index + 1).to_string(),
+            Self::Alpha => {
+                let mut label = Vec::new();
+                let mut n = index + 1;
+                while n > 0 {
+                    n -= 1;
+                    label.push(b'A' + (n % 26) as u8);
+                    n /= 26;
+                }
+                label.reverse();
+                String::from_utf8(label).unwrap_or_default()
+            }
+        }
+    }
+
+    /// Zero-based option index named by a single response token, if it is a label in this style.
+    /// Numeric labels ignore surrounding punctuation; alpha labels must be uppercase.
+    pub fn index_of(self, token: &str) -> Option<usize> {
+        match self {
+            Self::Numeric => {
+                let digits: String = token.chars().filter(|c| c.is_ascii_digit()).collect();
+                digits.parse::<usize>().ok()?.checked_sub(1)
+            }
+            Self::Alpha => {
+                let letters = token.trim_matches(|c: char| !c.is_ascii_alphanumeric());
+                if letters.is_empty() || !letters.bytes().all(|b| b.is_ascii_uppercase()) {
+                    return None;
+                }
+                letters
+                    .bytes()
+                    .try_fold(0usize, |acc, b| {
+                        acc.checked_mul(26)?.checked_add((b - b'A') as usize + 1)
+                    })?
+                    .checked_sub(1)
+            }
+        }
+    }
 }
 
 /// Core-friendly description of a red flagger instance that needs no YAML context.
@@ -362,8 +822,24 @@ pub struct StepMetrics {
     pub resamples: usize,
     pub red_flags: Vec<RedFlagIncident>,
     pub vote_margin: Option<usize>,
+    /// Solution votes received by each candidate, aligned with `candidate_solutions`.
+    #[serde(default)]
+    pub vote_histogram: Vec<usize>,
+    /// Option order each voter saw when candidates were shuffled, keyed by vote role
+    /// (`decomposition_vote`, `solution_vote`); `order[shown] = original`, one per ballot.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vote_orders: BTreeMap<String, Vec<Vec<usize>>>,
     pub duration_ms: Option<u128>,
     pub verification_passed: Option<bool>,
+    /// Why verification failed, when a verifier reported one.
+    #[serde(default)]
+    pub verification_reason: Option<String>,
+    /// Whether the domain's `pre_verifier` allowed the apply; `None` when it did not run.
+    #[serde(default)]
+    pub pre_verification_passed: Option<bool>,
+    /// Why the `pre_verifier` refused the apply.
+    #[serde(default)]
+    pub pre_verification_reason: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -374,7 +850,9 @@ pub struct RedFlagIncident {
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct VoteStats {
+    /// The last `RECENT_MARGIN_WINDOW` vote margins, oldest first.
     pub recent_margins: VecDeque<usize>,
     pub total_votes: usize,
 }
@@ -398,6 +876,27 @@ impl WorkItem {
             | WorkItem::ApplyVerify { step_id } => step_id,
         }
     }
+
+    /// Snake-case name of the variant, used in logs.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            WorkItem::Decomposition { .. } => "decomposition",
+            WorkItem::DecompositionVote { .. } => "decomposition_vote",
+            WorkItem::Solve { .. } => "solve",
+            WorkItem::SolutionVote { .. } => "solution_vote",
+            WorkItem::ApplyVerify { .. } => "apply_verify",
+        }
+    }
+}
+
+/// One work queue change: `op` (`enqueue`, `enqueue_front`, or `dequeue`) applied to an item,
+/// and the queue length after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueTransition {
+    pub op: &'static str,
+    pub kind: &'static str,
+    pub step_id: usize,
+    pub queue_len: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -411,6 +910,94 @@ pub struct WaitState {
 mod tests {
     use super::*;
 
+    #[test]
+    fn work_queue_transitions_are_recorded_in_order() {
+        // `Context::new` queues the root decomposition.
+        let mut ctx = Context::new("Fix it", "code");
+        let item = ctx.dequeue_work().unwrap();
+        ctx.enqueue_work_front(WorkItem::DecompositionVote {
+            step_id: item.step_id(),
+        });
+        ctx.dequeue_work();
+        ctx.enqueue_work(WorkItem::Solve { step_id: 1 });
+        ctx.enqueue_work(WorkItem::Solve { step_id: 2 });
+        ctx.dequeue_work();
+        assert!(ctx.has_pending_work());
+
+        let transitions: Vec<String> = ctx
+            .drain_queue_transitions()
+            .iter()
+            .map(|t| format!("{} {} {} {}", t.op, t.kind, t.step_id, t.queue_len))
+            .collect();
+        assert_eq!(
+            transitions,
+            [
+                "enqueue decomposition 0 1",
+                "dequeue decomposition 0 0",
+                "enqueue_front decomposition_vote 0 1",
+                "dequeue decomposition_vote 0 0",
+                "enqueue solve 1 1",
+                "enqueue solve 2 2",
+                "dequeue solve 1 1",
+            ]
+        );
+        assert!(ctx.drain_queue_transitions().is_empty());
+    }
+
+    #[test]
+    fn top_flaggers_counts_rejections_per_flagger() {
+        let incident = |flagger: &str| RedFlagIncident {
+            flagger: flagger.into(),
+            reason: "rejected".into(),
+            sample_preview: String::new(),
+        };
+        let mut metrics = WorkflowMetrics::default();
+        metrics.record_red_flags(1, [incident("length"), incident("syntax")]);
+        metrics.record_red_flags(2, [incident("syntax"), incident("syntax")]);
+        metrics.record_red_flags(3, [incident("length"), incident("granularity")]);
+
+        assert_eq!(
+            metrics.top_flaggers(),
+            vec![
+                ("syntax".to_string(), 3),
+                ("length".to_string(), 2),
+                ("granularity".to_string(), 1),
+            ]
+        );
+        assert_eq!(metrics.red_flag_hits, 6);
+        assert_eq!(metrics.step_metrics(2).unwrap().red_flags.len(), 2);
+    }
+
+    #[test]
+    fn seed_plan_replaces_root_decomposition() {
+        let mut ctx = Context::new("test prompt", "code");
+        let root = ctx.root_step_id().unwrap();
+
+        let children = ctx.seed_plan(&["task A".into(), "task B".into()]);
+
+        assert_eq!(children.len(), 2);
+        assert_eq!(ctx.step(root).unwrap().children, children);
+        assert!(
+            ctx.work_queue
+                .iter()
+                .all(|item| matches!(item, WorkItem::Solve { .. }))
+        );
+        assert_eq!(ctx.work_queue.len(), 2);
+    }
+
+    #[test]
+    fn discard_children_drops_steps_and_queued_work() {
+        let mut ctx = Context::new("test prompt", "code");
+        let root = ctx.root_step_id().unwrap();
+        let children = ctx.seed_plan(&["task A".into(), "task B".into()]);
+
+        ctx.discard_children(root);
+
+        assert!(ctx.step(root).unwrap().children.is_empty());
+        assert!(children.iter().all(|id| ctx.step(*id).is_none()));
+        assert!(ctx.work_queue.is_empty());
+    }
+
     #[test]
     fn test_candidate_persistence() {
         let mut ctx = Context::new("test prompt", "code");
@@ -440,4 +1027,24 @@ mod tests {
         assert_eq!(d_step.candidate_decompositions.len(), 2);
         assert_eq!(d_step.candidate_decompositions[1].raw, "prop2");
     }
+
+    #[test]
+    fn checkpoints_mark_unfinished_steps_awaiting_human() {
+        let legacy: StepStatus = serde_json::from_str("\"WaitingOnInput\"").unwrap();
+        assert_eq!(legacy, StepStatus::AwaitingHuman);
+
+        let mut ctx = Context::new("Task", "code");
+        let root = ctx.ensure_root();
+        let child = ctx.add_child_step(root, "child");
+        ctx.mark_step_status(root, StepStatus::Completed);
+        ctx.set_checkpoint(root, "step_by_step_checkpoint", "done");
+        assert_eq!(ctx.step(root).unwrap().status, StepStatus::Completed);
+
+        ctx.clear_wait_state();
+        ctx.mark_step_status(child, StepStatus::Running);
+        ctx.set_checkpoint(child, "step_by_step_pre_apply", "preview");
+        assert_eq!(ctx.step(child).unwrap().status, StepStatus::AwaitingHuman);
+        ctx.clear_wait_state();
+        assert_eq!(ctx.step(child).unwrap().status, StepStatus::Running);
+    }
 }
@@ -7,7 +7,10 @@ use std::path::PathBuf;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 
-use crate::core::error::Result;
+use crate::core::{
+    domain::{AdaptiveKLimits, LineEnding, RecursionDecision},
+    error::Result,
+};
 
 /// Request to start a new workflow session.
 #[derive(Debug, Clone)]
@@ -15,17 +18,100 @@ pub struct RunSessionRequest {
     pub prompt: String,
     pub domain: String,
     pub config_path: PathBuf,
+    /// Base directory for relative prompt templates instead of the config's; saved for `resume`.
+    pub template_dir: Option<PathBuf>,
     pub llm_provider: String,
     pub llm_model: String,
     pub api_key: Option<String>,
+    /// Extra HTTP headers for every LLM request; override the domain's `llm_headers`.
+    pub llm_headers: Vec<(String, String)>,
     pub samples: usize,
     pub k: usize,
     pub adaptive_k: bool,
+    /// Bounds and step size for `adaptive_k` adjustments.
+    pub adaptive_k_limits: AdaptiveKLimits,
+    /// Shrink or grow the solver's sample count as recent solution votes get more or less decisive.
+    pub adaptive_samples: bool,
     pub max_concurrent_llm: usize,
     pub dry_run: bool,
+    /// Run the full flow but report apply diffs instead of writing any files.
+    pub dry_run_apply: bool,
     pub step_by_step: bool,
     pub human_low_margin_threshold: usize,
+    /// Overrides the maximum decomposition depth (clamped to at least 1).
+    pub max_depth: Option<usize>,
+    /// Stop decomposing once this many child steps have been spawned in total.
+    pub max_children_total: Option<usize>,
     pub output_dir: Option<PathBuf>,
+    /// Optional fixed plan that replaces the LLM decomposition of the root step.
+    pub plan_file: Option<PathBuf>,
+    /// Write every rendered prompt under `<output_dir>/prompts/`.
+    pub dump_prompts: bool,
+    /// Save each step's rendered prompts with the session.
+    pub record_prompts: bool,
+    /// Maximum concurrent red-flag evaluations per sampling batch.
+    pub red_flag_concurrency: usize,
+    /// Evaluate a sample's red flaggers concurrently, stopping at the first rejection.
+    pub parallel_red_flaggers: bool,
+    /// Reject samples larger than this many bytes before red-flag evaluation; `None` is unlimited.
+    pub max_response_bytes: Option<usize>,
+    /// Red-flag and resample responses the provider cut off at its output token limit.
+    pub flag_truncated: bool,
+    /// Drop blank solver responses and resample an all-blank batch up to this many times; `0`
+    /// keeps blank responses as candidates.
+    pub retry_on_empty: usize,
+    /// Clean samples a sampling stage must accept before voting; `None` defers to the domain.
+    pub min_samples_accepted: Option<usize>,
+    /// Custom `{{name}}` template variables for every role's prompt; saved for `resume`.
+    pub prompt_vars: Vec<(String, String)>,
+    /// Overrides the domain's line endings for files written by `overwrite_file`.
+    pub line_endings: Option<LineEnding>,
+    /// Forces a UTF-8 BOM on files written by `overwrite_file`.
+    pub bom: bool,
+    /// Write the full final `Context` as JSON to this path once the runner stops.
+    pub dump_context: Option<PathBuf>,
+    /// Format of the run summary file; setting this or `summary_name` writes one.
+    pub summary_format: Option<SummaryFormat>,
+    /// Summary file name under the output directory; `{session_id}`, `{date}`, and `{timestamp}`
+    /// expand. Defaults to `summary.<ext>`.
+    pub summary_name: Option<String>,
+    /// Merge duplicate solver candidates before voting and weight votes by multiplicity.
+    pub dedupe_candidates: bool,
+    /// Run entirely in memory: nothing is saved to the session store, so the run cannot be resumed.
+    pub no_persist: bool,
+    /// Record why each step was or was not decomposed further and report it in the outcome.
+    pub explain: bool,
+    /// Solve a step directly when its winning plan only restates it as a single subtask.
+    pub collapse_trivial_decomposition: bool,
+    /// Decompose only the root and solve every subtask directly; saved for `resume`.
+    pub flat: bool,
+    /// Present vote options in a seeded random order to reduce positional bias.
+    pub shuffle_candidates: bool,
+    /// Seed for `shuffle_candidates`; defaults to the run's start time.
+    pub shuffle_seed: Option<u64>,
+    /// Pause with `token_budget_exhausted` once the run's LLM calls have used more input plus
+    /// output tokens than this.
+    pub max_total_tokens: Option<u64>,
+}
+
+/// File formats for the run summary written after `run`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SummaryFormat {
+    #[default]
+    Json,
+    Yaml,
+    Markdown,
+}
+
+impl SummaryFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            SummaryFormat::Json => "json",
+            SummaryFormat::Yaml => "yaml",
+            SummaryFormat::Markdown => "md",
+        }
+    }
 }
 
 /// Request to resume an existing session.
@@ -33,13 +119,23 @@ pub struct RunSessionRequest {
 pub struct ResumeSessionRequest {
     pub session_id: String,
     pub config_path: Option<PathBuf>,
+    /// Overrides the template directory stored with the session.
+    pub template_dir: Option<PathBuf>,
     pub llm_provider: Option<String>,
     pub llm_model: Option<String>,
     pub api_key: Option<String>,
+    /// Extra HTTP headers for every LLM request; override the domain's `llm_headers`.
+    pub llm_headers: Vec<(String, String)>,
     pub samples: Option<usize>,
     pub k: Option<usize>,
     pub max_concurrent_llm: Option<usize>,
     pub human_low_margin_threshold: Option<usize>,
+    /// Reset this step and its descendants and re-run them; other steps keep their results.
+    pub from_step: Option<usize>,
+    /// Fail instead of warning when the config changed since the session was saved.
+    pub strict: bool,
+    /// Replaces the saved token limit, e.g. to continue a session paused by the budget.
+    pub max_total_tokens: Option<u64>,
 }
 
 /// Request to run a subprocess (single-step execution).
@@ -52,11 +148,41 @@ pub struct SubprocessRequest {
     pub llm_provider: String,
     pub llm_model: String,
     pub api_key: Option<String>,
+    /// Extra HTTP headers for every LLM request; override the domain's `llm_headers`.
+    pub llm_headers: Vec<(String, String)>,
     pub samples: usize,
     pub k: usize,
     pub max_concurrent_llm: usize,
 }
 
+/// Request to list the models a provider offers.
+#[derive(Debug, Clone)]
+pub struct ListModelsRequest {
+    pub llm_provider: String,
+    pub api_key: Option<String>,
+    /// Extra HTTP headers for the listing request.
+    pub llm_headers: Vec<(String, String)>,
+}
+
+/// Request to run a domain's verifier without any LLM work.
+#[derive(Debug, Clone)]
+pub struct VerifyRequest {
+    pub domain: String,
+    pub config_path: PathBuf,
+}
+
+/// Result of a standalone verifier run.
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifyOutcome {
+    pub domain: String,
+    pub command: String,
+    pub passed: bool,
+    /// Exit code, or `None` if the process was killed by a signal.
+    pub status: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
 /// Response from session execution (run or resume).
 #[derive(Debug, Clone, Serialize)]
 pub struct SessionOutcome {
@@ -64,6 +190,131 @@ pub struct SessionOutcome {
     pub completed: bool,
     pub paused: bool,
     pub pause_reason: Option<PauseInfo>,
+    /// Steps that ended `Failed` even though the run drained its queue.
+    pub failed_steps: Vec<usize>,
+    pub metrics: SessionRunMetrics,
+    /// Recursion decisions recorded with `--explain`, in step order.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub recursion: Vec<RecursionExplanation>,
+    /// Winning solution of every step that has one, in step order.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub solutions: Vec<StepSolution>,
+    /// Diffs recorded instead of writing under `--dry-run-apply`, in step order.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub apply_previews: Vec<StepApplyPreview>,
+}
+
+/// Files one step's apply would have written, with a line diff against their current contents.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepApplyPreview {
+    pub step_id: usize,
+    pub files: Vec<String>,
+    pub diff: String,
+}
+
+/// The winning solution chosen for one step.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepSolution {
+    pub step_id: usize,
+    pub description: String,
+    pub solution: String,
+}
+
+/// Why one step was or was not decomposed further.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecursionExplanation {
+    pub step_id: usize,
+    pub description: String,
+    #[serde(flatten)]
+    pub decision: RecursionDecision,
+}
+
+/// Headline counters for a finished or paused run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionRunMetrics {
+    pub total_steps: usize,
+    pub completed_steps: usize,
+    pub samples: usize,
+    pub resamples: usize,
+    pub vote_attempts: usize,
+    pub red_flag_hits: usize,
+    /// Red flaggers that rejected samples, most rejections first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub top_flaggers: Vec<FlaggerRejections>,
+    /// Time spent per stage, slowest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stage_timings: Vec<StageTiming>,
+}
+
+/// How many samples one red flagger rejected during a session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FlaggerRejections {
+    pub flagger: String,
+    pub rejections: usize,
+}
+
+impl FlaggerRejections {
+    /// Convert `WorkflowMetrics::top_flaggers` output.
+    pub fn from_counts(counts: Vec<(String, usize)>) -> Vec<Self> {
+        counts
+            .into_iter()
+            .map(|(flagger, rejections)| Self {
+                flagger,
+                rejections,
+            })
+            .collect()
+    }
+}
+
+/// Milliseconds a session spent in one stage (`decomposition`, `solve`, ...) across all steps.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StageTiming {
+    pub stage: String,
+    pub duration_ms: u128,
+}
+
+impl StageTiming {
+    /// Convert `WorkflowMetrics::stage_timings` output.
+    pub fn from_durations(durations: Vec<(String, u128)>) -> Vec<Self> {
+        durations
+            .into_iter()
+            .map(|(stage, duration_ms)| Self { stage, duration_ms })
+            .collect()
+    }
+
+    /// One-line breakdown such as `solve 1200 ms (60%), decomposition 800 ms (40%)`.
+    pub fn summarize(stages: &[Self]) -> String {
+        let total: u128 = stages.iter().map(|stage| stage.duration_ms).sum();
+        stages
+            .iter()
+            .map(|stage| {
+                let share = (stage.duration_ms * 100)
+                    .checked_div(total)
+                    .unwrap_or_default();
+                format!("{} {} ms ({share}%)", stage.stage, stage.duration_ms)
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Stage breakdown served by `GET /sessions/{id}/timing`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionTiming {
+    pub session_id: String,
+    /// Sum of all stage durations.
+    pub total_duration_ms: u128,
+    pub stages: Vec<StageTiming>,
+}
+
+impl SessionTiming {
+    pub fn new(session_id: impl Into<String>, stages: Vec<StageTiming>) -> Self {
+        Self {
+            session_id: session_id.into(),
+            total_duration_ms: stages.iter().map(|stage| stage.duration_ms).sum(),
+            stages,
+        }
+    }
 }
 
 /// Information about why a session paused.
@@ -99,6 +350,9 @@ pub struct SessionSummary {
     pub domain: String,
     pub prompt: String,
     pub status: String,
+    /// What a paused session is waiting on (e.g. `low_margin`); absent for other statuses.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pause_trigger: Option<String>,
     pub updated_at: String,
 }
 
@@ -111,8 +365,49 @@ pub struct SessionDetail {
     pub status: String,
     pub updated_at: String,
     pub steps_completed: usize,
+    /// True while the session is still `running`: the stored snapshot is incomplete.
+    #[serde(default)]
+    pub partial: bool,
+    /// Steps currently `Running` or `Applying`.
+    #[serde(default)]
+    pub steps_running: usize,
+    /// Red flaggers that rejected samples, most rejections first.
+    #[serde(default)]
+    pub top_flaggers: Vec<FlaggerRejections>,
+    /// Time spent per stage, slowest first.
+    #[serde(default)]
+    pub stage_timings: Vec<StageTiming>,
     pub wait_state: Option<PauseInfo>,
     pub metadata: SessionMetadataInfo,
+    /// Operator annotations, oldest first.
+    #[serde(default)]
+    pub notes: Vec<SessionNote>,
+}
+
+/// A note attached to a session with `annotate` or `POST /sessions/{id}/notes`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionNote {
+    pub note: String,
+    /// Unix seconds, formatted like `SessionDetail::updated_at`.
+    pub created_at: String,
+}
+
+/// One step's candidates and the vote that chose between them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepDetail {
+    pub session_id: String,
+    pub step_id: usize,
+    pub description: String,
+    pub status: String,
+    pub parent: Option<usize>,
+    pub depth: usize,
+    pub children: Vec<usize>,
+    pub candidate_solutions: Vec<String>,
+    pub winning_solution: Option<String>,
+    /// Votes received by each entry of `candidate_solutions`; empty until the step is voted on.
+    #[serde(default)]
+    pub vote_histogram: Vec<usize>,
+    pub vote_margin: Option<usize>,
 }
 
 /// Session metadata for detail view.
@@ -132,6 +427,19 @@ pub struct DryRunResult {
     pub response: String,
 }
 
+/// What `run --config-check-only` validated: everything a run sets up before executing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigCheck {
+    /// Domain the run would use; the configured `default_domain` when the requested one is
+    /// not defined.
+    pub domain: String,
+    pub provider: String,
+    pub model: String,
+}
+
+/// Callback that receives one serialized session per invocation during a streaming export.
+pub type SessionExportSink<'a> = dyn FnMut(&str) -> Result<()> + Send + 'a;
+
 /// The primary application service trait that driving adapters consume.
 ///
 /// This trait defines the use-case port for workflow operations. CLI and HTTP
@@ -148,12 +456,46 @@ pub trait WorkflowService: Send + Sync {
     /// Run a single-step subprocess and return results.
     async fn run_subprocess(&self, request: SubprocessRequest) -> Result<SubprocessOutcome>;
 
+    /// Run several single-step subprocesses in order, sharing an LLM client between items with
+    /// the same provider, model, and headers. Fails on the first item that errors.
+    async fn run_subprocess_batch(
+        &self,
+        requests: Vec<SubprocessRequest>,
+    ) -> Result<Vec<SubprocessOutcome>>;
+
     /// Get detailed information about a specific session.
     async fn get_session(&self, session_id: &str) -> Result<Option<SessionDetail>>;
 
+    /// Append a timestamped note to a session; `None` if the session does not exist.
+    async fn add_session_note(&self, session_id: &str, note: &str) -> Result<Option<SessionNote>>;
+
+    /// Get one step of a stored session; `None` if the session or step does not exist.
+    async fn get_step(&self, session_id: &str, step_id: usize) -> Result<Option<StepDetail>>;
+
     /// List recent sessions.
     async fn list_sessions(&self, limit: usize) -> Result<Vec<SessionSummary>>;
 
+    /// Recent sessions whose prompt contains `query` (case-insensitive), newest first.
+    async fn search_sessions(&self, query: &str, limit: usize) -> Result<Vec<SessionSummary>>;
+
+    /// Stream stored sessions (newest first) into `sink` one at a time, returning how many were
+    /// exported. Implementations must not buffer the full result set.
+    async fn export_sessions(
+        &self,
+        limit: Option<usize>,
+        sink: &mut SessionExportSink<'_>,
+    ) -> Result<usize>;
+
     /// Run a dry-run probe to test LLM connectivity.
     async fn dry_run_probe(&self, request: &RunSessionRequest) -> Result<DryRunResult>;
+
+    /// Perform a run's setup (config, domain, output dir, plan file, LLM client construction)
+    /// without executing the flow, persisting a session, or calling the provider.
+    async fn check_run_config(&self, request: &RunSessionRequest) -> Result<ConfigCheck>;
+
+    /// Run the domain's configured verifier once, without solving anything.
+    async fn verify(&self, request: VerifyRequest) -> Result<VerifyOutcome>;
+
+    /// Model ids the provider offers to the resolved API key, sorted.
+    async fn list_models(&self, request: ListModelsRequest) -> Result<Vec<String>>;
 }
@@ -1,7 +1,10 @@
+use crate::core::domain::{Context, WaitState};
 use crate::core::error::Result;
 use async_trait::async_trait;
+use serde::Serialize;
 use std::collections::HashMap;
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 /// Data transfer object for saving a session.
 #[derive(Debug, Clone)]
@@ -10,6 +13,8 @@ pub struct SessionSaveRequest {
     pub domain: String,
     pub prompt: String,
     pub status: String,
+    /// `WaitState::trigger` of a paused session, stored alongside the status.
+    pub pause_trigger: Option<String>,
     pub context_json: String,
     pub metadata_json: String,
 }
@@ -21,20 +26,72 @@ pub struct SessionLoadResponse {
     pub domain: String,
     pub prompt: String,
     pub status: String,
+    pub pause_trigger: Option<String>,
     pub context_json: String,
     pub metadata_json: String,
     pub updated_at: i64,
 }
 
+/// A note attached to a stored session; `created_at` is in Unix seconds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionNoteRecord {
+    pub note: String,
+    pub created_at: i64,
+}
+
+/// Criteria for [`SessionRepository::find_sessions`]; unset fields match every session.
+#[derive(Debug, Clone, Default)]
+pub struct SessionFilter {
+    pub status: Option<String>,
+    pub domain: Option<String>,
+    pub limit: Option<usize>,
+    /// Case-insensitive (ASCII) substring the prompt must contain.
+    pub prompt_contains: Option<String>,
+}
+
+impl SessionFilter {
+    pub fn matches(&self, session: &SessionLoadResponse) -> bool {
+        self.status.as_ref().is_none_or(|s| *s == session.status)
+            && self.domain.as_ref().is_none_or(|d| *d == session.domain)
+            && self.prompt_contains.as_ref().is_none_or(|needle| {
+                session
+                    .prompt
+                    .to_ascii_lowercase()
+                    .contains(&needle.to_ascii_lowercase())
+            })
+    }
+}
+
+/// Callback that receives one stored session per invocation while streaming.
+pub type SessionVisitor<'a> = dyn FnMut(SessionLoadResponse) -> Result<()> + Send + 'a;
+
 /// Abstraction for storing and retrieving session state.
 #[async_trait]
 pub trait SessionRepository: Send + Sync {
     /// Save the current context of a session.
     async fn save_session(&self, request: &SessionSaveRequest) -> Result<()>;
+    /// Store a brand-new session, failing with `InvalidState` instead of overwriting when a
+    /// session with the same ID already exists.
+    async fn insert_session(&self, request: &SessionSaveRequest) -> Result<()>;
     /// Load a session by its ID.
     async fn load_session(&self, session_id: &str) -> Result<Option<SessionLoadResponse>>;
     /// List all available sessions (returning summary info).
     async fn list_sessions(&self, limit: usize) -> Result<Vec<SessionLoadResponse>>;
+    /// Sessions matching `filter`, newest first.
+    async fn find_sessions(&self, filter: &SessionFilter) -> Result<Vec<SessionLoadResponse>>;
+    /// Remove a session and its notes, returning whether it existed.
+    async fn delete_session(&self, session_id: &str) -> Result<bool>;
+    /// Append a timestamped note to a session; `None` when the session does not exist.
+    async fn add_note(&self, session_id: &str, note: &str) -> Result<Option<SessionNoteRecord>>;
+    /// Notes attached to a session, oldest first.
+    async fn list_notes(&self, session_id: &str) -> Result<Vec<SessionNoteRecord>>;
+    /// Stream sessions (newest first) into `visit` one at a time without buffering the full
+    /// result set, returning how many were visited.
+    async fn for_each_session(
+        &self,
+        limit: Option<usize>,
+        visit: &mut SessionVisitor<'_>,
+    ) -> Result<usize>;
 }
 
 /// Abstraction for interacting with an LLM provider.
@@ -47,6 +104,192 @@ pub trait LlmClient: Send + Sync {
         prompt: &str,
         options: &LlmOptions,
     ) -> Result<String>;
+
+    /// Like [`chat_completion`](Self::chat_completion), but also reports why the provider
+    /// stopped generating. Backends that cannot tell leave `finish_reason` unset.
+    async fn chat_completion_with_finish_reason(
+        &self,
+        model: &str,
+        prompt: &str,
+        options: &LlmOptions,
+    ) -> Result<LlmResponse> {
+        self.chat_completion(model, prompt, options)
+            .await
+            .map(LlmResponse::new)
+    }
+
+    /// Send a structured conversation. Backends that cannot carry roles fall back to a single
+    /// prompt made of the message contents in order.
+    async fn chat(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        options: &LlmOptions,
+    ) -> Result<String> {
+        let prompt = messages
+            .iter()
+            .map(|message| message.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        self.chat_completion(model, &prompt, options).await
+    }
+
+    /// Like [`chat`](Self::chat), but also reports the finish reason and token usage.
+    /// Backends that cannot tell leave them unset.
+    async fn chat_with_finish_reason(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        options: &LlmOptions,
+    ) -> Result<LlmResponse> {
+        self.chat(model, messages, options)
+            .await
+            .map(LlmResponse::new)
+    }
+
+    /// Ids of the models the provider offers to this client's key. Backends without a model
+    /// listing report an error instead.
+    async fn list_models(&self) -> Result<Vec<String>> {
+        Err(crate::core::error::Error::InvalidState(
+            "this LLM backend cannot list its models".into(),
+        ))
+    }
+}
+
+/// Speaker of a [`ChatMessage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    System,
+    User,
+    Assistant,
+}
+
+/// One turn of a conversation sent through [`LlmClient::chat`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self {
+            role: ChatRole::System,
+            content: content.into(),
+        }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: ChatRole::User,
+            content: content.into(),
+        }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self {
+            role: ChatRole::Assistant,
+            content: content.into(),
+        }
+    }
+}
+
+/// A completion together with the provider's reason for ending it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LlmResponse {
+    pub content: String,
+    /// Stop reason as the provider reports it (`stop`, `length`, `max_tokens`, `MAX_TOKENS`,
+    /// ...), or `None` when the backend does not expose one.
+    pub finish_reason: Option<String>,
+    /// Tokens the provider billed for this call, when it reports them.
+    pub usage: Option<TokenUsage>,
+}
+
+/// Input and output token counts of one LLM call.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn total(&self) -> u64 {
+        self.input_tokens.saturating_add(self.output_tokens)
+    }
+}
+
+/// Running total of tokens spent by one session against a hard limit. Shared between the LLM
+/// client decorator that records usage and the runner that pauses once it runs out.
+#[derive(Debug)]
+pub struct TokenBudget {
+    limit: u64,
+    used: AtomicU64,
+}
+
+impl TokenBudget {
+    pub fn new(limit: u64) -> Self {
+        Self::with_used(limit, 0)
+    }
+
+    /// A budget that has already spent `used` tokens, e.g. restored from a saved session.
+    pub fn with_used(limit: u64, used: u64) -> Self {
+        Self {
+            limit,
+            used: AtomicU64::new(used),
+        }
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::SeqCst)
+    }
+
+    /// Whether the session has spent more than its limit.
+    pub fn exhausted(&self) -> bool {
+        self.used() > self.limit
+    }
+
+    /// Add one call's usage and return the new total.
+    pub fn record(&self, usage: TokenUsage) -> u64 {
+        self.used
+            .fetch_add(usage.total(), Ordering::SeqCst)
+            .saturating_add(usage.total())
+    }
+}
+
+impl LlmResponse {
+    pub fn new(content: impl Into<String>) -> Self {
+        Self {
+            content: content.into(),
+            finish_reason: None,
+            usage: None,
+        }
+    }
+
+    pub fn with_finish_reason(mut self, reason: impl Into<String>) -> Self {
+        self.finish_reason = Some(reason.into());
+        self
+    }
+
+    pub fn with_usage(mut self, usage: TokenUsage) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    /// Whether generation stopped because it ran into the output token limit, which leaves the
+    /// content cut off mid-answer.
+    pub fn truncated(&self) -> bool {
+        self.finish_reason.as_deref().is_some_and(|reason| {
+            matches!(
+                reason.to_ascii_lowercase().as_str(),
+                "length" | "max_tokens" | "max_output_tokens"
+            )
+        })
+    }
 }
 
 /// Options for an LLM request.
@@ -55,6 +298,8 @@ pub struct LlmOptions {
     pub temperature: Option<f32>,
     pub max_tokens: Option<usize>,
     pub reasoning_effort: Option<String>,
+    /// Sequences that end generation; the matched sequence is not part of the response.
+    pub stop_sequences: Vec<String>,
 }
 
 /// Abstraction for rendering prompt templates.
@@ -78,12 +323,68 @@ pub trait FileSystem: Send + Sync {
     fn read_to_string(&self, path: &Path) -> Result<String>;
     /// Write a string to a file.
     fn write(&self, path: &Path, content: &str) -> Result<()>;
+    /// Append a string to a file, creating it if missing.
+    fn append(&self, path: &Path, content: &str) -> Result<()>;
     /// Check if a file exists.
     fn exists(&self, path: &Path) -> bool;
     /// Recursively create a directory if it does not exist.
     fn create_dir_all(&self, path: &Path) -> Result<()>;
 }
 
+/// Exit status and captured output of a finished shell command.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandOutput {
+    /// Process exit code; `None` when the process was terminated by a signal.
+    pub status: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CommandOutput {
+    /// Whether the command exited with status 0.
+    pub fn success(&self) -> bool {
+        self.status == Some(0)
+    }
+}
+
+/// Abstraction for running shell commands (verifiers, appliers).
+pub trait CommandRunner: Send + Sync {
+    /// Run `command` through the shell and wait for it to finish.
+    fn run(&self, command: &str) -> Result<CommandOutput>;
+}
+
+/// [`CommandRunner`] used until a real one is injected; refuses every command.
+#[derive(Debug, Default)]
+pub struct NoCommandRunner;
+
+impl CommandRunner for NoCommandRunner {
+    fn run(&self, command: &str) -> Result<CommandOutput> {
+        Err(crate::core::error::Error::InvalidState(format!(
+            "No command runner configured to run `{command}`"
+        )))
+    }
+}
+
+/// Answer given at a step-by-step checkpoint when a human reviews it in-process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckpointDecision {
+    /// Continue with the plan or solution as shown.
+    Approve,
+    /// Discard it and regenerate (re-decompose a plan, re-solve a step).
+    Reject,
+    /// Continue with replacement text: one subtask per line for a plan, otherwise the solution.
+    Edit(String),
+    /// Stop here and persist the session for a later `resume`, as without a reviewer.
+    Pause,
+}
+
+/// Abstraction for reviewing step-by-step checkpoints without pausing the session.
+pub trait CheckpointReviewer: Send + Sync {
+    /// Decide how to proceed from the checkpoint described by `wait`. May block (e.g. on
+    /// terminal input); the runner calls it on the blocking thread pool.
+    fn review(&self, context: &Context, wait: &WaitState) -> CheckpointDecision;
+}
+
 /// Abstraction for getting the current time.
 pub trait Clock: Send + Sync {
     /// Get the current UTC timestamp in milliseconds.
@@ -94,4 +395,7 @@ pub trait Clock: Send + Sync {
 pub trait TelemetrySink: Send + Sync {
     /// Record a generic event.
     fn record_event(&self, event_name: &str, properties: HashMap<String, String>);
+
+    /// Write out any buffered events. Sinks that write through immediately need not override it.
+    fn flush(&self) {}
 }
@@ -0,0 +1,198 @@
+//! Line diffs computed with Myers' O((N+M)·D) algorithm and rendered as unified hunks.
+
+use std::fmt::Write;
+
+/// Lines of unchanged context shown around each hunk, as `diff -u` does.
+pub const DEFAULT_CONTEXT: usize = 3;
+
+/// One line of an edit script turning the old text into the new one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffLine<'a> {
+    Equal(&'a str),
+    Delete(&'a str),
+    Insert(&'a str),
+}
+
+/// Shortest edit script between the lines of `old` and `new`; within a change, deletions come
+/// before insertions.
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<DiffLine<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let offset = n + m;
+    let index = |k: isize| (k + offset) as usize;
+
+    // Furthest x reached on each diagonal k = x - y, saved before every round for backtracking
+    let mut furthest = vec![0isize; 2 * (n + m) as usize + 2];
+    let mut trace = Vec::new();
+    'search: for d in 0..=(n + m) {
+        trace.push(furthest.clone());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && furthest[index(k - 1)] < furthest[index(k + 1)]) {
+                furthest[index(k + 1)]
+            } else {
+                furthest[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            furthest[index(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut script = Vec::with_capacity((n + m) as usize);
+    let (mut x, mut y) = (n, m);
+    for (d, furthest) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && furthest[index(k - 1)] < furthest[index(k + 1)]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = furthest[index(prev_k)];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            script.push(DiffLine::Equal(old[x as usize - 1]));
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == prev_x {
+                script.push(DiffLine::Insert(new[y as usize - 1]));
+            } else {
+                script.push(DiffLine::Delete(old[x as usize - 1]));
+            }
+        }
+        (x, y) = (prev_x, prev_y);
+    }
+    script.reverse();
+    script
+}
+
+/// Lines added plus lines removed between `old` and `new`, as `git diff --stat` counts them.
+pub fn changed_lines(old: &str, new: &str) -> usize {
+    diff_lines(old, new)
+        .iter()
+        .filter(|line| !matches!(line, DiffLine::Equal(_)))
+        .count()
+}
+
+/// Unified diff hunks (`@@ -l,s +l,s @@`) with `context` unchanged lines around each change.
+/// Empty when the texts have the same lines. File headers are left to the caller.
+pub fn unified_diff(old: &str, new: &str, context: usize) -> String {
+    let script = diff_lines(old, new);
+    let changes: Vec<usize> = script
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, DiffLine::Equal(_)))
+        .map(|(idx, _)| idx)
+        .collect();
+
+    // Line numbers (0-based) each script entry starts at in the old and new text
+    let mut positions = Vec::with_capacity(script.len());
+    let (mut old_line, mut new_line) = (0usize, 0usize);
+    for line in &script {
+        positions.push((old_line, new_line));
+        match line {
+            DiffLine::Equal(_) => {
+                old_line += 1;
+                new_line += 1;
+            }
+            DiffLine::Delete(_) => old_line += 1,
+            DiffLine::Insert(_) => new_line += 1,
+        }
+    }
+
+    let mut out = String::new();
+    let mut idx = 0;
+    while idx < changes.len() {
+        // Merge changes whose context windows touch into one hunk
+        let mut last = idx;
+        while last + 1 < changes.len() && changes[last + 1] - changes[last] <= 2 * context + 1 {
+            last += 1;
+        }
+        let start = changes[idx].saturating_sub(context);
+        let end = (changes[last] + context + 1).min(script.len());
+        let hunk = &script[start..end];
+        let old_len = hunk
+            .iter()
+            .filter(|line| !matches!(line, DiffLine::Insert(_)))
+            .count();
+        let new_len = hunk
+            .iter()
+            .filter(|line| !matches!(line, DiffLine::Delete(_)))
+            .count();
+        let (old_start, new_start) = positions[start];
+        let _ = writeln!(
+            out,
+            "@@ -{} +{} @@",
+            hunk_range(old_start, old_len),
+            hunk_range(new_start, new_len)
+        );
+        for line in hunk {
+            let _ = match line {
+                DiffLine::Equal(text) => writeln!(out, " {text}"),
+                DiffLine::Delete(text) => writeln!(out, "-{text}"),
+                DiffLine::Insert(text) => writeln!(out, "+{text}"),
+            };
+        }
+        idx = last + 1;
+    }
+    out
+}
+
+/// `start,len` of a hunk side in 1-based lines; an empty side names the line before it.
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{len}", start + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deletions_precede_insertions_within_a_change() {
+        let diff = unified_diff("a\nb\nc\n", "a\nB\nc\nd\n", DEFAULT_CONTEXT);
+        assert_eq!(diff, "@@ -1,3 +1,4 @@\n a\n-b\n+B\n c\n+d\n");
+    }
+
+    #[test]
+    fn distant_changes_get_separate_hunks_with_context() {
+        let old: String = (1..=20).map(|n| format!("{n}\n")).collect();
+        let new: String = (1..=20)
+            .map(|n| match n {
+                2 => "two\n".to_string(),
+                18 => "eighteen\n".to_string(),
+                _ => format!("{n}\n"),
+            })
+            .collect();
+        let diff = unified_diff(&old, &new, 1);
+        assert_eq!(
+            diff,
+            "@@ -1,3 +1,3 @@\n 1\n-2\n+two\n 3\n@@ -17,3 +17,3 @@\n 17\n-18\n+eighteen\n 19\n"
+        );
+    }
+
+    #[test]
+    fn new_files_and_identical_texts() {
+        assert_eq!(unified_diff("", "x\ny\n", 3), "@@ -0,0 +1,2 @@\n+x\n+y\n");
+        assert_eq!(unified_diff("same\n", "same", 3), "");
+    }
+
+    #[test]
+    fn changed_lines_counts_additions_and_removals() {
+        assert_eq!(changed_lines("a\nb\nc\n", "a\nB\nc\n"), 2);
+        assert_eq!(changed_lines("", "a\nb\n"), 2);
+        assert_eq!(changed_lines("a\nb\n", "a\nb\n"), 0);
+    }
+}
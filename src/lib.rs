@@ -2,8 +2,10 @@
 
 pub mod adapters;
 pub mod application;
+pub mod apply_ignore;
 pub mod config;
 pub mod core;
+pub mod diff;
 pub mod paths;
 pub mod red_flaggers;
 pub mod status_export;
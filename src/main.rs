@@ -9,27 +9,38 @@
 
 use std::{
     fs,
-    net::SocketAddr,
-    path::PathBuf,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
     sync::{Arc, OnceLock},
     time::Duration,
 };
 
-use anyhow::{Result, anyhow};
+use anyhow::{Context, Result, anyhow};
 use clap::Parser;
 
 use microfactory::{
     adapters::{
-        inbound::{Cli, CliAdapter, Commands, LlmProvider, ServeArgs, ServeOptions, ServerAdapter},
+        inbound::{
+            Cli, CliAdapter, Commands, LlmProvider, ServeArgs, ServeOptions, ServerAdapter,
+            cli::TerminalReviewer, normalize_base_path,
+        },
         llm::RigLlmClient,
         outbound::{
-            clock::SystemClock, filesystem::StdFileSystem, persistence::SessionStore,
-            telemetry::TracingTelemetrySink,
+            clock::SystemClock,
+            filesystem::StdFileSystem,
+            llm_trace::{RecordingLlmClient, ReplayLlmClient},
+            persistence::SessionStore,
+            process::StdCommandRunner,
+            telemetry::{FileTelemetrySink, TracingTelemetrySink},
+            token_budget::TokenBudgetLlmClient,
         },
         templating::HandlebarsRenderer,
     },
     application::service::{ApiKeyResolver, AppService, LlmClientFactory},
-    core::ports::{Clock, FileSystem, LlmClient, TelemetrySink, WorkflowService},
+    core::ports::{
+        CheckpointReviewer, Clock, FileSystem, LlmClient, SessionRepository, TelemetrySink,
+        TokenBudget, WorkflowService,
+    },
     paths, tracing_setup,
 };
 
@@ -64,16 +75,36 @@ async fn main() -> Result<()> {
     let log_session_id = compute_log_session_id(&cli.command);
 
     // Initialize tracing (holds file handle)
-    let _guard = tracing_setup::init(
+    let log_guard = tracing_setup::init(
         cli.verbose,
         cli.log_json,
         json_format,
         cli.inspect,
         log_session_id.as_deref(),
+        cli.log_max_mb.map(|mb| mb.saturating_mul(1024 * 1024)),
+        console_target(&cli.command),
     );
 
+    // `run --interactive` answers checkpoints on the terminal instead of pausing the session
+    let checkpoint_reviewer = match &cli.command {
+        Commands::Run(args) if args.interactive => {
+            Some(Arc::new(TerminalReviewer::stdio()) as Arc<dyn CheckpointReviewer>)
+        }
+        _ => None,
+    };
+
+    // `providers` reports which API key env vars are set, including those from ~/.env
+    if matches!(cli.command, Commands::Providers(_)) {
+        ensure_home_env_loaded();
+    }
+
     // Build the application service with all dependencies
-    let service = build_app_service()?;
+    let service = build_app_service(
+        checkpoint_reviewer,
+        cli.trace_llm_to.clone(),
+        cli.replay_trace.clone(),
+        cli.telemetry_file.as_deref(),
+    )?;
 
     // Dispatch command to appropriate adapter
     let result = match cli.command {
@@ -88,30 +119,74 @@ async fn main() -> Result<()> {
         tracing::error!("Command failed: {:#}", e);
     }
 
+    log_guard.shutdown();
     result
 }
 
 /// Build the application service with all injected dependencies.
-fn build_app_service() -> Result<Arc<dyn WorkflowService>> {
-    let store = SessionStore::open(None)?;
+fn build_app_service(
+    checkpoint_reviewer: Option<Arc<dyn CheckpointReviewer>>,
+    trace_llm_to: Option<PathBuf>,
+    replay_trace: Option<PathBuf>,
+    telemetry_file: Option<&Path>,
+) -> Result<Arc<dyn WorkflowService>> {
+    let store: Arc<dyn SessionRepository> = Arc::new(SessionStore::open(None)?);
     let renderer = Arc::new(HandlebarsRenderer::new());
-    let (file_system, clock, telemetry) = default_runner_deps();
+    let (file_system, clock, telemetry) = default_runner_deps(telemetry_file)?;
 
+    let trace_deps = (file_system.clone(), clock.clone());
+    let replaying = replay_trace.is_some();
     let llm_factory: LlmClientFactory = Arc::new(
-        |provider: &str, model: &str, max_concurrent: usize, api_key: String| {
-            let llm_provider = LlmProvider::from_name(provider)
-                .ok_or_else(|| anyhow!("Unknown LLM provider: {}", provider))?;
-            let client =
-                RigLlmClient::new(llm_provider, api_key, model.to_string(), max_concurrent)?;
-            Ok(Arc::new(client) as Arc<dyn LlmClient>)
+        move |provider: &str,
+              model: &str,
+              max_concurrent: usize,
+              api_key: String,
+              headers: &[(String, String)],
+              token_budget: Option<Arc<TokenBudget>>| {
+            let client = match &replay_trace {
+                // `--replay-trace` answers every call from the trace; no provider is contacted
+                Some(path) => {
+                    tracing::info!("Replaying LLM responses from {}", path.display());
+                    Arc::new(ReplayLlmClient::open(trace_deps.0.as_ref(), path)?)
+                        as Arc<dyn LlmClient>
+                }
+                None => {
+                    let llm_provider = LlmProvider::from_name(provider)
+                        .ok_or_else(|| anyhow!("Unknown LLM provider: {}", provider))?;
+                    Arc::new(RigLlmClient::new(
+                        llm_provider,
+                        api_key,
+                        model.to_string(),
+                        max_concurrent,
+                        headers,
+                    )?) as Arc<dyn LlmClient>
+                }
+            };
+            let client = match &trace_llm_to {
+                Some(path) => Arc::new(RecordingLlmClient::new(
+                    client,
+                    trace_deps.0.clone(),
+                    trace_deps.1.clone(),
+                    path.clone(),
+                )) as Arc<dyn LlmClient>,
+                None => client,
+            };
+            Ok(match token_budget {
+                Some(budget) => Arc::new(TokenBudgetLlmClient::new(client, budget)),
+                None => client,
+            })
         },
     );
 
-    let api_key_resolver: ApiKeyResolver = Arc::new(|cli_value: Option<String>, provider: &str| {
-        let llm_provider = LlmProvider::from_name(provider)
-            .ok_or_else(|| anyhow!("Unknown LLM provider: {}", provider))?;
-        resolve_api_key(cli_value, llm_provider)
-    });
+    let api_key_resolver: ApiKeyResolver =
+        Arc::new(move |cli_value: Option<String>, provider: &str| {
+            if replaying {
+                return Ok(String::new());
+            }
+            let llm_provider = LlmProvider::from_name(provider)
+                .ok_or_else(|| anyhow!("Unknown LLM provider: {}", provider))?;
+            resolve_api_key(cli_value, llm_provider)
+        });
 
     let service = AppService::new(
         store,
@@ -121,20 +196,31 @@ fn build_app_service() -> Result<Arc<dyn WorkflowService>> {
         telemetry,
         llm_factory,
         api_key_resolver,
-    );
+    )
+    .with_command_runner(Arc::new(StdCommandRunner::new()))
+    .with_checkpoint_reviewer(checkpoint_reviewer);
 
     Ok(Arc::new(service))
 }
 
 /// Handle the serve command separately since it needs special setup.
 async fn serve_command(args: ServeArgs, service: Arc<dyn WorkflowService>) -> Result<()> {
-    let addr: SocketAddr = format!("{}:{}", args.bind, args.port)
-        .parse()
-        .map_err(|_| anyhow!("Invalid bind/port combination for serve command"))?;
+    let addr = parse_bind_addr(&args.bind, args.port)?;
+    // Check the prefix before binding so a bad value is reported before the port is taken.
+    let base_path = match args.base_path.as_deref() {
+        Some(raw) => normalize_base_path(raw)?,
+        None => None,
+    };
 
     let options = ServeOptions {
         default_limit: args.limit.max(1),
-        poll_interval: Duration::from_millis(args.poll_interval_ms.max(250)),
+        poll_interval: Duration::from_millis(args.poll_interval_ms),
+        min_poll_interval: Duration::from_millis(args.min_poll_ms),
+        sse_event_name: args.sse_event_name,
+        recent_capacity: args.recent_capacity.max(1),
+        base_path,
+        json_case: args.json_case.into(),
+        sse_idle_timeout: args.sse_idle_timeout_secs.map(Duration::from_secs),
     };
 
     tracing::info!("Serving session API on http://{addr}");
@@ -142,6 +228,23 @@ async fn serve_command(args: ServeArgs, service: Arc<dyn WorkflowService>) -> Re
     adapter.run(addr).await
 }
 
+/// Combine `--bind` and `--port` into a socket address. `--bind` takes a bare IPv4 or IPv6
+/// address, with or without brackets (`127.0.0.1`, `::1`, `[::]`).
+fn parse_bind_addr(bind: &str, port: u16) -> Result<SocketAddr> {
+    let trimmed = bind.trim();
+    let host = trimmed
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .unwrap_or(trimmed);
+    host.parse::<IpAddr>()
+        .map(|ip| SocketAddr::new(ip, port))
+        .map_err(|_| {
+            anyhow!(
+                "Invalid --bind '{bind}': expected an IPv4 or IPv6 address such as 127.0.0.1, 0.0.0.0, ::1, or [::] (set the port with --port)"
+            )
+        })
+}
+
 /// Compute the session ID for log file naming.
 fn compute_log_session_id(command: &Commands) -> Option<String> {
     match command {
@@ -149,16 +252,55 @@ fn compute_log_session_id(command: &Commands) -> Option<String> {
         Commands::Resume(args) => Some(args.session_id.clone()),
         Commands::Subprocess(_) => Some(format!("subprocess-{}", uuid::Uuid::new_v4())),
         Commands::Status(args) => args.session_id.clone(),
-        Commands::Serve(_) | Commands::Help(_) => None,
+        Commands::Serve(_)
+        | Commands::Annotate(_)
+        | Commands::Export(_)
+        | Commands::Verify(_)
+        | Commands::Providers(_)
+        | Commands::Models(_)
+        | Commands::Compare(_)
+        | Commands::Help(_) => None,
+    }
+}
+
+/// Send console logs to stderr for commands whose stdout is a JSON document, so automation
+/// can parse stdout as a whole.
+fn console_target(command: &Commands) -> tracing_setup::ConsoleTarget {
+    let json_stdout = match command {
+        Commands::Run(args) => args.json,
+        Commands::Status(args) => args.json,
+        Commands::Verify(args) => args.json,
+        Commands::Compare(args) => args.json,
+        Commands::Providers(args) => args.json,
+        Commands::Models(args) => args.json,
+        Commands::Subprocess(_) | Commands::Export(_) => true,
+        Commands::Resume(_) | Commands::Serve(_) | Commands::Annotate(_) | Commands::Help(_) => {
+            false
+        }
+    };
+    if json_stdout {
+        tracing_setup::ConsoleTarget::Stderr
+    } else {
+        tracing_setup::ConsoleTarget::Stdout
     }
 }
 
-/// Create default runtime dependencies for the runner.
-fn default_runner_deps() -> (Arc<dyn FileSystem>, Arc<dyn Clock>, Arc<dyn TelemetrySink>) {
+/// File system, clock, and telemetry sink shared by the service and the runner.
+type RunnerDeps = (Arc<dyn FileSystem>, Arc<dyn Clock>, Arc<dyn TelemetrySink>);
+
+/// Create default runtime dependencies for the runner. Telemetry goes to the log unless
+/// `--telemetry-file` names a JSONL file for it.
+fn default_runner_deps(telemetry_file: Option<&Path>) -> Result<RunnerDeps> {
     let file_system: Arc<dyn FileSystem> = Arc::new(StdFileSystem::new());
     let clock: Arc<dyn Clock> = Arc::new(SystemClock::new());
-    let telemetry: Arc<dyn TelemetrySink> = Arc::new(TracingTelemetrySink::new());
-    (file_system, clock, telemetry)
+    let telemetry: Arc<dyn TelemetrySink> = match telemetry_file {
+        Some(path) => Arc::new(
+            FileTelemetrySink::open(path)
+                .with_context(|| format!("Failed to open telemetry file {}", path.display()))?,
+        ),
+        None => Arc::new(TracingTelemetrySink::new()),
+    };
+    Ok((file_system, clock, telemetry))
 }
 
 /// Resolve API key from CLI value or environment.
@@ -166,8 +308,16 @@ fn resolve_api_key(cli_value: Option<String>, provider: LlmProvider) -> Result<S
     ensure_home_env_loaded();
     let env_var = provider.env_var();
     let env_value = std::env::var(env_var).ok();
-    pick_api_key(cli_value, env_value)
-        .map_err(|_| anyhow!("Missing API key: pass --api-key or set {env_var}"))
+    pick_api_key(cli_value, env_value).map_err(|_| missing_api_key(provider))
+}
+
+/// Error for a provider with no usable key, naming the env var `resolve_api_key` reads.
+fn missing_api_key(provider: LlmProvider) -> anyhow::Error {
+    anyhow!(
+        "Missing API key for provider '{}': pass --api-key or set {}",
+        provider.as_str(),
+        provider.env_var()
+    )
 }
 
 fn pick_api_key(cli_value: Option<String>, env_value: Option<String>) -> Result<String> {
@@ -272,6 +422,14 @@ mod tests {
         assert!(err.to_string().contains("Missing API key"));
     }
 
+    #[test]
+    fn missing_api_key_names_provider_and_env_var() {
+        let message = missing_api_key(LlmProvider::Grok).to_string();
+        assert!(message.contains("'grok'"), "{message}");
+        assert!(message.contains("XAI_API_KEY"), "{message}");
+        assert!(message.contains("--api-key"), "{message}");
+    }
+
     #[test]
     fn parse_env_assignment_handles_export_and_quotes() {
         let parsed =
@@ -307,4 +465,54 @@ mod tests {
             std::env::remove_var(EXISTING_VAR);
         }
     }
+
+    #[test]
+    fn parse_bind_addr_accepts_ipv4_and_ipv6() {
+        assert_eq!(
+            parse_bind_addr("127.0.0.1", 8080).unwrap(),
+            "127.0.0.1:8080".parse::<SocketAddr>().unwrap()
+        );
+        assert_eq!(
+            parse_bind_addr("::1", 8080).unwrap(),
+            "[::1]:8080".parse::<SocketAddr>().unwrap()
+        );
+        assert_eq!(
+            parse_bind_addr("[::]", 9000).unwrap(),
+            "[::]:9000".parse::<SocketAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_bind_addr_names_the_bad_input() {
+        for bind in ["localhost:8080", "[::1]:8080", "256.0.0.1", ""] {
+            let message = parse_bind_addr(bind, 8080).unwrap_err().to_string();
+            assert!(message.contains(&format!("'{bind}'")), "{message}");
+            assert!(message.contains("IPv6"), "{message}");
+        }
+    }
+
+    #[test]
+    fn telemetry_file_receives_runner_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("telemetry.jsonl");
+        let (_, _, telemetry) = default_runner_deps(Some(&path)).unwrap();
+        telemetry.record_event("runner_outcome", Default::default());
+        drop(telemetry);
+
+        let written = fs::read_to_string(&path).unwrap();
+        assert!(written.contains(r#""event":"runner_outcome""#), "{written}");
+    }
+
+    #[test]
+    fn models_json_sends_logs_to_stderr() {
+        let target = |argv: &[&str]| console_target(&Cli::parse_from(argv).command);
+        assert_eq!(
+            target(&["microfactory", "models", "--json"]),
+            tracing_setup::ConsoleTarget::Stderr
+        );
+        assert_eq!(
+            target(&["microfactory", "models"]),
+            tracing_setup::ConsoleTarget::Stdout
+        );
+    }
 }
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 
 /// Returns the user's home directory using common environment variables.
 pub fn home_dir() -> Option<PathBuf> {
@@ -62,10 +62,117 @@ pub fn data_dir() -> PathBuf {
     }
 }
 
+/// Expands `{session_id}`, `{date}` (UTC `YYYY-MM-DD`), and `{timestamp}` (Unix milliseconds)
+/// in an output path template. Paths without placeholders are returned unchanged; a
+/// templated path must not contain unknown placeholders, `..` components, or `.git`.
+/// `label` names the option in error messages, e.g. `output dir`.
+pub fn expand_output_dir(
+    template: &Path,
+    label: &str,
+    session_id: &str,
+    now_ms: u128,
+) -> Result<PathBuf, String> {
+    let raw = template.to_string_lossy();
+    if !raw.contains('{') {
+        return Ok(template.to_path_buf());
+    }
+
+    let mut expanded = String::with_capacity(raw.len());
+    let mut rest = raw.as_ref();
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .map(|offset| start + offset)
+            .ok_or_else(|| format!("Unclosed placeholder in {label} {raw}"))?;
+        match &rest[start + 1..end] {
+            "session_id" => expanded.push_str(session_id),
+            "date" => expanded.push_str(&utc_date(now_ms)),
+            "timestamp" => expanded.push_str(&now_ms.to_string()),
+            other => {
+                return Err(format!(
+                    "Unknown placeholder {{{other}}} in {label} {raw} (expected session_id, date, or timestamp)"
+                ));
+            }
+        }
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+
+    let path = PathBuf::from(&expanded);
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                return Err(format!("Path traversal (..) is forbidden: {expanded}"));
+            }
+            Component::Normal(name) if name == ".git" => {
+                return Err(format!("Writing into .git is forbidden: {expanded}"));
+            }
+            _ => {}
+        }
+    }
+    Ok(path)
+}
+
+/// Format Unix milliseconds as a UTC calendar date.
+fn utc_date(now_ms: u128) -> String {
+    // Civil-from-days conversion (Howard Hinnant's algorithm).
+    let days = (now_ms / 86_400_000) as i64;
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn output_dir_placeholders_expand() {
+        // 2024-02-29T12:00:00Z
+        let now_ms = 1_709_208_000_000;
+        assert_eq!(
+            expand_output_dir(
+                Path::new("runs/{session_id}/{date}"),
+                "output dir",
+                "abc",
+                now_ms
+            )
+            .unwrap(),
+            PathBuf::from("runs/abc/2024-02-29")
+        );
+        assert_eq!(
+            expand_output_dir(
+                Path::new("/tmp/run-{timestamp}"),
+                "output dir",
+                "abc",
+                now_ms
+            )
+            .unwrap(),
+            PathBuf::from("/tmp/run-1709208000000")
+        );
+        assert_eq!(
+            expand_output_dir(Path::new("../plain"), "output dir", "abc", now_ms).unwrap(),
+            PathBuf::from("../plain")
+        );
+        assert!(expand_output_dir(Path::new("runs/{user}"), "output dir", "abc", now_ms).is_err());
+        assert!(expand_output_dir(Path::new("runs/{date"), "output dir", "abc", now_ms).is_err());
+        assert!(
+            expand_output_dir(Path::new("../runs/{date}"), "output dir", "abc", now_ms).is_err()
+        );
+        assert!(expand_output_dir(Path::new(".git/{date}"), "output dir", "abc", now_ms).is_err());
+        let err =
+            expand_output_dir(Path::new("{user}.md"), "summary name", "abc", now_ms).unwrap_err();
+        assert!(err.contains("in summary name {user}.md"), "{err}");
+    }
+
     #[test]
     fn env_candidates_prioritize_microfactory_home() {
         let primary = PathBuf::from("micro-home");
@@ -1,14 +1,17 @@
 use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::{Context, Result, anyhow};
 use async_trait::async_trait;
 use serde_json::Value;
-use tree_sitter::{Parser, Tree};
+use tokio::task::JoinSet;
+use tree_sitter::{Language, LanguageError, Parser, Tree};
 
 use crate::core::domain::RedFlaggerDescriptor;
 use crate::core::error::Error as CoreError;
-use crate::core::ports::{LlmClient, LlmOptions, RedFlagger};
+use crate::core::ports::{FileSystem, LlmClient, LlmOptions, RedFlagger};
+use crate::diff::changed_lines;
 use crate::utils::extract_xml_files;
 
 /// Describes a single red-flag incident that caused a sample to be rejected.
@@ -18,9 +21,19 @@ pub struct RedFlagMatch {
     pub reason: String,
 }
 
+/// Where the files named by a solution's `<file>` blocks live today, so flaggers can compare a
+/// solution against them instead of treating every file as new.
+#[derive(Clone)]
+pub struct Workspace {
+    pub file_system: Arc<dyn FileSystem>,
+    /// Directory relative paths resolve against; `None` uses the working directory.
+    pub root: Option<PathBuf>,
+}
+
 #[derive(Default)]
 pub struct RedFlagPipeline {
-    flaggers: Vec<Box<dyn RedFlagger>>,
+    flaggers: Vec<Arc<dyn RedFlagger>>,
+    parallel: bool,
 }
 
 impl RedFlagPipeline {
@@ -28,19 +41,30 @@ impl RedFlagPipeline {
         configs: &[RedFlaggerDescriptor],
         llm: Option<Arc<dyn LlmClient>>,
     ) -> Result<Self> {
-        let mut flaggers: Vec<Box<dyn RedFlagger>> = Vec::new();
+        Self::from_configs_in(configs, llm, None)
+    }
+
+    /// Like [`Self::from_configs`], but flaggers that compare against existing files (the
+    /// `granularity` line limit) read them from `workspace`.
+    pub fn from_configs_in(
+        configs: &[RedFlaggerDescriptor],
+        llm: Option<Arc<dyn LlmClient>>,
+        workspace: Option<Workspace>,
+    ) -> Result<Self> {
+        let mut flaggers: Vec<Arc<dyn RedFlagger>> = Vec::new();
         for cfg in configs {
-            let flagger: Box<dyn RedFlagger> = match cfg.kind.as_str() {
+            let flagger: Arc<dyn RedFlagger> = match cfg.kind.as_str() {
                 "length" => {
                     let max_tokens = extract_usize(&cfg.params, "max_tokens")?;
-                    Box::new(LengthRedFlagger { max_tokens })
+                    Arc::new(LengthRedFlagger { max_tokens })
                 }
                 "syntax" => {
                     let language = extract_string(&cfg.params, "language")?;
                     let extract_xml = extract_bool(&cfg.params, "extract_xml")?.unwrap_or(false);
-                    Box::new(SyntaxRedFlagger {
+                    Arc::new(SyntaxRedFlagger {
                         language,
                         extract_xml,
+                        load_grammar: install_grammar,
                     })
                 }
                 "llm_critique" => {
@@ -49,22 +73,40 @@ impl RedFlagPipeline {
                         .context("LLM client required for llm_critique red flagger")?;
                     let model = extract_string(&cfg.params, "model")?;
                     let prompt_template = extract_string(&cfg.params, "prompt_template")?;
-                    Box::new(LlmRedFlagger {
+                    Arc::new(LlmRedFlagger {
                         client,
                         model,
                         prompt_template,
                     })
                 }
+                "granularity" => Arc::new(GranularityRedFlagger {
+                    max_files: extract_optional_usize(&cfg.params, "max_files")?,
+                    max_lines_changed: extract_optional_usize(&cfg.params, "max_lines_changed")?,
+                    workspace: workspace.clone(),
+                }),
                 other => {
                     return Err(anyhow!("Unknown red flagger type: {other}"));
                 }
             };
             flaggers.push(flagger);
         }
-        Ok(Self { flaggers })
+        Ok(Self {
+            flaggers,
+            parallel: false,
+        })
+    }
+
+    /// Run a sample's flaggers concurrently and stop at the first match, instead of running
+    /// every flagger in order and reporting all matches.
+    pub fn with_parallel_flaggers(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self
     }
 
     pub async fn evaluate(&self, candidate: &str) -> Vec<RedFlagMatch> {
+        if self.parallel && self.flaggers.len() > 1 {
+            return self.evaluate_parallel(candidate).await;
+        }
         let mut matches = Vec::new();
         for flagger in &self.flaggers {
             match flagger.check(candidate).await {
@@ -84,6 +126,41 @@ impl RedFlagPipeline {
         matches
     }
 
+    /// Race every flagger; the first rejection aborts the rest, since one match already
+    /// rejects the sample.
+    async fn evaluate_parallel(&self, candidate: &str) -> Vec<RedFlagMatch> {
+        let candidate: Arc<str> = Arc::from(candidate);
+        let mut checks = JoinSet::new();
+        for flagger in &self.flaggers {
+            let flagger = flagger.clone();
+            let candidate = candidate.clone();
+            checks.spawn(async move {
+                let result = flagger.check(&candidate).await;
+                (flagger, result)
+            });
+        }
+        while let Some(joined) = checks.join_next().await {
+            match joined {
+                Ok((_, Ok(()))) => {}
+                Ok((_, Err(CoreError::RedFlag { flagger, reason }))) => {
+                    checks.abort_all();
+                    return vec![RedFlagMatch { flagger, reason }];
+                }
+                Ok((flagger, Err(e))) => {
+                    tracing::warn!(
+                        flagger = flagger.name(),
+                        error = ?e,
+                        "Red flagger failed to execute"
+                    );
+                }
+                Err(e) => {
+                    tracing::warn!(error = %e, "Red flagger task failed");
+                }
+            }
+        }
+        Vec::new()
+    }
+
     pub fn is_empty(&self) -> bool {
         self.flaggers.is_empty()
     }
@@ -115,9 +192,91 @@ impl RedFlagger for LengthRedFlagger {
     }
 }
 
+/// Enforces a domain's `step_granularity`: rejects solutions whose `<file>` blocks touch more
+/// than `max_files` files or change more than `max_lines_changed` lines in total, counting
+/// added plus removed lines against the current file. Candidates without `<file>` blocks
+/// (e.g. decomposition plans) always pass.
+struct GranularityRedFlagger {
+    max_files: Option<usize>,
+    max_lines_changed: Option<usize>,
+    /// Without a workspace, or for a missing or unreadable file, every line counts as added.
+    workspace: Option<Workspace>,
+}
+
+impl GranularityRedFlagger {
+    fn current_contents(&self, path: &str) -> String {
+        let Some(workspace) = &self.workspace else {
+            return String::new();
+        };
+        let relative = Path::new(path);
+        // Paths the applier would reject are never read
+        if relative.is_absolute()
+            || relative
+                .components()
+                .any(|component| matches!(component, Component::ParentDir))
+        {
+            return String::new();
+        }
+        let path = match &workspace.root {
+            Some(root) => root.join(relative),
+            None => relative.to_path_buf(),
+        };
+        if !workspace.file_system.exists(&path) {
+            return String::new();
+        }
+        workspace
+            .file_system
+            .read_to_string(&path)
+            .unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl RedFlagger for GranularityRedFlagger {
+    fn name(&self) -> &str {
+        "granularity"
+    }
+
+    async fn check(&self, candidate: &str) -> crate::core::Result<()> {
+        let files = extract_xml_files(candidate);
+        if let Some(max_files) = self.max_files
+            && files.len() > max_files
+        {
+            return Err(CoreError::RedFlag {
+                flagger: self.name().into(),
+                reason: format!(
+                    "solution touches {} files exceeding limit {max_files}",
+                    files.len()
+                ),
+            });
+        }
+        let lines: usize = files
+            .iter()
+            .map(|(path, content)| changed_lines(&self.current_contents(path), content))
+            .sum();
+        if let Some(max_lines) = self.max_lines_changed
+            && lines > max_lines
+        {
+            return Err(CoreError::RedFlag {
+                flagger: self.name().into(),
+                reason: format!("solution changes {lines} lines exceeding limit {max_lines}"),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Installs a tree-sitter grammar on a parser; tests swap it to simulate load failures.
+type GrammarLoader = fn(&mut Parser, &Language) -> std::result::Result<(), LanguageError>;
+
+fn install_grammar(parser: &mut Parser, language: &Language) -> Result<(), LanguageError> {
+    parser.set_language(language)
+}
+
 struct SyntaxRedFlagger {
     language: String,
     extract_xml: bool,
+    load_grammar: GrammarLoader,
 }
 
 #[async_trait]
@@ -132,7 +291,7 @@ impl RedFlagger for SyntaxRedFlagger {
             if !files.is_empty() {
                 for (path, content) in files {
                     let lang = infer_language(&path).unwrap_or(&self.language);
-                    if let Some(error) = check_syntax(&content, lang)
+                    if let Some(error) = check_syntax(&content, lang, self.load_grammar)
                         .map_err(|e| CoreError::System(e.to_string()))?
                     {
                         return Err(CoreError::RedFlag {
@@ -146,8 +305,8 @@ impl RedFlagger for SyntaxRedFlagger {
         }
 
         // Fallback
-        if let Some(error) =
-            check_syntax(candidate, &self.language).map_err(|e| CoreError::System(e.to_string()))?
+        if let Some(error) = check_syntax(candidate, &self.language, self.load_grammar)
+            .map_err(|e| CoreError::System(e.to_string()))?
         {
             Err(CoreError::RedFlag {
                 flagger: self.name().into(),
@@ -173,7 +332,13 @@ fn infer_language(path: &str) -> Option<&str> {
     }
 }
 
-fn check_syntax(content: &str, language_name: &str) -> Result<Option<String>> {
+/// Parse `content` with the grammar for `language_name`. Unsupported languages, and grammars
+/// that fail to load (e.g. an ABI version mismatch), fall back to a delimiter-balance check.
+fn check_syntax(
+    content: &str,
+    language_name: &str,
+    load_grammar: GrammarLoader,
+) -> Result<Option<String>> {
     let language = match language_name {
         "python" => tree_sitter_python::LANGUAGE.into(),
         "java" => tree_sitter_java::LANGUAGE.into(),
@@ -190,9 +355,16 @@ fn check_syntax(content: &str, language_name: &str) -> Result<Option<String>> {
     };
 
     let mut parser = Parser::new();
-    parser
-        .set_language(&language)
-        .context("Error loading grammar")?;
+    if let Err(err) = load_grammar(&mut parser, &language) {
+        tracing::warn!(
+            language = language_name,
+            error = %err,
+            "Failed to load tree-sitter grammar; falling back to delimiter check"
+        );
+        return Ok(is_unbalanced(content).then(|| {
+            format!("{language_name} delimiters appear unbalanced (grammar unavailable)")
+        }));
+    }
 
     let tree = parser
         .parse(content, None)
@@ -277,24 +449,18 @@ impl RedFlagger for LlmRedFlagger {
 fn is_unbalanced(text: &str) -> bool {
     let mut stack = Vec::new();
     for ch in text.chars() {
-        match ch {
-            '(' | '[' | '{' => stack.push(ch),
-            ')' => {
-                if stack.pop() != Some('(') {
-                    return true;
-                }
-            }
-            ']' => {
-                if stack.pop() != Some('[') {
-                    return true;
-                }
+        let open = match ch {
+            '(' | '[' | '{' => {
+                stack.push(ch);
+                continue;
             }
-            '}' => {
-                if stack.pop() != Some('{') {
-                    return true;
-                }
-            }
-            _ => {}
+            ')' => '(',
+            ']' => '[',
+            '}' => '{',
+            _ => continue,
+        };
+        if stack.pop() != Some(open) {
+            return true;
         }
     }
     !stack.is_empty()
@@ -308,6 +474,16 @@ fn extract_usize(map: &HashMap<String, Value>, key: &str) -> Result<usize> {
         .context(format!("Parameter '{key}' must be a positive integer"))
 }
 
+fn extract_optional_usize(map: &HashMap<String, Value>, key: &str) -> Result<Option<usize>> {
+    match map.get(key) {
+        Some(Value::Null) | None => Ok(None),
+        Some(val) => val
+            .as_u64()
+            .map(|v| Some(v as usize))
+            .context(format!("Parameter '{key}' must be a positive integer")),
+    }
+}
+
 fn extract_string(map: &HashMap<String, Value>, key: &str) -> Result<String> {
     map.get(key)
         .context(format!("Missing red flagger parameter '{key}'"))?
@@ -337,11 +513,59 @@ mod tests {
         assert!(flagger.check("one two").await.is_ok());
     }
 
+    #[tokio::test]
+    async fn granularity_flagger_enforces_file_and_line_limits() {
+        let flagger = GranularityRedFlagger {
+            max_files: Some(1),
+            max_lines_changed: Some(3),
+            workspace: None,
+        };
+        let small = "<file path=\"a.rs\">\nfn a() {}\n</file>";
+        assert!(flagger.check(small).await.is_ok());
+        assert!(flagger.check("1. plan step\n2. another").await.is_ok());
+
+        let two_files = format!("{small}\n<file path=\"b.rs\">\nfn b() {{}}\n</file>");
+        let err = flagger.check(&two_files).await.unwrap_err();
+        assert!(err.to_string().contains("2 files"), "{err}");
+
+        let long = "<file path=\"a.rs\">\n1\n2\n3\n4\n</file>";
+        let err = flagger.check(long).await.unwrap_err();
+        assert!(err.to_string().contains("4 lines"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn granularity_flagger_counts_lines_changed_against_the_workspace() {
+        let tmp = tempfile::tempdir().unwrap();
+        let original: String = (1..=10).map(|n| format!("line {n}\n")).collect();
+        std::fs::write(tmp.path().join("a.rs"), &original).unwrap();
+        let flagger = GranularityRedFlagger {
+            max_files: None,
+            max_lines_changed: Some(3),
+            workspace: Some(Workspace {
+                file_system: Arc::new(crate::adapters::outbound::filesystem::StdFileSystem::new()),
+                root: Some(tmp.path().to_path_buf()),
+            }),
+        };
+
+        // One edited line in a ten-line file is one removal plus one addition
+        let edited = original.replace("line 5", "line five");
+        let solution = format!("<file path=\"a.rs\">{edited}</file>");
+        assert!(flagger.check(&solution).await.is_ok());
+
+        // A new file still counts every line it writes
+        let err = flagger
+            .check("<file path=\"b.rs\">1\n2\n3\n4</file>")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("4 lines"), "{err}");
+    }
+
     #[tokio::test]
     async fn syntax_flagger_detects_errors() {
         let flagger = SyntaxRedFlagger {
             language: "python".into(),
             extract_xml: false,
+            load_grammar: install_grammar,
         };
         // Invalid Python
         assert!(flagger.check("def foo() pass").await.is_err());
@@ -351,6 +575,7 @@ mod tests {
         let rust_flagger = SyntaxRedFlagger {
             language: "rust".into(),
             extract_xml: false,
+            load_grammar: install_grammar,
         };
         // Invalid Rust: missing semicolon
         assert!(rust_flagger.check("fn main() { let x = 1 }").await.is_err());
@@ -360,6 +585,7 @@ mod tests {
         let java_flagger = SyntaxRedFlagger {
             language: "java".into(),
             extract_xml: false,
+            load_grammar: install_grammar,
         };
         // Invalid Java: missing semicolon
         assert!(
@@ -382,6 +608,7 @@ mod tests {
         let flagger = SyntaxRedFlagger {
             language: "python".into(),
             extract_xml: true,
+            load_grammar: install_grammar,
         };
 
         let valid_xml = r#"
@@ -423,6 +650,98 @@ mod tests {
         assert!(err.to_string().contains("Syntax error in main.rs"));
     }
 
+    #[tokio::test]
+    async fn parallel_flaggers_short_circuit_on_first_rejection() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::time::Duration;
+
+        struct SlowFlagger {
+            finished: Arc<AtomicBool>,
+        }
+
+        #[async_trait]
+        impl RedFlagger for SlowFlagger {
+            async fn check(&self, _content: &str) -> crate::core::Result<()> {
+                tokio::time::sleep(Duration::from_secs(30)).await;
+                self.finished.store(true, Ordering::SeqCst);
+                Ok(())
+            }
+
+            fn name(&self) -> &str {
+                "slow"
+            }
+        }
+
+        struct FastRejecter;
+
+        #[async_trait]
+        impl RedFlagger for FastRejecter {
+            async fn check(&self, _content: &str) -> crate::core::Result<()> {
+                Err(CoreError::RedFlag {
+                    flagger: "fast".into(),
+                    reason: "rejected immediately".into(),
+                })
+            }
+
+            fn name(&self) -> &str {
+                "fast"
+            }
+        }
+
+        let finished = Arc::new(AtomicBool::new(false));
+        let pipeline = RedFlagPipeline {
+            // The slow flagger comes first, so sequential evaluation would wait on it.
+            flaggers: vec![
+                Arc::new(SlowFlagger {
+                    finished: finished.clone(),
+                }),
+                Arc::new(FastRejecter),
+            ],
+            parallel: false,
+        }
+        .with_parallel_flaggers(true);
+
+        let matches = tokio::time::timeout(Duration::from_secs(5), pipeline.evaluate("sample"))
+            .await
+            .expect("fast rejection short-circuits the slow flagger");
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].flagger, "fast");
+        assert!(!finished.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn syntax_flagger_degrades_when_grammar_fails_to_load() {
+        let flagger = SyntaxRedFlagger {
+            language: "rust".into(),
+            extract_xml: true,
+            load_grammar: |_, _| Err(LanguageError::Version(999)),
+        };
+        let pipeline = RedFlagPipeline {
+            flaggers: vec![Arc::new(flagger)],
+            parallel: false,
+        };
+
+        // Missing semicolon slips through the heuristic, but the sample is still evaluated.
+        assert!(
+            pipeline
+                .evaluate("fn main() { let x = 1 }")
+                .await
+                .is_empty()
+        );
+
+        let matches = pipeline
+            .evaluate(r#"<file path="main.rs">fn main() { let x = (1; }</file>"#)
+            .await;
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].flagger, "syntax");
+        assert!(
+            matches[0].reason.contains("grammar unavailable"),
+            "{}",
+            matches[0].reason
+        );
+    }
+
     #[tokio::test]
     async fn pipeline_builds_from_config() {
         let configs = vec![RedFlaggerDescriptor {
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    adapters::persistence::{SessionMetadata, SessionRecord, SessionSummary},
+    adapters::persistence::{SessionMetadata, SessionRecord, SessionStatus, SessionSummary},
     core::domain::{
         Context, DecompositionProposal, StepStatus, WaitState, WorkflowMetrics, WorkflowStep,
     },
@@ -27,6 +27,8 @@ impl SessionListExport {
 pub struct SessionSummaryExport {
     pub session_id: String,
     pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pause_trigger: Option<String>,
     pub prompt: String,
     pub domain: String,
     pub updated_at: i64,
@@ -37,6 +39,7 @@ impl From<SessionSummary> for SessionSummaryExport {
         Self {
             session_id: value.session_id,
             status: value.status.as_str().to_string(),
+            pause_trigger: value.pause_trigger,
             prompt: value.prompt,
             domain: value.domain,
             updated_at: value.updated_at,
@@ -57,6 +60,12 @@ pub struct SessionDetailExport {
     pub metadata: SessionMetadata,
     pub completed_steps: usize,
     pub total_steps: usize,
+    /// True while the session is still `running`, i.e. the snapshot is incomplete.
+    #[serde(default)]
+    pub partial: bool,
+    /// Steps currently `Running` or `Applying`.
+    #[serde(default)]
+    pub steps_running: usize,
     pub steps: Vec<WorkflowStep>,
     pub metrics: WorkflowMetrics,
 }
@@ -88,6 +97,8 @@ impl SessionDetailExport {
             metadata: record.envelope.metadata.clone(),
             completed_steps: count_completed_steps(context),
             total_steps: context.steps.len(),
+            partial: record.status == SessionStatus::Running,
+            steps_running: count_running_steps(context),
             steps: context.steps.clone(),
             metrics: context.metrics.clone(),
         }
@@ -106,3 +117,10 @@ pub fn count_completed_steps(ctx: &Context) -> usize {
         .filter(|step| matches!(step.status, StepStatus::Completed))
         .count()
 }
+
+pub fn count_running_steps(ctx: &Context) -> usize {
+    ctx.steps
+        .iter()
+        .filter(|step| matches!(step.status, StepStatus::Running | StepStatus::Applying))
+        .count()
+}
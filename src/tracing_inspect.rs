@@ -31,11 +31,17 @@ where
     S: Subscriber + for<'a> LookupSpan<'a>,
     W: Write + 'static,
 {
-    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
-        // We only care about span completion data, not standalone events.
-        // However, we could capture `reqwest::connect` events here if needed for ops,
-        // but the plan focuses on `on_close` for spans.
-        let _ = event;
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        // Providers may emit `gen_ai.*` values (notably `gen_ai.output.messages`) as events inside
+        // the LLM span rather than recording them on it; merge those into the span's data so
+        // `on_close` sees them either way. Events outside a tracked span are ignored.
+        let Some(span) = ctx.event_span(event) else {
+            return;
+        };
+        let mut ext = span.extensions_mut();
+        if let Some(data) = ext.get_mut::<SpanData>() {
+            event.record(&mut SpanVisitor(data));
+        }
     }
 
     fn on_close(&self, id: Id, ctx: Context<'_, S>) {
@@ -121,7 +127,12 @@ struct SpanData {
 struct SpanVisitor<'a>(&'a mut SpanData);
 
 impl<'a> tracing::field::Visit for SpanVisitor<'a> {
-    fn record_debug(&mut self, _field: &tracing::field::Field, _value: &dyn std::fmt::Debug) {}
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        // `%value` fields (common in events) arrive here; their Debug output is the Display text.
+        if field.name().starts_with("gen_ai.") {
+            self.record_str(field, &debug_text(value));
+        }
+    }
 
     fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
         match field.name() {
@@ -142,6 +153,19 @@ impl<'a> tracing::field::Visit for SpanVisitor<'a> {
     }
 }
 
+/// Text of a field recorded through `Debug`. A `?value` string arrives quoted and escaped, so
+/// its quotes and escapes are undone.
+fn debug_text(value: &dyn std::fmt::Debug) -> String {
+    let text = format!("{value:?}");
+    match text
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+    {
+        Some(inner) => serde_json::from_str(&text).unwrap_or_else(|_| inner.to_string()),
+        None => text,
+    }
+}
+
 // -- Inspection Logic --
 
 impl<W: Write> InspectionLayer<W> {
@@ -331,6 +355,39 @@ fn unescape_html_recursive(mut val: serde_json::Value) -> serde_json::Value {
     val
 }
 
+/// Mask credentials in `val`: values under credential-like keys and provider key tokens
+/// (`sk-...`, `xai-...`) inside strings. Unlike the inspect view, nothing is truncated.
+pub(crate) fn redact_secrets(val: &mut serde_json::Value) {
+    static KEY_RE: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+    let key_re = KEY_RE.get_or_init(|| {
+        regex::Regex::new(r"\b(?:sk|xai)-[A-Za-z0-9_-]{16,}").expect("valid key regex")
+    });
+    match val {
+        serde_json::Value::String(s) if key_re.is_match(s) => {
+            *s = key_re.replace_all(s, "[redacted_key]").into_owned();
+        }
+        serde_json::Value::Array(arr) => {
+            for v in arr {
+                redact_secrets(v);
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for (k, v) in map {
+                if is_sensitive_key(k) {
+                    *v = serde_json::Value::String("[redacted]".to_string());
+                } else {
+                    redact_secrets(v);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_sensitive_key(key: &str) -> bool {
+    key.contains("api_key") || key.contains("token") || key.contains("auth")
+}
+
 fn redact_recursive(val: &mut serde_json::Value) {
     match val {
         serde_json::Value::String(s) => {
@@ -350,7 +407,7 @@ fn redact_recursive(val: &mut serde_json::Value) {
         }
         serde_json::Value::Object(map) => {
             for (k, v) in map {
-                if k.contains("api_key") || k.contains("token") || k.contains("auth") {
+                if is_sensitive_key(k) {
                     *v = serde_json::Value::String("[redacted]".to_string());
                 } else {
                     redact_recursive(v);
@@ -433,4 +490,65 @@ mod tests {
         assert!(s.len() < 5000);
         assert!(s.ends_with("... [truncated]"));
     }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn output_messages_from_in_span_events_are_rendered() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let buffer = SharedBuffer::default();
+        let subscriber = tracing_subscriber::registry()
+            .with(InspectionLayer::new(InspectMode::Messages, buffer.clone()));
+        let output = json!([{ "role": "assistant", "content": "Option 2 wins" }]).to_string();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!(
+                "chat",
+                gen_ai.provider.name = "openai",
+                gen_ai.request.model = "gpt-test"
+            );
+            let _entered = span.enter();
+            tracing::info!(gen_ai.output.messages = %output, "response received");
+        });
+
+        let rendered = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(rendered.contains("[assistant] (Response)"), "{rendered}");
+        assert!(rendered.contains("Option 2 wins"), "{rendered}");
+    }
+
+    #[test]
+    fn debug_recorded_strings_lose_their_quotes() {
+        assert_eq!(debug_text(&"gpt-test"), "gpt-test");
+        assert_eq!(debug_text(&String::from("say \"hi\"")), "say \"hi\"");
+        assert_eq!(debug_text(&42), "42");
+
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let buffer = SharedBuffer::default();
+        let subscriber = tracing_subscriber::registry()
+            .with(InspectionLayer::new(InspectMode::Messages, buffer.clone()));
+        let output = json!([{ "role": "assistant", "content": "Option 1 wins" }]).to_string();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("chat", gen_ai.provider.name = "openai");
+            let _entered = span.enter();
+            tracing::info!(gen_ai.output.messages = ?output, "response received");
+        });
+
+        let rendered = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(rendered.contains("[assistant] (Response)"), "{rendered}");
+        assert!(rendered.contains("Option 1 wins"), "{rendered}");
+    }
 }
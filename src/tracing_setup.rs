@@ -1,8 +1,13 @@
 use std::{
-    fs::{self, OpenOptions},
-    path::Path,
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
 };
-use tracing_appender::rolling::{RollingFileAppender, Rotation};
 use tracing_subscriber::{
     EnvFilter, Layer, Registry, filter::Targets, fmt, layer::SubscriberExt, util::SubscriberInitExt,
 };
@@ -12,24 +17,48 @@ use crate::paths;
 use crate::cli::InspectMode;
 use crate::tracing_inspect::InspectionLayer;
 
+/// Rotated files kept per session log when `--log-max-mb` is set (`.1` newest … `.N` oldest).
+pub const ROTATED_LOG_FILES: usize = 3;
+
 #[derive(Clone, Copy, Debug)]
 pub enum JsonLogFormat {
     Pretty,
     Compact,
 }
 
+/// Stream that receives console log lines. Commands whose stdout is a JSON document log to
+/// stderr so the document stays parseable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConsoleTarget {
+    Stdout,
+    Stderr,
+}
+
+impl ConsoleTarget {
+    fn writer(self) -> Box<dyn Write + Send> {
+        match self {
+            ConsoleTarget::Stdout => Box::new(io::stdout()),
+            ConsoleTarget::Stderr => Box::new(io::stderr()),
+        }
+    }
+}
+
 /// Initializes the tracing subscriber with layered output:
-/// 1. Stdout: Formatted based on `log_json` and `verbose` flags.
+/// 1. Console (`console`, normally stdout): Formatted based on `log_json` and `verbose` flags.
 /// 2. File: Full JSON debug logs to `~/.microfactory/logs/session-<id>.log` (if session_id provided).
+///    With `log_max_bytes`, the file rotates once it would grow past the cap, keeping
+///    [`ROTATED_LOG_FILES`] older generations.
 ///
-/// Returns a WorkerGuard that must be held by main() to ensure file logs are flushed.
+/// Returns a [`LogGuard`] that must be held by main() to ensure file logs are flushed.
 pub fn init(
     verbose: bool,
     log_json: bool,
     json_format: JsonLogFormat,
     inspect: Option<InspectMode>,
     session_id: Option<&str>,
-) -> Option<tracing_appender::non_blocking::WorkerGuard> {
+    log_max_bytes: Option<u64>,
+    console: ConsoleTarget,
+) -> LogGuard {
     let registry = tracing_subscriber::registry();
     let stdout_filter =
         EnvFilter::try_from_default_env().unwrap_or_else(|_| default_env_filter(verbose));
@@ -50,21 +79,32 @@ pub fn init(
                 );
                 (None, None)
             } else {
-                let (prefix, suffix) = file_name
-                    .rsplit_once('.')
-                    .map(|(p, s)| (p.to_string(), Some(s.to_string())))
-                    .unwrap_or((file_name.clone(), None));
-
-                let mut builder = RollingFileAppender::builder().rotation(Rotation::NEVER);
-                builder = builder.filename_prefix(prefix);
-                if let Some(suffix) = suffix {
-                    builder = builder.filename_suffix(suffix);
-                }
+                let writer = match log_max_bytes {
+                    Some(max_bytes) => {
+                        SizeCappedLog::open(file_path.clone(), max_bytes, ROTATED_LOG_FILES)
+                            .map(tracing_appender::non_blocking)
+                            .map_err(|err| err.to_string())
+                    }
+                    None => {
+                        let (prefix, suffix) = file_name
+                            .rsplit_once('.')
+                            .map(|(p, s)| (p.to_string(), Some(s.to_string())))
+                            .unwrap_or((file_name.clone(), None));
 
-                match builder.build(&log_dir) {
-                    Ok(file_appender) => {
-                        let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
+                        let mut builder = RollingFileAppender::builder().rotation(Rotation::NEVER);
+                        builder = builder.filename_prefix(prefix);
+                        if let Some(suffix) = suffix {
+                            builder = builder.filename_suffix(suffix);
+                        }
+                        builder
+                            .build(&log_dir)
+                            .map(tracing_appender::non_blocking)
+                            .map_err(|err| err.to_string())
+                    }
+                };
 
+                match writer {
+                    Ok((non_blocking, guard)) => {
                         let layer = fmt::layer()
                             .json()
                             .with_writer(non_blocking)
@@ -87,7 +127,7 @@ pub fn init(
         (None, None)
     };
 
-    // --- 2. Stdout Layer ---
+    // --- 2. Console Layer ---
     // We use Box<dyn Layer<Registry> + Send + Sync> to erase the type differences
     let stdout_layer: Box<dyn Layer<Registry> + Send + Sync> = if let Some(mode) = inspect {
         // When inspecting, we use our custom layer and filter for relevant spans
@@ -100,26 +140,26 @@ pub fn init(
             JsonLogFormat::Pretty => Box::new(
                 fmt::layer()
                     .json()
-                    .with_writer(|| PrettyJsonWriter::new(std::io::stdout()))
+                    .with_writer(move || PrettyJsonWriter::new(console.writer()))
                     .with_filter(stdout_filter.clone()),
             ),
             JsonLogFormat::Compact => Box::new(
                 fmt::layer()
                     .json()
-                    .with_writer(std::io::stdout)
+                    .with_writer(move || console.writer())
                     .with_filter(stdout_filter.clone()),
             ),
         }
     } else if verbose {
         Box::new(
             fmt::layer()
-                .with_writer(std::io::stdout)
+                .with_writer(move || console.writer())
                 .with_filter(stdout_filter.clone()),
         )
     } else {
         Box::new(
             fmt::layer()
-                .with_writer(std::io::stdout)
+                .with_writer(move || console.writer())
                 .without_time()
                 .with_target(false)
                 .with_level(true)
@@ -129,7 +169,33 @@ pub fn init(
 
     registry.with(stdout_layer).with(file_layer).init();
 
-    guard
+    LogGuard { file: guard }
+}
+
+/// Keeps the background file-log writer alive. Dropping it, or calling [`LogGuard::shutdown`]
+/// explicitly, blocks until every pending record has been written and flushes stdout.
+#[must_use = "dropping the guard stops file logging"]
+pub struct LogGuard {
+    file: Option<WorkerGuard>,
+}
+
+impl LogGuard {
+    /// Flush pending log records now instead of when the guard goes out of scope.
+    pub fn shutdown(mut self) {
+        self.flush();
+    }
+
+    fn flush(&mut self) {
+        // Dropping the worker guard waits for the writer thread to drain its queue.
+        drop(self.file.take());
+        let _ = io::stdout().flush();
+    }
+}
+
+impl Drop for LogGuard {
+    fn drop(&mut self) {
+        self.flush();
+    }
 }
 
 struct PrettyJsonWriter<W: std::io::Write> {
@@ -179,6 +245,77 @@ fn default_env_filter(verbose: bool) -> EnvFilter {
     EnvFilter::new(spec)
 }
 
+/// Append-only log file that rotates `path` to `path.1` (shifting older generations up to
+/// `path.<keep>` and deleting the rest) before a write would push it past `max_bytes`.
+struct SizeCappedLog {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    file: File,
+    written: u64,
+}
+
+impl SizeCappedLog {
+    fn open(path: PathBuf, max_bytes: u64, keep: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_bytes: max_bytes.max(1),
+            keep,
+            file,
+            written,
+        })
+    }
+
+    fn generation(&self, idx: usize) -> PathBuf {
+        let mut name = OsString::from(self.path.as_os_str());
+        name.push(format!(".{idx}"));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.keep > 0 {
+            let oldest = self.generation(self.keep);
+            if oldest.exists() {
+                fs::remove_file(&oldest)?;
+            }
+            for idx in (1..self.keep).rev() {
+                let from = self.generation(idx);
+                if from.exists() {
+                    fs::rename(&from, self.generation(idx + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.generation(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeCappedLog {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Never split a record: rotate first unless the file is empty (oversized single records
+        // still land whole).
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
 fn ensure_log_file(path: &Path) -> std::io::Result<()> {
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)?;
@@ -189,3 +326,26 @@ fn ensure_log_file(path: &Path) -> std::io::Result<()> {
         .open(path)
         .map(|_| ())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_capped_log_rotates_and_prunes_generations() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("session-demo.log");
+        let mut log = SizeCappedLog::open(path.clone(), 10, 2).unwrap();
+
+        for record in ["first-1\n", "second\n", "third-3\n", "fourth\n"] {
+            log.write_all(record.as_bytes()).unwrap();
+        }
+        log.flush().unwrap();
+
+        let read = |suffix: &str| fs::read_to_string(format!("{}{suffix}", path.display())).ok();
+        assert_eq!(read("").as_deref(), Some("fourth\n"));
+        assert_eq!(read(".1").as_deref(), Some("third-3\n"));
+        assert_eq!(read(".2").as_deref(), Some("second\n"));
+        assert_eq!(read(".3"), None, "generations beyond `keep` are pruned");
+    }
+}